@@ -3,7 +3,5 @@ function description()
 end
 
 function run()
-  local windex = focused_window_index()
-  go_back(windex, focused_webview_index(windex))
-  return true
+  return go_back_focused()
 end
@@ -0,0 +1,22 @@
+function description()
+  return "Runs a command in every open window"
+end
+
+-- Usage: broadcast COMMAND [ARGUMENTS...]
+function run()
+  if #arguments < 1 then
+    log_info("Invalid arguments passed to 'broadcast'")
+    return false
+  end
+  local failures = run_command_in_all_windows(table.concat(arguments, " "))
+  if #failures == 0 then
+    return true
+  end
+  local message = "Command failed in windows: " .. table.concat(failures, ", ")
+  log_info(message)
+  local windex = focused_window_index()
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, message)
+  end
+  return false
+end
@@ -2,10 +2,10 @@ function run()
   windex = focused_window_index()
   if #arguments > 0 then
     index = tonumber(arguments[1])
-    if index > webview_count(windex) then
+    if not webview_exists(windex, index) then
       return false
     end
-    focus_webview(windex, tonumber(arguments[1]))
+    focus_webview(windex, index)
   end
   return true
 end
@@ -18,6 +18,10 @@ function complete_command()
   local total = webview_count(windex)
   for i = 0, total - 1 do
     title = "" .. i .. " : " .. webview_title(windex, i):gsub(","," ")
+    local idle = math.floor(webview_idle(windex, i) / 60)
+    if idle > 0 then
+      title = title .. " (idle " .. idle .. "m)"
+    end
     if string.sub(title, 1, #query) == query then
       titles[#titles + 1] = title
     end
@@ -3,11 +3,10 @@ function description()
 end
 
 function run()
-  windex = focused_window_index()
-  target = focused_webview_index(windex) - 1
-  if target < 0 then
-    target = webview_count(windex) - 1
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
   end
-  focus_webview(windex, target)
+  focus_previous_webview(windex)
   return true
 end
@@ -0,0 +1,17 @@
+function description()
+  return "Toggles navigating pages using a movable text cursor"
+end
+
+function run()
+  local enabled = not caret_browsing()
+  set_caret_browsing(enabled)
+  local windex = focused_window_index()
+  if windex ~= NOT_FOUND then
+    if enabled then
+      set_command_field_text(windex, "Caret browsing on")
+    else
+      set_command_field_text(windex, "Caret browsing off")
+    end
+  end
+  return true
+end
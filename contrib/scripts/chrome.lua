@@ -0,0 +1,37 @@
+function description()
+  return "Shows, hides, or toggles part of the focused window, such as the command-bar"
+end
+
+function argspec()
+  return "component [state]"
+end
+
+function run()
+  local windex = focused_window_index()
+  local component = arguments[1]
+  if windex == NOT_FOUND or component == nil then
+    return false
+  end
+  local state = arguments[2] or "toggle"
+  local visible
+  if state == "show" then
+    visible = true
+  elseif state == "hide" then
+    visible = false
+  elseif state == "toggle" then
+    visible = not chrome_visible(windex, component)
+  else
+    log_info("Expected show, hide, or toggle: " .. state)
+    return false
+  end
+  return set_chrome_visible(windex, component, visible)
+end
+
+function complete_command()
+  if argument_index == 1 then
+    return {"command-bar"}
+  elseif argument_index == 2 then
+    return {"show", "hide", "toggle"}
+  end
+  return {}
+end
@@ -0,0 +1,20 @@
+function description()
+  return "Clicks a link in the focused webview by its position on the page"
+end
+
+function argspec()
+  return "index:integer"
+end
+
+-- Links are numbered from 0 in the order highlight-links.lua labels them,
+-- skipping links which are not visible.
+function run()
+  local index = tonumber(arguments[1])
+  if index < 0 then
+    return false
+  end
+  local windex = focused_window_index()
+  local webdex = focused_webview_index(windex)
+  click_element_by_index(windex, webdex, index)
+  return true
+end
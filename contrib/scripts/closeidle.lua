@@ -0,0 +1,26 @@
+function description()
+  return "Closes webviews which have not been focused for a number of minutes (default 60)"
+end
+
+function argspec()
+  return "[minutes:number]"
+end
+
+function run()
+  local minutes = tonumber(arguments[1]) or 60
+  local closed = 0
+  local webviews = all_webviews()
+  -- Close from the last webview so the indices of the rest are unchanged
+  for i = #webviews, 1, -1 do
+    local windex, webdex = webviews[i][1], webviews[i][2]
+    if webview_idle(windex, webdex) > minutes * 60 then
+      close_webview(windex, webdex)
+      closed = closed + 1
+    end
+  end
+  local windex = focused_window_index()
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, string.format("Closed %d idle webviews", closed))
+  end
+  return true
+end
@@ -0,0 +1,20 @@
+function description()
+  return "Disables commands by name, or lists disabled commands"
+end
+
+function run()
+  if #arguments == 0 then
+    local windex = focused_window_index()
+    local disabled = lookup_strings(config_file_path, "commands.disabled")
+    if windex ~= NOT_FOUND then
+      set_command_field_text(windex, "Disabled: " .. table.concat(disabled, ", "))
+    end
+    return true
+  end
+  for _, name in ipairs(arguments) do
+    if not set_command_disabled(name, true) then
+      return false
+    end
+  end
+  return true
+end
@@ -0,0 +1,14 @@
+function description()
+  return "Shows a notification when a download finishes or fails"
+end
+
+-- Include the command name in `commands.on-download-finish` and
+-- `commands.on-download-fail` to enable notifications
+function on_download_finish()
+  local name = string.match(download_path, "([^/]*)$") or download_path
+  notify("Download finished", name)
+end
+
+function on_download_fail()
+  notify("Download failed", error_message)
+end
@@ -0,0 +1,27 @@
+function description()
+  return "Edits the configuration file and reloads it when the editor exits"
+end
+
+-- Opens the configuration file using the editor command set in the
+-- configuration option `general.editor`, falling back to the EDITOR
+-- environment variable. The editor command must not exit until editing is
+-- complete, for example `open -W -t` or `code --wait`. The configuration is
+-- reloaded and content filters are reapplied only if the editor exits
+-- successfully.
+function run()
+  local editor = lookup_string(config_file_path, "general.editor")
+  if #editor == 0 then
+    editor = os.getenv("EDITOR") or ""
+  end
+  if #editor == 0 then
+    log_info("No editor set in general.editor or EDITOR")
+    return false
+  end
+  local command = string.format("%s '%s'", editor, (string.gsub(config_file_path, "'", "'\\''")))
+  log_info(string.format("Running command: %s", command))
+  if not os.execute(command) then
+    log_info("Editor exited with an error, skipping reload")
+    return false
+  end
+  return reload_config()
+end
@@ -0,0 +1,27 @@
+function description()
+  return "Enables commands previously disabled by name"
+end
+
+function run()
+  if #arguments == 0 then
+    log_info("No command names passed to 'enable'")
+    return false
+  end
+  for _, name in ipairs(arguments) do
+    if not set_command_disabled(name, false) then
+      return false
+    end
+  end
+  return true
+end
+
+function complete_command()
+  local query = arguments[argument_index] or ""
+  local matches = {}
+  for _, name in ipairs(lookup_strings(config_file_path, "commands.disabled")) do
+    if string.sub(name, 1, #query) == query then
+      table.insert(matches, name)
+    end
+  end
+  return table.concat(matches, ",")
+end
@@ -0,0 +1,13 @@
+function description()
+  return "Returns a fullscreen video or other element in the focused webview to the page"
+end
+
+function run()
+  local windex = focused_window_index()
+  local webdex = focused_webview_index(windex)
+  if not is_element_fullscreen(windex, webdex) then
+    return false
+  end
+  exit_element_fullscreen(windex, webdex)
+  return true
+end
@@ -0,0 +1,33 @@
+function description()
+  return "Shows which commands would run for page events on a URI, defaulting to the focused page"
+end
+
+function argspec()
+  return "[uri]"
+end
+
+local EVENTS = {"request", "load", "focus", "fail", "fail-loop"}
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  local uri = arguments[1]
+  if uri == nil then
+    uri = webview_uri(windex, focused_webview_index(windex))
+  end
+  local descriptions = {}
+  for _, event in ipairs(EVENTS) do
+    local commands = commands_for_uri_event(event, uri)
+    if #commands > 0 then
+      table.insert(descriptions, event .. ": " .. table.concat(commands, ", "))
+    end
+  end
+  if #descriptions == 0 then
+    set_command_field_text(windex, "No commands run for " .. uri)
+  else
+    set_command_field_text(windex, table.concat(descriptions, "; "))
+  end
+  return true
+end
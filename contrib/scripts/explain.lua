@@ -0,0 +1,28 @@
+function description()
+  return "Shows which command file would run for the text following 'explain'"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND or #arguments == 0 then
+    return false
+  end
+  local name, alias, path, args, disabled = explain_command(table.concat(arguments, " "))
+  local message = name
+  if alias then
+    message = string.format("%s (alias of %s)", arguments[1], name)
+  end
+  if #path == 0 then
+    message = message .. ": no matching command file"
+  else
+    message = message .. ": " .. path
+  end
+  if #args > 0 then
+    message = message .. ", arguments: " .. table.concat(args, " ")
+  end
+  if disabled then
+    message = message .. ", disabled"
+  end
+  set_command_field_text(windex, message)
+  return true
+end
@@ -0,0 +1,27 @@
+function description()
+  return "Copies the configuration and the files it refers to into a directory"
+end
+
+function completion_source()
+  return "files"
+end
+
+function argspec()
+  return "destination"
+end
+
+function run()
+  local count, err = export_bundle(config_file_path, arguments[1])
+  local windex = focused_window_index()
+  if count == NOT_FOUND then
+    log_info(err)
+    if windex ~= NOT_FOUND then
+      set_command_field_text(windex, err)
+    end
+    return false
+  end
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, string.format("Exported configuration and %d files", count))
+  end
+  return true
+end
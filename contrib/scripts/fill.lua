@@ -0,0 +1,20 @@
+function description()
+  return "Fills form fields in the focused webview using the form-autofill values"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  local webdex = focused_webview_index(windex)
+  return fill_form(config_file_path, windex, webdex)
+end
+
+function on_form_filled()
+  if filled_count == 1 then
+    set_command_field_text(window_index, "Filled 1 field")
+  else
+    set_command_field_text(window_index, string.format("Filled %d fields", filled_count))
+  end
+end
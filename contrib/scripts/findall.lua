@@ -0,0 +1,12 @@
+function description()
+  return "Find text in the current webview, highlighting every match"
+end
+
+function run()
+  if #arguments > 0 then
+    windex = focused_window_index()
+    query  = table.concat(arguments, " ")
+    find_with_options(windex, focused_webview_index(windex), query, true)
+  end
+  return false -- continue to display command
+end
@@ -0,0 +1,15 @@
+function description()
+  return "Zooms the image or PDF in the focused webview to fit the window"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  if not fit_to_window(windex, focused_webview_index(windex)) then
+    set_command_field_text(windex, "Only images and PDFs can be fitted")
+    return false
+  end
+  return true
+end
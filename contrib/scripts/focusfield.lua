@@ -0,0 +1,21 @@
+function description()
+  return "Focuses a form field in the focused webview by its position on the page"
+end
+
+function argspec()
+  return "[index:integer]"
+end
+
+-- Fields are numbered from 0, skipping fields which are not visible. The
+-- first field is focused if no index is given.
+function run()
+  local index = tonumber(arguments[1] or "0")
+  if index < 0 then
+    return false
+  end
+  local windex = focused_window_index()
+  local webdex = focused_webview_index(windex)
+  focus_element_by_index(windex, webdex, index)
+  focus_webview_in_window(windex)
+  return true
+end
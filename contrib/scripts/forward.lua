@@ -1,6 +1,7 @@
-function run()
-  windex = focused_window_index()
-  go_forward(windex, focused_webview_index(windex))
-  return true
+function description()
+  return "Navigates forward in the focused web view"
 end
 
+function run()
+  return go_forward_focused()
+end
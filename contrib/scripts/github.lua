@@ -35,7 +35,10 @@ end
 
 function complete_command()
   local subcommands = {"api","dev","gist", "help", "status"}
-  local query = table.concat(arguments," ")
+  if argument_index > 1 then
+    return ""
+  end
+  local query = arguments[1] or ""
   if #query == 0 then
     return table.concat(subcommands,",")
   end
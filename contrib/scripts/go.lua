@@ -4,10 +4,7 @@ end
 
 function run()
   if #arguments > 0 then
-    windex = focused_window_index()
-    target = arguments[1]
-    load_uri(windex, focused_webview_index(windex), target)
-    return true
+    return load_uri_focused(arguments[1])
   end
   log_debug("No URL specified")
   return false
@@ -0,0 +1,20 @@
+function description()
+  return "Runs a previously run command, completing commands containing the text typed"
+end
+
+function completion_source()
+  return "history"
+end
+
+function argspec()
+  return "command..."
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  run_command(windex, table.concat(arguments, " "))
+  return true
+end
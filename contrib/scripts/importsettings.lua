@@ -0,0 +1,27 @@
+function description()
+  return "Replaces the configuration using a directory created by exportsettings"
+end
+
+function completion_source()
+  return "files"
+end
+
+function argspec()
+  return "bundle"
+end
+
+function run()
+  local count, err = import_bundle(config_file_path, arguments[1])
+  local windex = focused_window_index()
+  if count == NOT_FOUND then
+    log_info(err)
+    if windex ~= NOT_FOUND then
+      set_command_field_text(windex, err)
+    end
+    return false
+  end
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, string.format("Imported configuration and %d files", count))
+  end
+  return true
+end
@@ -0,0 +1,14 @@
+function description()
+  return "Shows the configuration directory in the file manager"
+end
+
+-- Reveals the directory set in the configuration option `general.config-dir`,
+-- falling back to the directory containing the configuration file
+function run()
+  local config_dir = lookup_string(config_file_path, "general.config-dir")
+  if #config_dir == 0 then
+    config_dir = string.match(config_file_path, "^(.*)/[^/]*$") or config_file_path
+  end
+  reveal_in_file_manager(config_dir)
+  return true
+end
@@ -0,0 +1,23 @@
+function description()
+  return "Toggles private browsing for the current site, saving the option"
+end
+
+-- Usage: private [on|off]
+-- The webview is reopened to use a data store matching the option.
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  local vindex = focused_webview_index(windex)
+  local enabled = not private_browsing(windex, vindex)
+  if arguments[1] == "on" then
+    enabled = true
+  elseif arguments[1] == "off" then
+    enabled = false
+  elseif #arguments > 0 then
+    log_info("Invalid arguments passed to 'private'")
+    return false
+  end
+  return set_private_browsing(windex, vindex, enabled)
+end
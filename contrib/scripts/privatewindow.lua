@@ -0,0 +1,8 @@
+function description()
+  return "Opens a new window where browsing data is discarded on close"
+end
+
+function run()
+  open_private_window()
+  return true
+end
@@ -0,0 +1,28 @@
+function description()
+  return "Logs the process and memory use of each webview, showing the largest"
+end
+
+function run()
+  local largest, largest_memory = nil, -1
+  for _, webview in ipairs(all_webviews()) do
+    local windex, webdex = webview[1], webview[2]
+    local pid, memory = webview_process_stats(windex, webdex)
+    local title = webview_title(windex, webdex)
+    log_info(string.format("(%d, %d) pid %d, %.1f MB: %s", windex, webdex, pid,
+                           memory / 1048576, title))
+    if memory > largest_memory then
+      largest, largest_memory = title, memory
+    end
+  end
+  local windex = focused_window_index()
+  if windex == NOT_FOUND or largest == nil then
+    return false
+  end
+  if largest_memory == 0 then
+    set_command_field_text(windex, "Process memory is not available")
+  else
+    set_command_field_text(windex, string.format("Largest: %s (%.1f MB)", largest,
+                                                 largest_memory / 1048576))
+  end
+  return true
+end
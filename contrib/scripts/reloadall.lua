@@ -0,0 +1,19 @@
+function description()
+  return "Reloads every open view"
+end
+
+function run()
+  local disable_filters = false
+  if #arguments == 1 and (arguments[1] == "f" or arguments[1] == "force") then
+    disable_filters = true
+  elseif #arguments > 0 then
+    log_info("Invalid arguments passed to 'reloadall'")
+    return false
+  end
+  local count = reload_all(disable_filters)
+  local windex = focused_window_index()
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, string.format("Reloaded %d views", count))
+  end
+  return true
+end
@@ -0,0 +1,11 @@
+function description()
+  return "Recompiles content blocking rules from the configured filter lists"
+end
+
+function run()
+  if not reload_filters(focused_window_index()) then
+    log_info("No content filtering is configured")
+    return false
+  end
+  return true
+end
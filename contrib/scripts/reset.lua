@@ -0,0 +1,9 @@
+function description()
+  return "Returns the current view to the start page with empty history"
+end
+
+function run()
+  window_index = focused_window_index()
+  webview_index = focused_webview_index(window_index)
+  return reset_webview(window_index, webview_index)
+end
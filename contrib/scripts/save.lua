@@ -0,0 +1,30 @@
+function description()
+  return "Saves the current page to a file as HTML or a web archive"
+end
+
+function completion_source()
+  return "files"
+end
+
+function argspec()
+  return "path [format]"
+end
+
+-- The format is either "html" or "webarchive", defaulting to a web archive for
+-- paths ending in ".webarchive" and HTML otherwise.
+function run()
+  local path = arguments[1]
+  local format = arguments[2]
+  if not format then
+    if string.sub(path, -string.len(".webarchive")) == ".webarchive" then
+      format = "webarchive"
+    else
+      format = "html"
+    end
+  end
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  return save_page(windex, focused_webview_index(windex), path, format)
+end
@@ -0,0 +1,15 @@
+function description()
+  return "Sorts the webviews of the focused window by host (default), title, or recent"
+end
+
+function argspec()
+  return "[order]"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  return sort_webviews(windex, arguments[1] or "host")
+end
@@ -0,0 +1,17 @@
+function description()
+  return "Shows the word count and estimated reading time of the current page"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  local words, minutes = page_statistics(windex, focused_webview_index(windex))
+  if words == NOT_FOUND then
+    log_info("Page statistics are not available until the page loads")
+    return false
+  end
+  set_command_field_text(windex, string.format("%d words, %d min read", words, minutes))
+  return true
+end
@@ -0,0 +1,12 @@
+function description()
+  return "Stops loading every open view"
+end
+
+function run()
+  local count = stop_all()
+  local windex = focused_window_index()
+  if windex ~= NOT_FOUND then
+    set_command_field_text(windex, string.format("Stopped %d views", count))
+  end
+  return true
+end
@@ -0,0 +1,22 @@
+function description()
+  return "Focuses an open webview showing a URI or host"
+end
+
+function completion_source()
+  return "tabs"
+end
+
+function run()
+  if #arguments ~= 1 then
+    log_info("Invalid arguments passed to 'switch'")
+    return false
+  end
+  local windex, vindex = find_webview(arguments[1])
+  if windex == NOT_FOUND then
+    log_info("No webview found for " .. arguments[1])
+    return false
+  end
+  show_window(windex)
+  focus_webview(windex, vindex)
+  return true
+end
@@ -0,0 +1,7 @@
+function description()
+  return "Arranges all open windows into a grid on the current screen"
+end
+
+function run()
+  return tile_windows() > 0
+end
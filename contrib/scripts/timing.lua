@@ -0,0 +1,15 @@
+function description()
+  return "Shows how long each stage of loading the current page took"
+end
+
+function run()
+  local windex = focused_window_index()
+  if windex == NOT_FOUND then
+    return false
+  end
+  local dns, tcp, first_byte, dom_loaded, load = page_timing(windex, focused_webview_index(windex))
+  set_command_field_text(windex, string.format(
+    "DNS %d ms, TCP %d ms, first byte %d ms, DOMContentLoaded %d ms, load %d ms",
+    dns, tcp, first_byte, dom_loaded, load))
+  return true
+end
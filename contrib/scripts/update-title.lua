@@ -18,10 +18,14 @@ function on_load_uri()
   update_title(window_index, webview_index)
 end
 
+function on_input_mode_change()
+  update_title(window_index, webview_index)
+end
+
 function update_title(window_index, webview_index)
   title = webview_title(window_index, webview_index)
   if #title > 0 then
-    set_window_title(window_index, default_title(window_index) .. title)
+    set_window_title(window_index, default_title(window_index) .. title .. mode_indicator(window_index, webview_index))
   end
 end
 
@@ -29,3 +33,10 @@ function default_title(window_index)
   index = focused_webview_index(window_index)
   return "(" .. index .. ") "
 end
+
+function mode_indicator(window_index, webview_index)
+  if input_mode(window_index, webview_index) == "insert" then
+    return " -- INSERT --"
+  end
+  return ""
+end
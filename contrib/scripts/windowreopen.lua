@@ -0,0 +1,7 @@
+function description()
+  return "Reopens the most recently closed window"
+end
+
+function run()
+  return reopen_closed_window() ~= NOT_FOUND
+end
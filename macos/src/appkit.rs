@@ -2,7 +2,7 @@ use std::ops::BitOr;
 
 use objc::runtime::{YES,NO,BOOL,Sel};
 use foundation::{NSString,NSMutableArray,NSArray,NSInteger,NSUInteger,NSRect,
-                 NSPoint,NSURL};
+                 NSPoint,NSSize,NSURL};
 use core_graphics::CGFloat;
 
 use super::{Id,ObjCClass,nil};
@@ -53,6 +53,10 @@ pub enum NSEventModifierFlags {
     Function   = 1 << 23,
 }
 
+/// The value returned by `NSAlert::run_modal` when the first button is
+/// pressed. Later buttons return consecutive values.
+pub const NS_ALERT_FIRST_BUTTON_RETURN: NSInteger = 1000;
+
 pub enum NSBackingStoreType {
     Retained    = 0,
     Nonretained = 1,
@@ -79,7 +83,10 @@ impl BitOr for NSWindowMask {
     }
 }
 
+impl_objc_class!(NSAlert);
+impl_objc_class!(NSAppearance);
 impl_objc_class!(NSApplication);
+impl_objc_class!(NSColor);
 impl_objc_class!(NSControl);
 impl_objc_class!(NSEvent);
 impl_objc_class!(NSLayoutConstraint);
@@ -87,6 +94,7 @@ impl_objc_class!(NSMenu);
 impl_objc_class!(NSMenuItem);
 impl_objc_class!(NSPasteboard);
 impl_objc_class!(NSResponder);
+impl_objc_class!(NSScreen);
 impl_objc_class!(NSTextField);
 impl_objc_class!(NSView);
 impl_objc_class!(NSWindow);
@@ -314,6 +322,14 @@ impl NSTextField {
     pub fn set_delegate<T: ObjCClass>(&self, delegate: &T) {
         unsafe { msg_send![self.ptr, setDelegate:delegate.ptr()] }
     }
+
+    pub fn set_placeholder(&self, text: &str) {
+        unsafe { msg_send![self.ptr, setPlaceholderString:NSString::from(text).ptr()] }
+    }
+
+    pub fn set_frame_size(&self, width: CGFloat, height: CGFloat) {
+        unsafe { msg_send![self.ptr, setFrameSize:NSSize { width: width, height: height }] }
+    }
 }
 
 impl NSResponder {
@@ -323,6 +339,74 @@ impl NSResponder {
     }
 }
 
+impl NSAlert {
+
+    pub fn new() -> Self {
+        NSAlert { ptr: unsafe { msg_send![class!(NSAlert), new] } }
+    }
+
+    pub fn set_message_text(&self, text: &str) {
+        unsafe { msg_send![self.ptr, setMessageText:NSString::from(text).ptr()] }
+    }
+
+    pub fn set_informative_text(&self, text: &str) {
+        unsafe { msg_send![self.ptr, setInformativeText:NSString::from(text).ptr()] }
+    }
+
+    pub fn add_button(&self, title: &str) {
+        unsafe {
+            let _: Id = msg_send![self.ptr, addButtonWithTitle:NSString::from(title).ptr()];
+        }
+    }
+
+    pub fn set_accessory_view<T: ObjCClass>(&self, view: &T) {
+        unsafe { msg_send![self.ptr, setAccessoryView:view.ptr()] }
+    }
+
+    pub fn run_modal(&self) -> NSInteger {
+        unsafe { msg_send![self.ptr, runModal] }
+    }
+}
+
+impl NSAppearance {
+
+    pub fn named(name: &str) -> Option<Self> {
+        NSAppearance::from_ptr(unsafe {
+            msg_send![class!(NSAppearance), appearanceNamed:NSString::from(name).ptr()]
+        })
+    }
+}
+
+impl NSColor {
+
+    /// Create a color in the sRGB color space from components between 0 and 1
+    pub fn from_rgba(red: CGFloat, green: CGFloat, blue: CGFloat, alpha: CGFloat) -> Self {
+        NSColor {
+            ptr: unsafe {
+                msg_send![class!(NSColor), colorWithSRGBRed:red green:green blue:blue alpha:alpha]
+            }
+        }
+    }
+}
+
+impl NSScreen {
+
+    /// The screens attached to the computer, starting with the screen
+    /// containing the menu bar
+    pub fn screens() -> NSArray {
+        NSArray::from_ptr(unsafe { msg_send![class!(NSScreen), screens] }).unwrap()
+    }
+
+    pub fn frame(&self) -> NSRect {
+        unsafe { msg_send![self.ptr, frame] }
+    }
+
+    /// The frame of the screen excluding the menu bar and dock
+    pub fn visible_frame(&self) -> NSRect {
+        unsafe { msg_send![self.ptr, visibleFrame] }
+    }
+}
+
 impl NSView {
 
     pub fn new() -> Self {
@@ -371,6 +455,22 @@ impl NSView {
         unsafe { msg_send![self.ptr, addSubview:view.ptr()] }
     }
 
+    /// Move a subview to another index among the subviews, keeping any
+    /// constraints on the subview
+    pub fn move_subview(&self, from: NSUInteger, to: NSUInteger) {
+        if let Some(subviews) = self.subviews().map(|views| views.as_mut().autorelease()) {
+            if from >= subviews.count() || to >= subviews.count() {
+                return;
+            }
+            unsafe {
+                let view: Id = msg_send![subviews.ptr(), objectAtIndex:from];
+                let _: () = msg_send![subviews.ptr(), removeObjectAtIndex:from];
+                let _: () = msg_send![subviews.ptr(), insertObject:view atIndex:to];
+                let _: () = msg_send![self.ptr, setSubviews:subviews.ptr()];
+            }
+        }
+    }
+
     pub fn set_hidden(&self, hidden: bool) {
         let value = if hidden { YES } else { NO };
         unsafe { msg_send![self.ptr, setHidden:value] }
@@ -388,6 +488,13 @@ impl NSView {
     pub fn window(&self) -> Option<NSWindow> {
         NSWindow::from_ptr(unsafe { msg_send![self.ptr, window] })
     }
+
+    /// Set the appearance of the view, or `None` to inherit the appearance
+    /// of the window
+    pub fn set_appearance(&self, appearance: Option<NSAppearance>) {
+        let ptr = appearance.map(|a| a.ptr()).unwrap_or(nil);
+        unsafe { msg_send![self.ptr, setAppearance:ptr] }
+    }
 }
 
 impl NSWindow {
@@ -448,6 +555,18 @@ impl NSWindow {
         unsafe { msg_send![self.ptr, setTitle:NSString::from(title)] }
     }
 
+    /// The URL of the document the window represents, shown as an icon in
+    /// the title bar
+    pub fn represented_url(&self) -> Option<NSURL> {
+        NSURL::from_ptr(unsafe { msg_send![self.ptr, representedURL] })
+    }
+
+    /// Set or clear the URL of the document the window represents
+    pub fn set_represented_url(&self, url: Option<&NSURL>) {
+        let ptr = url.map_or(nil, |url| url.ptr());
+        unsafe { msg_send![self.ptr, setRepresentedURL:ptr] }
+    }
+
     pub fn make_key_and_order_front(&self) {
         unsafe { msg_send![self.ptr, makeKeyAndOrderFront:nil] }
     }
@@ -463,6 +582,23 @@ impl NSWindow {
     pub fn frame(&self) -> NSRect {
         unsafe { msg_send![self.ptr, frame] }
     }
+
+    pub fn screen(&self) -> Option<NSScreen> {
+        NSScreen::from_ptr(unsafe { msg_send![self.ptr, screen] })
+    }
+
+    pub fn is_miniaturized(&self) -> bool {
+        let miniaturized: BOOL = unsafe { msg_send![self.ptr, isMiniaturized] };
+        miniaturized == YES
+    }
+
+    pub fn miniaturize(&self) {
+        unsafe { msg_send![self.ptr, miniaturize:nil] }
+    }
+
+    pub fn deminiaturize(&self) {
+        unsafe { msg_send![self.ptr, deminiaturize:nil] }
+    }
 }
 
 impl NSWorkspace {
@@ -474,4 +610,9 @@ impl NSWorkspace {
     pub fn open_url(&self, url: NSURL) {
         unsafe { msg_send![self.ptr, openURL:url.ptr()] }
     }
+
+    /// Open file viewer windows selecting the file URLs
+    pub fn activate_file_viewer_selecting(&self, urls: NSArray) {
+        unsafe { msg_send![self.ptr, activateFileViewerSelectingURLs:urls.ptr()] }
+    }
 }
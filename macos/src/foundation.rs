@@ -2,10 +2,10 @@ use std::{str,slice};
 use std::cmp::PartialEq;
 
 use objc::{Encode,Encoding};
-use objc::runtime::{Object,Class,YES,BOOL};
+use objc::runtime::{Object,Class,Sel,YES,NO,BOOL};
 use libc;
 
-use super::{Id,ObjCClass};
+use super::{Id,ObjCClass,nil};
 use core_graphics::{CGRect,CGSize,CGPoint};
 
 
@@ -28,20 +28,37 @@ pub type NSPoint = CGPoint;
 
 const UTF8_ENCODING: NSUInteger = 4;
 
+/// Default timeout for requests, in seconds
+const DEFAULT_REQUEST_TIMEOUT: f64 = 60.;
+
+#[derive(PartialEq)]
+pub enum NSURLRequestCachePolicy {
+    UseProtocolCachePolicy = 0,
+    ReloadIgnoringLocalCacheData = 1,
+    ReturnCacheDataElseLoad = 2,
+    ReturnCacheDataDontLoad = 3,
+}
+
 impl_objc_class!(NSAppleEventDescriptor);
 impl_objc_class!(NSAppleEventManager);
 impl_objc_class!(NSArray);
 impl_objc_class!(NSAutoreleasePool);
 impl_objc_class!(NSBundle);
+impl_objc_class!(NSData);
 impl_objc_class!(NSDictionary);
 impl_objc_class!(NSError);
 impl_objc_class!(NSMutableArray);
+impl_objc_class!(NSMutableURLRequest);
 impl_objc_class!(NSNotification);
 impl_objc_class!(NSNumber);
 impl_objc_class!(NSProcessInfo);
+impl_objc_class!(NSProgress);
 impl_objc_class!(NSString);
+impl_objc_class!(NSTimer);
 impl_objc_class!(NSURL);
 impl_objc_class!(NSURLRequest);
+impl_objc_class!(NSUserNotification);
+impl_objc_class!(NSUserNotificationCenter);
 
 impl NSAppleEventDescriptor {
 
@@ -200,6 +217,25 @@ impl NSBundle {
     }
 }
 
+impl NSData {
+
+    pub fn len(&self) -> usize {
+        let length: NSUInteger = unsafe { msg_send![self.ptr, length] };
+        length as usize
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        let length = self.len();
+        if length == 0 {
+            return &[];
+        }
+        unsafe {
+            let bytes: *const u8 = msg_send![self.ptr, bytes];
+            slice::from_raw_parts(bytes, length)
+        }
+    }
+}
+
 impl NSDictionary {
 
     /// Creates a reference to an object in a dictionary if the specified type
@@ -226,6 +262,14 @@ impl NSError {
         NSString::from_ptr(unsafe { msg_send![self.ptr, localizedFailureReason] })
     }
 
+    pub fn code(&self) -> NSInteger {
+        unsafe { msg_send![self.ptr, code] }
+    }
+
+    pub fn domain(&self) -> Option<NSString> {
+        NSString::from_ptr(unsafe { msg_send![self.ptr, domain] })
+    }
+
 }
 
 impl NSNotification {
@@ -239,11 +283,45 @@ impl NSNotification {
     }
 }
 
+impl NSProgress {
+
+    pub fn completed_unit_count(&self) -> i64 {
+        unsafe { msg_send![self.ptr, completedUnitCount] }
+    }
+
+    /// The total units of work, or a negative number if unknown
+    pub fn total_unit_count(&self) -> i64 {
+        unsafe { msg_send![self.ptr, totalUnitCount] }
+    }
+
+    /// Register an object to receive key-value observing notifications when
+    /// the value at a key path changes
+    pub fn add_observer<T: ObjCClass>(&self, observer: &T, key_path: &str) {
+        unsafe {
+            msg_send![self.ptr, addObserver:observer.ptr()
+                                forKeyPath:NSString::from(key_path).ptr
+                                   options:0 as NSUInteger
+                                   context:nil]
+        }
+    }
+
+    pub fn remove_observer<T: ObjCClass>(&self, observer: &T, key_path: &str) {
+        unsafe {
+            msg_send![self.ptr, removeObserver:observer.ptr()
+                                   forKeyPath:NSString::from(key_path).ptr]
+        }
+    }
+}
+
 impl NSNumber {
 
     pub fn integer_value(&self) -> NSInteger {
         unsafe { msg_send![self.ptr, integerValue] }
     }
+
+    pub fn double_value(&self) -> f64 {
+        unsafe { msg_send![self.ptr, doubleValue] }
+    }
 }
 
 #[repr(C)]
@@ -382,6 +460,48 @@ unsafe impl Encode for NSRange {
     }
 }
 
+impl NSTimer {
+
+    /// Schedule a timer on the current run loop which sends `selector` to
+    /// `target` after `interval` seconds, passing the timer as the argument
+    pub fn scheduled<T: ObjCClass>(interval: f64, target: &T, selector: Sel, repeats: bool) -> Self {
+        let repeats: BOOL = if repeats { YES } else { NO };
+        NSTimer {
+            ptr: unsafe {
+                msg_send![class!(NSTimer), scheduledTimerWithTimeInterval:interval
+                                                                  target:target.ptr()
+                                                                selector:selector
+                                                                userInfo:nil
+                                                                 repeats:repeats]
+            }
+        }
+    }
+
+    /// Schedule a timer on the current run loop which does not repeat,
+    /// keeping a reference to `user_info` until it fires or is invalidated
+    pub fn scheduled_with_info<T: ObjCClass, U: ObjCClass>(interval: f64, target: &T, selector: Sel, user_info: &U) -> Self {
+        NSTimer {
+            ptr: unsafe {
+                msg_send![class!(NSTimer), scheduledTimerWithTimeInterval:interval
+                                                                  target:target.ptr()
+                                                                selector:selector
+                                                                userInfo:user_info.ptr()
+                                                                 repeats:NO]
+            }
+        }
+    }
+
+    /// The object passed to `scheduled_with_info`
+    pub fn user_info(&self) -> Id {
+        unsafe { msg_send![self.ptr, userInfo] }
+    }
+
+    /// Stop the timer from firing and release its target and user info
+    pub fn invalidate(&self) {
+        unsafe { msg_send![self.ptr, invalidate] }
+    }
+}
+
 impl NSURL {
 
     /// Create a new `NSURL` from an `NSString`
@@ -401,6 +521,14 @@ impl NSURL {
         NSURL { ptr: ptr }
     }
 
+    /// Create a file URL from a path
+    pub fn file_url(path: NSString) -> Self {
+        let ptr: *mut Object = unsafe {
+            msg_send![class!(NSURL), fileURLWithPath:path.ptr]
+        };
+        NSURL { ptr: ptr }
+    }
+
     pub fn absolute_string(&self) -> NSString {
         let ptr: *mut Object = unsafe { msg_send![self.ptr, absoluteString] };
         NSString { ptr: ptr }
@@ -412,6 +540,32 @@ impl NSURL {
     }
 }
 
+impl NSUserNotification {
+
+    pub fn new(title: &str, body: &str) -> Self {
+        let ptr: *mut Object = unsafe {
+            let notification: Id = msg_send![class!(NSUserNotification), new];
+            let () = msg_send![notification, setTitle:NSString::from(title).ptr];
+            let () = msg_send![notification, setInformativeText:NSString::from(body).ptr];
+            notification
+        };
+        NSUserNotification { ptr: ptr }
+    }
+}
+
+impl NSUserNotificationCenter {
+
+    pub fn default_center() -> Self {
+        NSUserNotificationCenter {
+            ptr: unsafe { msg_send![class!(NSUserNotificationCenter), defaultUserNotificationCenter] }
+        }
+    }
+
+    pub fn deliver(&self, notification: &NSUserNotification) {
+        unsafe { msg_send![self.ptr, deliverNotification:notification.ptr] }
+    }
+}
+
 impl NSURLRequest {
 
     pub fn from(url: NSURL) -> Self {
@@ -421,9 +575,49 @@ impl NSURLRequest {
         NSURLRequest { ptr: ptr }
     }
 
+    pub fn with_cache_policy(url: NSURL, policy: NSURLRequestCachePolicy) -> Self {
+        let ptr: *mut Object = unsafe {
+            msg_send![class!(NSURLRequest), requestWithURL:url.ptr
+                                               cachePolicy:policy as NSUInteger
+                                           timeoutInterval:DEFAULT_REQUEST_TIMEOUT]
+        };
+        NSURLRequest { ptr: ptr }
+    }
+
     pub fn url(&self) -> NSURL {
         NSURL { ptr: unsafe { msg_send![self.ptr, URL] } }
     }
+
+    pub fn cache_policy(&self) -> NSURLRequestCachePolicy {
+        unsafe { msg_send![self.ptr, cachePolicy] }
+    }
+
+    pub fn value_for_http_header_field(&self, field: &str) -> Option<NSString> {
+        let field = NSString::from(field);
+        NSString::from_ptr(unsafe { msg_send![self.ptr, valueForHTTPHeaderField:field.ptr] })
+    }
+
+    pub fn mutable_copy(&self) -> NSMutableURLRequest {
+        NSMutableURLRequest { ptr: unsafe { msg_send![self.ptr, mutableCopy] } }
+    }
+}
+
+impl NSMutableURLRequest {
+
+    pub fn set_cache_policy(&self, policy: NSURLRequestCachePolicy) {
+        unsafe { msg_send![self.ptr, setCachePolicy:policy as NSUInteger] }
+    }
+
+    /// Set the value of a header, or remove the header if the value is `None`
+    pub fn set_value_for_http_header_field(&self, value: Option<&str>, field: &str) {
+        let field = NSString::from(field);
+        let value = value.map(|value| NSString::from(value).ptr).unwrap_or(nil);
+        unsafe { msg_send![self.ptr, setValue:value forHTTPHeaderField:field.ptr] }
+    }
+
+    pub fn into_request(self) -> NSURLRequest {
+        NSURLRequest { ptr: self.ptr }
+    }
 }
 
 #[test]
@@ -29,6 +29,8 @@ pub trait ObjCClass: Sized {
 
     fn autorelease(&self) -> Self;
 
+    fn retain(&self) -> Self;
+
     fn release(&mut self);
 }
 
@@ -77,6 +79,10 @@ macro_rules! impl_objc_class {
                 $name { ptr: unsafe { msg_send![self.ptr, autorelease] } }
             }
 
+            fn retain(&self) -> Self {
+                $name { ptr: unsafe { msg_send![self.ptr, retain] } }
+            }
+
             fn release(&mut self) {
                 let () = unsafe { msg_send![self.ptr(), release] };
                 self.ptr = 0 as Id;
@@ -90,4 +96,5 @@ pub mod core_foundation;
 pub mod core_graphics;
 pub mod core_services;
 pub mod foundation;
+pub mod libproc;
 pub mod webkit;
@@ -0,0 +1,46 @@
+use std::mem;
+
+use libc::{c_int,c_void};
+
+
+/// The `flavor` of `proc_pidinfo` returning a `proc_taskinfo`
+const PROC_PIDTASKINFO: c_int = 4;
+
+#[repr(C)]
+#[derive(Default)]
+struct ProcTaskInfo {
+    virtual_size: u64,
+    resident_size: u64,
+    total_user: u64,
+    total_system: u64,
+    threads_user: u64,
+    threads_system: u64,
+    policy: i32,
+    faults: i32,
+    pageins: i32,
+    cow_faults: i32,
+    messages_sent: i32,
+    messages_received: i32,
+    syscalls_mach: i32,
+    syscalls_unix: i32,
+    csw: i32,
+    threadnum: i32,
+    numrunning: i32,
+    priority: i32,
+}
+
+// Provided by libSystem
+extern {
+    fn proc_pidinfo(pid: c_int, flavor: c_int, arg: u64, buffer: *mut c_void, size: c_int) -> c_int;
+}
+
+/// The resident memory size of a process in bytes, or `None` if the process
+/// does not exist or cannot be inspected
+pub fn resident_size(pid: i32) -> Option<u64> {
+    let mut info = ProcTaskInfo::default();
+    let size = mem::size_of::<ProcTaskInfo>() as c_int;
+    let written = unsafe {
+        proc_pidinfo(pid, PROC_PIDTASKINFO, 0, &mut info as *mut _ as *mut c_void, size)
+    };
+    if written == size { Some(info.resident_size) } else { None }
+}
@@ -1,8 +1,9 @@
 use std::ops::Deref;
 
 use objc::runtime::{YES,NO,BOOL};
-use foundation::{NSString,NSURLRequest,NSURL,NSUInteger};
-use core_graphics::CGRect;
+use appkit::NSColor;
+use foundation::{NSProgress,NSString,NSURLRequest,NSURL,NSUInteger,NSInteger};
+use core_graphics::{CGFloat,CGPoint,CGRect};
 use block::Block;
 
 use super::{Id,ObjCClass,nil};
@@ -12,6 +13,8 @@ use super::{Id,ObjCClass,nil};
 extern {}
 
 pub type ContentExtensionCompletionHandler = dyn Deref<Target=Block<(Id, Id), ()>>;
+pub type JavaScriptCompletionHandler = dyn Deref<Target=Block<(Id, Id), ()>>;
+pub type WebArchiveCompletionHandler = dyn Deref<Target=Block<(Id, Id), ()>>;
 
 pub enum WKFindOptions {
     CaseInsensitive = 1 << 0,
@@ -29,6 +32,29 @@ pub enum WKNavigationActionPolicy {
     Allow  = 1,
 }
 
+pub enum WKNavigationResponsePolicy {
+    Cancel   = 0,
+    Allow    = 1,
+    Download = 2,
+}
+
+pub enum WKPermissionDecision {
+    Prompt = 0,
+    Grant  = 1,
+    Deny   = 2,
+}
+
+pub enum WKMediaCaptureType {
+    Camera               = 0,
+    Microphone           = 1,
+    CameraAndMicrophone  = 2,
+}
+
+pub enum WKUserScriptInjectionTime {
+    AtDocumentStart = 0,
+    AtDocumentEnd   = 1,
+}
+
 #[derive(PartialEq)]
 pub enum WKNavigationType {
     LinkActivated   = 0,
@@ -39,11 +65,18 @@ pub enum WKNavigationType {
     Other           = -1,
 }
 
+impl_objc_class!(WKBackForwardList);
+impl_objc_class!(WKDownload);
 impl_objc_class!(WKFrameInfo);
 impl_objc_class!(WKNavigation);
 impl_objc_class!(WKNavigationAction);
+impl_objc_class!(WKNavigationResponse);
 impl_objc_class!(WKPreferences);
+impl_objc_class!(WKProcessPool);
+impl_objc_class!(WKScriptMessage);
+impl_objc_class!(WKSecurityOrigin);
 impl_objc_class!(WKUserContentController);
+impl_objc_class!(WKUserScript);
 impl_objc_class!(WKWebView);
 impl_objc_class!(WKWebViewConfiguration);
 impl_objc_class!(WKWebsiteDataStore);
@@ -51,6 +84,25 @@ impl_objc_class!(_WKUserContentExtensionStore);
 impl_objc_class!(_WKUserContentFilter);
 impl_objc_class!(_WKUserStyleSheet);
 
+impl WKBackForwardList {
+
+    /// Remove every item from the list, including the current item
+    pub fn remove_all_items(&self) {
+        unsafe { msg_send![self.ptr, _removeAllItems] }
+    }
+}
+
+impl WKDownload {
+
+    pub fn set_delegate<T: ObjCClass>(&self, delegate: &T) {
+        unsafe { msg_send![self.ptr, setDelegate:delegate.ptr()] }
+    }
+
+    pub fn progress(&self) -> Option<NSProgress> {
+        NSProgress::from_ptr(unsafe { msg_send![self.ptr, progress] })
+    }
+}
+
 impl WKFrameInfo {
 
     pub fn is_main_frame(&self) -> bool {
@@ -94,6 +146,34 @@ impl WKNavigationAction {
     }
 }
 
+impl WKNavigationResponse {
+
+    pub fn can_show_mime_type(&self) -> bool {
+        let can_show: BOOL = unsafe { msg_send![self.ptr, canShowMIMEType] };
+        can_show == YES
+    }
+
+    pub fn is_for_main_frame(&self) -> bool {
+        let main_frame: BOOL = unsafe { msg_send![self.ptr, isForMainFrame] };
+        main_frame == YES
+    }
+
+    /// The MIME type of the response, if known
+    pub fn mime_type(&self) -> Option<NSString> {
+        NSString::from_ptr(unsafe {
+            let response: Id = msg_send![self.ptr, response];
+            msg_send![response, MIMEType]
+        })
+    }
+}
+
+impl WKSecurityOrigin {
+
+    pub fn host(&self) -> Option<NSString> {
+        NSString::from_ptr(unsafe { msg_send![self.ptr, host] })
+    }
+}
+
 impl WKPreferences {
 
     pub fn set_javascript_enabled(&self, enabled: bool) {
@@ -105,18 +185,71 @@ impl WKPreferences {
         let value = if enabled { YES } else { NO };
         unsafe { msg_send![self.ptr, setPlugInsEnabled:value] }
     }
+
+    pub fn set_fullscreen_enabled(&self, enabled: bool) {
+        let value = if enabled { YES } else { NO };
+        unsafe { msg_send![self.ptr, _setFullScreenEnabled:value] }
+    }
+
+    pub fn set_caret_browsing_enabled(&self, enabled: bool) {
+        let value = if enabled { YES } else { NO };
+        unsafe { msg_send![self.ptr, _setCaretBrowsingEnabled:value] }
+    }
+}
+
+impl WKProcessPool {
+
+    pub fn new() -> Self {
+        WKProcessPool { ptr: unsafe { msg_send![class!(WKProcessPool), new] } }
+    }
+}
+
+impl WKScriptMessage {
+
+    pub fn body<T: ObjCClass>(&self) -> Option<T> {
+        T::from_ptr(unsafe { msg_send![self.ptr, body] })
+    }
+
+    pub fn web_view(&self) -> Option<WKWebView> {
+        WKWebView::from_ptr(unsafe { msg_send![self.ptr, webView] })
+    }
 }
 
 impl WKUserContentController {
 
+    pub fn add_script_message_handler<T: ObjCClass>(&self, handler: &T, name: &str) {
+        let name = NSString::from(name).autorelease();
+        unsafe { msg_send![self.ptr, addScriptMessageHandler:handler.ptr() name:name.ptr()] }
+    }
+
     pub fn add_user_content_filter(&self, filter: _WKUserContentFilter) {
         unsafe { msg_send![self.ptr, _addUserContentFilter:filter.ptr()] }
     }
 
+    pub fn add_user_script(&self, script: WKUserScript) {
+        unsafe { msg_send![self.ptr, addUserScript:script.ptr()] }
+    }
+
+    pub fn remove_all_user_scripts(&self) {
+        unsafe { msg_send![self.ptr, removeAllUserScripts] }
+    }
+
+    pub fn remove_all_user_content_filters(&self) {
+        unsafe { msg_send![self.ptr, _removeAllUserContentFilters] }
+    }
+
     pub fn add_user_style_sheet(&self, stylesheet: _WKUserStyleSheet) {
         unsafe { msg_send![self.ptr, _addUserStyleSheet:stylesheet.ptr()] }
     }
 
+    pub fn remove_user_style_sheet(&self, stylesheet: &_WKUserStyleSheet) {
+        unsafe { msg_send![self.ptr, _removeUserStyleSheet:stylesheet.ptr()] }
+    }
+
+    pub fn remove_all_user_style_sheets(&self) {
+        unsafe { msg_send![self.ptr, _removeAllUserStyleSheets] }
+    }
+
     pub fn can_add_user_style_sheet(&self) -> bool {
         let responds: BOOL = unsafe {
             msg_send![self.ptr, respondsToSelector:sel!(_addUserStyleSheet:)]
@@ -149,12 +282,20 @@ impl WKWebView {
         unsafe { msg_send![self.ptr, setNavigationDelegate:delegate.ptr()] }
     }
 
+    pub fn set_ui_delegate<T: ObjCClass>(&self, delegate: T) {
+        unsafe { msg_send![self.ptr, setUIDelegate:delegate.ptr()] }
+    }
+
     pub fn configuration(&self) -> WKWebViewConfiguration {
         WKWebViewConfiguration {
             ptr: unsafe { msg_send![self.ptr, configuration] }
         }
     }
 
+    pub fn back_forward_list(&self) -> WKBackForwardList {
+        WKBackForwardList { ptr: unsafe { msg_send![self.ptr, backForwardList] } }
+    }
+
     pub fn can_go_back(&self) -> bool {
         let can: BOOL = unsafe { msg_send![self.ptr, canGoBack] };
         can == YES
@@ -177,6 +318,10 @@ impl WKWebView {
         unsafe { msg_send![self.ptr, reload:nil] }
     }
 
+    pub fn reload_from_origin(&self) {
+        unsafe { msg_send![self.ptr, reloadFromOrigin:nil] }
+    }
+
     pub fn reload_without_content_blockers(&self) {
         if self.can_reload_without_content_blockers() {
             unsafe { msg_send![self.ptr, _reloadWithoutContentBlockers] }
@@ -200,11 +345,15 @@ impl WKWebView {
         has == YES
     }
 
-    pub fn load_html_string(&self, contents: &str, base_url: &str) {
-        unsafe {
+    /// Display HTML, returning whether a navigation started
+    pub fn load_html_string(&self, contents: &str, base_url: Option<&str>) -> bool {
+        let base_url: Id = base_url
+            .map_or(nil, |url| NSURL::from(NSString::from(url)).ptr());
+        let navigation: Id = unsafe {
             msg_send![self.ptr, loadHTMLString:NSString::from(contents)
-                                       baseURL:NSURL::from(NSString::from(base_url))]
-        }
+                                       baseURL:base_url]
+        };
+        navigation != nil
     }
 
     pub fn is_loading(&self) -> bool {
@@ -220,6 +369,19 @@ impl WKWebView {
         NSString::from_ptr(unsafe { msg_send![self.ptr, title] })
     }
 
+    /// Set the color drawn behind page content, such as between navigations,
+    /// falling back to the background color of the view in versions of
+    /// WebKit without `underPageBackgroundColor`
+    pub fn set_background_color(&self, color: &NSColor) {
+        let responds: BOOL = unsafe {
+            msg_send![self.ptr, respondsToSelector:sel!(setUnderPageBackgroundColor:)]
+        };
+        match responds == YES {
+            true => unsafe { msg_send![self.ptr, setUnderPageBackgroundColor:color.ptr()] },
+            false => unsafe { msg_send![self.ptr, _setBackgroundColor:color.ptr()] },
+        }
+    }
+
     pub fn set_custom_user_agent(&self, user_agent: &str) {
         unsafe {
             msg_send![self.ptr, setCustomUserAgent:NSString::from(user_agent)]
@@ -237,12 +399,30 @@ impl WKWebView {
         }
     }
 
-    pub fn find_string(&self, query: &str) {
-        let options: NSUInteger = WKFindOptions::CaseInsensitive as NSUInteger |
-                                  WKFindOptions::WrapAround as NSUInteger |
-                                  WKFindOptions::ShowFindIndicator as NSUInteger |
-                                  WKFindOptions::TreatMedialCapitalAsWordStart as NSUInteger |
-                                  WKFindOptions::ShowHighlight as NSUInteger;
+    pub fn evaluate_javascript_with_completion(&self, script: &str,
+                                               block: &JavaScriptCompletionHandler) {
+        unsafe {
+            msg_send![self.ptr, evaluateJavaScript:NSString::from(script)
+                                 completionHandler:block.deref()]
+        }
+    }
+
+    pub fn web_archive_data(&self, block: &WebArchiveCompletionHandler) {
+        unsafe {
+            msg_send![self.ptr, _getWebArchiveDataWithCompletionHandler:block.deref()]
+        }
+    }
+
+    /// Find and indicate the next match of a query, highlighting every match
+    /// if `highlight_all` is `true`
+    pub fn find_string(&self, query: &str, highlight_all: bool) {
+        let mut options: NSUInteger = WKFindOptions::CaseInsensitive as NSUInteger |
+                                      WKFindOptions::WrapAround as NSUInteger |
+                                      WKFindOptions::ShowFindIndicator as NSUInteger |
+                                      WKFindOptions::TreatMedialCapitalAsWordStart as NSUInteger;
+        if highlight_all {
+            options |= WKFindOptions::ShowHighlight as NSUInteger;
+        }
         unsafe {
             msg_send![self.ptr, _findString:NSString::from(query)
                                     options:options
@@ -258,6 +438,22 @@ impl WKWebView {
         unsafe { msg_send![self.ptr, removeFromSuperview] }
     }
 
+    pub fn bounds(&self) -> CGRect {
+        unsafe { msg_send![self.ptr, bounds] }
+    }
+
+    pub fn set_allows_magnification(&self, allows: bool) {
+        let value = if allows { YES } else { NO };
+        unsafe { msg_send![self.ptr, setAllowsMagnification:value] }
+    }
+
+    /// Scale the page content by a factor, keeping the top left corner in
+    /// place
+    pub fn set_magnification(&self, magnification: CGFloat) {
+        let origin = CGPoint { x: 0., y: 0. };
+        unsafe { msg_send![self.ptr, setMagnification:magnification centeredAtPoint:origin] }
+    }
+
     pub fn release_delegates(&self) {
         unsafe {
             let nav_delegate: Id = msg_send![self.ptr, navigationDelegate];
@@ -272,6 +468,12 @@ impl WKWebView {
     pub fn close(&self) {
         unsafe { msg_send![self.ptr, _close] }
     }
+
+    /// The identifier of the web content process, or `0` if the process has
+    /// not launched or has exited
+    pub fn web_process_identifier(&self) -> i32 {
+        unsafe { msg_send![self.ptr, _webProcessIdentifier] }
+    }
 }
 
 impl WKWebViewConfiguration {
@@ -301,6 +503,14 @@ impl WKWebViewConfiguration {
     pub fn set_website_data_store(&self, store: WKWebsiteDataStore) {
         unsafe { msg_send![self.ptr, setWebsiteDataStore:store.ptr()] }
     }
+
+    pub fn process_pool(&self) -> WKProcessPool {
+        WKProcessPool { ptr: unsafe { msg_send![self.ptr, processPool] } }
+    }
+
+    pub fn set_process_pool(&self, pool: &WKProcessPool) {
+        unsafe { msg_send![self.ptr, setProcessPool:pool.ptr()] }
+    }
 }
 
 impl WKWebsiteDataStore {
@@ -320,6 +530,11 @@ impl WKWebsiteDataStore {
             }
         }
     }
+
+    pub fn is_persistent(&self) -> bool {
+        let persistent: BOOL = unsafe { msg_send![self.ptr, isPersistent] };
+        persistent == YES
+    }
 }
 
 impl _WKUserContentExtensionStore {
@@ -356,6 +571,21 @@ impl _WKUserContentExtensionStore {
     }
 }
 
+impl WKUserScript {
+
+    pub fn new(source: &str, time: WKUserScriptInjectionTime) -> Self {
+        let source = NSString::from(source);
+        let ptr = unsafe {
+            let script: Id = msg_send![class!(WKUserScript), alloc];
+            let script: Id = msg_send![script, initWithSource:source.ptr()
+                                                injectionTime:time as NSInteger
+                                             forMainFrameOnly:YES];
+            script
+        };
+        WKUserScript { ptr: ptr }
+    }
+}
+
 impl _WKUserStyleSheet {
 
     pub fn new(styles: &str) -> Self {
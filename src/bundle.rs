@@ -0,0 +1,314 @@
+//! Settings bundles for moving configuration between machines
+//!
+//! A bundle is a directory containing the configuration file along with the
+//! command scripts, content filters, user scripts, and other files the
+//! configuration refers to. Each file is stored under a name derived from
+//! the option referring to it, so importing a bundle copies files to the
+//! paths named by the bundled configuration on the new machine. Those paths
+//! must be within the directory of the configuration file being replaced.
+//!
+//! ```text
+//! config.toml
+//! commands/0/           commands.search-paths, by position
+//! content-filter        general.content-filter
+//! filter-lists/0        block.filter-lists, by position
+//! global-stylesheet     general.global-stylesheet
+//! rc-file               general.rc-file
+//! user-scripts/         general.user-scripts-directory
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component,Path};
+
+use config::Config;
+use ui::BrowserConfiguration;
+
+/// The name of the configuration file within a bundle
+pub const CONFIG_FILE: &'static str = "config.toml";
+/// Entries holding one file or directory per value of an array option
+const INDEXED_ENTRIES: [&'static str; 2] = ["commands", "filter-lists"];
+
+/// Errors resulting from exporting or importing a bundle
+#[derive(Debug,Clone,PartialEq)]
+pub enum BundleError {
+    /// The export destination exists and is not an empty directory
+    DestinationNotEmpty(String),
+    /// The bundle does not contain a configuration file
+    MissingConfig,
+    /// The configuration file in the bundle is not valid TOML
+    InvalidConfig,
+    /// A file in the bundle does not match an option in the bundled
+    /// configuration
+    UnexpectedEntry(String),
+    /// The bundled configuration names a path outside of the configuration
+    /// directory
+    OutsideConfigDir(String),
+    /// The path (first) could not be read or written, described by the
+    /// second value
+    Io(String, String),
+}
+
+impl Error for BundleError {
+
+    fn description(&self) -> &str {
+        match self {
+            &BundleError::DestinationNotEmpty(_) => "bundle destination not empty",
+            &BundleError::MissingConfig => "bundle configuration missing",
+            &BundleError::InvalidConfig => "invalid bundle configuration",
+            &BundleError::UnexpectedEntry(_) => "unexpected bundle entry",
+            &BundleError::OutsideConfigDir(_) => "bundle path outside of configuration directory",
+            &BundleError::Io(_, _) => "bundle file unreadable or unwritable",
+        }
+    }
+}
+
+impl fmt::Display for BundleError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &BundleError::DestinationNotEmpty(ref path) =>
+                write!(f, "Bundle destination is not empty: {}", path),
+            &BundleError::MissingConfig =>
+                write!(f, "Bundle does not contain {}", CONFIG_FILE),
+            &BundleError::InvalidConfig =>
+                write!(f, "Failed to parse the bundled {}", CONFIG_FILE),
+            &BundleError::UnexpectedEntry(ref name) =>
+                write!(f, "Bundle contains an entry not referred to by its configuration: {}", name),
+            &BundleError::OutsideConfigDir(ref path) =>
+                write!(f, "Bundle refers to a path outside of the configuration directory: {}", path),
+            &BundleError::Io(ref path, ref reason) =>
+                write!(f, "Failed to copy bundle file ({}): {}", path, reason),
+        }
+    }
+}
+
+pub type BundleResult<T> = Result<T, BundleError>;
+
+/// A file or directory referred to by the configuration, along with its
+/// name within a bundle
+#[derive(Debug,Clone,PartialEq)]
+pub struct BundleEntry {
+    pub name: String,
+    pub path: String,
+}
+
+/// The files and directories referred to by a configuration
+pub fn entries(config: &Config) -> Vec<BundleEntry> {
+    let mut entries: Vec<BundleEntry> = vec![];
+    for (index, path) in config.command_search_paths().into_iter().enumerate() {
+        entries.push(BundleEntry { name: format!("commands/{}", index), path: path });
+    }
+    for (index, path) in config.filter_list_paths().into_iter().enumerate() {
+        entries.push(BundleEntry { name: format!("filter-lists/{}", index), path: path });
+    }
+    let options = [
+        ("content-filter", config.content_filter_path()),
+        ("global-stylesheet", config.lookup_str("general.global-stylesheet")),
+        ("rc-file", config.rc_file_path()),
+        ("user-scripts", config.user_scripts_directory()),
+    ];
+    for &(ref name, ref path) in options.iter() {
+        if let &Some(ref path) = path {
+            entries.push(BundleEntry { name: String::from(*name), path: path.clone() });
+        }
+    }
+    entries
+}
+
+/// Copy the configuration file at a path and the files it refers to into a
+/// new directory, returning the number of files and directories copied
+/// besides the configuration. Missing files are skipped.
+pub fn export(config_path: &str, destination: &str) -> BundleResult<usize> {
+    let config = Config::parse_file(config_path).ok_or(BundleError::InvalidConfig)?;
+    let destination = Path::new(destination);
+    if destination.exists() {
+        let empty = fs::read_dir(destination).map(|mut contents| contents.next().is_none()).unwrap_or(false);
+        if !empty {
+            return Err(BundleError::DestinationNotEmpty(destination.display().to_string()));
+        }
+    }
+    fs::create_dir_all(destination).map_err(|err| io_error(destination, err))?;
+    copy_file(Path::new(config_path), &destination.join(CONFIG_FILE))?;
+    let mut count = 0;
+    for entry in entries(&config) {
+        let source = Path::new(&entry.path);
+        if !source.exists() {
+            info!("Skipping missing bundle entry {}: {}", entry.name, entry.path);
+            continue;
+        }
+        copy_path(source, &destination.join(&entry.name))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Check that a bundle contains a valid configuration and only files
+/// referred to by it, returning the bundled configuration
+pub fn validate(bundle: &str) -> BundleResult<Config> {
+    let bundle = Path::new(bundle);
+    let config_path = bundle.join(CONFIG_FILE);
+    if !config_path.is_file() {
+        return Err(BundleError::MissingConfig);
+    }
+    let config = config_path.to_str()
+        .and_then(Config::parse_file)
+        .ok_or(BundleError::InvalidConfig)?;
+    let names: Vec<String> = entries(&config).into_iter().map(|entry| entry.name).collect();
+    for name in entry_names(bundle)? {
+        if name != CONFIG_FILE && !names.contains(&name) {
+            return Err(BundleError::UnexpectedEntry(name));
+        }
+    }
+    Ok(config)
+}
+
+/// Copy the files in a bundle to the paths named by the bundled
+/// configuration, then replace the configuration file at a path, returning
+/// the number of files and directories copied besides the configuration.
+/// Existing files are overwritten. Nothing is copied if any path is outside
+/// of the directory containing the configuration file.
+pub fn import(bundle: &str, config_path: &str) -> BundleResult<usize> {
+    let config = validate(bundle)?;
+    let config_dir = Path::new(config_path).parent().unwrap_or(Path::new(""));
+    for entry in entries(&config) {
+        if !is_within(Path::new(&entry.path), config_dir) {
+            return Err(BundleError::OutsideConfigDir(entry.path));
+        }
+    }
+    let bundle = Path::new(bundle);
+    let mut count = 0;
+    for entry in entries(&config) {
+        let source = bundle.join(&entry.name);
+        if source.exists() {
+            copy_path(&source, Path::new(&entry.path))?;
+            count += 1;
+        }
+    }
+    copy_file(&bundle.join(CONFIG_FILE), Path::new(config_path))?;
+    Ok(count)
+}
+
+/// Whether a path is within a directory, without `..` components which could
+/// lead outside of it
+fn is_within(path: &Path, dir: &Path) -> bool {
+    path.is_absolute() && path.starts_with(dir)
+        && !path.components().any(|component| component == Component::ParentDir)
+}
+
+/// The names of the entries in a bundle, listing the contents of indexed
+/// entries individually
+fn entry_names(bundle: &Path) -> BundleResult<Vec<String>> {
+    let mut names: Vec<String> = vec![];
+    for name in dir_names(bundle)? {
+        if INDEXED_ENTRIES.contains(&name.as_str()) && bundle.join(&name).is_dir() {
+            for index in dir_names(&bundle.join(&name))? {
+                names.push(format!("{}/{}", name, index));
+            }
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn dir_names(dir: &Path) -> BundleResult<Vec<String>> {
+    let contents = fs::read_dir(dir).map_err(|err| io_error(dir, err))?;
+    Ok(contents.filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect())
+}
+
+/// Copy a file or directory, merging directories into any existing
+/// directory at the destination
+fn copy_path(source: &Path, destination: &Path) -> BundleResult<()> {
+    if !source.is_dir() {
+        return copy_file(source, destination);
+    }
+    fs::create_dir_all(destination).map_err(|err| io_error(destination, err))?;
+    let contents = fs::read_dir(source).map_err(|err| io_error(source, err))?;
+    for entry in contents {
+        let entry = entry.map_err(|err| io_error(source, err))?;
+        copy_path(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn copy_file(source: &Path, destination: &Path) -> BundleResult<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|err| io_error(parent, err))?;
+    }
+    fs::copy(source, destination).map(|_| ()).map_err(|err| io_error(source, err))
+}
+
+fn io_error(path: &Path, err: io::Error) -> BundleError {
+    BundleError::Io(path.display().to_string(), err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+
+    #[test]
+    fn export_and_import_bundle() {
+        let root = temp_dir().join("webkitten-bundle");
+        let _ = fs::remove_dir_all(&root);
+        let commands = root.join("commands");
+        fs::create_dir_all(commands.join("lib")).unwrap();
+        fs::write(commands.join("go.lua"), "-- go").unwrap();
+        fs::write(commands.join("lib").join("util.lua"), "-- util").unwrap();
+        let config_path = root.join("config.toml");
+        fs::write(&config_path, format!(r#"
+        [commands]
+        search-paths = ["{}"]
+        [general]
+        rc-file = "{}"
+        "#, commands.display(), root.join("missing-rc").display())).unwrap();
+        let bundle = root.join("bundle");
+        assert_eq!(Ok(1), export(config_path.to_str().unwrap(), bundle.to_str().unwrap()));
+        assert!(bundle.join("commands/0/lib/util.lua").is_file());
+        assert_eq!(Err(BundleError::DestinationNotEmpty(bundle.display().to_string())),
+                   export(config_path.to_str().unwrap(), bundle.to_str().unwrap()));
+
+        fs::remove_dir_all(&commands).unwrap();
+        let imported_config = root.join("imported.toml");
+        assert_eq!(Ok(1), import(bundle.to_str().unwrap(), imported_config.to_str().unwrap()));
+        assert_eq!("-- util", fs::read_to_string(commands.join("lib").join("util.lua")).unwrap());
+        assert!(imported_config.is_file());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn import_rejects_paths_outside_config_dir() {
+        let root = temp_dir().join("webkitten-bundle-outside");
+        let _ = fs::remove_dir_all(&root);
+        let bundle = root.join("bundle");
+        fs::create_dir_all(&bundle).unwrap();
+        fs::write(bundle.join("rc-file"), "-- rc").unwrap();
+        let rc_file = root.join("config").join("..").join("rc.lua");
+        fs::write(bundle.join(CONFIG_FILE), format!("[general]\nrc-file = \"{}\"", rc_file.display())).unwrap();
+        let config_path = root.join("config").join("config.toml");
+        assert_eq!(Err(BundleError::OutsideConfigDir(rc_file.display().to_string())),
+                   import(bundle.to_str().unwrap(), config_path.to_str().unwrap()));
+        assert!(!root.join("rc.lua").exists());
+        assert!(!config_path.exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn validate_rejects_unexpected_entries() {
+        let bundle = temp_dir().join("webkitten-bundle-invalid");
+        let _ = fs::remove_dir_all(&bundle);
+        fs::create_dir_all(bundle.join("commands").join("1")).unwrap();
+        assert_eq!(Err(BundleError::MissingConfig), validate(bundle.to_str().unwrap()).map(|_| ()));
+        fs::write(bundle.join(CONFIG_FILE), "[commands]\nsearch-paths = [\"/tmp/commands\"]").unwrap();
+        assert_eq!(Err(BundleError::UnexpectedEntry(String::from("commands/1"))),
+                   validate(bundle.to_str().unwrap()).map(|_| ()));
+        fs::remove_dir_all(bundle.join("commands").join("1")).unwrap();
+        assert!(validate(bundle.to_str().unwrap()).is_ok());
+        let _ = fs::remove_dir_all(&bundle);
+    }
+}
@@ -0,0 +1,389 @@
+//! Size-bounded storage for values recorded during a session
+use std::collections::{HashMap,VecDeque};
+use std::hash::Hash;
+use std::time::{Duration,Instant};
+
+/// A keyed cache holding a limited number of values, evicting the least
+/// recently inserted value when full
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::cache::BoundedCache;
+///
+/// let mut cache = BoundedCache::new(2);
+/// cache.insert("https://example.com", (0., 120.));
+/// cache.insert("https://example.org", (0., 40.));
+/// cache.insert("https://example.net", (0., 10.));
+/// assert_eq!(None, cache.get("https://example.com"));
+/// assert_eq!(Some(&(0., 40.)), cache.get("https://example.org"));
+/// ```
+pub struct BoundedCache<V> {
+    capacity: usize,
+    entries: VecDeque<(String, V)>,
+}
+
+impl<V> BoundedCache<V> {
+
+    /// Create an empty cache holding at most `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        BoundedCache { capacity: capacity, entries: VecDeque::new() }
+    }
+
+    /// Store a value for a key, replacing any existing value
+    pub fn insert<K: Into<String>>(&mut self, key: K, value: V) {
+        let key = key.into();
+        self.remove(&key);
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, value));
+    }
+
+    /// The value stored for a key, if any
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.entries.iter()
+            .find(|&&(ref entry_key, _)| entry_key == key)
+            .map(|&(_, ref value)| value)
+    }
+
+    /// Remove and return the value stored for a key, if any
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.entries.iter()
+            .position(|&(ref entry_key, _)| entry_key == key)
+            .and_then(|index| self.entries.remove(index))
+            .map(|(_, value)| value)
+    }
+
+    /// The number of values stored
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A stack holding a limited number of values, discarding the oldest value
+/// when full
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::cache::BoundedStack;
+///
+/// let mut stack = BoundedStack::new(2);
+/// stack.push("a");
+/// stack.push("b");
+/// stack.push("c");
+/// assert_eq!(vec![&"c", &"b"], stack.recent().collect::<Vec<_>>());
+/// assert_eq!(Some("c"), stack.pop());
+/// assert_eq!(Some("b"), stack.pop());
+/// assert_eq!(None, stack.pop());
+/// ```
+pub struct BoundedStack<V> {
+    capacity: usize,
+    entries: VecDeque<V>,
+}
+
+impl<V> BoundedStack<V> {
+
+    /// Create an empty stack holding at most `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        BoundedStack { capacity: capacity, entries: VecDeque::new() }
+    }
+
+    /// Add a value to the top of the stack
+    pub fn push(&mut self, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(value);
+    }
+
+    /// Remove and return the most recently added value, if any
+    pub fn pop(&mut self) -> Option<V> {
+        self.entries.pop_back()
+    }
+
+    /// The number of values stored
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The stored values, most recently added first
+    pub fn recent(&self) -> impl Iterator<Item=&V> {
+        self.entries.iter().rev()
+    }
+}
+
+/// Counts of recent occurrences of events by key, forgetting occurrences
+/// older than a time window
+///
+/// ## Examples
+///
+/// ```
+/// use std::time::{Duration,Instant};
+/// use webkitten::cache::RecentEvents;
+///
+/// let mut events = RecentEvents::new(Duration::from_secs(10));
+/// let start = Instant::now();
+/// assert_eq!(1, events.record("https://example.com", start));
+/// assert_eq!(2, events.record("https://example.com", start + Duration::from_secs(5)));
+/// assert_eq!(1, events.record("https://example.com", start + Duration::from_secs(20)));
+/// ```
+pub struct RecentEvents {
+    window: Duration,
+    occurrences: HashMap<String, Vec<Instant>>,
+}
+
+impl RecentEvents {
+
+    /// Create an empty record counting occurrences within `window`
+    pub fn new(window: Duration) -> Self {
+        RecentEvents { window: window, occurrences: HashMap::new() }
+    }
+
+    /// Record an occurrence of an event at a time, returning the number of
+    /// occurrences of the event within the window. Expired occurrences of
+    /// all events are discarded.
+    pub fn record<K: Into<String>>(&mut self, key: K, time: Instant) -> usize {
+        let window = self.window;
+        self.occurrences.retain(|_, times| {
+            times.retain(|recorded| time.duration_since(*recorded) < window);
+            !times.is_empty()
+        });
+        let times = self.occurrences.entry(key.into()).or_insert_with(Vec::new);
+        times.push(time);
+        times.len()
+    }
+
+    /// Forget all occurrences of an event
+    pub fn clear(&mut self, key: &str) {
+        self.occurrences.remove(key);
+    }
+}
+
+/// Limits how often events are reported by key, allowing an event only once
+/// an interval has passed since the last allowed event with the same key
+///
+/// ## Examples
+///
+/// ```
+/// use std::time::{Duration,Instant};
+/// use webkitten::cache::Throttle;
+///
+/// let mut throttle = Throttle::new(Duration::from_millis(500));
+/// let start = Instant::now();
+/// assert!(throttle.allow("download-1", start));
+/// assert!(!throttle.allow("download-1", start + Duration::from_millis(100)));
+/// assert!(throttle.allow("download-2", start + Duration::from_millis(100)));
+/// assert!(throttle.allow("download-1", start + Duration::from_millis(600)));
+/// ```
+pub struct Throttle {
+    interval: Duration,
+    allowed: HashMap<String, Instant>,
+}
+
+impl Throttle {
+
+    /// Create a throttle allowing one event per key within `interval`
+    pub fn new(interval: Duration) -> Self {
+        Throttle { interval: interval, allowed: HashMap::new() }
+    }
+
+    /// Whether an event with a key occurring at a time should be reported,
+    /// recording the time if so
+    pub fn allow<K: Into<String>>(&mut self, key: K, time: Instant) -> bool {
+        let key = key.into();
+        if let Some(last) = self.allowed.get(&key) {
+            if time.duration_since(*last) < self.interval {
+                return false;
+            }
+        }
+        self.allowed.insert(key, time);
+        true
+    }
+
+    /// Forget the last event with a key, so the next event is allowed
+    pub fn clear(&mut self, key: &str) {
+        self.allowed.remove(key);
+    }
+}
+
+/// Values waiting for the number of loads in progress to fall below a limit,
+/// such as webviews to open. Loads are tracked by key from start to finish,
+/// and are forgotten once `timeout` passes in case the finish is missed.
+///
+/// ## Examples
+///
+/// ```
+/// use std::time::{Duration,Instant};
+/// use webkitten::cache::LoadQueue;
+///
+/// let mut queue = LoadQueue::new(Duration::from_secs(30));
+/// let now = Instant::now();
+/// queue.push("https://example.com");
+/// queue.push("https://example.org");
+/// assert_eq!(Some("https://example.com"), queue.next(1, now));
+/// assert_eq!(None, queue.next(1, now));
+/// queue.start((0, 1), now);
+/// queue.finish(&(0, 1));
+/// assert_eq!(Some("https://example.org"), queue.next(1, now));
+/// ```
+pub struct LoadQueue<K: Hash + Eq, V> {
+    timeout: Duration,
+    loading: HashMap<K, Instant>,
+    /// Times at which values were taken from the queue whose loads have not
+    /// yet started
+    reserved: VecDeque<Instant>,
+    pending: VecDeque<V>,
+}
+
+impl<K: Hash + Eq, V> LoadQueue<K, V> {
+
+    /// Create an empty queue forgetting loads after `timeout`
+    pub fn new(timeout: Duration) -> Self {
+        LoadQueue {
+            timeout: timeout,
+            loading: HashMap::new(),
+            reserved: VecDeque::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Add a value to the end of the queue
+    pub fn push(&mut self, value: V) {
+        self.pending.push_back(value);
+    }
+
+    /// Record a load starting, filling the oldest slot reserved by `next`
+    pub fn start(&mut self, key: K, time: Instant) {
+        if !self.loading.contains_key(&key) {
+            self.reserved.pop_front();
+        }
+        self.loading.insert(key, time);
+    }
+
+    /// Record a load finishing
+    pub fn finish(&mut self, key: &K) {
+        self.loading.remove(key);
+    }
+
+    /// Remove the next value from the queue if fewer than `limit` loads are
+    /// in progress or reserved, reserving a slot for its load
+    pub fn next(&mut self, limit: usize, time: Instant) -> Option<V> {
+        let timeout = self.timeout;
+        self.loading.retain(|_, started| time.duration_since(*started) < timeout);
+        self.reserved.retain(|reserved| time.duration_since(*reserved) < timeout);
+        if self.loading.len() + self.reserved.len() >= limit {
+            return None;
+        }
+        let value = self.pending.pop_front();
+        if value.is_some() {
+            self.reserved.push_back(time);
+        }
+        value
+    }
+
+    /// The number of values in the queue
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether the queue has no values
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundedCache,BoundedStack,LoadQueue,RecentEvents,Throttle};
+    use std::time::{Duration,Instant};
+
+    #[test]
+    fn insert_replaces_existing_value() {
+        let mut cache = BoundedCache::new(3);
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+        assert_eq!(1, cache.len());
+        assert_eq!(Some(&2), cache.get("a"));
+    }
+
+    #[test]
+    fn insert_evicts_oldest_value() {
+        let mut cache = BoundedCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+        cache.insert("c", 4);
+        assert_eq!(None, cache.get("b"));
+        assert_eq!(Some(&3), cache.get("a"));
+        assert_eq!(Some(&4), cache.get("c"));
+    }
+
+    #[test]
+    fn insert_without_capacity() {
+        let mut cache = BoundedCache::new(0);
+        cache.insert("a", 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn clear_recent_events() {
+        let mut events = RecentEvents::new(Duration::from_secs(10));
+        let now = Instant::now();
+        events.record("a", now);
+        events.record("b", now);
+        events.clear("a");
+        assert_eq!(1, events.record("a", now));
+        assert_eq!(2, events.record("b", now));
+    }
+
+    #[test]
+    fn push_without_capacity() {
+        let mut stack = BoundedStack::new(0);
+        stack.push(1);
+        assert!(stack.is_empty());
+        assert_eq!(None, stack.pop());
+    }
+
+    #[test]
+    fn throttle_allows_after_clear() {
+        let mut throttle = Throttle::new(Duration::from_secs(1));
+        let start = Instant::now();
+        assert!(throttle.allow("a", start));
+        throttle.clear("a");
+        assert!(throttle.allow("a", start));
+    }
+
+    #[test]
+    fn load_queue_forgets_expired_loads() {
+        let mut queue = LoadQueue::new(Duration::from_secs(30));
+        let start = Instant::now();
+        queue.push("a");
+        queue.push("b");
+        queue.push("c");
+        assert_eq!(Some("a"), queue.next(2, start));
+        assert_eq!(Some("b"), queue.next(2, start));
+        queue.start((0, 0), start);
+        queue.start((0, 0), start);
+        assert_eq!(None, queue.next(2, start));
+        assert_eq!(Some("c"), queue.next(2, start + Duration::from_secs(31)));
+        assert!(queue.is_empty());
+    }
+}
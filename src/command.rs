@@ -1,8 +1,15 @@
+use std::env;
+use std::fmt;
 use std::path::Path;
 use std::fs::{File,metadata,read_dir};
 
 use config::Config;
-use ui::BrowserConfiguration;
+use ui::{BrowserConfiguration,uri_host};
+
+/// The tokens replaced in buffer event commands with the full URI and the
+/// host of the URI of the event
+const URI_TOKEN: &'static str = "{uri}";
+const HOST_TOKEN: &'static str = "{host}";
 
 /// A representation of a script which executes and returns a boolean value
 /// indicating success
@@ -16,10 +23,23 @@ impl Command {
 
     /// Parse a command name and arguments into an instance of Command
     pub fn parse(input: &str, config: &Config, suffix: &str) -> Option<Self> {
+        Command::parse_for_uri(input, config, suffix, None)
+    }
+
+    /// Parse a command name and arguments into an instance of Command,
+    /// resolving aliases specific to the site of a URI
+    pub fn parse_for_uri(input: &str, config: &Config, suffix: &str, uri: Option<&str>) -> Option<Self> {
+        Command::parse_with(input, config, suffix, uri, &file_exists)
+    }
+
+    /// Parse a command, checking whether each candidate path exists using a
+    /// function in place of the filesystem
+    fn parse_with<F>(input: &str, config: &Config, suffix: &str, uri: Option<&str>, exists: &F) -> Option<Self>
+        where F: Fn(&str) -> bool {
         let mut components = input.split_whitespace();
         components.next()
-            .and_then(|name| config.resolved_command_name(name))
-            .and_then(|name| resolve_command(config.command_search_paths(), &name, suffix))
+            .and_then(|name| config.resolved_command_name(name, uri))
+            .and_then(|name| resolve_command(config.command_search_paths(), &name, suffix, exists))
             .and_then(|path| {
                 Some(Command {
                     path: path,
@@ -29,23 +49,32 @@ impl Command {
     }
 
     pub fn list_commands(prefix: &str, config: &Config) -> Vec<String> {
-        let mut entries: Vec<String> = vec![];
+        let mut file_names: Vec<String> = vec![];
         for search_path in config.command_search_paths() {
             if let Ok(contents) = read_dir(search_path) {
                 for entry in contents {
                     if let Ok(entry) = entry {
                         let path = entry.path();
                         if path.is_file() {
-                            if let Some(stem) = path.file_stem().and_then(|p| p.to_str()) {
-                                if stem.starts_with(prefix) {
-                                    entries.push(String::from(stem));
-                                }
+                            if let Some(name) = path.file_name().and_then(|p| p.to_str()) {
+                                file_names.push(String::from(name));
                             }
                         }
                     }
                 }
             }
         }
+        Command::list_matching(prefix, config, file_names)
+    }
+
+    /// The sorted names of the enabled commands among file names in the
+    /// search paths which begin with a prefix
+    fn list_matching(prefix: &str, config: &Config, file_names: Vec<String>) -> Vec<String> {
+        let mut entries: Vec<String> = file_names.iter()
+            .filter_map(|name| Path::new(name).file_stem().and_then(|stem| stem.to_str()))
+            .filter(|stem| stem.starts_with(prefix) && !config.command_disabled(stem))
+            .map(String::from)
+            .collect();
         entries.sort();
         entries
     }
@@ -54,35 +83,516 @@ impl Command {
     pub fn file(&self) -> Option<File> {
         File::open(&self.path).ok()
     }
+
+    /// The name of the command file without the extension
+    pub fn name(&self) -> &str {
+        Path::new(&self.path).file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("")
+    }
+}
+
+/// How command bar text resolves to a command, for debugging aliases and
+/// command search paths
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct CommandExplanation {
+    /// The command name after resolving aliases
+    pub name: String,
+    /// Whether the name was resolved from an alias
+    pub alias: bool,
+    /// The first matching command file in the search paths, if any
+    pub path: Option<String>,
+    pub arguments: Vec<String>,
+    /// Whether the command is listed in `commands.disabled`
+    pub disabled: bool,
+}
+
+impl CommandExplanation {
+
+    /// Describe how a command name and arguments would be parsed into an
+    /// instance of Command
+    pub fn explain(input: &str, config: &Config, suffix: &str) -> Self {
+        CommandExplanation::explain_with(input, config, suffix, &file_exists)
+    }
+
+    /// Describe how a command would be parsed, checking whether each
+    /// candidate path exists using a function in place of the filesystem
+    fn explain_with<F>(input: &str, config: &Config, suffix: &str, exists: &F) -> Self
+        where F: Fn(&str) -> bool {
+        if let Some(command) = Command::parse_with(input, config, suffix, None, exists) {
+            let typed = input.split_whitespace().next().unwrap_or("");
+            return CommandExplanation {
+                name: String::from(command.name()),
+                alias: typed != command.name(),
+                path: Some(command.path),
+                arguments: command.arguments,
+                disabled: false,
+            };
+        }
+        let mut components = input.split_whitespace();
+        let typed = components.next().unwrap_or("");
+        let name = config.command_alias(typed, None);
+        CommandExplanation {
+            alias: typed != name,
+            path: resolve_command(config.command_search_paths(), &name, suffix, exists),
+            arguments: components.map(|arg| String::from(arg)).collect(),
+            disabled: config.command_disabled(&name),
+            name: name,
+        }
+    }
+}
+
+/// Expand a leading `~` to the home directory and `$NAME` to the value of
+/// environment variables in a path. Unset variables are left unchanged.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::command::expand_path;
+///
+/// std::env::set_var("WEBKITTEN_EXAMPLE_DIR", "/tmp/example");
+/// assert_eq!("/tmp/example/page.html", expand_path("$WEBKITTEN_EXAMPLE_DIR/page.html"));
+/// assert_eq!("$WEBKITTEN_UNSET_DIR/a", expand_path("$WEBKITTEN_UNSET_DIR/a"));
+/// ```
+pub fn expand_path(path: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        if let Some(home) = dirs::home_dir().as_ref().and_then(|home| home.to_str()) {
+            expanded.push_str(home);
+            rest = &rest[1 ..];
+        }
+    }
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[.. index]);
+        let name_len = rest[index + 1 ..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - index - 1);
+        let name = &rest[index + 1 .. index + 1 + name_len];
+        match env::var(name) {
+            Ok(ref value) if !name.is_empty() => expanded.push_str(value),
+            _ => expanded.push_str(&rest[index .. index + 1 + name_len]),
+        }
+        rest = &rest[index + 1 + name_len ..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// List the file paths beginning with a prefix, expanding `~` and
+/// environment variables in the prefix. Completions keep the prefix as
+/// written and directories end with a trailing slash. Hidden files are only
+/// included if the prefix names one.
+pub fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+    let search_dir = match dir.is_empty() {
+        true => String::from("."),
+        false => expand_path(dir),
+    };
+    let mut entries: Vec<String> = vec![];
+    if let Ok(contents) = read_dir(search_dir) {
+        for entry in contents {
+            if let Ok(entry) = entry {
+                if let Some(file_name) = entry.file_name().to_str() {
+                    if !file_name.starts_with(name) || (file_name.starts_with('.') && !name.starts_with('.')) {
+                        continue;
+                    }
+                    let mut completion = format!("{}{}", dir, file_name);
+                    if entry.path().is_dir() {
+                        completion.push('/');
+                    }
+                    entries.push(completion);
+                }
+            }
+        }
+    }
+    entries.sort();
+    entries
+}
+
+/// A built-in source of argument completions which a command may use in
+/// place of its own completion method
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CompletionSource {
+    /// The URIs of open webviews
+    Tabs,
+    /// File paths, as listed by `complete_path`
+    Files,
+    /// Previously run commands, as listed by `Engine::search_history`
+    History,
+}
+
+impl CompletionSource {
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "tabs" => Some(CompletionSource::Tabs),
+            "files" => Some(CompletionSource::Files),
+            "history" => Some(CompletionSource::History),
+            _ => None
+        }
+    }
+}
+
+/// The type of value expected for a command argument
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ArgType {
+    String,
+    Number,
+    Integer,
+}
+
+impl ArgType {
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "string" => Some(ArgType::String),
+            "number" => Some(ArgType::Number),
+            "integer" => Some(ArgType::Integer),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            ArgType::String => "string",
+            ArgType::Number => "number",
+            ArgType::Integer => "integer",
+        }
+    }
+
+    fn accepts(&self, value: &str) -> bool {
+        match *self {
+            ArgType::String => true,
+            ArgType::Number => value.parse::<f64>().is_ok(),
+            ArgType::Integer => value.parse::<i64>().is_ok(),
+        }
+    }
+}
+
+/// A command argument declared in an argument specification
+#[derive(Debug,Clone,PartialEq)]
+pub struct ArgSpecEntry {
+    pub name: String,
+    pub arg_type: ArgType,
+    pub optional: bool,
+}
+
+/// The arguments accepted by a command, declared as a list of names
+/// separated by spaces. Names may be followed by `:number` or `:integer` to
+/// require a type, wrapped in brackets if optional, and the last name may be
+/// followed by `...` to accept any number of further arguments.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::command::ArgSpec;
+///
+/// let spec = ArgSpec::parse("path [format]").unwrap();
+/// assert!(spec.validate(&[String::from("page.html")]).is_ok());
+/// assert!(spec.validate(&[]).is_err());
+///
+/// let spec = ArgSpec::parse("count:integer words...").unwrap();
+/// assert!(spec.validate(&[String::from("2"), String::from("a"), String::from("b")]).is_ok());
+/// assert!(spec.validate(&[String::from("two"), String::from("a")]).is_err());
+/// ```
+#[derive(Debug,Clone,PartialEq)]
+pub struct ArgSpec {
+    pub entries: Vec<ArgSpecEntry>,
+    /// Whether the last entry accepts any number of further arguments
+    pub variadic: bool,
+}
+
+impl ArgSpec {
+
+    /// Parse an argument specification, describing the first invalid entry
+    /// on failure
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = spec.split_whitespace().collect();
+        let mut entries: Vec<ArgSpecEntry> = vec![];
+        let mut variadic = false;
+        for (index, token) in tokens.iter().enumerate() {
+            let optional = token.starts_with('[') && token.ends_with(']') && token.len() > 1;
+            let mut entry = if optional { &token[1 .. token.len() - 1] } else { token };
+            if entry.ends_with("...") {
+                if index + 1 < tokens.len() {
+                    return Err(format!("only the last argument may repeat: {}", token));
+                }
+                variadic = true;
+                entry = &entry[.. entry.len() - 3];
+            }
+            let mut parts = entry.splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            let arg_type = match parts.next() {
+                Some(name) => ArgType::parse(name)
+                    .ok_or_else(|| format!("unknown argument type: {}", token))?,
+                None => ArgType::String,
+            };
+            if name.is_empty() || name.contains(|c| c == '[' || c == ']') {
+                return Err(format!("invalid argument: {}", token));
+            }
+            if !optional && entries.last().map_or(false, |entry| entry.optional) {
+                return Err(format!("required argument follows optional argument: {}", token));
+            }
+            entries.push(ArgSpecEntry {
+                name: String::from(name),
+                arg_type: arg_type,
+                optional: optional,
+            });
+        }
+        Ok(ArgSpec { entries: entries, variadic: variadic })
+    }
+
+    /// Check arguments against the specification, describing the first
+    /// mismatch on failure
+    pub fn validate(&self, arguments: &[String]) -> Result<(), String> {
+        let required = self.entries.iter().filter(|entry| !entry.optional).count();
+        if arguments.len() < required {
+            return Err(format!("Expected at least {} arguments, found {}", required, arguments.len()));
+        }
+        if !self.variadic && arguments.len() > self.entries.len() {
+            return Err(format!("Expected at most {} arguments, found {}", self.entries.len(), arguments.len()));
+        }
+        for (index, argument) in arguments.iter().enumerate() {
+            if let Some(entry) = self.entries.get(index).or(self.entries.last()) {
+                if !entry.arg_type.accepts(argument) {
+                    return Err(format!("Expected {} to be a {}, found '{}'",
+                                       entry.name, entry.arg_type.name(), argument));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ArgSpec {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.entries.len();
+        let entries: Vec<String> = self.entries.iter().enumerate().map(|(index, entry)| {
+            let mut text = entry.name.clone();
+            if entry.arg_type != ArgType::String {
+                text = format!("{}:{}", text, entry.arg_type.name());
+            }
+            if self.variadic && index + 1 == count {
+                text.push_str("...");
+            }
+            match entry.optional {
+                true => format!("[{}]", text),
+                false => text,
+            }
+        }).collect();
+        write!(f, "{}", entries.join(" "))
+    }
+}
+
+/// The command lines in the contents of a command script file, skipping
+/// blank lines and comments beginning with `#`
+pub fn script_lines(contents: &str) -> Vec<&str> {
+    contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("#"))
+        .collect()
+}
+
+/// Command text for a buffer event, replacing the `{uri}` and `{host}`
+/// tokens with details of the URI of the event. If the text contains no
+/// tokens, the URI is appended as the last argument.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::command::event_command_text;
+///
+/// let uri = Some("https://example.com/page");
+/// assert_eq!("redirect example.com", event_command_text("redirect {host}", uri));
+/// assert_eq!("log https://example.com/page", event_command_text("log", uri));
+/// assert_eq!("log ", event_command_text("log {uri}", None));
+/// assert_eq!("log", event_command_text("log", None));
+/// ```
+pub fn event_command_text(text: &str, uri: Option<&str>) -> String {
+    if !text.contains(URI_TOKEN) && !text.contains(HOST_TOKEN) {
+        return match uri {
+            Some(uri) => format!("{} {}", text, uri),
+            None => String::from(text),
+        };
+    }
+    let host = uri.and_then(uri_host).unwrap_or(String::new());
+    text.replace(URI_TOKEN, uri.unwrap_or("")).replace(HOST_TOKEN, &host)
+}
+
+/// The index of the argument being completed in command bar text, counting
+/// from zero after the command name. Text ending in whitespace begins a new
+/// argument.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::command::active_argument;
+///
+/// assert_eq!(0, active_argument("open "));
+/// assert_eq!(0, active_argument("open file"));
+/// assert_eq!(1, active_argument("open file "));
+/// assert_eq!(1, active_argument("open file pd"));
+/// ```
+pub fn active_argument(input: &str) -> usize {
+    let arguments = input.split_whitespace().count().saturating_sub(1);
+    match input.ends_with(char::is_whitespace) {
+        true => arguments,
+        false => arguments.saturating_sub(1),
+    }
 }
 
 /// Iterate over search paths returning the first file path in search paths
 /// with the provided name
-fn resolve_command(search_paths: Vec<String>, name: &str, suffix: &str) -> Option<String> {
+fn resolve_command<F>(search_paths: Vec<String>, name: &str, suffix: &str, exists: &F) -> Option<String>
+        where F: Fn(&str) -> bool {
     if name.is_empty() {
         return None
     }
     let mut ordered_paths: Vec<String> = search_paths.iter()
         .filter_map(|path| join_paths(&path, &format!("{}.{}", name, suffix)))
-        .filter(|path| metadata(&path).is_ok())
+        .filter(|path| exists(&path))
         .collect();
     ordered_paths.reverse();
     return ordered_paths.pop();
 }
 
+/// Whether a path exists on the filesystem
+fn file_exists(path: &str) -> bool {
+    metadata(path).is_ok()
+}
+
 /// Join a directory and file name into a string path if possible
 fn join_paths(dir: &str, file_name: &str) -> Option<String> {
     let buf = Path::new(dir).join(file_name);
     buf.to_str().and_then(|path| Some(String::from(path)))
 }
 
+/// Builders for parsing commands from file contents held in memory, so the
+/// handling of aliases, disabled commands and search path order can be
+/// tested without creating files.
+///
+/// Search paths are added in order of precedence and files are added to a
+/// search path by name, including the file extension:
+///
+/// ```ignore
+/// let commands = CommandFixture::new()
+///     .file("/user", "open.lua", "function run() return true end")
+///     .file("/shared", "open.lua", "")
+///     .alias("o", "open")
+///     .disabled("close");
+/// let command = commands.parse("o example.com", "lua").unwrap();
+/// assert_eq!("/user/open.lua", command.path);
+/// assert_eq!(Some("function run() return true end"), commands.contents(&command));
+/// ```
+#[cfg(test)]
+pub mod test_support {
+    use std::collections::HashMap;
+
+    use config::Config;
+    use ui::BrowserConfiguration;
+    use super::{Command,CommandExplanation,join_paths};
+
+    /// Search paths and command files held in memory, along with the
+    /// configuration options which affect command parsing
+    #[derive(Debug,Clone,Default)]
+    pub struct CommandFixture {
+        search_paths: Vec<String>,
+        files: HashMap<String, String>,
+        disabled: Vec<String>,
+        aliases: Vec<(String, String)>,
+    }
+
+    impl CommandFixture {
+
+        pub fn new() -> Self {
+            CommandFixture::default()
+        }
+
+        /// Add an empty search path, after any search paths already added
+        pub fn search_path(mut self, path: &str) -> Self {
+            if !self.search_paths.iter().any(|existing| existing == path) {
+                self.search_paths.push(String::from(path));
+            }
+            self
+        }
+
+        /// Add a file to a search path, adding the search path if needed
+        pub fn file(mut self, search_path: &str, file_name: &str, contents: &str) -> Self {
+            self = self.search_path(search_path);
+            if let Some(path) = join_paths(search_path, file_name) {
+                self.files.insert(path, String::from(contents));
+            }
+            self
+        }
+
+        /// Add a command name to `commands.disabled`
+        pub fn disabled(mut self, name: &str) -> Self {
+            self.disabled.push(String::from(name));
+            self
+        }
+
+        /// Add an alias for a command name to `commands.aliases`
+        pub fn alias(mut self, alias: &str, name: &str) -> Self {
+            self.aliases.push((String::from(alias), String::from(name)));
+            self
+        }
+
+        /// The configuration containing the search paths, disabled commands
+        /// and aliases
+        pub fn config(&self) -> Config {
+            let mut aliases = String::new();
+            for &(ref alias, ref name) in &self.aliases {
+                aliases.push_str(&format!("\"{}\" = \"{}\"\n", alias, name));
+            }
+            Config::parse(&format!("[commands]\nsearch-paths = {:?}\ndisabled = {:?}\n[commands.aliases]\n{}",
+                                   self.search_paths, self.disabled, aliases))
+                .expect("invalid command fixture configuration")
+        }
+
+        /// Parse command bar text as `Command::parse` would if the files
+        /// existed
+        pub fn parse(&self, input: &str, suffix: &str) -> Option<Command> {
+            Command::parse_with(input, &self.config(), suffix, None, &|path| self.files.contains_key(path))
+        }
+
+        /// Explain command bar text as `CommandExplanation::explain` would if
+        /// the files existed
+        pub fn explain(&self, input: &str, suffix: &str) -> CommandExplanation {
+            CommandExplanation::explain_with(input, &self.config(), suffix, &|path| self.files.contains_key(path))
+        }
+
+        /// List commands as `Command::list_commands` would if the files
+        /// existed
+        pub fn list_commands(&self, prefix: &str) -> Vec<String> {
+            let file_names = self.files.keys()
+                .filter_map(|path| path.rsplit('/').next())
+                .map(String::from)
+                .collect();
+            Command::list_matching(prefix, &self.config(), file_names)
+        }
+
+        /// The contents of the file of a parsed command
+        pub fn contents(&self, command: &Command) -> Option<&str> {
+            self.files.get(&command.path).map(|contents| contents.as_str())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::test_support::CommandFixture;
+    use headless::test_support::TestFixture;
+    use ui::ApplicationUI;
     use config::Config;
     use ui::BrowserConfiguration;
     use std::env::temp_dir;
-    use std::fs::{File,remove_file};
+    use std::fs::{self,File,remove_file};
     use std::io::Write;
     use std::path::Path;
 
@@ -100,6 +610,126 @@ mod tests {
         assert_eq!(command.path, path);
     }
 
+    #[test]
+    #[allow(unused_must_use)]
+    fn explain_disabled_alias() {
+        let dir = temp_dir().join("webkitten-explain");
+        fs::create_dir_all(&dir);
+        let path = dir.join("open.lua");
+        fs::write(&path, "");
+        let config = Config::parse(&format!(r#"
+            [commands]
+            search-paths = ["{}"]
+            disabled = ["open"]
+            [commands.aliases]
+            o = "open"
+        "#, dir.to_str().unwrap())).unwrap();
+        let explanation = CommandExplanation::explain("o example.com", &config, "lua");
+        fs::remove_dir_all(&dir);
+        assert_eq!(CommandExplanation {
+            name: String::from("open"),
+            alias: true,
+            path: Some(String::from(path.to_str().unwrap())),
+            arguments: vec![String::from("example.com")],
+            disabled: true,
+        }, explanation);
+        assert_eq!(None, CommandExplanation::explain("missing", &config, "lua").path);
+    }
+
+    #[test]
+    fn resolve_from_first_search_path() {
+        let commands = CommandFixture::new()
+            .file("/user", "open.lua", "user")
+            .file("/shared", "open.lua", "shared")
+            .file("/shared", "close.lua", "");
+        let command = commands.parse("open example.com", "lua").unwrap();
+        assert_eq!("/user/open.lua", command.path);
+        assert_eq!(Some("user"), commands.contents(&command));
+        assert_eq!("/shared/close.lua", commands.parse("close", "lua").unwrap().path);
+        assert!(commands.parse("open", "js").is_none());
+    }
+
+    #[test]
+    fn resolve_alias_to_disabled_command() {
+        let commands = CommandFixture::new()
+            .file("/user", "open.lua", "")
+            .file("/user", "openall.lua", "")
+            .alias("o", "open")
+            .disabled("open");
+        assert!(commands.parse("o example.com", "lua").is_none());
+        let explanation = commands.explain("o example.com", "lua");
+        assert!(explanation.alias && explanation.disabled);
+        assert_eq!(Some(String::from("/user/open.lua")), explanation.path);
+        assert_eq!(vec![String::from("openall")], commands.list_commands("op"));
+    }
+
+    #[test]
+    fn complete_path_marks_directories() {
+        let dir = temp_dir().join("webkitten-complete-path");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("pages")).unwrap();
+        fs::write(dir.join("page.html"), "").unwrap();
+        fs::write(dir.join(".pagerc"), "").unwrap();
+        env::set_var("WEBKITTEN_COMPLETE_DIR", dir.to_str().unwrap());
+        assert_eq!(vec![String::from("$WEBKITTEN_COMPLETE_DIR/page.html"),
+                        String::from("$WEBKITTEN_COMPLETE_DIR/pages/")],
+                   complete_path("$WEBKITTEN_COMPLETE_DIR/pa"));
+        assert_eq!(vec![String::from("$WEBKITTEN_COMPLETE_DIR/.pagerc")],
+                   complete_path("$WEBKITTEN_COMPLETE_DIR/.p"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parse_completion_source() {
+        assert_eq!(Some(CompletionSource::Tabs), CompletionSource::parse("tabs"));
+        assert_eq!(Some(CompletionSource::Files), CompletionSource::parse(" files\n"));
+        assert_eq!(Some(CompletionSource::History), CompletionSource::parse("history"));
+        assert_eq!(None, CompletionSource::parse("bookmarks"));
+    }
+
+    #[test]
+    fn parse_argspec_round_trip() {
+        let spec = ArgSpec::parse("  x:number [y:integer] [rest...]").unwrap();
+        assert_eq!("x:number [y:integer] [rest...]", spec.to_string());
+        assert!(spec.validate(&[String::from("1.5")]).is_ok());
+        assert!(spec.validate(&[String::from("1.5"), String::from("2"), String::from("a")]).is_ok());
+        assert!(spec.validate(&[String::from("1.5"), String::from("2.5")]).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_argspec() {
+        assert!(ArgSpec::parse("[a] b").is_err());
+        assert!(ArgSpec::parse("a... b").is_err());
+        assert!(ArgSpec::parse("a:date").is_err());
+        assert!(ArgSpec::parse("[]").is_err());
+    }
+
+    #[test]
+    fn script_lines_skip_comments() {
+        let lines = script_lines("# open the usual pages\ngo example.com\n  # indented\nbuffernew");
+        assert_eq!(vec!["go example.com", "buffernew"], lines);
+    }
+
+    #[test]
+    fn script_lines_skip_blank_lines() {
+        let lines = script_lines("\ngo example.com\n   \n\nreload\n");
+        assert_eq!(vec!["go example.com", "reload"], lines);
+    }
+
+    #[test]
+    fn pass_uri_arguments_to_event_commands() {
+        let ui = TestFixture::new("on-load-uri = [\"titled {host} kitten\", \"titled\"]");
+        ui.write_command("titled", r#"
+        function description() return "" end
+        function on_load_uri()
+          set_window_title(window_index, table.concat(arguments, ","))
+        end
+        "#);
+        ui.open_window(Some("https://example.com/page"), None::<Config>);
+        ui.assert_called("set_window_title(0, example.com,kitten)");
+        ui.assert_called("set_window_title(0, https://example.com/page)");
+    }
+
     #[allow(unused_must_use)]
     fn create_command(name: &str, content: &[u8], invocation: &str) -> (String, Option<Command>) {
         let dir = temp_dir();
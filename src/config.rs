@@ -1,11 +1,16 @@
 //! Configuration manipulation and handling for common browser options
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self,File};
+use std::io::{self,Read,Write};
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path,PathBuf};
+use std::process;
+use std::sync::RwLock;
 
-use toml::Value;
+use toml::{Parser,Table,Value};
 
-use ui::BrowserConfiguration;
+use ui::{BrowserConfiguration,uri_host};
 
 
 pub const DEFAULT_CONFIG: &'static str = r#"
@@ -25,6 +30,39 @@ const CONFIG_DIR: &'static str = "CONFIG_DIR";
 
 const HOME: &'static str = "HOME";
 
+/// Errors resulting from reading a configuration file
+#[derive(Debug,Clone,PartialEq)]
+pub enum ConfigError {
+    /// The file at the path (first) could not be read, described by the
+    /// second value
+    Unreadable(String, String),
+    /// The contents are not valid TOML, described by one message per error
+    /// including the line and column
+    InvalidSyntax(Vec<String>),
+}
+
+impl Error for ConfigError {
+
+    fn description(&self) -> &str {
+        match self {
+            &ConfigError::Unreadable(_, _) => "configuration unreadable",
+            &ConfigError::InvalidSyntax(_) => "invalid configuration syntax",
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ConfigError::Unreadable(ref path, ref reason) =>
+                write!(f, "Failed to read configuration ({}): {}", path, reason),
+            &ConfigError::InvalidSyntax(ref errors) =>
+                write!(f, "Failed to parse configuration: {}", errors.join("; ")),
+        }
+    }
+}
+
 /// Configuration option storage and lookup
 ///
 /// ## Examples
@@ -33,13 +71,13 @@ const HOME: &'static str = "HOME";
 ///
 /// ```
 /// use webkitten::config::Config;
-/// use webkitten::ui::BrowserConfiguration;
+/// use webkitten::ui::{BrowserConfiguration,WindowContext};
 ///
 /// let config = Config::parse(r#"
 /// [window]
 /// start-page = "file:///path/to/the/page.html"
 /// "#).unwrap();
-/// let start_page = config.start_page().unwrap();
+/// let start_page = config.start_page(&WindowContext::Default).unwrap();
 /// assert_eq!("file:///path/to/the/page.html", &start_page);
 /// ```
 ///
@@ -47,7 +85,7 @@ const HOME: &'static str = "HOME";
 ///
 /// ```
 /// use webkitten::config::Config;
-/// use webkitten::ui::BrowserConfiguration;
+/// use webkitten::ui::{BrowserConfiguration,WindowContext};
 ///
 /// let config = Config::parse(r#"
 /// [general]
@@ -55,7 +93,7 @@ const HOME: &'static str = "HOME";
 /// [window]
 /// start-page = "file://CONFIG_DIR/page.html"
 /// "#).unwrap();
-/// let start_page = config.start_page().unwrap();
+/// let start_page = config.start_page(&WindowContext::Default).unwrap();
 /// assert_eq!("file:///path/to/config/page.html", &start_page);
 /// ```
 ///
@@ -106,18 +144,22 @@ const HOME: &'static str = "HOME";
 /// assert_eq!("/path/to/bin", &path.unwrap());
 /// ```
 pub struct Config {
-    value: Value
+    value: RwLock<Value>
 }
 
 impl BrowserConfiguration for Config {
 
     fn parse(raw_input: &str) -> Option<Self> {
-        match raw_input.parse() {
-            Ok(value) => Some(Config { value: value }),
-            Err(errors) => {
+        match Config::parse_str(raw_input) {
+            Ok(config) => Some(config),
+            Err(ConfigError::InvalidSyntax(errors)) => {
                 for err in errors { error!("Failed to parse toml: {}", err); }
                 None
             },
+            Err(err) => {
+                error!("{}", err);
+                None
+            }
         }
     }
 
@@ -128,8 +170,7 @@ impl BrowserConfiguration for Config {
 
     fn lookup_raw_str<'a>(&'a self, key: &'a str) -> Option<String> {
         self.lookup(key)
-            .and_then(|value| value.as_str())
-            .and_then(|value| Some(String::from(value)))
+            .and_then(|value| value.as_str().map(String::from))
     }
 
     fn lookup_str<'a>(&'a self, key: &'a str) -> Option<String> {
@@ -142,8 +183,13 @@ impl BrowserConfiguration for Config {
             .and_then(|value| value.as_integer())
     }
 
+    fn lookup_value(&self, key: &str) -> Option<Value> {
+        self.lookup(key)
+    }
+
     fn lookup_str_table(&self, key: &str) -> Option<HashMap<String, String>> {
-        if let Some(table) = self.lookup(key).and_then(|value| value.as_table()) {
+        let value = self.lookup(key);
+        if let Some(table) = value.as_ref().and_then(|value| value.as_table()) {
             let mut map: HashMap<String, String> = HashMap::new();
             for (key, raw_value) in table {
                 if let Some(value) = raw_value.as_str() {
@@ -156,7 +202,8 @@ impl BrowserConfiguration for Config {
     }
 
     fn lookup_str_vec(&self, key: &str) -> Option<Vec<String>> {
-        self.lookup(key)
+        let value = self.lookup(key);
+        value.as_ref()
             .and_then(|value| value.as_slice())
             .and_then(|values| {
                 let mut str_values: Vec<String> = vec![];
@@ -179,12 +226,15 @@ impl Config {
 
     /// Reload cached configuration from disk returns true if parsing is
     /// successful
-    pub fn load(&mut self, path: &str) -> bool {
-        if let Some(update) = Config::parse_file(path) {
-            self.value = update.value;
-            true
-        } else {
-            false
+    pub fn load(&self, path: &str) -> bool {
+        let update = Config::parse_file(path)
+            .and_then(|update| update.value.into_inner().ok());
+        match (update, self.value.write()) {
+            (Some(update), Ok(mut value)) => {
+                *value = update;
+                true
+            },
+            _ => false
         }
     }
 
@@ -196,9 +246,210 @@ impl Config {
             .and_then(|_| Config::parse(buffer.as_str()))
     }
 
-    /// Look up the raw TOML value for a key
-    fn lookup<'a>(&'a self, key: &'a str) -> Option<&Value> {
-        self.value.lookup(&key.clone())
+    /// Parse a file at a path, first writing the default configuration to
+    /// the path if no file exists. The default configuration is used if the
+    /// file cannot be created, read, or parsed.
+    pub fn parse_file_or_default(path: &str) -> Self {
+        if !Path::new(path).exists() {
+            match write_default_file(path) {
+                Ok(()) => info!("Created default configuration: {}", path),
+                Err(err) => warn!("Failed to create default configuration ({}): {}", path, err),
+            }
+        }
+        Config::read_file(path).unwrap_or_else(|err| {
+            warn!("{}, using the default configuration", err);
+            Config::default().expect("Failed to parse the default configuration")
+        })
+    }
+
+    /// Parse a file at a path, describing why the file could not be read or
+    /// parsed on failure
+    pub fn read_file(path: &str) -> Result<Self, ConfigError> {
+        let mut buffer = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut buffer))
+            .map_err(|err| ConfigError::Unreadable(String::from(path), err.to_string()))?;
+        Config::parse_str(&buffer)
+    }
+
+    /// Parse TOML, describing the location of each syntax error on failure
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::config::{Config,ConfigError};
+    ///
+    /// match Config::parse_str("[general]\nprivate-browsing = ") {
+    ///     Err(ConfigError::InvalidSyntax(errors)) => {
+    ///         assert!(errors[0].starts_with("line 2, column 20: "));
+    ///     },
+    ///     _ => panic!("Expected a syntax error"),
+    /// }
+    /// ```
+    pub fn parse_str(raw_input: &str) -> Result<Self, ConfigError> {
+        let mut parser = Parser::new(raw_input);
+        match parser.parse() {
+            Some(table) => Ok(Config { value: RwLock::new(Value::Table(table)) }),
+            None => Err(ConfigError::InvalidSyntax(parser.errors.iter().map(|err| {
+                let (line, column) = parser.to_linecol(err.lo);
+                format!("line {}, column {}: {}", line + 1, column + 1, err.desc)
+            }).collect()))
+        }
+    }
+
+    /// Set an option in the cached configuration, creating any missing
+    /// tables along the key path, or remove the option if `value` is `None`.
+    /// Tables left empty by a removal are removed as well. Returns `false`
+    /// if the key path passes through a value which is not a table.
+    pub fn set_value(&self, key_path: &[&str], value: Option<Value>) -> bool {
+        match (self.value.write(), value) {
+            (Ok(mut config), Some(value)) => match *config {
+                Value::Table(ref mut table) => insert_value(table, key_path, value),
+                _ => false
+            },
+            (Ok(mut config), None) => {
+                if let Value::Table(ref mut table) = *config {
+                    remove_value(table, key_path);
+                }
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Set a site-specific boolean option for the host of a URI, or remove
+    /// the override if `value` is `None`
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::config::Config;
+    /// use webkitten::ui::BrowserConfiguration;
+    ///
+    /// let config = Config::parse("[general]\nprivate-browsing = false").unwrap();
+    /// let uri = "https://example.com/page";
+    /// assert!(config.set_site_bool(uri, "general.private-browsing", Some(true)));
+    /// assert!(config.use_private_browsing(uri));
+    /// assert!(config.set_site_bool(uri, "general.private-browsing", None));
+    /// assert!(!config.use_private_browsing(uri));
+    /// ```
+    pub fn set_site_bool(&self, uri: &str, key: &str, value: Option<bool>) -> bool {
+        match uri_host(uri) {
+            Some(host) => {
+                let mut key_path = vec!["sites", &host];
+                key_path.extend(key.split('.'));
+                self.set_value(&key_path, value.map(Value::Boolean))
+            },
+            None => {
+                warn!("Failed to parse URI: {}", uri);
+                false
+            }
+        }
+    }
+
+    /// Add a command name to `commands.disabled`, or remove it if `disabled`
+    /// is `false`. The option is removed when no commands remain disabled.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::config::Config;
+    /// use webkitten::ui::BrowserConfiguration;
+    ///
+    /// let config = Config::parse("[commands]\ndisabled = [\"go\"]").unwrap();
+    /// assert!(config.set_command_disabled("back", true));
+    /// assert!(config.command_disabled("back"));
+    /// assert!(config.set_command_disabled("go", false));
+    /// assert!(!config.command_disabled("go"));
+    /// ```
+    pub fn set_command_disabled(&self, name: &str, disabled: bool) -> bool {
+        let mut names = self.lookup_str_vec("commands.disabled").unwrap_or(vec![]);
+        names.retain(|disabled_name| disabled_name != name);
+        if disabled {
+            names.push(String::from(name));
+        }
+        let value = match names.is_empty() {
+            true => None,
+            false => Some(Value::Array(names.into_iter().map(Value::String).collect())),
+        };
+        self.set_value(&["commands", "disabled"], value)
+    }
+
+    /// Apply a change to the configuration file at a path and to the cached
+    /// configuration, returning `true` if both succeed. The change is made
+    /// to a fresh copy of the file so that options set only for the session,
+    /// such as by `set_value`, are not written. Only the options which
+    /// changed are rewritten, keeping comments and formatting, and the file
+    /// is replaced atomically so a failed write leaves it intact.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::{env,fs};
+    /// use webkitten::config::Config;
+    /// use webkitten::ui::BrowserConfiguration;
+    ///
+    /// let path = env::temp_dir().join("webkitten-update-file.toml");
+    /// let path = path.to_str().unwrap();
+    /// fs::write(path, "# Commands\n[commands]\ndisabled = [\"go\"]\n").unwrap();
+    /// let config = Config::read_file(path).unwrap();
+    /// assert!(config.update_file(path, |config| config.set_command_disabled("back", true)));
+    /// assert!(config.command_disabled("back"));
+    /// assert_eq!("# Commands\n[commands]\ndisabled = [\"go\", \"back\"]\n",
+    ///            fs::read_to_string(path).unwrap());
+    /// # fs::remove_file(path).unwrap();
+    /// ```
+    pub fn update_file<F>(&self, path: &str, update: F) -> bool
+        where F: Fn(&Config) -> bool {
+        let read = fs::read_to_string(path)
+            .map_err(|err| ConfigError::Unreadable(String::from(path), err.to_string()))
+            .and_then(|text| Config::parse_str(&text).map(|saved| (text, saved)));
+        let (text, saved) = match read {
+            Ok(read) => read,
+            Err(err) => {
+                warn!("{}, not saving configuration", err);
+                return false;
+            }
+        };
+        let original = match saved.table() {
+            Some(original) => original,
+            None => return false
+        };
+        update(&saved) && saved.save(path, &text, &original) && update(self)
+    }
+
+    /// Write the cached configuration to a path, returning `true` if
+    /// successful. `text` is the current contents of the file, parsed as
+    /// `original`, and only the options differing from it are rewritten.
+    /// The whole file is serialized if the text cannot be updated in place.
+    fn save(&self, path: &str, text: &str, original: &Table) -> bool {
+        let updated = match self.table() {
+            Some(updated) => updated,
+            None => return false
+        };
+        let contents = update_text(text, original, &updated).unwrap_or_else(|| {
+            warn!("Failed to update configuration in place ({}), rewriting it", path);
+            Value::Table(updated).to_string()
+        });
+        match write_atomically(path, &contents) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Failed to write configuration ({}): {}", path, err);
+                false
+            }
+        }
+    }
+
+    /// A copy of the cached configuration
+    fn table(&self) -> Option<Table> {
+        self.value.read().ok()
+            .and_then(|value| value.as_table().cloned())
+    }
+
+    /// Look up a copy of the raw TOML value for a key
+    fn lookup(&self, key: &str) -> Option<Value> {
+        self.value.read().ok()
+            .and_then(|value| value.lookup(key).cloned())
     }
 
     fn parse_path(&self, value: &str) -> String {
@@ -221,11 +472,381 @@ impl Config {
     }
 }
 
+/// Write the default configuration to a path, creating any missing parent
+/// directories
+fn write_default_file(path: &str) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, DEFAULT_CONFIG)
+}
+
+/// Write contents to a temporary file beside a path, then rename it into
+/// place. If the path is a symbolic link, the file it points to is replaced.
+fn write_atomically(path: &str, contents: &str) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let mut temp_path = path.clone().into_os_string();
+    temp_path.push(format!(".{}", process::id()));
+    let result = File::create(&temp_path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()).and_then(|_| file.sync_all()))
+        .and_then(|_| match fs::metadata(&path) {
+            Ok(metadata) => fs::set_permissions(&temp_path, metadata.permissions()),
+            Err(_) => Ok(())
+        })
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// An option set in the text of a configuration file
+struct TextOption {
+    key: String,
+    /// The first line of the option
+    start: usize,
+    /// The last line of the option, which differs from `start` when an array
+    /// or inline table spans multiple lines
+    end: usize,
+}
+
+/// A table in the text of a configuration file
+struct TextTable {
+    /// The key path of the table, or `None` for an array of tables
+    path: Option<Vec<String>>,
+    /// The line of the table header, or `None` for the root table
+    header: Option<usize>,
+    options: Vec<TextOption>,
+    /// The line following the last line of the table
+    end: usize,
+}
+
+/// Apply the changes between two versions of a configuration to the TOML
+/// text of the first version, keeping comments, formatting, and the order
+/// of options. Returns `None` if the updated text does not match `updated`.
+fn update_text(text: &str, original: &Table, updated: &Table) -> Option<String> {
+    let mut changes = vec![];
+    table_changes(original, updated, &mut vec![], &mut changes);
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    for (key_path, value) in changes {
+        update_option(&mut lines, &key_path, value.as_ref())?;
+    }
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    match Parser::new(&contents).parse() {
+        Some(ref table) if without_empty_tables(table) == without_empty_tables(updated) => {
+            Some(contents)
+        },
+        _ => None
+    }
+}
+
+/// Collect the key paths of the options which differ between two tables,
+/// with the updated value of each or `None` if the option was removed
+fn table_changes(original: &Table, updated: &Table, path: &mut Vec<String>,
+                 changes: &mut Vec<(Vec<String>, Option<Value>)>) {
+    let empty = Table::new();
+    for (key, value) in updated {
+        path.push(key.clone());
+        match (original.get(key), value) {
+            (Some(&Value::Table(ref before)), &Value::Table(ref after)) => {
+                table_changes(before, after, path, changes)
+            },
+            (None, &Value::Table(ref after)) => table_changes(&empty, after, path, changes),
+            (before, after) => if before != Some(after) {
+                changes.push((path.clone(), Some(after.clone())));
+            }
+        }
+        path.pop();
+    }
+    for (key, value) in original.iter().filter(|&(key, _)| !updated.contains_key(key)) {
+        path.push(key.clone());
+        match *value {
+            Value::Table(ref before) => table_changes(before, &empty, path, changes),
+            _ => changes.push((path.clone(), None)),
+        }
+        path.pop();
+    }
+}
+
+/// Set or remove a single option in the lines of a configuration file,
+/// adding a table header if the table of the option is not declared
+fn update_option(lines: &mut Vec<String>, key_path: &[String], value: Option<&Value>) -> Option<()> {
+    let (key, table_path) = key_path.split_last()?;
+    let tables = text_tables(lines);
+    let table = tables.iter().find(|table| table.path.as_ref().map(|path| &path[..]) == Some(table_path));
+    let option_line = value.map(|value| format!("{} = {}", format_key(key), value));
+    let (table, option_line) = match (table, option_line) {
+        (None, Some(option_line)) => {
+            if lines.last().map(|line| !line.trim().is_empty()).unwrap_or(false) {
+                lines.push(String::new());
+            }
+            let header: Vec<String> = table_path.iter().map(|key| format_key(key)).collect();
+            lines.push(format!("[{}]", header.join(".")));
+            lines.push(option_line);
+            return Some(());
+        },
+        (None, None) => return Some(()),
+        (Some(table), option_line) => (table, option_line),
+    };
+    let option = match table.options.iter().find(|option| &option.key == key) {
+        Some(option) => option,
+        None => {
+            let line = table.options.last().map(|option| option.end + 1)
+                .or(table.header.map(|header| header + 1))
+                .unwrap_or(0);
+            if let Some(option_line) = option_line {
+                lines.insert(line, option_line);
+            }
+            return Some(());
+        }
+    };
+    match option_line {
+        Some(option_line) => {
+            let comment = match option.start == option.end {
+                true => comment_start(&lines[option.start]).map(|index| &lines[option.start][index ..]),
+                false => None,
+            };
+            let option_line = match comment {
+                Some(comment) => format!("{} {}", option_line, comment),
+                None => option_line,
+            };
+            lines.splice(option.start .. option.end + 1, Some(option_line));
+        },
+        None => {
+            let remaining = (table.header.map(|header| header + 1).unwrap_or(0) .. table.end)
+                .filter(|line| *line < option.start || *line > option.end)
+                .any(|line| !lines[line].trim().is_empty());
+            match (table.header, remaining) {
+                (Some(header), false) => { lines.drain(header .. table.end); },
+                _ => { lines.drain(option.start .. option.end + 1); },
+            }
+        }
+    }
+    Some(())
+}
+
+/// Find the tables and options declared in the lines of a configuration
+/// file
+fn text_tables(lines: &[String]) -> Vec<TextTable> {
+    let mut tables = vec![TextTable { path: Some(vec![]), header: None, options: vec![], end: 0 }];
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim();
+        if line.starts_with('[') {
+            let path = match line.starts_with("[[") {
+                true => None,
+                false => Parser::new(&format!("{}\n", line)).parse().as_ref().and_then(header_path),
+            };
+            tables.push(TextTable { path, header: Some(index), options: vec![], end: 0 });
+        } else if let Some((key, value)) = option_key(line) {
+            let start = index;
+            let mut depth = bracket_depth(value, 0);
+            while depth > 0 && index + 1 < lines.len() {
+                index += 1;
+                depth = bracket_depth(&lines[index], depth);
+            }
+            if let Some(table) = tables.last_mut() {
+                table.options.push(TextOption { key, start, end: index });
+            }
+        }
+        index += 1;
+        if let Some(table) = tables.last_mut() {
+            table.end = index;
+        }
+    }
+    tables
+}
+
+/// The key path of the only table in a parsed table header
+fn header_path(table: &Table) -> Option<Vec<String>> {
+    let mut path = vec![];
+    let mut table = table;
+    while let Some((key, value)) = table.iter().next() {
+        if table.len() > 1 {
+            return None;
+        }
+        path.push(key.clone());
+        table = value.as_table()?;
+    }
+    Some(path)
+}
+
+/// The key of an option line and the text following `=`, or `None` if the
+/// line does not set an option
+fn option_key(line: &str) -> Option<(String, &str)> {
+    let end = match line.chars().next()? {
+        '"' => {
+            let mut escaped = false;
+            line.char_indices().skip(1).find(|&(_, c)| {
+                let end = !escaped && c == '"';
+                escaped = !escaped && c == '\\';
+                end
+            })?.0 + 1
+        },
+        '\'' => line[1 ..].find('\'')? + 2,
+        _ => line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))?,
+    };
+    let value = line[end ..].trim_start();
+    if end == 0 || !value.starts_with('=') {
+        return None;
+    }
+    Parser::new(&format!("{} = 0", &line[.. end])).parse()
+        .and_then(|table| table.keys().next().cloned())
+        .map(|key| (key, &value[1 ..]))
+}
+
+/// Add the brackets opened and subtract the brackets closed in a line to
+/// a depth, ignoring strings and comments
+fn bracket_depth(line: &str, depth: i32) -> i32 {
+    let end = comment_start(line).unwrap_or(line.len());
+    unquoted_chars(&line[.. end]).fold(depth, |depth, (_, c)| match c {
+        '[' | '{' => depth + 1,
+        ']' | '}' => depth - 1,
+        _ => depth,
+    })
+}
+
+/// The index of a comment in a line, if any
+fn comment_start(line: &str) -> Option<usize> {
+    unquoted_chars(line).find(|&(_, c)| c == '#').map(|(index, _)| index)
+}
+
+/// The characters of a line outside of strings with their indices
+fn unquoted_chars<'a>(line: &'a str) -> impl Iterator<Item=(usize, char)> + 'a {
+    let mut quote = None;
+    let mut escaped = false;
+    line.char_indices().filter(move |&(_, c)| match quote {
+        Some(quote_char) => {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && quote_char == '"' {
+                escaped = true;
+            } else if c == quote_char {
+                quote = None;
+            }
+            false
+        },
+        None => {
+            if c == '"' || c == '\'' {
+                quote = Some(c);
+            }
+            true
+        }
+    })
+}
+
+/// Format a key for a TOML option or table header, quoting it if needed
+fn format_key(key: &str) -> String {
+    match key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') && !key.is_empty() {
+        true => String::from(key),
+        false => Value::String(String::from(key)).to_string(),
+    }
+}
+
+/// Copy a table without any tables which are empty or contain only empty
+/// tables
+fn without_empty_tables(table: &Table) -> Table {
+    let mut copy = Table::new();
+    for (key, value) in table {
+        match *value {
+            Value::Table(ref inner) => {
+                let inner = without_empty_tables(inner);
+                if !inner.is_empty() {
+                    copy.insert(key.clone(), Value::Table(inner));
+                }
+            },
+            ref value => { copy.insert(key.clone(), value.clone()); },
+        }
+    }
+    copy
+}
+
+fn insert_value(table: &mut Table, key_path: &[&str], value: Value) -> bool {
+    match key_path.split_first() {
+        Some((key, &[])) => {
+            table.insert(String::from(*key), value);
+            true
+        },
+        Some((key, rest)) => {
+            let entry = table.entry(String::from(*key))
+                .or_insert_with(|| Value::Table(Table::new()));
+            match *entry {
+                Value::Table(ref mut inner) => insert_value(inner, rest, value),
+                _ => false
+            }
+        },
+        None => false
+    }
+}
+
+fn remove_value(table: &mut Table, key_path: &[&str]) {
+    match key_path.split_first() {
+        Some((key, &[])) => {
+            table.remove(*key);
+        },
+        Some((key, rest)) => {
+            let is_empty = match table.get_mut(*key) {
+                Some(&mut Value::Table(ref mut inner)) => {
+                    remove_value(inner, rest);
+                    inner.is_empty()
+                },
+                _ => false
+            };
+            if is_empty {
+                table.remove(*key);
+            }
+        },
+        None => ()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::Config;
-    use ui::{BrowserConfiguration,BufferEvent};
+    use super::{Config,DEFAULT_CONFIG};
+    use std::{env,fs};
+    use ui::{BrowserConfiguration,BufferEvent,CacheMode,ColorScheme,ConsoleLevel,ConsoleMessage,
+             DownloadEvent,MediaCaptureKind,NavigationType,OpenIn,ReferrerPolicy,TabMatch,TabSwitchOrder,
+             WindowContext};
+    use toml::Value;
+    use log::LevelFilter;
+
+    #[test]
+    fn lookup_open_in_by_site() {
+        let config = Config::parse(r#"
+        [sites."chat.example.com"]
+        open-in = "window"
+        [sites."example.org"]
+        open-in = "background"
+        [sites."example.net"]
+        open-in = "elsewhere"
+        [new-frame]
+        opens-in-focused-window = true
+        "#).unwrap();
+        assert_eq!(OpenIn::Window, config.open_in("https://chat.example.com/room"));
+        assert_eq!(OpenIn::Background, config.open_in("https://example.org"));
+        assert_eq!(OpenIn::Tab, config.open_in("https://example.net"));
+        assert_eq!(OpenIn::Tab, config.open_in("https://example.com"));
+        assert_eq!(OpenIn::Window, Config::parse("").unwrap().open_in("https://example.com"));
+    }
+
+    #[test]
+    fn lookup_start_page_by_context() {
+        let config = Config::parse(r#"
+        [window]
+        start-page = "https://example.com"
+        private-start-page = "about:blank"
+        [profiles.work]
+        start-page = "https://example.org"
+        "#).unwrap();
+        assert_eq!(Some(String::from("https://example.com")), config.start_page(&WindowContext::Default));
+        assert_eq!(Some(String::from("about:blank")), config.start_page(&WindowContext::Private));
+        assert_eq!(Some(String::from("https://example.org")),
+                   config.start_page(&WindowContext::Profile(String::from("work"))));
+        assert_eq!(Some(String::from("https://example.com")),
+                   config.start_page(&WindowContext::Profile(String::from("home"))));
+    }
 
     #[test]
     fn lookup_fail_uri_commands() {
@@ -239,13 +860,35 @@ mod tests {
         assert_eq!(String::from("refresh"), commands[1]);
     }
 
+    #[test]
+    fn lookup_fail_loop_uri_commands() {
+        let config = Config::parse(r#"
+        [commands]
+        on-fail-uri = ["refresh"]
+        on-fail-loop-uri = ["bob"]
+        "#).unwrap();
+        let commands = config.on_buffer_event_commands(&BufferEvent::FailLoop(String::new()));
+        assert_eq!(vec![String::from("bob")], commands);
+    }
+
+    #[test]
+    fn lookup_fail_loop_threshold() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(Some(3), config.fail_loop_threshold());
+        let config = Config::parse(r#"
+        [general]
+        fail-loop-threshold = 0
+        "#).unwrap();
+        assert_eq!(None, config.fail_loop_threshold());
+    }
+
     #[test]
     fn lookup_request_uri_commands() {
         let config = Config::parse(r#"
         [commands]
         on-request-uri = ["bob","refresh"]
         "#).unwrap();
-        let commands = config.on_buffer_event_commands(&BufferEvent::Request);
+        let commands = config.on_buffer_event_commands(&BufferEvent::Request(NavigationType::Other));
         assert_eq!(2, commands.len());
         assert_eq!(String::from("bob"), commands[0]);
         assert_eq!(String::from("refresh"), commands[1]);
@@ -276,4 +919,585 @@ mod tests {
         assert_eq!(1, commands.len());
         assert_eq!(String::from("frut"), commands[0]);
     }
+
+    #[test]
+    fn lookup_cache_mode() {
+        let config = Config::parse(r#"
+        [general]
+        cache-mode = "offline"
+        "#).unwrap();
+        assert_eq!(CacheMode::Offline, config.cache_mode());
+    }
+
+    #[test]
+    fn lookup_invalid_cache_mode() {
+        let config = Config::parse(r#"
+        [general]
+        cache-mode = "sometimes"
+        "#).unwrap();
+        assert_eq!(CacheMode::Default, config.cache_mode());
+    }
+
+    #[test]
+    fn lookup_site_always_reload() {
+        let config = Config::parse(r#"
+        [sites."localhost"]
+        always-reload = true
+        "#).unwrap();
+        assert!(config.always_reload("http://localhost:8000/index.html"));
+        assert!(!config.always_reload("http://example.com"));
+    }
+
+    #[test]
+    fn lookup_site_allow_geolocation() {
+        let config = Config::parse(r#"
+        [sites."maps.example.com"]
+        allow-geolocation = true
+        "#).unwrap();
+        assert!(config.allow_geolocation("https://maps.example.com"));
+        assert!(!config.allow_geolocation("https://example.com"));
+    }
+
+    #[test]
+    fn lookup_site_allow_media_capture() {
+        let config = Config::parse(r#"
+        [sites."meet.example.com"]
+        allow-camera = true
+        allow-microphone = true
+        [sites."radio.example.com"]
+        allow-microphone = true
+        "#).unwrap();
+        assert!(config.allow_media_capture("meet.example.com", MediaCaptureKind::CameraAndMicrophone));
+        assert!(config.allow_media_capture("radio.example.com", MediaCaptureKind::Microphone));
+        assert!(!config.allow_media_capture("radio.example.com", MediaCaptureKind::CameraAndMicrophone));
+        assert!(!config.allow_media_capture("example.com", MediaCaptureKind::Camera));
+    }
+
+    #[test]
+    fn lookup_fake_location() {
+        assert_eq!(None, Config::parse("").unwrap().fake_location());
+        let config = Config::parse("[general]\nfake-location = [51.5, -0.1]").unwrap();
+        assert_eq!(Some((51.5, -0.1)), config.fake_location());
+        let config = Config::parse("[general]\nfake-location = [51.5]").unwrap();
+        assert_eq!(None, config.fake_location());
+    }
+
+    #[test]
+    fn lookup_site_allow_notifications() {
+        let config = Config::parse(r#"
+        [sites."mail.example.com"]
+        allow-notifications = true
+        "#).unwrap();
+        assert!(config.allow_notifications("https://mail.example.com/inbox"));
+        assert!(!config.allow_notifications("https://example.com"));
+    }
+
+    #[test]
+    fn lookup_site_suppress_dialogs() {
+        let config = Config::parse(r#"
+        [sites."ads.example.com"]
+        suppress-dialogs = true
+        "#).unwrap();
+        assert!(config.suppress_dialogs("https://ads.example.com/popup"));
+        assert!(!config.suppress_dialogs("https://example.com"));
+    }
+
+    #[test]
+    fn lookup_startup_commands() {
+        let config = Config::parse(r#"
+        [commands]
+        on-startup = ["go example.com","theme dark"]
+        "#).unwrap();
+        let commands = config.startup_commands();
+        assert_eq!(vec![String::from("go example.com"), String::from("theme dark")],
+                   commands);
+    }
+
+    #[test]
+    fn lookup_site_stylesheet_path() {
+        let config = Config::parse(r#"
+        [general]
+        global-stylesheet = "/styles/global.css"
+        [sites."example.com"]
+        stylesheet = "/styles/example.css"
+        "#).unwrap();
+        assert_eq!(Some(String::from("/styles/example.css")),
+                   config.stylesheet_path("https://example.com/news"));
+        assert_eq!(Some(String::from("/styles/global.css")),
+                   config.stylesheet_path("https://example.org"));
+    }
+
+    #[test]
+    fn lookup_site_hide_selectors() {
+        let config = Config::parse(r#"
+        [block]
+        hide-selectors = ["div.ad"]
+        [sites."example.com"]
+        hide-selectors = [".sponsored"]
+        "#).unwrap();
+        assert_eq!(vec![String::from("div.ad"), String::from(".sponsored")],
+                   config.hide_selectors("https://example.com/news"));
+        assert_eq!(vec![String::from("div.ad")],
+                   config.hide_selectors("https://example.org"));
+    }
+
+    #[test]
+    fn lookup_site_color_scheme() {
+        let config = Config::parse(r#"
+        [general]
+        prefer-dark = true
+        [sites."example.com".general]
+        prefer-dark = false
+        "#).unwrap();
+        assert_eq!(ColorScheme::Dark, config.color_scheme("example.org"));
+        assert_eq!(ColorScheme::Light, config.color_scheme("example.com"));
+    }
+
+    #[test]
+    fn lookup_default_color_scheme() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(ColorScheme::System, config.color_scheme("example.com"));
+    }
+
+    #[test]
+    fn resolve_site_alias() {
+        let config = Config::parse(r#"
+        [commands.aliases]
+        s = "search"
+        [sites."github.com".aliases]
+        s = "github-search"
+        "#).unwrap();
+        assert_eq!(Some(String::from("github-search")),
+                   config.resolved_command_name("s", Some("https://github.com/ozbe")));
+        assert_eq!(Some(String::from("search")),
+                   config.resolved_command_name("s", Some("https://example.com")));
+        assert_eq!(Some(String::from("search")),
+                   config.resolved_command_name("s", None));
+    }
+
+    #[test]
+    fn lookup_deduplicate_tabs() {
+        let config = Config::parse(r#"
+        [general]
+        deduplicate-tabs = true
+        deduplicate-tabs-match = "host"
+        "#).unwrap();
+        assert_eq!(Some(TabMatch::Host), config.deduplicate_tabs());
+        let config = Config::parse("[general]\ndeduplicate-tabs = true").unwrap();
+        assert_eq!(Some(TabMatch::Uri), config.deduplicate_tabs());
+        let config = Config::parse("[general]\ndeduplicate-tabs-match = \"host\"").unwrap();
+        assert_eq!(None, config.deduplicate_tabs());
+    }
+
+    #[test]
+    fn lookup_form_autofill_values() {
+        let config = Config::parse(r#"
+        [form-autofill]
+        email = "me@example.com"
+        given-name = "Ada"
+        new-password = "hunter2"
+        cc-number = "4111"
+        "#).unwrap();
+        assert_eq!(vec![(String::from("email"), String::from("me@example.com")),
+                        (String::from("given-name"), String::from("Ada"))],
+                   config.form_autofill_values());
+        assert!(Config::parse("").unwrap().form_autofill_values().is_empty());
+    }
+
+    #[test]
+    fn lookup_referrer_policy() {
+        let config = Config::parse(r#"
+        [general]
+        referrer-policy = "strict-origin"
+        [sites."example.com".general]
+        referrer-policy = "no-referrer"
+        [sites."example.org".general]
+        referrer-policy = "sometimes"
+        "#).unwrap();
+        assert_eq!(ReferrerPolicy::StrictOrigin, config.referrer_policy("https://example.net/"));
+        assert_eq!(ReferrerPolicy::NoReferrer, config.referrer_policy("https://example.com/"));
+        assert_eq!(ReferrerPolicy::Default, config.referrer_policy("https://example.org/"));
+        assert_eq!(ReferrerPolicy::Default, Config::parse("").unwrap().referrer_policy("https://example.com/"));
+    }
+
+    #[test]
+    fn lookup_privacy_headers() {
+        let config = Config::parse(r#"
+        [general]
+        send-dnt = true
+        [sites."example.com".general]
+        send-dnt = false
+        send-gpc = true
+        "#).unwrap();
+        assert_eq!(vec![(String::from("DNT"), String::from("1"))],
+                   config.privacy_headers("https://example.org/"));
+        assert_eq!(vec![(String::from("Sec-GPC"), String::from("1"))],
+                   config.privacy_headers("https://example.com/"));
+        assert!(Config::parse("").unwrap().privacy_headers("https://example.com/").is_empty());
+    }
+
+    #[test]
+    fn lookup_site_headers() {
+        let config = Config::parse(r#"
+        [sites."example.com".headers]
+        X-Token = "abc"
+        Referer = ""
+        "Bad Name" = "value"
+        "#).unwrap();
+        assert_eq!(vec![(String::from("Referer"), String::new()),
+                        (String::from("X-Token"), String::from("abc"))],
+                   config.site_headers("https://example.com/"));
+        assert!(config.site_headers("https://example.org/").is_empty());
+    }
+
+    #[test]
+    fn lookup_site_find_highlight_color() {
+        let config = Config::parse(r##"
+        [general]
+        find-highlight-color = "orange"
+        [sites."example.com"]
+        find-highlight-color = "#0af"
+        [sites."example.org"]
+        find-highlight-color = "url(evil)"
+        "##).unwrap();
+        assert_eq!(Some(String::from("#0af")), config.find_highlight_color("https://example.com/"));
+        assert_eq!(Some(String::from("orange")), config.find_highlight_color("https://example.net/"));
+        assert_eq!(None, config.find_highlight_color("https://example.org/"));
+    }
+
+    #[test]
+    fn lookup_site_webview_background_color() {
+        let config = Config::parse(r##"
+        [general]
+        webview-background-color = "#000"
+        [sites."example.com"]
+        webview-background-color = "#ffffff80"
+        [sites."example.org"]
+        webview-background-color = "black"
+        "##).unwrap();
+        assert_eq!(Some((1., 1., 1., 128. / 255.)), config.webview_background_color("https://example.com/"));
+        assert_eq!(Some((0., 0., 0., 1.)), config.webview_background_color("https://example.net/"));
+        assert_eq!(None, config.webview_background_color("https://example.org/"));
+    }
+
+    #[test]
+    fn lookup_tab_switch_order() {
+        let config = Config::parse("[commands]\ntab-switch-order = \"mru\"").unwrap();
+        assert_eq!(TabSwitchOrder::Mru, config.tab_switch_order());
+        let config = Config::parse("[commands]\ntab-switch-order = \"recent\"").unwrap();
+        assert_eq!(TabSwitchOrder::Index, config.tab_switch_order());
+    }
+
+    #[test]
+    fn lookup_log_options() {
+        let config = Config::parse(r#"
+        [general]
+        log-level = "debug"
+        log-file-max-size = 0
+        "#).unwrap();
+        assert_eq!(Some(LevelFilter::Debug), config.log_level());
+        assert_eq!(None, config.log_file_max_size());
+        let config = Config::parse("[general]\nlog-level = \"loud\"").unwrap();
+        assert_eq!(None, config.log_level());
+    }
+
+    #[test]
+    fn lookup_discard_background_tabs_after() {
+        assert_eq!(None, Config::parse("").unwrap().discard_background_tabs_after());
+        let config = Config::parse("[general]\ndiscard-background-tabs-after = 0").unwrap();
+        assert_eq!(None, config.discard_background_tabs_after());
+        let config = Config::parse("[general]\ndiscard-background-tabs-after = 600").unwrap();
+        assert_eq!(Some(600), config.discard_background_tabs_after());
+    }
+
+    #[test]
+    fn lookup_site_refresh_interval() {
+        let config = Config::parse(r#"
+        [sites."status.example.com"]
+        refresh-interval = 60
+        refresh-when-focused = false
+        [sites."example.org"]
+        refresh-interval = 0
+        "#).unwrap();
+        assert_eq!(Some(60), config.refresh_interval("https://status.example.com/dashboard"));
+        assert!(!config.refresh_when_focused("https://status.example.com/dashboard"));
+        assert_eq!(None, config.refresh_interval("https://example.org"));
+        assert_eq!(None, config.refresh_interval("https://example.com"));
+        assert!(config.refresh_when_focused("https://example.com"));
+    }
+
+    #[test]
+    fn lookup_max_concurrent_loads() {
+        assert_eq!(None, Config::parse("").unwrap().max_concurrent_loads());
+        let config = Config::parse("[general]\nmax-concurrent-loads = 0").unwrap();
+        assert_eq!(None, config.max_concurrent_loads());
+        let config = Config::parse("[general]\nmax-concurrent-loads = 4").unwrap();
+        assert_eq!(Some(4), config.max_concurrent_loads());
+    }
+
+    #[test]
+    fn lookup_lazy_restore() {
+        assert!(!Config::parse("").unwrap().lazy_restore());
+        assert!(Config::parse("[window]\nlazy-restore = true").unwrap().lazy_restore());
+    }
+
+    #[test]
+    fn lookup_notifications() {
+        assert!(Config::parse("").unwrap().notifications());
+        let config = Config::parse("[general]\nnotifications = false").unwrap();
+        assert!(!config.notifications());
+    }
+
+    #[test]
+    fn lookup_share_process_pool() {
+        assert!(Config::parse("").unwrap().share_process_pool());
+        let config = Config::parse("[general]\nshare-process-pool = false").unwrap();
+        assert!(!config.share_process_pool());
+    }
+
+    #[test]
+    fn lookup_console_commands() {
+        let config = Config::parse(r#"
+        [general]
+        capture-console = true
+        [commands]
+        on-console-message = ["console-banner"]
+        "#).unwrap();
+        assert!(config.capture_console());
+        let message = ConsoleMessage {
+            level: ConsoleLevel::Warn,
+            message: String::new(),
+            source: String::new(),
+            line: 0,
+        };
+        assert_eq!(vec![String::from("console-banner")],
+                   config.on_buffer_event_commands(&BufferEvent::Console(message)));
+    }
+
+    #[test]
+    fn lookup_incremental_find_delay() {
+        let config = Config::parse("[commands]\nincremental-find-delay = 40").unwrap();
+        assert_eq!(Some(40), config.incremental_find_delay());
+        let config = Config::parse("[commands]\nincremental-find-delay = -1").unwrap();
+        assert_eq!(Some(150), config.incremental_find_delay());
+        let config = Config::parse("[commands]\nincremental-find = false").unwrap();
+        assert_eq!(None, config.incremental_find_delay());
+    }
+
+    #[test]
+    fn lookup_completion_trigger() {
+        let config = Config::parse("[commands]\ncompletion-min-length = 2\ncompletion-delay-ms = 80").unwrap();
+        assert_eq!(2, config.completion_min_length());
+        assert_eq!(Some(80), config.completion_delay());
+        let config = Config::parse("[commands]\ncompletion-min-length = -1").unwrap();
+        assert_eq!(0, config.completion_min_length());
+        assert_eq!(None, config.completion_delay());
+    }
+
+    #[test]
+    fn lookup_structured_value() {
+        let config = Config::parse(r#"
+        [custom.reader]
+        width = 60
+        fonts = ["Georgia", "serif"]
+        "#).unwrap();
+        let value = config.lookup_value("custom.reader").unwrap();
+        let table = value.as_table().unwrap();
+        assert_eq!(Some(60), table.get("width").and_then(|width| width.as_integer()));
+        assert_eq!(Some(&Value::String(String::from("serif"))),
+                   config.lookup_value("custom.reader.fonts").as_ref()
+                       .and_then(|fonts| fonts.as_slice())
+                       .and_then(|fonts| fonts.get(1)));
+        assert_eq!(None, config.lookup_value("custom.missing"));
+    }
+
+    #[test]
+    fn lookup_command_config() {
+        let config = Config::parse(r#"
+        [command-config.pinboard]
+        token = "abc"
+        tags = ["read"]
+        "#).unwrap();
+        let options = config.command_config("pinboard").unwrap();
+        assert_eq!(Some("abc"), options.lookup("token").and_then(|token| token.as_str()));
+        assert_eq!(None, config.command_config("buku"));
+    }
+
+    #[test]
+    fn lookup_bookmarklets() {
+        let config = Config::parse(r#"
+        [general]
+        allow-bookmarklets = true
+        [bookmarklets]
+        "print page" = "javascript:window.print()"
+        top = "window.scrollTo(0, 0)"
+        greet = "javascript:alert(%22100%25%20%E2%9C%93%22)"
+        modulo = "alert(7 % 20)"
+        "#).unwrap();
+        assert_eq!(vec![String::from("print page")], config.bookmarklet_names("pr"));
+        assert_eq!(Some(String::from("window.print()")), config.bookmarklet("print page"));
+        assert_eq!(Some(String::from("window.scrollTo(0, 0)")), config.bookmarklet("top"));
+        assert_eq!(Some(String::from("alert(\"100% \u{2713}\")")), config.bookmarklet("greet"));
+        assert_eq!(Some(String::from("alert(7 % 20)")), config.bookmarklet("modulo"));
+        assert_eq!(None, config.bookmarklet("missing"));
+    }
+
+    #[test]
+    fn disallow_bookmarklets_by_default() {
+        let config = Config::parse(r#"
+        [bookmarklets]
+        top = "javascript:window.scrollTo(0, 0)"
+        "#).unwrap();
+        assert!(config.bookmarklet_names("").is_empty());
+        assert_eq!(None, config.bookmarklet("top"));
+    }
+
+    #[test]
+    fn lookup_external_scheme_command() {
+        let config = Config::parse(r#"
+        [external-schemes]
+        mailto = "compose"
+        "#).unwrap();
+        assert_eq!(Some(String::from("compose")), config.external_scheme_command("mailto"));
+        assert_eq!(None, config.external_scheme_command("tel"));
+    }
+
+    #[test]
+    fn lookup_download_options() {
+        let config = Config::parse(r#"
+        [general]
+        download-dir = "/tmp/downloads"
+        [commands]
+        on-download-finish = ["notify"]
+        "#).unwrap();
+        assert_eq!("/tmp/downloads", config.download_dir());
+        assert_eq!(vec![String::from("notify")],
+                   config.on_download_event_commands(&DownloadEvent::Finish(String::from("/tmp/a"))));
+        assert!(config.on_download_event_commands(&DownloadEvent::Fail(String::new())).is_empty());
+        assert_eq!("~/Downloads", Config::parse("").unwrap().download_dir());
+    }
+
+    #[test]
+    fn parse_missing_file_or_default() {
+        let dir = env::temp_dir().join("webkitten-missing-config");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("config.toml");
+        let path = path.to_str().unwrap();
+        let config = Config::parse_file_or_default(path);
+        assert_eq!(Some(false), config.lookup_bool("general.private-browsing"));
+        assert_eq!(DEFAULT_CONFIG, fs::read_to_string(path).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lookup_site_path_prefix() {
+        let config = Config::parse(r#"
+        [general]
+        allow-javascript = true
+        [sites."example.com"]
+        stylesheet = "site.css"
+        [sites."example.com".general]
+        allow-javascript = true
+        [sites."example.com/admin".general]
+        allow-javascript = false
+        [sites."example.com/admin/public".general]
+        allow-javascript = true
+        "#).unwrap();
+        assert!(config.use_javascript("https://example.com/"));
+        assert!(!config.use_javascript("https://example.com/admin"));
+        assert!(!config.use_javascript("https://example.com/admin/users?page=2"));
+        assert!(config.use_javascript("https://example.com/admin/public/help"));
+        assert!(config.use_javascript("https://example.com/administrator"));
+        assert_eq!(Some(String::from("site.css")), config.stylesheet_path("https://example.com/admin/users"));
+    }
+
+    #[test]
+    fn set_site_bool_round_trip() {
+        let config = Config::parse(r#"
+        [general]
+        private-browsing = false
+        [sites."example.com".general]
+        allow-javascript = false
+        "#).unwrap();
+        assert!(config.set_site_bool("https://example.com/", "general.private-browsing", Some(true)));
+        assert!(config.set_site_bool("https://example.org/", "general.private-browsing", Some(true)));
+        let saved = Config::parse(&config.value.read().unwrap().to_string()).unwrap();
+        assert!(saved.use_private_browsing("https://example.com/"));
+        assert!(!saved.use_javascript("https://example.com/"));
+        assert!(saved.set_site_bool("https://example.org/", "general.private-browsing", None));
+        assert!(!saved.use_private_browsing("https://example.org/"));
+        assert_eq!(None, saved.lookup("sites.\"example.org\""));
+    }
+
+    #[test]
+    fn enable_last_disabled_command() {
+        let config = Config::parse("[commands]\ndisabled = [\"go\"]").unwrap();
+        assert!(config.set_command_disabled("go", false));
+        assert_eq!(None, config.lookup("commands"));
+    }
+
+    #[test]
+    fn set_value_through_non_table() {
+        let config = Config::parse("[general]\nprivate-browsing = false").unwrap();
+        assert!(!config.set_value(&["general", "private-browsing", "enabled"], Some(Value::Boolean(true))));
+        assert!(config.set_value(&["general", "private-browsing", "enabled"], None));
+        assert_eq!(Some(false), config.lookup_bool("general.private-browsing"));
+    }
+
+    #[test]
+    fn update_file_without_session_options() {
+        let path = env::temp_dir().join("webkitten-update-config.toml");
+        let path = path.to_str().unwrap();
+        fs::write(path, "[general]\nprivate-browsing = false\n[window]\nstart-page = \"about:blank\"").unwrap();
+        let config = Config::read_file(path).unwrap();
+        assert!(config.set_value(&["general", "caret-browsing"], Some(Value::Boolean(true))));
+        fs::write(path, "[general]\nprivate-browsing = false\n[window]\nstart-page = \"https://example.com\"").unwrap();
+        assert!(config.update_file(path, |config| {
+            config.set_site_bool("https://example.com/", "general.private-browsing", Some(true))
+        }));
+        assert!(config.use_private_browsing("https://example.com/"));
+        assert_eq!(Some(true), config.lookup_bool("general.caret-browsing"));
+        let saved = Config::read_file(path).unwrap();
+        assert!(saved.use_private_browsing("https://example.com/"));
+        assert_eq!(None, saved.lookup_bool("general.caret-browsing"));
+        assert_eq!(Some(String::from("https://example.com")), saved.lookup_str("window.start-page"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn update_file_keeps_comments_and_order() {
+        let path = env::temp_dir().join("webkitten-update-comments.toml");
+        let path = path.to_str().unwrap();
+        fs::write(path, r#"# Browser options
+[window]
+start-page = "about:blank" # blank page
+
+[commands]
+disabled = [
+  "go", # navigation
+]
+search-paths = ["/tmp"]
+
+[sites."example.org".general]
+private-browsing = true
+"#).unwrap();
+        let config = Config::read_file(path).unwrap();
+        assert!(config.update_file(path, |config| {
+            config.set_command_disabled("go", false)
+                && config.set_site_bool("https://example.com/", "general.private-browsing", Some(true))
+                && config.set_site_bool("https://example.org/", "general.private-browsing", None)
+                && config.set_value(&["window", "start-page"], Some(Value::String(String::from("about:home"))))
+        }));
+        assert_eq!(r#"# Browser options
+[window]
+start-page = "about:home" # blank page
+
+[commands]
+search-paths = ["/tmp"]
+
+[sites."example.com".general]
+private-browsing = true
+"#, fs::read_to_string(path).unwrap());
+        let _ = fs::remove_file(path);
+    }
 }
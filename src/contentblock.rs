@@ -0,0 +1,679 @@
+//! Compilation of content blocking rules into the JSON format used by WebKit
+//! content extensions
+use std::error::Error;
+use std::fmt;
+
+use ui::BrowserConfiguration;
+
+/// Regular expression prefix matching the scheme and any subdomains of a URL
+const DOMAIN_ANCHOR: &'static str = r"^[a-z]+://([^/]*\.)?";
+/// Regular expression matching a separator character in a filter list rule
+const SEPARATOR: &'static str = "[^a-zA-Z0-9_.%-]";
+
+/// The behavior of a content rule when its trigger matches a resource
+#[derive(Debug,Clone,PartialEq)]
+pub enum RuleAction {
+    /// Prevent the resource from loading
+    Block,
+    /// Skip any previously declared rules matching the resource
+    IgnorePreviousRules,
+    /// Hide elements matching a CSS selector
+    CssDisplayNone(String),
+}
+
+/// A single content rule, applying an action to resources with URLs matching
+/// a filter
+#[derive(Debug,Clone,PartialEq)]
+pub struct ContentRule {
+    /// Regular expression matched against resource URLs
+    pub url_filter: String,
+    pub action: RuleAction,
+    /// Whether `url_filter` is matched case-sensitively
+    pub case_sensitive: bool,
+    /// Domains of pages the rule is limited to
+    pub if_domains: Vec<String>,
+    /// Domains of pages the rule does not apply to
+    pub unless_domains: Vec<String>,
+    /// Kinds of resources the rule is limited to, such as `script` or `image`
+    pub resource_types: Vec<String>,
+    /// Origins of resources the rule is limited to, either `first-party` or
+    /// `third-party`
+    pub load_types: Vec<String>,
+}
+
+/// Rules converted from a filter list
+#[derive(Debug,Clone,PartialEq)]
+pub struct FilterList {
+    pub rules: Vec<ContentRule>,
+    /// The number of rules which could not be converted
+    pub skipped: usize,
+}
+
+/// A declared rule which could not be compiled
+#[derive(Debug,Clone,PartialEq)]
+pub struct RuleError {
+    /// The rule as declared
+    pub source: String,
+    pub reason: String,
+}
+
+impl Error for RuleError {
+
+    fn description(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for RuleError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid content rule '{}': {}", self.source, self.reason)
+    }
+}
+
+impl RuleError {
+
+    fn new(source: &str, reason: &str) -> Self {
+        RuleError { source: String::from(source), reason: String::from(reason) }
+    }
+}
+
+impl ContentRule {
+
+    /// Create a rule applying an action to all resources matching a filter
+    pub fn new<F: Into<String>>(url_filter: F, action: RuleAction) -> Self {
+        ContentRule {
+            url_filter: url_filter.into(),
+            action: action,
+            case_sensitive: false,
+            if_domains: vec![],
+            unless_domains: vec![],
+            resource_types: vec![],
+            load_types: vec![],
+        }
+    }
+
+    /// Create rules blocking all resources from a domain and its subdomains.
+    /// WebKit does not support disjunctions, so URLs continuing after the
+    /// domain and URLs ending with the domain are matched by separate rules.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::contentblock::ContentRule;
+    ///
+    /// let rules = ContentRule::block_domain("ads.example.com").unwrap();
+    /// assert_eq!(r"^[a-z]+://([^/]*\.)?ads\.example\.com[:/?#]", rules[0].url_filter);
+    /// assert_eq!(r"^[a-z]+://([^/]*\.)?ads\.example\.com$", rules[1].url_filter);
+    /// ```
+    pub fn block_domain(domain: &str) -> Result<Vec<Self>, RuleError> {
+        let domain = domain.trim();
+        if domain.is_empty() {
+            return Err(RuleError::new(domain, "domain is empty"));
+        }
+        let valid = domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+        if !valid || domain.starts_with('.') || domain.ends_with('.') {
+            return Err(RuleError::new(domain, "domain contains invalid characters"));
+        }
+        let filter = format!("{}{}", DOMAIN_ANCHOR, domain.to_lowercase().replace(".", r"\."));
+        Ok(vec![ContentRule::new(format!("{}[:/?#]", filter), RuleAction::Block),
+                ContentRule::new(format!("{}$", filter), RuleAction::Block)])
+    }
+
+    /// Create a rule blocking resources with URLs matching a regular
+    /// expression, if the expression is supported by WebKit content
+    /// extensions
+    pub fn block_pattern(pattern: &str) -> Result<Self, RuleError> {
+        validate_pattern(pattern).map(|_| ContentRule::new(pattern, RuleAction::Block))
+    }
+
+    /// Encode the rule as a JSON object
+    pub fn to_json(&self) -> String {
+        let mut trigger = format!(r#""url-filter":"{}""#, escape_json(&self.url_filter));
+        if self.case_sensitive {
+            trigger.push_str(r#","url-filter-is-case-sensitive":true"#);
+        }
+        for &(key, values) in &[("if-domain", &self.if_domains),
+                                ("unless-domain", &self.unless_domains),
+                                ("resource-type", &self.resource_types),
+                                ("load-type", &self.load_types)] {
+            if !values.is_empty() {
+                trigger.push_str(&format!(r#","{}":{}"#, key, encode_strings(values)));
+            }
+        }
+        let action = match self.action {
+            RuleAction::Block => String::from(r#""type":"block""#),
+            RuleAction::IgnorePreviousRules => String::from(r#""type":"ignore-previous-rules""#),
+            RuleAction::CssDisplayNone(ref selector) =>
+                format!(r#""type":"css-display-none","selector":"{}""#, escape_json(selector)),
+        };
+        format!(r#"{{"trigger":{{{}}},"action":{{{}}}}}"#, trigger, action)
+    }
+}
+
+/// Convert the rules of a filter list in Adblock Plus format, such as
+/// EasyList, into content rules. Exception rules are placed after all other
+/// rules so they take precedence. Unsupported rules are counted and skipped.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::contentblock::{parse_filter_list,RuleAction};
+///
+/// let list = parse_filter_list("[Adblock Plus 2.0]\n! Title: Example\n||ads.example.com^\n##.banner\n");
+/// assert_eq!(3, list.rules.len());
+/// assert_eq!(0, list.skipped);
+/// assert_eq!(RuleAction::CssDisplayNone(String::from(".banner")), list.rules[2].action);
+/// ```
+pub fn parse_filter_list(contents: &str) -> FilterList {
+    let mut rules = vec![];
+    let mut exceptions = vec![];
+    let mut skipped = 0;
+    for line in contents.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            continue;
+        }
+        match parse_filter(line) {
+            Ok(ref parsed) if parsed[0].action == RuleAction::IgnorePreviousRules => {
+                exceptions.extend_from_slice(parsed);
+            },
+            Ok(parsed) => rules.extend(parsed),
+            Err(reason) => {
+                debug!("Skipping filter '{}': {}", line, reason);
+                skipped += 1;
+            }
+        }
+    }
+    rules.extend(exceptions);
+    FilterList { rules: rules, skipped: skipped }
+}
+
+/// Compile the rules declared in `block.domains` and `block.patterns`,
+/// returning the valid rules and errors describing any invalid rules
+pub fn config_rules<C: BrowserConfiguration>(config: &C) -> (Vec<ContentRule>, Vec<RuleError>) {
+    let mut rules = vec![];
+    let mut errors = vec![];
+    for domain in config.block_domains() {
+        match ContentRule::block_domain(&domain) {
+            Ok(domain_rules) => rules.extend(domain_rules),
+            Err(err) => errors.push(err),
+        }
+    }
+    for pattern in config.block_patterns() {
+        match ContentRule::block_pattern(&pattern) {
+            Ok(rule) => rules.push(rule),
+            Err(err) => errors.push(err),
+        }
+    }
+    (rules, errors)
+}
+
+/// Create a stylesheet hiding elements matching CSS selectors. Each selector
+/// is declared in a separate rule so an unsupported selector does not prevent
+/// other elements from being hidden. Selectors which could break out of a
+/// rule are skipped. Returns `None` if there are no valid selectors.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::contentblock::hiding_stylesheet;
+///
+/// let selectors = vec![String::from("div.ad")];
+/// assert_eq!(Some(String::from("div.ad { display: none !important; }\n")),
+///            hiding_stylesheet(&selectors));
+/// ```
+pub fn hiding_stylesheet(selectors: &[String]) -> Option<String> {
+    let mut styles = String::new();
+    for selector in selectors {
+        let selector = selector.trim();
+        if selector.is_empty() {
+            continue;
+        }
+        if selector.contains(|c| c == '{' || c == '}' || c == ';') {
+            warn!("Skipping invalid element hiding selector: {}", selector);
+            continue;
+        }
+        styles.push_str(&format!("{} {{ display: none !important; }}\n", selector));
+    }
+    if styles.is_empty() { None } else { Some(styles) }
+}
+
+/// Encode rules as a JSON array
+pub fn encode(rules: &[ContentRule]) -> String {
+    let encoded: Vec<String> = rules.iter().map(|rule| rule.to_json()).collect();
+    format!("[{}]", encoded.join(","))
+}
+
+/// Append rules to an existing JSON array of rules, such as the contents of
+/// the file at `general.content-filter`. Returns `None` if the existing rules
+/// are not a JSON array.
+pub fn merge(existing: &str, rules: &[ContentRule]) -> Option<String> {
+    let existing = existing.trim();
+    if !existing.starts_with('[') || !existing.ends_with(']') {
+        return None;
+    }
+    let existing_rules = existing[1 .. existing.len() - 1].trim();
+    let added_rules = encode(rules);
+    let added_rules = &added_rules[1 .. added_rules.len() - 1];
+    Some(match (existing_rules.is_empty(), added_rules.is_empty()) {
+        (true, _) => format!("[{}]", added_rules),
+        (false, true) => format!("[{}]", existing_rules),
+        (false, false) => format!("[{},{}]", existing_rules, added_rules),
+    })
+}
+
+/// Count the rules in a JSON array of rules, such as a merged content filter
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::contentblock::count_rules;
+///
+/// assert_eq!(0, count_rules("[]"));
+/// let filter = r#"[{"trigger":{"url-filter":"{a}"},"action":{"type":"block"}},
+///                  {"trigger":{"url-filter":"b"},"action":{"type":"block"}}]"#;
+/// assert_eq!(2, count_rules(filter));
+/// ```
+pub fn count_rules(filter: &str) -> usize {
+    let mut count = 0;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for character in filter.chars() {
+        if in_string {
+            match (escaped, character) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match character {
+            '"' => in_string = true,
+            '[' | '{' => {
+                if character == '{' && depth == 1 {
+                    count += 1;
+                }
+                depth += 1;
+            },
+            ']' | '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    count
+}
+
+/// Convert a single filter list rule into content rules. Most rules convert
+/// to a single content rule, but a trailing separator needs a second rule
+/// matching the end of the URL.
+fn parse_filter(line: &str) -> Result<Vec<ContentRule>, &'static str> {
+    if !line.is_ascii() {
+        return Err("non-ASCII rules are not supported");
+    }
+    if line.contains("#@#") {
+        return Err("element hiding exceptions are not supported");
+    }
+    if line.contains("#?#") || line.contains("#$#") {
+        return Err("extended element hiding is not supported");
+    }
+    if let Some(index) = line.find("##") {
+        return parse_hiding_filter(&line[.. index], &line[index + 2 ..]).map(|rule| vec![rule]);
+    }
+    let (action, line) = if line.starts_with("@@") {
+        (RuleAction::IgnorePreviousRules, &line[2 ..])
+    } else {
+        (RuleAction::Block, line)
+    };
+    let (pattern, options) = if is_regex_filter(line) {
+        (line, None)
+    } else {
+        match line.rfind('$') {
+            Some(index) => (&line[.. index], Some(&line[index + 1 ..])),
+            None => (line, None),
+        }
+    };
+    let mut rule = ContentRule::new("", action);
+    if let Some(options) = options {
+        apply_filter_options(&mut rule, options)?;
+    }
+    Ok(convert_url_pattern(pattern)?.into_iter()
+       .map(|filter| ContentRule { url_filter: filter, .. rule.clone() })
+       .collect())
+}
+
+/// Convert an element hiding rule, optionally limited to a comma-separated
+/// list of domains
+fn parse_hiding_filter(domains: &str, selector: &str) -> Result<ContentRule, &'static str> {
+    let selector = selector.trim();
+    if selector.is_empty() || selector.contains(|c| c == '{' || c == '}') {
+        return Err("invalid selector");
+    }
+    let mut rule = ContentRule::new(".*", RuleAction::CssDisplayNone(String::from(selector)));
+    apply_domains(&mut rule, domains.split(','))?;
+    Ok(rule)
+}
+
+/// Convert a filter list URL pattern into regular expressions. A trailing
+/// separator matches either a separator character or the end of the URL, so
+/// it is converted into one expression for each.
+fn convert_url_pattern(pattern: &str) -> Result<Vec<String>, &'static str> {
+    if is_regex_filter(pattern) {
+        let regex = &pattern[1 .. pattern.len() - 1];
+        return validate_pattern(regex)
+            .map(|_| vec![String::from(regex)])
+            .map_err(|_| "unsupported regular expression");
+    }
+    let mut filter = String::new();
+    let mut pattern = pattern;
+    if pattern.starts_with("||") {
+        filter.push_str(DOMAIN_ANCHOR);
+        pattern = &pattern[2 ..];
+    } else if pattern.starts_with('|') {
+        filter.push('^');
+        pattern = &pattern[1 ..];
+    }
+    let end_anchor = pattern.ends_with('|');
+    if end_anchor {
+        pattern = &pattern[.. pattern.len() - 1];
+    }
+    let trailing_separator = pattern.ends_with('^');
+    if trailing_separator {
+        pattern = &pattern[.. pattern.len() - 1];
+    }
+    for c in pattern.chars() {
+        match c {
+            '*' => filter.push_str(".*"),
+            '^' => filter.push_str(SEPARATOR),
+            '|' => return Err("unexpected anchor"),
+            '.' | '+' | '?' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' => {
+                filter.push('\\');
+                filter.push(c);
+            },
+            c => filter.push(c),
+        }
+    }
+    let end = if end_anchor { "$" } else { "" };
+    if trailing_separator {
+        return Ok(vec![format!("{}{}{}", filter, SEPARATOR, end), format!("{}$", filter)]);
+    }
+    filter.push_str(end);
+    if filter.is_empty() {
+        filter.push_str(".*");
+    }
+    Ok(vec![filter])
+}
+
+/// Apply a comma-separated list of filter options to a rule
+fn apply_filter_options(rule: &mut ContentRule, options: &str) -> Result<(), &'static str> {
+    for option in options.split(',') {
+        if option.starts_with("domain=") {
+            apply_domains(rule, option["domain=".len() ..].split('|'))?;
+            continue;
+        }
+        let (negated, name) = if option.starts_with('~') {
+            (true, &option[1 ..])
+        } else {
+            (false, option)
+        };
+        match (name, negated) {
+            ("third-party", false) => rule.load_types.push(String::from("third-party")),
+            ("third-party", true) => rule.load_types.push(String::from("first-party")),
+            ("match-case", false) => rule.case_sensitive = true,
+            (name, false) => match resource_type(name) {
+                Some(resource_type) => rule.resource_types.push(String::from(resource_type)),
+                None => return Err("unsupported option"),
+            },
+            _ => return Err("unsupported option"),
+        }
+    }
+    Ok(())
+}
+
+/// Limit a rule to or exclude it from domains. Excluded domains are prefixed
+/// with `~`.
+fn apply_domains<'a, I>(rule: &mut ContentRule, domains: I) -> Result<(), &'static str>
+    where I: Iterator<Item=&'a str> {
+    for domain in domains.map(|domain| domain.trim()).filter(|domain| !domain.is_empty()) {
+        if domain.starts_with('~') {
+            rule.unless_domains.push(format!("*{}", domain[1 ..].to_lowercase()));
+        } else {
+            rule.if_domains.push(format!("*{}", domain.to_lowercase()));
+        }
+    }
+    if !rule.if_domains.is_empty() && !rule.unless_domains.is_empty() {
+        Err("rules both including and excluding domains are not supported")
+    } else {
+        Ok(())
+    }
+}
+
+/// The content rule resource type matching a filter list option
+fn resource_type(option: &str) -> Option<&'static str> {
+    match option {
+        "font" => Some("font"),
+        "image" => Some("image"),
+        "media" => Some("media"),
+        "popup" => Some("popup"),
+        "script" => Some("script"),
+        "stylesheet" => Some("style-sheet"),
+        "subdocument" => Some("document"),
+        "xmlhttprequest" | "other" => Some("raw"),
+        _ => None,
+    }
+}
+
+fn is_regex_filter(pattern: &str) -> bool {
+    pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/')
+}
+
+/// Check that a regular expression only uses the syntax supported by WebKit
+/// content extensions
+fn validate_pattern(pattern: &str) -> Result<(), RuleError> {
+    if pattern.is_empty() {
+        return Err(RuleError::new(pattern, "pattern is empty"));
+    }
+    if !pattern.is_ascii() {
+        return Err(RuleError::new(pattern, "pattern contains non-ASCII characters"));
+    }
+    let mut groups = 0;
+    let mut in_class = false;
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            _ if in_class => (),
+            '(' => groups += 1,
+            ')' if groups == 0 => {
+                return Err(RuleError::new(pattern, "unbalanced parentheses"));
+            },
+            ')' => groups -= 1,
+            '|' => return Err(RuleError::new(pattern, "disjunctions are not supported")),
+            '{' | '}' => {
+                return Err(RuleError::new(pattern, "counted repetition is not supported"));
+            },
+            _ => (),
+        }
+    }
+    if escaped {
+        Err(RuleError::new(pattern, "pattern ends with an escape"))
+    } else if in_class {
+        Err(RuleError::new(pattern, "unterminated character class"))
+    } else if groups > 0 {
+        Err(RuleError::new(pattern, "unbalanced parentheses"))
+    } else {
+        Ok(())
+    }
+}
+
+fn encode_strings(values: &[String]) -> String {
+    let encoded: Vec<String> = values.iter()
+        .map(|value| format!("\"{}\"", escape_json(value)))
+        .collect();
+    format!("[{}]", encoded.join(","))
+}
+
+/// Escape a value for use within a JSON string literal
+pub fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use ui::BrowserConfiguration;
+
+    #[test]
+    fn block_invalid_domain() {
+        assert!(ContentRule::block_domain("ads example.com").is_err());
+        assert!(ContentRule::block_domain("").is_err());
+    }
+
+    #[test]
+    fn block_pattern_with_disjunction() {
+        let err = ContentRule::block_pattern("ads|tracker").err().unwrap();
+        assert!(err.reason.contains("disjunction"));
+    }
+
+    #[test]
+    fn block_pattern_with_unbalanced_groups() {
+        assert!(ContentRule::block_pattern("(ads").is_err());
+        assert!(ContentRule::block_pattern("ads)").is_err());
+        assert!(ContentRule::block_pattern("[ads").is_err());
+    }
+
+    #[test]
+    fn block_pattern_allows_escaped_characters() {
+        assert!(ContentRule::block_pattern(r"/ads\(\)\|[|(]").is_ok());
+    }
+
+    #[test]
+    fn encode_rule_escapes_filter() {
+        let rule = ContentRule::block_pattern(r"\.gif$").unwrap();
+        assert_eq!(r#"[{"trigger":{"url-filter":"\\.gif$"},"action":{"type":"block"}}]"#,
+                   encode(&[rule]));
+    }
+
+    #[test]
+    fn merge_into_existing_rules() {
+        let rule = ContentRule::block_pattern("ads").unwrap();
+        let merged = merge("[{\"a\":1}]\n", &[rule.clone()]).unwrap();
+        assert_eq!(format!("[{{\"a\":1}},{}]", rule.to_json()), merged);
+        assert_eq!(format!("[{}]", rule.to_json()), merge("[ ]", &[rule]).unwrap());
+        assert_eq!(None, merge("{}", &[]));
+    }
+
+    #[test]
+    fn hiding_stylesheet_skips_invalid_selectors() {
+        let selectors = vec![String::from(".ad"), String::from("} body {"), String::from(" ")];
+        assert_eq!(Some(String::from(".ad { display: none !important; }\n")),
+                   hiding_stylesheet(&selectors));
+        assert_eq!(None, hiding_stylesheet(&selectors[1..]));
+    }
+
+    #[test]
+    fn encode_rule_with_trigger_options() {
+        let mut rule = ContentRule::new(".*", RuleAction::CssDisplayNone(String::from(".ad")));
+        rule.if_domains.push(String::from("*example.com"));
+        assert_eq!(r#"{"trigger":{"url-filter":".*","if-domain":["*example.com"]},"action":{"type":"css-display-none","selector":".ad"}}"#,
+                   rule.to_json());
+    }
+
+    #[test]
+    fn parse_domain_anchored_filter() {
+        let rules = parse_filter("||ads.example.com^$script,third-party").unwrap();
+        assert_eq!(2, rules.len());
+        assert_eq!(r"^[a-z]+://([^/]*\.)?ads\.example\.com[^a-zA-Z0-9_.%-]", rules[0].url_filter);
+        assert_eq!(r"^[a-z]+://([^/]*\.)?ads\.example\.com$", rules[1].url_filter);
+        for rule in rules {
+            assert_eq!(vec![String::from("script")], rule.resource_types);
+            assert_eq!(vec![String::from("third-party")], rule.load_types);
+        }
+    }
+
+    #[test]
+    fn parse_wildcard_filter() {
+        let rules = parse_filter("|https://*/ad^banner.gif|").unwrap();
+        assert_eq!(r"^https://.*/ad[^a-zA-Z0-9_.%-]banner\.gif$", rules[0].url_filter);
+    }
+
+    #[test]
+    fn parse_exception_filter() {
+        let rule = parse_filter("@@||example.com/ads.js$domain=example.com|~www.example.com");
+        assert!(rule.is_err());
+        let rule = parse_filter("@@/ads.js$domain=example.com|example.org").unwrap().remove(0);
+        assert_eq!(RuleAction::IgnorePreviousRules, rule.action);
+        assert_eq!(vec![String::from("*example.com"), String::from("*example.org")],
+                   rule.if_domains);
+    }
+
+    #[test]
+    fn parse_regex_filter() {
+        assert_eq!(r"banner[0-9]+\.gif$", parse_filter(r"/banner[0-9]+\.gif$/").unwrap()[0].url_filter);
+        assert!(parse_filter("/ads|banners/").is_err());
+    }
+
+    #[test]
+    fn parse_site_hiding_filter() {
+        let rule = parse_filter("~example.com,~example.org##.sponsored").unwrap().remove(0);
+        assert_eq!(RuleAction::CssDisplayNone(String::from(".sponsored")), rule.action);
+        assert_eq!(vec![String::from("*example.com"), String::from("*example.org")],
+                   rule.unless_domains);
+    }
+
+    #[test]
+    fn parse_filter_list_counts_unsupported_rules() {
+        let list = parse_filter_list("@@||example.com^\n||ads.example.com^$object\nexample.com#@#.ad\n/ads/\n");
+        assert_eq!(2, list.skipped);
+        assert_eq!(3, list.rules.len());
+        assert_eq!(RuleAction::Block, list.rules[0].action);
+        assert_eq!(RuleAction::IgnorePreviousRules, list.rules[1].action);
+        assert_eq!(RuleAction::IgnorePreviousRules, list.rules[2].action);
+    }
+
+    #[test]
+    fn generated_filters_are_supported_by_webkit() {
+        let list = parse_filter_list("||ads.example.com^\n@@||example.com^$script\n|https://*/ad^\n/ads/\n");
+        let config = Config::parse(r#"
+        [block]
+        domains = ["ads.example.com"]
+        patterns = ["/banner/.*\\.png"]
+        "#).unwrap();
+        let (rules, _) = config_rules(&config);
+        for rule in list.rules.iter().chain(rules.iter()) {
+            assert_eq!(Ok(()), validate_pattern(&rule.url_filter));
+        }
+    }
+
+    #[test]
+    fn compile_config_rules() {
+        let config = Config::parse(r#"
+        [block]
+        domains = ["ads.example.com", "bad domain"]
+        patterns = ["/banner/.*\\.png", "a{2}"]
+        "#).unwrap();
+        let (rules, errors) = config_rules(&config);
+        assert_eq!(3, rules.len());
+        assert_eq!(2, errors.len());
+        assert_eq!("bad domain", errors[0].source);
+        assert_eq!("a{2}", errors[1].source);
+    }
+}
@@ -0,0 +1,233 @@
+//! A local control socket accepting commands from other processes, configured
+//! using `general.control-socket`
+//!
+//! The protocol is line-based: each line received is run as command bar text
+//! and answered with a single line of JSON describing the result, such as
+//! `{"success":true,"error":null,"message":"Saved"}`. Lines beginning with
+//! `@open ` instead open the remaining text as a URI in a new window, which is
+//! used to forward URIs from a second instance of the application.
+use std::fs::{self,DirBuilder,Permissions};
+use std::io::{self,BufRead,BufReader,Write};
+use std::os::unix::fs::{DirBuilderExt,FileTypeExt,PermissionsExt};
+use std::os::unix::net::{UnixListener,UnixStream};
+use std::path::Path;
+use std::process;
+use std::sync::{Arc,Mutex};
+use std::thread;
+
+use contentblock::escape_json;
+use ui::{CommandError,CommandOutput};
+
+/// Socket file permissions, restricting access to the current user
+const SOCKET_MODE: u32 = 0o600;
+/// Permissions of the directory the socket is created in before being moved
+/// into place, so that no other user can connect in the meantime
+const BIND_DIR_MODE: u32 = 0o700;
+/// The prefix of requests to open a URI
+const OPEN_PREFIX: &'static str = "@open ";
+
+/// A line received on the control socket
+#[derive(Debug,Clone,PartialEq)]
+pub enum Request {
+    /// Run command bar text
+    Command(String),
+    /// Open a URI in a new window
+    Open(String),
+}
+
+impl Request {
+
+    /// Parse a line received on the control socket
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::control::Request;
+    ///
+    /// assert_eq!(Request::Open(String::from("example.com")), Request::parse("@open example.com"));
+    /// assert_eq!(Request::Command(String::from("go example.com")), Request::parse(" go example.com"));
+    /// ```
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        match line.starts_with(OPEN_PREFIX) {
+            true => Request::Open(String::from(line[OPEN_PREFIX.len() ..].trim())),
+            false => Request::Command(String::from(line)),
+        }
+    }
+}
+
+/// Open URIs in an instance of the application listening on a control
+/// socket. Fails if no instance is listening.
+pub fn forward_uris(path: &str, uris: &[String]) -> io::Result<()> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut responses = BufReader::new(stream.try_clone()?).lines();
+    for uri in uris {
+        writeln!(stream, "{}{}", OPEN_PREFIX, uri)?;
+        match responses.next() {
+            Some(response) => info!("Forwarded {}: {}", uri, response?),
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "control socket closed")),
+        }
+    }
+    Ok(())
+}
+
+/// Listen for connections on a Unix domain socket on a background thread,
+/// replacing any existing socket at the path. Fails if the path exists and is
+/// not a socket, or if another instance is listening on it. Each line
+/// received is passed to `handler` and the returned output is written back
+/// as JSON. Commands from all connections are handled one at a time.
+pub fn listen<F>(path: &str, handler: F) -> io::Result<()>
+    where F: Fn(&str) -> CommandOutput + Send + 'static {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "path exists and is not a socket"));
+        } else if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another instance is listening"));
+        },
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+    let listener = bind_private(path)?;
+    info!("Listening for commands on {}", path);
+    let handler = Arc::new(Mutex::new(handler));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &handler) {
+                            warn!("Control connection failed: {}", err);
+                        }
+                    });
+                },
+                Err(err) => warn!("Failed to accept control connection: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Bind a socket inside a new directory only accessible by the current user,
+/// then move it to the path, replacing any existing socket
+fn bind_private(path: &str) -> io::Result<UnixListener> {
+    let dir = format!("{}.{}", path, process::id());
+    DirBuilder::new().mode(BIND_DIR_MODE).create(&dir)?;
+    let temp_path = Path::new(&dir).join("socket");
+    let result = UnixListener::bind(&temp_path).and_then(|listener| {
+        fs::set_permissions(&temp_path, Permissions::from_mode(SOCKET_MODE))?;
+        fs::rename(&temp_path, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&temp_path);
+    let _ = fs::remove_dir(&dir);
+    result
+}
+
+fn serve<F>(stream: UnixStream, handler: &Mutex<F>) -> io::Result<()>
+    where F: Fn(&str) -> CommandOutput {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+        info!("Received control command: {}", text);
+        let output = match handler.lock() {
+            Ok(handler) => handler(text),
+            Err(_) => CommandOutput { error: Some(CommandError::ErrorDuringExecution), message: None },
+        };
+        writeln!(writer, "{}", encode_output(&output))?;
+    }
+    Ok(())
+}
+
+/// Encode the result of a command as a single line of JSON
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::control::encode_output;
+/// use webkitten::ui::{CommandError,CommandOutput};
+///
+/// let output = CommandOutput { error: None, message: Some(String::from("a \"b\"")) };
+/// assert_eq!(r#"{"success":true,"error":null,"message":"a \"b\""}"#, encode_output(&output));
+/// let output = CommandOutput { error: Some(CommandError::CommandNotFound), message: None };
+/// assert_eq!(r#"{"success":false,"error":"CommandNotFound","message":null}"#, encode_output(&output));
+/// ```
+pub fn encode_output(output: &CommandOutput) -> String {
+    format!("{{\"success\":{},\"error\":{},\"message\":{}}}",
+            output.error.is_none(),
+            encode_optional(output.error.as_ref().map(error_name)),
+            encode_optional(output.message.as_ref().map(|message| message.as_str())))
+}
+
+fn encode_optional(value: Option<&str>) -> String {
+    value.map(|value| format!("\"{}\"", escape_json(value)))
+        .unwrap_or(String::from("null"))
+}
+
+fn error_name(error: &CommandError) -> &'static str {
+    match *error {
+        CommandError::CommandNotFound => "CommandNotFound",
+        CommandError::ErrorDuringExecution => "ErrorDuringExecution",
+        CommandError::InvalidArguments => "InvalidArguments",
+        CommandError::NoCommandSpecified => "NoCommandSpecified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn answer_each_line() {
+        let path = env::temp_dir().join("webkitten-control-test.sock");
+        let path = path.to_str().unwrap();
+        listen(path, |text| CommandOutput {
+            error: None,
+            message: Some(text.to_uppercase()),
+        }).unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(SOCKET_MODE, mode & 0o777);
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(b"go home\n\nback\n").unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(r#"{"success":true,"error":null,"message":"GO HOME"}"#, lines.next().unwrap().unwrap());
+        assert_eq!(r#"{"success":true,"error":null,"message":"BACK"}"#, lines.next().unwrap().unwrap());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn keep_existing_file() {
+        let path = env::temp_dir().join("webkitten-control-file.sock");
+        let path = path.to_str().unwrap();
+        fs::write(path, "notes").unwrap();
+        assert!(listen(path, |_| CommandOutput { error: None, message: None }).is_err());
+        assert_eq!("notes", fs::read_to_string(path).unwrap());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn keep_listening_socket() {
+        let path = env::temp_dir().join("webkitten-control-busy.sock");
+        let path = path.to_str().unwrap();
+        listen(path, |_| CommandOutput { error: None, message: Some(String::from("first")) }).unwrap();
+        assert!(listen(path, |_| CommandOutput { error: None, message: None }).is_err());
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(b"status\n").unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(r#"{"success":true,"error":null,"message":"first"}"#, lines.next().unwrap().unwrap());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn forward_uris_without_listener() {
+        let path = env::temp_dir().join("webkitten-control-missing.sock");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        assert!(forward_uris(path, &[String::from("example.com")]).is_err());
+    }
+}
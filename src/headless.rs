@@ -0,0 +1,1060 @@
+//! An implementation of `ApplicationUI` without a GUI, enabled using the
+//! `headless` feature
+//!
+//! Windows and webviews are kept in memory, so the engine can dispatch
+//! commands and buffer events exactly as it does for a GUI binding. Loading a
+//! URI succeeds immediately, invoking the `Request` and `Load` events, and
+//! pages respond to queries such as `query_selector_all` immediately. Each
+//! call which changes the UI is recorded as text, such as
+//! `set_uri(0, 0, https://example.com)`, for tests to check using the
+//! assertion helpers.
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::time::{Duration,Instant};
+
+use command::CommandExplanation;
+use config::Config;
+use script::ScriptingEngine;
+use ui::*;
+use optparse::RunConfiguration;
+use Engine;
+
+/// The page loaded in webviews opened without a URI when
+/// `window.start-page` is unset
+const BLANK_PAGE: &'static str = "about:blank";
+/// The size of the screen reported by `screen_frame`
+const SCREEN_FRAME: WindowFrame = WindowFrame { x: 0, y: 0, width: 1440, height: 900 };
+
+/// A UI holding windows and webviews in memory
+pub struct HeadlessUI<S: ScriptingEngine> {
+    pub engine: Engine,
+    windows: Mutex<Vec<Window>>,
+    focused_window: Mutex<Option<u32>>,
+    calls: Mutex<Vec<String>>,
+    dialog_response: Mutex<DialogResponse>,
+    engine_type: PhantomData<S>,
+}
+
+struct Window {
+    webviews: Vec<WebView>,
+    focused_webview: Option<u32>,
+    title: String,
+    represented_uri: Option<String>,
+    command_text: String,
+    command_visible: bool,
+    minimized: bool,
+    frame: WindowFrame,
+    private: bool,
+}
+
+struct WebView {
+    uri: String,
+    back: Vec<String>,
+    forward: Vec<String>,
+    private_browsing: bool,
+    focused_element: Option<ElementInfo>,
+    fullscreen: bool,
+    elements: HashMap<String, Vec<ElementInfo>>,
+    document_kind: DocumentKind,
+    find_options: FindOptions,
+    created: Instant,
+    /// When the webview was last focused or left focus
+    last_focused: Instant,
+}
+
+impl<S: ScriptingEngine> HeadlessUI<S> {
+
+    /// Create a UI using the configuration file at a path, which is created
+    /// with the default configuration if missing
+    pub fn with_config_path(path: &str) -> Option<Self> {
+        Engine::new(RunConfiguration {
+            path: String::from(path),
+            start_pages: vec![],
+            command: None,
+            exit_status: None,
+        }).and_then(|engine| <Self as ApplicationUI<S>>::new(engine))
+    }
+
+    /// Every recorded call, oldest first
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().map(|calls| calls.clone()).unwrap_or(vec![])
+    }
+
+    /// Forget the recorded calls
+    pub fn clear_calls(&self) {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.clear();
+        }
+    }
+
+    /// Whether any recorded call has a method name, such as `run_javascript`
+    pub fn called(&self, name: &str) -> bool {
+        let prefix = format!("{}(", name);
+        self.calls().iter().any(|call| call.starts_with(&prefix))
+    }
+
+    /// Panic unless a call matching the text was recorded
+    pub fn assert_called(&self, call: &str) {
+        let calls = self.calls();
+        assert!(calls.iter().any(|recorded| recorded == call),
+                "expected call {}, recorded: {:?}", call, calls);
+    }
+
+    /// Panic if a call matching the text was recorded
+    pub fn assert_not_called(&self, call: &str) {
+        let calls = self.calls();
+        assert!(!calls.iter().any(|recorded| recorded == call),
+                "unexpected call {}, recorded: {:?}", call, calls);
+    }
+
+    /// Panic unless a webview displays a URI
+    pub fn assert_uri(&self, window_index: u32, webview_index: u32, uri: &str) {
+        assert_eq!(uri, self.uri(window_index, webview_index),
+                   "URI of webview {} in window {}", webview_index, window_index);
+    }
+
+    /// Panic unless the command bar of a window contains text
+    pub fn assert_command_field_text(&self, window_index: u32, text: &str) {
+        assert_eq!(text, self.command_field_text(window_index),
+                   "command bar text of window {}", window_index);
+    }
+
+    /// Run command bar text in the focused window as if Return was pressed,
+    /// describing the result
+    pub fn submit_command(&self, text: &str) -> CommandOutput {
+        self.engine.execute_command_output::<Self, S>(self, text)
+    }
+
+    /// Open the initial pages and run command text as the `--execute` option
+    /// does, closing the engine afterward
+    pub fn execute(&self, text: &str) -> CommandOutput {
+        self.run();
+        let output = self.submit_command(text);
+        self.engine.close::<Self, S>(self);
+        output
+    }
+
+    /// Completions for command bar text
+    pub fn completions(&self, prefix: &str) -> Vec<String> {
+        self.engine.command_completions::<Self, S>(self, prefix)
+    }
+
+    /// Report a failure to load the URI of a webview to the engine
+    pub fn fail_load(&self, window_index: u32, webview_index: u32, message: &str) {
+        let uri = self.uri(window_index, webview_index);
+        self.engine.on_buffer_event::<Self, S>(self, window_index, webview_index, Some(&uri),
+                                               BufferEvent::Fail(String::from(message)));
+    }
+
+    /// Focus an element in the page of a webview, or remove focus from the
+    /// page if `None`, reporting the change to the engine
+    pub fn focus_element(&self, window_index: u32, webview_index: u32, element: Option<ElementInfo>) {
+        self.with_webview(window_index, webview_index, |webview| webview.focused_element = element.clone());
+        self.engine.on_focus_element_change::<Self, S>(self, window_index, webview_index, element.as_ref());
+    }
+
+    /// Set the elements in the page of a webview matching a selector until
+    /// another page is loaded
+    pub fn set_query_result(&self, window_index: u32, webview_index: u32, selector: &str, elements: Vec<ElementInfo>) {
+        self.with_webview(window_index, webview_index, |webview| {
+            webview.elements.insert(String::from(selector), elements)
+        });
+    }
+
+    /// Set the kind of document loaded in a webview until another page is
+    /// loaded
+    pub fn set_document_kind(&self, window_index: u32, webview_index: u32, kind: DocumentKind) {
+        self.with_webview(window_index, webview_index, |webview| webview.document_kind = kind);
+    }
+
+    /// Display an element in the page of a webview fullscreen, or return it
+    /// to the page, reporting the change to the engine
+    pub fn set_element_fullscreen(&self, window_index: u32, webview_index: u32, fullscreen: bool) {
+        let changed = self.with_webview(window_index, webview_index, |webview| {
+            let changed = webview.fullscreen != fullscreen;
+            webview.fullscreen = fullscreen;
+            changed
+        }).unwrap_or(false);
+        if changed {
+            self.engine.on_fullscreen_change::<Self, S>(self, window_index, webview_index, fullscreen);
+        }
+    }
+
+    /// Set the answer given to JavaScript dialogs which are displayed,
+    /// which is `DialogResponse::Dismiss` by default
+    pub fn set_dialog_response(&self, response: DialogResponse) {
+        if let Ok(mut dialog_response) = self.dialog_response.lock() {
+            *dialog_response = response;
+        }
+    }
+
+    /// Open a JavaScript dialog from the page in a webview, returning the
+    /// answer
+    pub fn open_dialog(&self, window_index: u32, webview_index: u32, kind: DialogKind, message: &str) -> DialogResponse {
+        self.engine.on_js_dialog::<Self, S>(self, window_index, webview_index, kind, message)
+    }
+
+    fn record(&self, call: String) {
+        info!("{}", call);
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(call);
+        }
+    }
+
+    /// Apply a change to a window, returning `None` if it does not exist
+    fn with_window<F, R>(&self, window_index: u32, change: F) -> Option<R>
+        where F: FnOnce(&mut Window) -> R {
+        self.windows.lock().ok()
+            .and_then(|mut windows| windows.get_mut(window_index as usize).map(change))
+    }
+
+    /// Apply a change to a webview, returning `None` if it does not exist
+    fn with_webview<F, R>(&self, window_index: u32, webview_index: u32, change: F) -> Option<R>
+        where F: FnOnce(&mut WebView) -> R {
+        self.with_window(window_index, |window| {
+            window.webviews.get_mut(webview_index as usize).map(change)
+        }).and_then(|result| result)
+    }
+
+    /// Report a URI loading in a webview to the engine
+    fn finish_load(&self, window_index: u32, webview_index: u32, uri: &str, navigation_type: NavigationType) {
+        self.with_webview(window_index, webview_index, |webview| {
+            webview.focused_element = None;
+            webview.elements.clear();
+            webview.document_kind = DocumentKind::Other;
+        });
+        self.engine.on_buffer_event::<Self, S>(self, window_index, webview_index, Some(uri),
+                                               BufferEvent::Request(navigation_type));
+        self.engine.on_buffer_event::<Self, S>(self, window_index, webview_index, Some(uri),
+                                               BufferEvent::Load);
+    }
+
+    fn add_window(&self, uri: Option<String>, private: bool) -> u32 {
+        let window_index = match self.windows.lock() {
+            Ok(mut windows) => {
+                windows.push(Window {
+                    webviews: vec![],
+                    focused_webview: None,
+                    title: String::new(),
+                    represented_uri: None,
+                    command_text: String::new(),
+                    command_visible: self.engine.config.show_chrome(Chrome::CommandBar),
+                    minimized: false,
+                    frame: SCREEN_FRAME,
+                    private: private,
+                });
+                windows.len() as u32 - 1
+            },
+            Err(_) => return 0,
+        };
+        if let Ok(mut focused) = self.focused_window.lock() {
+            *focused = Some(window_index);
+        }
+        self.add_webview(window_index, uri);
+        window_index
+    }
+
+    fn add_webview(&self, window_index: u32, uri: Option<String>) {
+        let uri = uri.unwrap_or_else(|| self.start_page(window_index));
+        let private_browsing = self.engine.config.use_private_browsing(&uri);
+        let now = Instant::now();
+        let webview_index = self.with_window(window_index, |window| {
+            if let Some(focused) = window.focused_webview {
+                window.webviews[focused as usize].last_focused = now;
+            }
+            window.webviews.push(WebView {
+                uri: uri.clone(),
+                back: vec![],
+                forward: vec![],
+                private_browsing: private_browsing || window.private,
+                focused_element: None,
+                fullscreen: false,
+                elements: HashMap::new(),
+                document_kind: DocumentKind::Other,
+                find_options: FindOptions::default(),
+                created: now,
+                last_focused: now,
+            });
+            let webview_index = window.webviews.len() as u32 - 1;
+            window.focused_webview = Some(webview_index);
+            webview_index
+        });
+        if let Some(webview_index) = webview_index {
+            self.finish_load(window_index, webview_index, &uri, NavigationType::Other);
+        }
+    }
+
+    /// The page opened in an empty webview in a window
+    fn start_page(&self, window_index: u32) -> String {
+        let context = match self.with_window(window_index, |window| window.private) {
+            Some(true) => WindowContext::Private,
+            _ => WindowContext::Default,
+        };
+        self.engine.config.start_page(&context).unwrap_or(String::from(BLANK_PAGE))
+    }
+
+    /// Move through the history of a webview, returning the URI loaded
+    fn traverse(&self, window_index: u32, webview_index: u32, forward: bool) -> Option<String> {
+        self.with_webview(window_index, webview_index, |webview| {
+            let uri = match forward {
+                true => webview.forward.pop(),
+                false => webview.back.pop(),
+            };
+            uri.map(|uri| {
+                let previous = webview.uri.clone();
+                match forward {
+                    true => webview.back.push(previous),
+                    false => webview.forward.push(previous),
+                }
+                webview.uri = uri.clone();
+                uri
+            })
+        }).and_then(|uri| uri)
+    }
+}
+
+impl<S: ScriptingEngine> ApplicationUI<S> for HeadlessUI<S> {
+
+    fn new(engine: Engine) -> Option<Self> {
+        Some(HeadlessUI {
+            engine: engine,
+            windows: Mutex::new(vec![]),
+            focused_window: Mutex::new(None),
+            calls: Mutex::new(vec![]),
+            dialog_response: Mutex::new(DialogResponse::Dismiss),
+            engine_type: PhantomData,
+        })
+    }
+
+    fn run(&self) {
+        self.record(String::from("run()"));
+        let pages = self.engine.initial_pages().clone();
+        let mut pages = pages.into_iter();
+        let window_index = self.open_window::<_, Config>(pages.next(), None);
+        for uri in pages {
+            self.queue_webview(window_index, &uri);
+        }
+        self.engine.on_startup::<Self, S>(self);
+    }
+
+    fn copy(&self, text: &str) {
+        self.record(format!("copy({})", text));
+    }
+
+    fn reveal_in_file_manager(&self, path: &str) {
+        self.record(format!("reveal_in_file_manager({})", path));
+    }
+
+    fn notify(&self, title: &str, body: &str) {
+        if self.engine.config.notifications() {
+            self.record(format!("notify({}, {})", title, body));
+        }
+    }
+
+    fn execute_command(&self, window_index: Option<u32>, text: &str) {
+        self.engine.execute_command::<Self, S>(self, window_index, text);
+    }
+
+    fn execute_command_in_all_windows(&self, text: &str) -> Vec<(u32, CommandError)> {
+        self.engine.execute_in_all_windows::<Self, S>(self, text)
+    }
+
+    fn focused_window_index(&self) -> Option<u32> {
+        self.focused_window.lock().ok().and_then(|focused| *focused)
+    }
+
+    fn window_count(&self) -> u32 {
+        self.windows.lock().map(|windows| windows.len() as u32).unwrap_or(0)
+    }
+
+    fn window_exists(&self, window_index: u32) -> bool {
+        window_index < self.window_count()
+    }
+
+    fn open_window<U, B>(&self, uri: Option<U>, _config: Option<B>) -> u32
+        where U: Into<String>,
+              B: BrowserConfiguration {
+        let uri = uri.map(|uri| uri.into());
+        self.record(format!("open_window({})", uri.as_ref().map(|uri| uri.as_str()).unwrap_or("")));
+        self.add_window(uri, false)
+    }
+
+    fn open_private_window(&self) -> u32 {
+        self.record(String::from("open_private_window()"));
+        self.add_window(None, true)
+    }
+
+    fn close_window(&self, index: u32) {
+        self.record(format!("close_window({})", index));
+        let hidden_chrome = self.hidden_chrome(index);
+        let closed = self.windows.lock().ok().and_then(|mut windows| {
+            match (index as usize) < windows.len() {
+                true => Some(windows.remove(index as usize)),
+                false => None,
+            }
+        });
+        if let Some(window) = closed {
+            if !window.private {
+                let uris = window.webviews.into_iter().map(|webview| webview.uri).collect();
+                self.engine.record_closed_window(uris, hidden_chrome);
+            }
+            let count = self.window_count();
+            if let Ok(mut focused) = self.focused_window.lock() {
+                *focused = if count > 0 { Some(count - 1) } else { None };
+            }
+        }
+    }
+
+    fn reopen_closed_window(&self) -> Option<u32> {
+        self.engine.take_closed_window().and_then(|closed| {
+            let hidden_chrome = closed.hidden_chrome;
+            let mut uris = closed.uris.into_iter();
+            uris.next().map(|uri| {
+                let window_index = self.open_window::<_, Config>(Some(uri), None);
+                for uri in uris {
+                    self.open_webview::<_, Config>(window_index, Some(uri), None);
+                }
+                for component in ALL_CHROME.iter() {
+                    let visible = !hidden_chrome.contains(component);
+                    self.set_chrome_visible(window_index, *component, visible);
+                }
+                window_index
+            })
+        })
+    }
+
+    fn focus_window(&self, index: u32) {
+        self.record(format!("focus_window({})", index));
+        if self.window_exists(index) {
+            if let Ok(mut focused) = self.focused_window.lock() {
+                *focused = Some(index);
+            }
+        }
+    }
+
+    fn focus_window_area(&self, index: u32, area: WindowArea) {
+        self.record(format!("focus_window_area({}, {:?})", index, area));
+        if area == WindowArea::CommandBar {
+            self.with_window(index, |window| window.command_visible = true);
+        }
+    }
+
+    fn toggle_window(&self, index: u32, visible: bool) {
+        self.record(format!("toggle_window({}, {})", index, visible));
+    }
+
+    fn resize_window(&self, window_index: u32, width: u32, height: u32) {
+        self.record(format!("resize_window({}, {}, {})", window_index, width, height));
+        self.with_window(window_index, |window| {
+            window.frame.width = width;
+            window.frame.height = height;
+        });
+    }
+
+    fn screen_frame(&self) -> Option<WindowFrame> {
+        Some(SCREEN_FRAME)
+    }
+
+    fn set_window_frame(&self, window_index: u32, frame: WindowFrame) {
+        self.record(format!("set_window_frame({}, {:?})", window_index, frame));
+        self.with_window(window_index, |window| window.frame = frame);
+    }
+
+    fn window_minimized(&self, window_index: u32) -> bool {
+        self.with_window(window_index, |window| window.minimized).unwrap_or(false)
+    }
+
+    fn minimize_window(&self, window_index: u32) {
+        self.record(format!("minimize_window({})", window_index));
+        self.with_window(window_index, |window| window.minimized = true);
+    }
+
+    fn restore_window(&self, window_index: u32) {
+        self.record(format!("restore_window({})", window_index));
+        self.with_window(window_index, |window| window.minimized = false);
+    }
+
+    fn command_field_text(&self, window_index: u32) -> String {
+        self.with_window(window_index, |window| window.command_text.clone())
+            .unwrap_or(String::new())
+    }
+
+    fn set_command_field_text(&self, window_index: u32, text: &str) {
+        self.record(format!("set_command_field_text({}, {})", window_index, text));
+        self.with_window(window_index, |window| window.command_text = String::from(text));
+    }
+
+    fn command_field_visible(&self, window_index: u32) -> bool {
+        self.with_window(window_index, |window| window.command_visible).unwrap_or(false)
+    }
+
+    fn set_command_field_visible(&self, window_index: u32, visible: bool) {
+        self.record(format!("set_command_field_visible({}, {})", window_index, visible));
+        self.with_window(window_index, |window| window.command_visible = visible);
+    }
+
+    fn window_title(&self, window_index: u32) -> String {
+        self.with_window(window_index, |window| window.title.clone())
+            .unwrap_or(String::new())
+    }
+
+    fn set_window_title(&self, window_index: u32, title: &str) {
+        self.record(format!("set_window_title({}, {})", window_index, title));
+        self.with_window(window_index, |window| window.title = String::from(title));
+    }
+
+    fn represented_uri(&self, window_index: u32) -> Option<String> {
+        self.with_window(window_index, |window| window.represented_uri.clone())
+            .and_then(|uri| uri)
+    }
+
+    fn set_represented_uri(&self, window_index: u32, uri: Option<&str>) {
+        self.record(format!("set_represented_uri({}, {:?})", window_index, uri));
+        self.with_window(window_index, |window| window.represented_uri = uri.map(String::from));
+    }
+
+    fn focused_webview_index(&self, window_index: u32) -> Option<u32> {
+        self.with_window(window_index, |window| window.focused_webview)
+            .and_then(|index| index)
+    }
+
+    fn webview_count(&self, window_index: u32) -> u32 {
+        self.with_window(window_index, |window| window.webviews.len() as u32)
+            .unwrap_or(0)
+    }
+
+    fn webview_age(&self, window_index: u32, webview_index: u32) -> Duration {
+        self.with_webview(window_index, webview_index, |webview| webview.created.elapsed())
+            .unwrap_or_default()
+    }
+
+    fn webview_idle(&self, window_index: u32, webview_index: u32) -> Duration {
+        if self.focused_webview_index(window_index) == Some(webview_index) {
+            return Duration::default();
+        }
+        self.with_webview(window_index, webview_index, |webview| webview.last_focused.elapsed())
+            .unwrap_or_default()
+    }
+
+    fn all_webviews(&self) -> Vec<(u32, u32)> {
+        let windows = match self.windows.lock() {
+            Ok(windows) => windows,
+            Err(_) => return vec![],
+        };
+        windows.iter().enumerate()
+            .flat_map(|(window_index, window)| {
+                (0 .. window.webviews.len()).map(move |webview_index| (window_index as u32, webview_index as u32))
+            })
+            .collect()
+    }
+
+    fn open_webview<'a, U, B>(&self, window_index: u32, uri: Option<U>, _config: Option<B>)
+        where U: Into<String>,
+              B: BrowserConfiguration {
+        let uri = uri.map(|uri| uri.into());
+        self.record(format!("open_webview({}, {})", window_index,
+                            uri.as_ref().map(|uri| uri.as_str()).unwrap_or("")));
+        self.add_webview(window_index, uri);
+    }
+
+    fn queue_webview(&self, window_index: u32, uri: &str) {
+        self.engine.queue_webview::<Self, S>(self, window_index, uri);
+    }
+
+    fn close_webview(&self, window_index: u32, webview_index: u32) {
+        self.record(format!("close_webview({}, {})", window_index, webview_index));
+        self.with_window(window_index, |window| {
+            if (webview_index as usize) < window.webviews.len() {
+                window.webviews.remove(webview_index as usize);
+                let count = window.webviews.len() as u32;
+                window.focused_webview = match window.focused_webview {
+                    _ if count == 0 => None,
+                    Some(focused) if focused >= count => Some(count - 1),
+                    focused => focused,
+                };
+            }
+        });
+    }
+
+    fn move_webview(&self, window_index: u32, from: u32, to: u32) -> bool {
+        self.record(format!("move_webview({}, {}, {})", window_index, from, to));
+        self.with_window(window_index, |window| {
+            let count = window.webviews.len() as u32;
+            if from >= count || to >= count {
+                return false;
+            }
+            let webview = window.webviews.remove(from as usize);
+            window.webviews.insert(to as usize, webview);
+            window.focused_webview = window.focused_webview.map(|focused| match focused {
+                _ if focused == from => to,
+                _ if from < focused && focused <= to => focused - 1,
+                _ if to <= focused && focused < from => focused + 1,
+                _ => focused,
+            });
+            true
+        }).unwrap_or(false)
+    }
+
+    fn focus_webview(&self, window_index: u32, webview_index: u32) {
+        self.record(format!("focus_webview({}, {})", window_index, webview_index));
+        if !self.webview_exists(window_index, webview_index) {
+            return;
+        }
+        let now = Instant::now();
+        self.with_window(window_index, |window| {
+            if let Some(focused) = window.focused_webview {
+                window.webviews[focused as usize].last_focused = now;
+            }
+            window.webviews[webview_index as usize].last_focused = now;
+            window.focused_webview = Some(webview_index);
+        });
+        self.engine.on_buffer_event::<Self, S>(self, window_index, webview_index, None, BufferEvent::Focus);
+    }
+
+    fn reload_webview(&self, window_index: u32, webview_index: u32, disable_filters: bool) {
+        self.record(format!("reload_webview({}, {}, {})", window_index, webview_index, disable_filters));
+        if self.webview_exists(window_index, webview_index) {
+            let uri = self.uri(window_index, webview_index);
+            self.finish_load(window_index, webview_index, &uri, NavigationType::Reload);
+        }
+    }
+
+    fn stop_webview(&self, window_index: u32, webview_index: u32) -> bool {
+        self.record(format!("stop_webview({}, {})", window_index, webview_index));
+        false
+    }
+
+    fn reset_webview(&self, window_index: u32, webview_index: u32) -> bool {
+        self.record(format!("reset_webview({}, {})", window_index, webview_index));
+        let uri = self.start_page(window_index);
+        let reset = self.with_webview(window_index, webview_index, |webview| {
+            webview.back.clear();
+            webview.forward.clear();
+            webview.uri = uri.clone();
+        }).is_some();
+        if reset {
+            self.finish_load(window_index, webview_index, &uri, NavigationType::Other);
+        }
+        reset
+    }
+
+    fn set_uri(&self, window_index: u32, webview_index: u32, uri: &str) {
+        self.record(format!("set_uri({}, {}, {})", window_index, webview_index, uri));
+        let loaded = self.with_webview(window_index, webview_index, |webview| {
+            let previous = webview.uri.clone();
+            webview.back.push(previous);
+            webview.forward.clear();
+            webview.uri = String::from(uri);
+        }).is_some();
+        if loaded {
+            self.finish_load(window_index, webview_index, uri, NavigationType::Other);
+        }
+    }
+
+    fn load_html(&self, window_index: u32, webview_index: u32, html: &str, base_uri: Option<&str>) -> bool {
+        self.record(format!("load_html({}, {}, {}, {:?})", window_index, webview_index, html, base_uri));
+        let uri = base_uri.unwrap_or(BLANK_PAGE);
+        let loaded = self.with_webview(window_index, webview_index, |webview| {
+            let previous = webview.uri.clone();
+            webview.back.push(previous);
+            webview.forward.clear();
+            webview.uri = String::from(uri);
+        }).is_some();
+        if loaded {
+            self.finish_load(window_index, webview_index, uri, NavigationType::Other);
+        }
+        loaded
+    }
+
+    fn go_back(&self, window_index: u32, webview_index: u32) -> bool {
+        self.record(format!("go_back({}, {})", window_index, webview_index));
+        match self.traverse(window_index, webview_index, false) {
+            Some(uri) => {
+                self.finish_load(window_index, webview_index, &uri, NavigationType::BackForward);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn go_forward(&self, window_index: u32, webview_index: u32) -> bool {
+        self.record(format!("go_forward({}, {})", window_index, webview_index));
+        match self.traverse(window_index, webview_index, true) {
+            Some(uri) => {
+                self.finish_load(window_index, webview_index, &uri, NavigationType::BackForward);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn can_go_back(&self, window_index: u32, webview_index: u32) -> bool {
+        self.with_webview(window_index, webview_index, |webview| !webview.back.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn can_go_forward(&self, window_index: u32, webview_index: u32) -> bool {
+        self.with_webview(window_index, webview_index, |webview| !webview.forward.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn uri(&self, window_index: u32, webview_index: u32) -> String {
+        self.with_webview(window_index, webview_index, |webview| webview.uri.clone())
+            .unwrap_or(String::new())
+    }
+
+    fn find_string_with_options(&self, window_index: u32, webview_index: u32, query: &str, options: FindOptions) {
+        self.with_webview(window_index, webview_index, |webview| webview.find_options = options);
+        self.record(format!("find_string({}, {}, {}, {:?})", window_index, webview_index, query, options));
+    }
+
+    fn find_options(&self, window_index: u32, webview_index: u32) -> FindOptions {
+        self.with_webview(window_index, webview_index, |webview| webview.find_options)
+            .unwrap_or(FindOptions::default())
+    }
+
+    fn hide_find_results(&self, window_index: u32, webview_index: u32) {
+        self.record(format!("hide_find_results({}, {})", window_index, webview_index));
+    }
+
+    fn set_find_highlight(&self, window_index: u32, webview_index: u32, color: Option<&str>) -> bool {
+        self.record(format!("set_find_highlight({}, {}, {})", window_index, webview_index,
+                            color.unwrap_or("")));
+        self.webview_exists(window_index, webview_index)
+    }
+
+    fn webview_title(&self, window_index: u32, webview_index: u32) -> String {
+        self.uri(window_index, webview_index)
+    }
+
+    fn run_javascript(&self, window_index: u32, webview_index: u32, script: &str) {
+        self.record(format!("run_javascript({}, {}, {})", window_index, webview_index, script));
+    }
+
+    fn inject_user_script(&self, window_index: u32, webview_index: u32, script: &str, at_start: bool) {
+        self.record(format!("inject_user_script({}, {}, {}, {})", window_index, webview_index,
+                            script, at_start));
+    }
+
+    fn clear_user_scripts(&self, window_index: u32, webview_index: u32) {
+        self.record(format!("clear_user_scripts({}, {})", window_index, webview_index));
+    }
+
+    fn save_page(&self, window_index: u32, webview_index: u32, path: &str, format: SaveFormat) -> bool {
+        self.record(format!("save_page({}, {}, {}, {:?})", window_index, webview_index, path, format));
+        false
+    }
+
+    fn set_custom_headers(&self, window_index: u32, webview_index: u32, headers: &[(String, String)]) {
+        self.record(format!("set_custom_headers({}, {}, {:?})", window_index, webview_index, headers));
+    }
+
+    fn page_statistics(&self, _window_index: u32, _webview_index: u32) -> Option<PageStats> {
+        None
+    }
+
+    fn page_timing(&self, _window_index: u32, _webview_index: u32) -> PageTiming {
+        PageTiming::default()
+    }
+
+    fn webview_process_stats(&self, _window_index: u32, _webview_index: u32) -> ProcessStats {
+        ProcessStats::default()
+    }
+
+    fn focused_element(&self, window_index: u32, webview_index: u32) -> Option<ElementInfo> {
+        self.with_webview(window_index, webview_index, |webview| webview.focused_element.clone())
+            .and_then(|element| element)
+    }
+
+    fn fill_form_fields(&self, window_index: u32, webview_index: u32, values: &[(String, String)],
+                        command: &str) -> bool {
+        let elements = match self.with_webview(window_index, webview_index, |webview| webview.elements.clone()) {
+            Some(elements) => elements,
+            None => return false,
+        };
+        let count = values.iter()
+            .filter_map(|&(ref key, _)| elements.get(&autofill_selector(key)))
+            .map(|fields| fields.len())
+            .sum();
+        self.run_javascript(window_index, webview_index, &fill_form_script(values));
+        self.engine.on_page_event::<Self, S>(self, command, window_index, webview_index,
+                                             PageEvent::FormFilled(count));
+        true
+    }
+
+    fn query_selector_all(&self, window_index: u32, webview_index: u32, selector: &str, command: &str) -> bool {
+        let elements = match self.with_webview(window_index, webview_index, |webview| webview.elements.get(selector).cloned()) {
+            Some(elements) => elements.unwrap_or(vec![]),
+            None => return false,
+        };
+        self.engine.on_page_event::<Self, S>(self, command, window_index, webview_index,
+                                             PageEvent::ElementsQueried(String::from(selector), elements));
+        true
+    }
+
+    fn fit_to_window(&self, window_index: u32, webview_index: u32) -> bool {
+        let kind = self.with_webview(window_index, webview_index, |webview| webview.document_kind);
+        if kind.map(|kind| kind.can_fit()).unwrap_or(false) {
+            self.record(format!("fit_to_window({}, {})", window_index, webview_index));
+            return true;
+        }
+        false
+    }
+
+    fn show_dialog(&self, window_index: u32, webview_index: u32, kind: &DialogKind, message: &str) -> DialogResponse {
+        self.record(format!("show_dialog({}, {}, {}, {})", window_index, webview_index, kind.name(), message));
+        self.dialog_response.lock()
+            .map(|response| response.clone())
+            .unwrap_or(DialogResponse::Dismiss)
+    }
+
+    fn is_element_fullscreen(&self, window_index: u32, webview_index: u32) -> bool {
+        self.with_webview(window_index, webview_index, |webview| webview.fullscreen)
+            .unwrap_or(false)
+    }
+
+    fn input_mode(&self, window_index: u32, webview_index: u32) -> InputMode {
+        self.engine.input_mode(window_index, webview_index)
+    }
+
+    fn set_input_mode(&self, window_index: u32, webview_index: u32, mode: InputMode) -> bool {
+        self.record(format!("set_input_mode({}, {}, {})", window_index, webview_index, mode.name()));
+        self.engine.set_input_mode::<Self, S>(self, window_index, webview_index, mode)
+    }
+
+    fn private_browsing(&self, window_index: u32, webview_index: u32) -> bool {
+        self.with_webview(window_index, webview_index, |webview| webview.private_browsing)
+            .unwrap_or(false)
+    }
+
+    fn set_private_browsing(&self, window_index: u32, webview_index: u32, enabled: bool) -> bool {
+        self.record(format!("set_private_browsing({}, {}, {})", window_index, webview_index, enabled));
+        let uri = self.uri(window_index, webview_index);
+        if uri.is_empty() || !self.engine.set_site_private_browsing(&uri, enabled) {
+            return false;
+        }
+        self.with_webview(window_index, webview_index, |webview| webview.private_browsing = enabled);
+        true
+    }
+
+    fn apply_styles(&self, window_index: u32, webview_index: u32, styles: &str) {
+        self.record(format!("apply_styles({}, {}, {})", window_index, webview_index, styles));
+    }
+
+    fn apply_styles_all(&self, styles: &str) {
+        self.record(format!("apply_styles_all({})", styles));
+    }
+
+    fn set_cache_mode(&self, mode: CacheMode) {
+        self.record(format!("set_cache_mode({:?})", mode));
+    }
+
+    fn scroll_to(&self, window_index: u32, webview_index: u32, x: f64, y: f64) {
+        self.record(format!("scroll_to({}, {}, {}, {})", window_index, webview_index, x, y));
+    }
+
+    fn set_color_scheme(&self, window_index: u32, webview_index: u32, scheme: ColorScheme) {
+        self.record(format!("set_color_scheme({}, {}, {:?})", window_index, webview_index, scheme));
+    }
+
+    fn reload_stylesheets(&self) {
+        self.record(String::from("reload_stylesheets()"));
+    }
+
+    fn reload_config(&self) -> bool {
+        self.record(String::from("reload_config()"));
+        self.engine.reload()
+    }
+
+    fn reload_filters(&self, window_index: Option<u32>) -> bool {
+        self.engine.reload_filters::<Self, S>(self, window_index)
+    }
+
+    fn explain_command(&self, text: &str) -> CommandExplanation {
+        self.engine.explain_command::<S>(text)
+    }
+
+    fn commands_for_uri_event(&self, event: &BufferEvent, uri: &str) -> Vec<String> {
+        self.engine.commands_for_uri_event::<S>(event, Some(uri))
+    }
+
+    fn set_command_disabled(&self, name: &str, disabled: bool) -> bool {
+        self.record(format!("set_command_disabled({}, {})", name, disabled));
+        self.engine.set_command_disabled::<S>(name, disabled)
+    }
+
+    fn kv_get(&self, namespace: &str, key: &str) -> Option<String> {
+        self.engine.kv_get(namespace, key)
+    }
+
+    fn kv_set(&self, namespace: &str, key: &str, value: Option<&str>) -> bool {
+        self.record(format!("kv_set({}, {}, {:?})", namespace, key, value));
+        self.engine.kv_set(namespace, key, value)
+    }
+
+    fn caret_browsing(&self) -> bool {
+        self.engine.caret_browsing()
+    }
+
+    fn set_caret_browsing(&self, enabled: bool) {
+        self.record(format!("set_caret_browsing({})", enabled));
+        self.engine.set_caret_browsing(enabled);
+    }
+
+    fn set_content_filter(&self, filter: &str, window_index: Option<u32>) {
+        self.record(format!("set_content_filter({} bytes)", filter.len()));
+        self.engine.on_content_filter_compiled::<Self, S>(self, window_index, filter, true);
+    }
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use std::env;
+    use std::fs;
+    use std::ops::Deref;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    use script::LuaEngine;
+    use super::HeadlessUI;
+
+    static NEXT_FIXTURE_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// A headless UI searching a new temporary directory for commands. The
+    /// directory is removed when the fixture is dropped.
+    pub struct TestFixture {
+        pub ui: HeadlessUI<LuaEngine>,
+        pub dir: PathBuf,
+    }
+
+    impl TestFixture {
+        /// Create a UI from `config`, in which `{dir}` is replaced with the
+        /// path of the command directory
+        pub fn new(config: &str) -> Self {
+            let id = NEXT_FIXTURE_ID.fetch_add(1, Ordering::SeqCst);
+            let dir = env::temp_dir().join(format!("webkitten-test-{}-{}", process::id(), id));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("config.toml");
+            let config = config.replace("{dir}", &dir.display().to_string());
+            fs::write(&path, format!("[commands]\nsearch-paths = [\"{}\"]\n{}", dir.display(), config)).unwrap();
+            let ui = HeadlessUI::with_config_path(path.to_str().unwrap()).unwrap();
+            TestFixture { ui, dir }
+        }
+
+        /// Write a Lua command named `name` into the command directory
+        pub fn write_command(&self, name: &str, script: &str) {
+            fs::write(self.dir.join(format!("{}.lua", name)), script).unwrap();
+        }
+    }
+
+    impl Deref for TestFixture {
+        type Target = HeadlessUI<LuaEngine>;
+
+        fn deref(&self) -> &HeadlessUI<LuaEngine> {
+            &self.ui
+        }
+    }
+
+    impl Drop for TestFixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::TestFixture;
+    use std::thread;
+
+    #[test]
+    fn run_command_script() {
+        let ui = TestFixture::new("");
+        ui.write_command("visit", r#"
+        function description() return "" end
+        function run()
+          load_uri_focused(arguments[1])
+          return true
+        end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert_eq!(None, ui.submit_command("visit https://example.org").error);
+        ui.assert_uri(0, 0, "https://example.org");
+        assert!(ui.can_go_back(0, 0));
+        assert!(ui.go_back(0, 0));
+        ui.assert_uri(0, 0, "https://example.com");
+    }
+
+    #[test]
+    fn run_load_event_commands() {
+        let ui = TestFixture::new("on-load-uri = [\"titled\"]");
+        ui.write_command("titled", r#"
+        function description() return "" end
+        function on_load_uri()
+          set_window_title(window_index, requested_uri)
+        end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert_eq!("https://example.com", ui.window_title(0));
+        ui.assert_called("set_window_title(0, https://example.com)");
+    }
+
+    #[test]
+    fn report_missing_command() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.clear_calls();
+        assert_eq!(Some(CommandError::CommandNotFound), ui.submit_command("missing").error);
+        assert!(!ui.called("set_uri"));
+    }
+
+    #[test]
+    fn reopen_closed_window() {
+        let ui = TestFixture::new("");
+        let window_index = ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.open_webview(window_index, Some("https://example.org"), None::<Config>);
+        assert_eq!(Some(1), ui.focused_webview_index(window_index));
+        ui.close_window(window_index);
+        assert_eq!(0, ui.window_count());
+        assert_eq!(Some(0), ui.reopen_closed_window());
+        ui.assert_uri(0, 1, "https://example.org");
+    }
+
+    #[test]
+    fn fit_images_to_window() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com/photo.png"), None::<Config>);
+        ui.write_command("fit", r#"
+        function description() return "" end
+        function run()
+          return fit_to_window(0, 0)
+        end
+        "#);
+        ui.submit_command("fit");
+        ui.assert_not_called("fit_to_window(0, 0)");
+        ui.set_document_kind(0, 0, DocumentKind::Image);
+        assert_eq!(None, ui.submit_command("fit").error);
+        ui.assert_called("fit_to_window(0, 0)");
+        ui.set_uri(0, 0, "https://example.com");
+        assert!(!ui.fit_to_window(0, 0));
+    }
+
+    #[test]
+    fn track_webview_idle_time() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.open_webview(0, Some("https://example.org"), None::<Config>);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(Duration::default(), ui.webview_idle(0, 1));
+        assert!(ui.webview_idle(0, 0) >= Duration::from_millis(20));
+        assert!(ui.webview_age(0, 0) >= ui.webview_idle(0, 0));
+        ui.focus_webview(0, 0);
+        assert_eq!(Duration::default(), ui.webview_idle(0, 0));
+        assert!(ui.webview_idle(0, 1) < Duration::from_millis(20));
+        assert_eq!(Duration::default(), ui.webview_age(0, 2));
+    }
+}
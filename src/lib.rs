@@ -1,17 +1,37 @@
 extern crate url;
+extern crate percent_encoding;
 extern crate toml;
 extern crate getopts;
 #[macro_use]
 extern crate log;
 extern crate dirs;
 
+pub mod bundle;
+pub mod cache;
 pub mod command;
 pub mod config;
+pub mod control;
+pub mod contentblock;
+#[cfg(any(test, feature = "headless"))]
+pub mod headless;
+pub mod logging;
 pub mod ui;
 pub mod optparse;
 pub mod script;
+pub mod store;
+pub mod userscripts;
 mod keybinding;
 
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self,File};
+use std::hash::{Hash,Hasher};
+use std::io::Read;
+use std::path::{Path,PathBuf};
+use std::sync::{Mutex,RwLock};
+use std::time::{Duration,Instant};
+
 use ui::*;
 use script::ScriptingEngine;
 
@@ -19,25 +39,122 @@ use script::ScriptingEngine;
 pub const WEBKITTEN_APP_ID: &'static str = "me.delisa.Webkitten";
 /// Application title for apps built with webkitten core
 pub const WEBKITTEN_TITLE: &'static str = "Webkitten";
+/// Maximum number of converted filter lists kept between compilations
+const FILTER_LIST_CACHE_CAPACITY: usize = 8;
+/// Maximum number of closed windows which can be reopened
+const CLOSED_WINDOW_CAPACITY: usize = 10;
+/// File name of the page describing configuration errors, written to the
+/// temporary directory
+const CONFIG_ERROR_PAGE: &'static str = "webkitten-config-error.html";
+/// The page displayed when a URI fails to load, unless
+/// `general.error-page-template` is set
+const DEFAULT_ERROR_PAGE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Failed to load page</title></head>
+<body>
+<h1>Failed to load page</h1>
+<p><a href="{uri}">{uri}</a> could not be loaded.</p>
+<pre>{error}</pre>
+</body>
+</html>
+"#;
+/// The command which highlights matches as it is typed
+const FIND_COMMAND: &'static str = "find";
+/// The period in which repeated load failures of a URI are counted towards
+/// `general.fail-loop-threshold`
+const FAIL_LOOP_WINDOW: Duration = Duration::from_secs(10);
+/// The period after which a load which has not finished no longer counts
+/// towards `general.max-concurrent-loads`
+const LOAD_QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum number of commands remembered for `Engine::search_history`
+const COMMAND_HISTORY_CAPACITY: usize = 200;
+/// The shortest period between progress reports of a download
+const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// File name of the key-value store within the configuration directory,
+/// unless `general.kv-store` is set
+const KV_STORE_FILE: &'static str = "kv-store.toml";
+/// The shortest period between writes of the key-value store while values
+/// are changing
+const KV_STORE_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
 
 /// The core of a webkitten application. The engine handles configuration options
 /// and responding to lifecycle and user events from the UI.
 pub struct Engine {
     pub config: config::Config,
+    config_error: RwLock<Option<config::ConfigError>>,
     run_config: optparse::RunConfiguration,
+    filter_lists: Mutex<cache::BoundedCache<contentblock::FilterList>>,
+    user_scripts: RwLock<Vec<userscripts::UserScript>>,
+    closed_windows: Mutex<cache::BoundedStack<ClosedWindow>>,
+    load_failures: Mutex<cache::RecentEvents>,
+    /// Webviews waiting to open while `general.max-concurrent-loads` pages
+    /// are loading, with the loads in progress keyed by window and webview
+    /// index
+    load_queue: Mutex<cache::LoadQueue<(u32, u32), (u32, String)>>,
+    download_progress: Mutex<cache::Throttle>,
+    /// Whether each host was granted permission to display notifications
+    notification_permissions: Mutex<HashMap<String, bool>>,
+    /// The input mode of each webview not in `InputMode::Normal`, keyed by
+    /// window and webview index
+    input_modes: Mutex<HashMap<(u32, u32), InputMode>>,
+    command_history: Mutex<cache::BoundedStack<String>>,
+    /// Caret browsing set for the session, overriding
+    /// `general.caret-browsing` until the application closes
+    caret_browsing: RwLock<Option<bool>>,
+    /// Values saved by commands using `kv_set`
+    kv_store: Mutex<store::KeyValueStore>,
 }
 
 impl Engine {
 
-    /// Create a new application engine
-    pub fn new(runtime: optparse::RunConfiguration) -> Option<Self> {
-        config::Config::parse_file(&runtime.path).and_then(|config| {
-            info!("Creating application engine with config path: {}", &runtime.path);
-            Some(Engine {
-                config: config,
-                run_config: runtime
-            })
-        })
+    /// Create a new application engine. If the configuration file does not
+    /// exist, the default configuration is written to the path. If the file
+    /// contains invalid syntax, the default configuration is used instead and
+    /// a page describing the error is opened first.
+    pub fn new(mut runtime: optparse::RunConfiguration) -> Option<Self> {
+        let (config, error) = match config::Config::read_file(&runtime.path) {
+            Ok(config) => (config, None),
+            Err(err @ config::ConfigError::InvalidSyntax(_)) => match config::Config::default() {
+                Some(config) => (config, Some(err)),
+                None => return None,
+            },
+            Err(_) => (config::Config::parse_file_or_default(&runtime.path), None),
+        };
+        logging::configure(&config);
+        if let Some(ref err) = error {
+            error!("{}", err);
+            warn!("Using the default configuration until {} is fixed", &runtime.path);
+            if let Some(uri) = write_config_error_page(&runtime.path, err) {
+                runtime.start_pages.insert(0, uri);
+            }
+        }
+        info!("Creating application engine with config path: {}", &runtime.path);
+        let engine = Engine {
+            config: config,
+            config_error: RwLock::new(error),
+            run_config: runtime,
+            filter_lists: Mutex::new(cache::BoundedCache::new(FILTER_LIST_CACHE_CAPACITY)),
+            user_scripts: RwLock::new(vec![]),
+            closed_windows: Mutex::new(cache::BoundedStack::new(CLOSED_WINDOW_CAPACITY)),
+            load_failures: Mutex::new(cache::RecentEvents::new(FAIL_LOOP_WINDOW)),
+            load_queue: Mutex::new(cache::LoadQueue::new(LOAD_QUEUE_TIMEOUT)),
+            download_progress: Mutex::new(cache::Throttle::new(DOWNLOAD_PROGRESS_INTERVAL)),
+            notification_permissions: Mutex::new(HashMap::new()),
+            input_modes: Mutex::new(HashMap::new()),
+            command_history: Mutex::new(cache::BoundedStack::new(COMMAND_HISTORY_CAPACITY)),
+            caret_browsing: RwLock::new(None),
+            kv_store: Mutex::new(store::KeyValueStore::open(None)),
+        };
+        engine.open_kv_store();
+        engine.load_user_scripts();
+        Some(engine)
+    }
+
+    /// The syntax error in the configuration file which caused the default
+    /// configuration to be used at launch, until the configuration is
+    /// successfully reloaded
+    pub fn config_error(&self) -> Option<config::ConfigError> {
+        self.config_error.read().ok().and_then(|error| error.clone())
     }
 
     /// Any arguments specified at launch to be opened
@@ -45,57 +162,860 @@ impl Engine {
         &self.run_config.start_pages
     }
 
-    /// Reload configuration from path
-    pub fn reload(&mut self) -> bool {
-        self.config.load(&self.run_config.path)
+    /// Reload configuration from path, applying logging options and
+    /// reloading the scripts in `general.user-scripts-directory`
+    pub fn reload(&self) -> bool {
+        if self.config.load(&self.run_config.path) {
+            logging::configure(&self.config);
+            if let Ok(mut error) = self.config_error.write() {
+                *error = None;
+            }
+            self.load_user_scripts();
+            self.open_kv_store();
+            if let Ok(mut permissions) = self.notification_permissions.lock() {
+                permissions.clear();
+            }
+            return true;
+        }
+        false
     }
 
-    fn use_argument_completion(&self, prefix: &str) -> bool {
-        prefix.contains(" ")
+    /// Copy the configuration file and the files it refers to into a new
+    /// directory, returning the number of files and directories copied
+    /// besides the configuration
+    pub fn export_bundle(&self, path: &str) -> bundle::BundleResult<usize> {
+        bundle::export(&self.run_config.path, path)
     }
-}
 
-impl EventHandler for Engine {
+    /// Replace the configuration and the files it refers to with the
+    /// contents of a bundle, then reload the configuration
+    pub fn import_bundle(&self, path: &str) -> bundle::BundleResult<usize> {
+        let count = bundle::import(path, &self.run_config.path)?;
+        self.reload();
+        Ok(count)
+    }
 
-    fn on_new_frame_request<T, S>(&self, ui: &T, window_index: u32, uri: &str)
+    /// Save whether private browsing is used for the host of a URI to the
+    /// configuration file. The site-specific override is removed when it
+    /// matches the global `general.private-browsing` option.
+    pub fn set_site_private_browsing(&self, uri: &str, enabled: bool) -> bool {
+        let default = self.config.lookup_bool("general.private-browsing").unwrap_or(false);
+        let value = if enabled == default { None } else { Some(enabled) };
+        info!("Setting private browsing for {} to {}", uri, enabled);
+        self.update_config(|config| config.set_site_bool(uri, "general.private-browsing", value))
+    }
+
+    /// Whether caret browsing is enabled, based on `general.caret-browsing`
+    /// unless it was set for the session
+    pub fn caret_browsing(&self) -> bool {
+        self.caret_browsing.read().ok()
+            .and_then(|enabled| *enabled)
+            .unwrap_or_else(|| self.config.caret_browsing())
+    }
+
+    /// Turn caret browsing on or off for the rest of the session, overriding
+    /// `general.caret-browsing` without saving the change. The override
+    /// remains in effect when the configuration is reloaded.
+    pub fn set_caret_browsing(&self, enabled: bool) {
+        info!("Setting caret browsing to {}", enabled);
+        if let Ok(mut caret_browsing) = self.caret_browsing.write() {
+            *caret_browsing = Some(enabled);
+        }
+    }
+
+    /// Save whether a command is disabled to `commands.disabled` in the
+    /// configuration file, taking effect immediately. Warns if no command
+    /// file matches the name.
+    pub fn set_command_disabled<S: ScriptingEngine>(&self, name: &str, disabled: bool) -> bool {
+        if command::CommandExplanation::explain(name, &self.config, S::file_extension()).path.is_none() {
+            warn!("No command found named {}", name);
+        }
+        info!("Setting command {} disabled to {}", name, disabled);
+        self.update_config(|config| config.set_command_disabled(name, disabled))
+    }
+
+    /// Apply a change to the configuration file and the cached
+    /// configuration. Refuses while the file has a syntax error, since the
+    /// cached configuration is then the default configuration.
+    fn update_config<F>(&self, update: F) -> bool
+        where F: Fn(&config::Config) -> bool {
+        if let Some(err) = self.config_error() {
+            warn!("Not saving configuration until {} is fixed: {}", &self.run_config.path, err);
+            return false;
+        }
+        self.config.update_file(&self.run_config.path, update)
+    }
+
+    /// Record the URIs of the webviews in a closed window and the parts of
+    /// its chrome which were hidden so the window can be reopened. Windows
+    /// without any URIs are skipped.
+    pub fn record_closed_window(&self, uris: Vec<String>, hidden_chrome: Vec<Chrome>) {
+        if uris.is_empty() {
+            return;
+        }
+        if let Ok(mut windows) = self.closed_windows.lock() {
+            windows.push(ClosedWindow { uris: uris, hidden_chrome: hidden_chrome });
+        }
+    }
+
+    /// Remove and return the most recently closed window, if any
+    pub fn take_closed_window(&self) -> Option<ClosedWindow> {
+        self.closed_windows.lock().ok().and_then(|mut windows| windows.pop())
+    }
+
+    /// Scripts loaded from `general.user-scripts-directory`
+    pub fn user_scripts(&self) -> Vec<userscripts::UserScript> {
+        self.user_scripts.read().ok()
+            .map(|scripts| scripts.clone())
+            .unwrap_or(vec![])
+    }
+
+    fn load_user_scripts(&self) {
+        let scripts = self.config.user_scripts_directory()
+            .map(|path| userscripts::load_directory(&path))
+            .unwrap_or(vec![]);
+        if let Ok(mut user_scripts) = self.user_scripts.write() {
+            *user_scripts = scripts;
+        }
+    }
+
+    /// The content blocking rules used in buffers, combining the rules in
+    /// the file at `general.content-filter` with any rules compiled from
+    /// `block.domains`, `block.patterns`, and the filter lists in
+    /// `block.filter-lists`. Invalid rules are logged and skipped.
+    pub fn content_filter(&self) -> Option<String> {
+        self.content_filter_with_rules(&self.configured_content_rules())
+    }
+
+    /// Recompile content blocking rules, re-reading the configured filter
+    /// lists and `general.content-filter`, and apply them to all webviews.
+    /// The result is reported by `on_content_filter_compiled` once
+    /// compilation finishes. Returns `false` if no content filtering is
+    /// configured.
+    pub fn reload_filters<T, S>(&self, ui: &T, window_index: Option<u32>) -> bool
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
-        if self.config.new_frame_uses_focused_window() {
-            ui.open_webview::<_, config::Config>(window_index, Some(uri), None);
-        } else {
-            ui.open_window::<_, config::Config>(Some(uri), None);
+        match self.content_filter() {
+            Some(filter) => {
+                ui.set_content_filter(&filter, window_index);
+                true
+            },
+            None => false
         }
     }
 
-    fn execute_command<T, S>(&self, ui: &T, window_index: Option<u32>, text: &str)
+    /// Report the result of compiling a content filter, showing the number
+    /// of rules in the command bar of a window if any
+    pub fn on_content_filter_compiled<T, S>(&self, ui: &T, window_index: Option<u32>,
+                                            filter: &str, compiled: bool)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let message = match compiled {
+            true => {
+                let message = format!("Compiled {} content rules", contentblock::count_rules(filter));
+                info!("{}", message);
+                message
+            },
+            false => {
+                let message = String::from("Failed to compile content rules, keeping the previous rules");
+                warn!("{}", message);
+                message
+            }
+        };
+        if let Some(index) = window_index {
+            ui.set_command_field_text(index, &message);
+        }
+    }
+
+    /// Rules compiled from `block.domains`, `block.patterns`, and
+    /// `block.filter-lists`
+    fn configured_content_rules(&self) -> Vec<contentblock::ContentRule> {
+        let (mut rules, errors) = contentblock::config_rules(&self.config);
+        for err in errors {
+            warn!("{}", err);
+        }
+        for path in self.config.filter_list_paths() {
+            if let Some(list) = self.filter_list(&path) {
+                rules.extend(list.rules);
+            }
+        }
+        rules
+    }
+
+    fn content_filter_with_rules(&self, rules: &[contentblock::ContentRule]) -> Option<String> {
+        let filter_path = self.config.content_filter_path();
+        let mut contents = String::new();
+        match filter_path.and_then(|path| File::open(path).ok()) {
+            Some(mut file) => {
+                if file.read_to_string(&mut contents).is_err() {
+                    return None;
+                }
+            },
+            None if rules.is_empty() => return None,
+            None => contents.push_str("[]"),
+        }
+        if rules.is_empty() {
+            return Some(contents);
+        }
+        info!("Adding {} configured content blocking rules", rules.len());
+        let merged = contentblock::merge(&contents, rules);
+        if merged.is_none() {
+            warn!("Content filter is not a list of rules, skipping configured rules");
+            return Some(contents);
+        }
+        merged
+    }
+
+    /// Read and convert a filter list, reusing previously converted rules if
+    /// the contents of the file are unchanged
+    fn filter_list(&self, path: &str) -> Option<contentblock::FilterList> {
+        let mut contents = String::new();
+        if File::open(path).and_then(|mut file| file.read_to_string(&mut contents)).is_err() {
+            warn!("Failed to read filter list: {}", path);
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let key = format!("{:x}", hasher.finish());
+        if let Some(list) = self.filter_lists.lock().ok().and_then(|lists| lists.get(&key).cloned()) {
+            info!("Using cached rules for filter list: {}", path);
+            return Some(list);
+        }
+        let list = contentblock::parse_filter_list(&contents);
+        info!("Converted {} rules from filter list: {}", list.rules.len(), path);
+        if list.skipped > 0 {
+            warn!("Skipped {} unsupported rules in filter list: {}", list.skipped, path);
+        }
+        if let Ok(mut lists) = self.filter_lists.lock() {
+            lists.insert(key, list.clone());
+        }
+        Some(list)
+    }
+
+    /// Execute each command in a file in order, returning `true` if all
+    /// commands ran successfully. Blank lines and lines beginning with `#` are
+    /// skipped. Execution stops at the first failing command unless
+    /// `general.rc-continue-on-error` is set.
+    pub fn run_script_file<T, S>(&self, ui: &T, path: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let mut contents = String::new();
+        if let Err(err) = File::open(path).and_then(|mut file| file.read_to_string(&mut contents)) {
+            warn!("Failed to read command file ({}): {}", path, err);
+            return false;
+        }
+        info!("Running commands from file: {}", path);
+        let continue_on_error = self.config.rc_continue_on_error();
+        let mut success = true;
+        for line in command::script_lines(&contents) {
+            if let Err(err) = self.run_command(ui, ui.focused_window_index(), line) {
+                warn!("Command failed ({:?}): {}", err, line);
+                success = false;
+                if !continue_on_error {
+                    break;
+                }
+            }
+        }
+        success
+    }
+
+    /// Open the pages specified at launch in an instance of the application
+    /// already listening on `general.control-socket`. Returns `false` if
+    /// there are no pages to open or no instance is listening, in which case
+    /// this instance should start normally.
+    pub fn forward_to_running_instance(&self) -> bool {
+        let path = match self.config.control_socket() {
+            Some(path) => path,
+            None => return false,
+        };
+        if self.run_config.start_pages.is_empty() {
+            return false;
+        }
+        match control::forward_uris(&path, &self.run_config.start_pages) {
+            Ok(()) => {
+                info!("Opened pages in the running instance");
+                true
+            },
+            Err(err) => {
+                info!("No running instance found on {}: {}", path, err);
+                false
+            }
+        }
+    }
+
+    /// Handle a line received on the control socket
+    pub fn execute_control_request<T, S>(&self, ui: &T, line: &str) -> CommandOutput
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        match control::Request::parse(line) {
+            control::Request::Open(uri) => {
+                ui.open_window::<_, config::Config>(Some(uri), None);
+                CommandOutput { error: None, message: None }
+            },
+            control::Request::Command(text) => self.execute_command_output(ui, &text),
+        }
+    }
+
+    /// Describe how command bar text would be parsed into a command, after
+    /// applying any command configured for its first character. Site-specific
+    /// aliases are not considered.
+    pub fn explain_command<S: ScriptingEngine>(&self, text: &str) -> command::CommandExplanation {
+        let text = self.config.command_matching_prefix(text).unwrap_or(String::from(text));
+        command::CommandExplanation::explain(&text, &self.config, S::file_extension())
+    }
+
+    /// The hook commands which would run in response to a buffer event for a
+    /// URI, resolved as they are when the event occurs: tokens are replaced,
+    /// aliases are resolved, and hooks without a matching enabled command
+    /// file are left out.
+    pub fn commands_for_uri_event<S: ScriptingEngine>(&self, event: &BufferEvent, uri: Option<&str>) -> Vec<String> {
+        self.uri_event_commands::<S>(event, uri).iter()
+            .map(|command| {
+                let mut text = String::from(command.name());
+                for argument in &command.arguments {
+                    text.push(' ');
+                    text.push_str(argument);
+                }
+                text
+            })
+            .collect()
+    }
+
+    /// The commands configured to run in response to a buffer event
+    fn uri_event_commands<S: ScriptingEngine>(&self, event: &BufferEvent, uri: Option<&str>) -> Vec<command::Command> {
+        self.config.on_buffer_event_commands(event).iter()
+            .filter_map(|name| {
+                let text = command::event_command_text(name, uri);
+                command::Command::parse(&text, &self.config, S::file_extension())
+            })
+            .collect()
+    }
+
+    /// Run a command in the focused window, describing the result. Any text
+    /// a command leaves in the command bar is returned as the message.
+    pub fn execute_command_output<T, S>(&self, ui: &T, text: &str) -> CommandOutput
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let window_index = ui.focused_window_index();
+        let initial_text = window_index.map(|index| ui.command_field_text(index));
+        self.record_command(text);
+        let error = match text.trim().is_empty() {
+            true => Some(CommandError::NoCommandSpecified),
+            false => self.run_command(ui, window_index, text).err(),
+        };
+        let message = window_index
+            .map(|index| ui.command_field_text(index))
+            .filter(|message| !message.is_empty() && Some(message) != initial_text.as_ref());
+        CommandOutput { error: error, message: message }
+    }
+
+    /// Previously run commands containing a query, most recent first and
+    /// without duplicates. An empty query matches every command.
+    pub fn search_history(&self, query: &str) -> Vec<String> {
+        let history = match self.command_history.lock() {
+            Ok(history) => history,
+            Err(_) => return vec![],
+        };
+        let mut matches: Vec<String> = vec![];
+        for text in history.recent() {
+            if text.contains(query) && !matches.contains(text) {
+                matches.push(text.clone());
+            }
+        }
+        matches
+    }
+
+    /// The value saved for a key within a namespace using `kv_set`
+    pub fn kv_get(&self, namespace: &str, key: &str) -> Option<String> {
+        self.kv_store.lock().ok().and_then(|store| store.get(namespace, key))
+    }
+
+    /// Save a value for a key within a namespace, usually the name of the
+    /// command saving it, or remove the key if the value is `None`. Changes
+    /// are written to the key-value store file periodically and on close.
+    pub fn kv_set(&self, namespace: &str, key: &str, value: Option<&str>) -> bool {
+        match self.kv_store.lock() {
+            Ok(mut store) => {
+                store.set(namespace, key, value);
+                store.flush_after(KV_STORE_FLUSH_INTERVAL)
+            },
+            Err(_) => false
+        }
+    }
+
+    /// Open the key-value store at `general.kv-store`, falling back to
+    /// `general.config-dir` or the directory containing the configuration
+    /// file. Pending changes to a previously opened store are written first.
+    fn open_kv_store(&self) {
+        let path = self.config.kv_store_path().or_else(|| {
+            let dir = self.config.config_dir().map(PathBuf::from)
+                .or_else(|| Path::new(&self.run_config.path).parent().map(Path::to_path_buf));
+            dir.map(|dir| dir.join(KV_STORE_FILE).display().to_string())
+        });
+        if let Ok(mut store) = self.kv_store.lock() {
+            if store.path() != path.as_ref().map(|path| path.as_str()) {
+                store.flush();
+                info!("Opening key-value store: {:?}", path);
+                *store = store::KeyValueStore::open(path);
+            }
+        }
+    }
+
+    /// Remember command text for `search_history`
+    fn record_command(&self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if let Ok(mut history) = self.command_history.lock() {
+            history.push(String::from(text));
+        }
+    }
+
+    /// Run the command configured in `external-schemes` for the scheme of a
+    /// URI, passing the URI as the last argument. Returns `false` if no
+    /// command is configured for the scheme.
+    pub fn open_external_uri<T, S>(&self, ui: &T, window_index: Option<u32>, uri: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let command = match uri.split(':').next().and_then(|scheme| self.config.external_scheme_command(scheme)) {
+            Some(command) => command,
+            None => return false,
+        };
+        info!("Opening {} using {}", uri, command);
+        if let Err(err) = self.run_command(ui, window_index, &format!("{} {}", command, uri)) {
+            warn!("Failed to open {} ({:?})", uri, err);
+        }
+        true
+    }
+
+    /// Run a command in each open window in turn, focusing each window while
+    /// the command runs and then restoring the previously focused window.
+    /// Returns the index of each window where the command failed along with
+    /// the failure. Windows closed while running the command are skipped.
+    pub fn execute_in_all_windows<T, S>(&self, ui: &T, text: &str) -> Vec<(u32, CommandError)>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let focused_index = ui.focused_window_index();
+        let mut failures = vec![];
+        for window_index in 0 .. ui.window_count() {
+            if !ui.window_exists(window_index) {
+                info!("Skipping closed window: {}", window_index);
+                continue;
+            }
+            ui.focus_window(window_index);
+            if let Err(err) = self.run_command(ui, Some(window_index), text) {
+                warn!("Command failed in window {} ({:?}): {}", window_index, err, text);
+                failures.push((window_index, err));
+            }
+        }
+        if let Some(index) = focused_index.filter(|index| ui.window_exists(*index)) {
+            ui.focus_window(index);
+        }
+        failures
+    }
+
+    fn use_argument_completion(&self, prefix: &str) -> bool {
+        prefix.contains(" ")
+    }
+
+    /// Run a command matching text, describing any failure. The command bar is
+    /// cleared after a successful command unless the command replaced its
+    /// text with output.
+    fn run_command<T, S>(&self, ui: &T, window_index: Option<u32>, text: &str) -> Result<(), CommandError>
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
         if let Some(text) = self.config.command_matching_prefix(text) {
-            return self.execute_command(ui, window_index, &text);
-        } else if let Some(command) = command::Command::parse(text, &self.config, S::file_extension()) {
+            return self.run_command(ui, window_index, &text);
+        }
+        let uri = window_index.and_then(|window_index| {
+            ui.focused_webview_index(window_index)
+                .map(|webview_index| ui.uri(window_index, webview_index))
+                .filter(|uri| !uri.is_empty())
+        });
+        let uri = uri.as_ref().map(|uri| uri.as_str());
+        if let Some(command) = command::Command::parse_for_uri(text, &self.config, S::file_extension(), uri) {
             info!("Found command match: {}", command.path);
+            if let Err(message) = self.check_arguments::<S>(&command) {
+                info!("{}", message);
+                if let Some(index) = window_index {
+                    ui.set_command_field_text(index, &message);
+                }
+                return Err(CommandError::InvalidArguments);
+            }
             if let Some(file) = command.file() {
-                match S::execute::<T, S>(file, command.arguments, ui, &self.run_config.path) {
-                    Err(err) => warn!("{}", err),
-                    Ok(success) => if let (true, Some(index)) = (success, window_index) {
-                        ui.set_command_field_text(index, "")
+                let initial_text = window_index.map(|index| ui.command_field_text(index));
+                let name = String::from(command.name());
+                return match S::execute::<T, S>(file, &name, command.arguments, ui, &self.run_config.path) {
+                    Err(err) => {
+                        warn!("{}", err);
+                        Err(CommandError::ErrorDuringExecution)
+                    },
+                    Ok(false) => {
+                        info!("Command failed: {}", command.path);
+                        Err(CommandError::ErrorDuringExecution)
+                    },
+                    Ok(true) => {
+                        if let Some(index) = window_index {
+                            if initial_text.as_ref() == Some(&ui.command_field_text(index)) {
+                                ui.set_command_field_text(index, "")
+                            }
+                        }
+                        Ok(())
                     }
                 }
             }
+        } else if let Some(script) = self.config.bookmarklet(text.trim()) {
+            return self.run_bookmarklet(ui, window_index, text.trim(), &script);
         } else if let Some(default) = self.config.default_command() {
             if !text.starts_with(&default) {
                 let mut command = String::from(default);
                 command.push_str(" ");
                 command.push_str(text);
                 info!("Running the default command: {}", command);
-                return self.execute_command(ui, window_index, &command);
+                return self.run_command(ui, window_index, &command);
+            }
+        }
+        Err(CommandError::CommandNotFound)
+    }
+
+    /// Run the script of a bookmarklet in the focused webview of a window
+    fn run_bookmarklet<T, S>(&self, ui: &T, window_index: Option<u32>, name: &str, script: &str) -> Result<(), CommandError>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let window_index = window_index.ok_or(CommandError::ErrorDuringExecution)?;
+        let webview_index = ui.focused_webview_index(window_index)
+            .ok_or(CommandError::ErrorDuringExecution)?;
+        info!("Running bookmarklet: {}", name);
+        ui.run_javascript(window_index, webview_index, script);
+        ui.set_command_field_text(window_index, "");
+        Ok(())
+    }
+
+    /// The input mode of a webview, which is `InputMode::Normal` until
+    /// changed
+    pub fn input_mode(&self, window_index: u32, webview_index: u32) -> InputMode {
+        self.input_modes.lock().ok()
+            .and_then(|modes| modes.get(&(window_index, webview_index)).cloned())
+            .unwrap_or(InputMode::Normal)
+    }
+
+    /// Change the input mode of a webview, invoking the commands in
+    /// `commands.on-input-mode-change` if the mode changed. Returns `true`
+    /// if the mode changed.
+    pub fn set_input_mode<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, mode: InputMode) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let changed = match self.input_modes.lock() {
+            Ok(mut modes) => match mode {
+                InputMode::Normal => modes.remove(&(window_index, webview_index)).is_some(),
+                mode => modes.insert((window_index, webview_index), mode) != Some(mode),
+            },
+            Err(_) => false,
+        };
+        if changed {
+            info!("Switching webview ({}, {}) to {} mode", window_index, webview_index, mode.name());
+            let uri = ui.uri(window_index, webview_index);
+            self.on_buffer_event(ui, window_index, webview_index, Some(&uri), BufferEvent::InputMode(mode));
+        }
+        changed
+    }
+
+    /// Run the command bound to a key in a window, unless the focused
+    /// webview is in `InputMode::Insert` and the command is listed in
+    /// `commands.normal-mode-keybindings`
+    pub fn run_keybinding<T, S>(&self, ui: &T, window_index: Option<u32>, command: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let webview_index = window_index.and_then(|index| ui.focused_webview_index(index));
+        if let (Some(window_index), Some(webview_index)) = (window_index, webview_index) {
+            if self.input_mode(window_index, webview_index) == InputMode::Insert
+                && self.config.normal_mode_keybinding(command) {
+                info!("Ignoring keybinding for {} in insert mode", command);
+                return;
             }
         }
+        if let Err(err) = self.run_command(ui, window_index, command) {
+            info!("Keybinding command not completed ({:?}): {}", err, command);
+        }
+    }
+
+    /// Track load failures of a URI, replacing a failure which reaches
+    /// `general.fail-loop-threshold` with `BufferEvent::FailLoop`. Returns
+    /// `None` for further failures in the loop, so commands which retry
+    /// loads are not invoked again. A successful load ends the loop.
+    fn detect_fail_loop(&self, uri: Option<&str>, event: BufferEvent) -> Option<BufferEvent> {
+        let (uri, threshold) = match (uri, self.config.fail_loop_threshold()) {
+            (Some(uri), Some(threshold)) => (uri, threshold as usize),
+            _ => return Some(event),
+        };
+        let mut failures = match self.load_failures.lock() {
+            Ok(failures) => failures,
+            Err(_) => return Some(event),
+        };
+        match event {
+            BufferEvent::Load => {
+                failures.clear(uri);
+                Some(event)
+            },
+            BufferEvent::Fail(message) => {
+                let count = failures.record(uri, Instant::now());
+                if count < threshold {
+                    Some(BufferEvent::Fail(message))
+                } else if count == threshold {
+                    warn!("Failed to load {} {} times, ignoring further failures", uri, count);
+                    Some(BufferEvent::FailLoop(message))
+                } else {
+                    info!("Ignoring repeated failure to load {}", uri);
+                    None
+                }
+            },
+            event => Some(event),
+        }
+    }
+
+    /// Open a webview for a URI in a window, or queue it until fewer than
+    /// `general.max-concurrent-loads` pages are loading
+    pub fn queue_webview<T, S>(&self, ui: &T, window_index: u32, uri: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        if self.config.max_concurrent_loads().is_none() {
+            ui.open_webview::<_, config::Config>(window_index, Some(uri), None);
+            return;
+        }
+        if let Ok(mut queue) = self.load_queue.lock() {
+            queue.push((window_index, String::from(uri)));
+        }
+        self.open_queued_webviews(ui);
+    }
+
+    /// Open queued webviews while fewer than `general.max-concurrent-loads`
+    /// pages are loading. Opening a webview may finish its load immediately,
+    /// so the queue is not locked while the UI is called.
+    fn open_queued_webviews<T, S>(&self, ui: &T)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let limit = self.config.max_concurrent_loads().unwrap_or(usize::max_value());
+        loop {
+            let next = match self.load_queue.lock() {
+                Ok(mut queue) => queue.next(limit, Instant::now()),
+                Err(_) => return,
+            };
+            match next {
+                Some((window_index, uri)) => match ui.window_exists(window_index) {
+                    true => ui.open_webview::<_, config::Config>(window_index, Some(uri), None),
+                    false => info!("Discarding queued webview for closed window: {}", uri),
+                },
+                None => return,
+            }
+        }
+    }
+
+    /// Track the start and finish of loads in a webview for
+    /// `general.max-concurrent-loads`, opening queued webviews as loads finish
+    fn update_load_queue<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, event: &BufferEvent)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let key = (window_index, webview_index);
+        match (event, self.load_queue.lock()) {
+            (&BufferEvent::Request(_), Ok(mut queue)) => {
+                queue.start(key, Instant::now());
+                return;
+            },
+            (&BufferEvent::Load, Ok(mut queue)) |
+            (&BufferEvent::Fail(_), Ok(mut queue)) |
+            (&BufferEvent::FailLoop(_), Ok(mut queue)) => queue.finish(&key),
+            _ => return,
+        }
+        self.open_queued_webviews(ui);
+    }
+
+    /// Set the URI represented by a window to the page of its focused webview,
+    /// clearing it for pages which are not documents
+    fn update_represented_uri<T, S>(&self, ui: &T, window_index: u32, webview_index: u32)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        if ui.focused_webview_index(window_index) != Some(webview_index) {
+            return;
+        }
+        let uri = ui.uri(window_index, webview_index);
+        let represented = Some(uri.as_str()).filter(|uri| is_document_uri(uri));
+        if ui.represented_uri(window_index).as_ref().map(|uri| uri.as_str()) != represented {
+            ui.set_represented_uri(window_index, represented);
+        }
+    }
+
+    /// Display the error page for a URI which failed to load, before any
+    /// `commands.on-fail-uri` commands run so they may load another page
+    fn show_error_page<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: &str, message: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let template = self.config.error_page_template_path()
+            .and_then(|path| match fs::read_to_string(&path) {
+                Ok(contents) => Some(contents),
+                Err(err) => {
+                    warn!("Failed to read error page template ({}): {}", path, err);
+                    None
+                }
+            })
+            .unwrap_or(String::from(DEFAULT_ERROR_PAGE));
+        let html = render_error_page(&template, uri, message);
+        if !ui.load_html(window_index, webview_index, &html, None) {
+            warn!("Failed to display error page for {}", uri);
+        }
+    }
+
+    /// Completions from the built-in source declared by a command, if any
+    fn source_completions<T, S>(&self, ui: &T, command: &command::Command) -> Option<Vec<String>>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let name = match command.file().map(S::completion_source) {
+            Some(Ok(Some(name))) => name,
+            Some(Err(err)) => {
+                warn!("Failed to read completion source for {}: {}", command.name(), err);
+                return None;
+            },
+            _ => return None,
+        };
+        match command::CompletionSource::parse(&name) {
+            Some(command::CompletionSource::Tabs) => {
+                Some(ui.tab_completions(&command.arguments.join(" ")))
+            },
+            Some(command::CompletionSource::Files) => {
+                let prefix = command.arguments.last().map(|arg| arg.as_str()).unwrap_or("");
+                Some(command::complete_path(prefix))
+            },
+            Some(command::CompletionSource::History) => {
+                Some(self.search_history(&command.arguments.join(" ")))
+            },
+            None => {
+                warn!("Unknown completion source for {}: {}", command.name(), name);
+                None
+            }
+        }
+    }
+
+    /// Invoke the commands configured for a download event
+    fn run_download_commands<T, S>(&self, ui: &T, download_id: u32, event: DownloadEvent)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        for name in self.config.on_download_event_commands(&event) {
+            if let Some(command) = command::Command::parse(&name, &self.config, S::file_extension()) {
+                if let Some(file) = command.file() {
+                    if let Err(err) = S::on_download_event::<T, S>(file, command.name(), ui, &self.run_config.path,
+                                                                  download_id, &event) {
+                        warn!("{}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Validate command arguments against the argument specification declared
+    /// by the command, if any, describing the mismatch and expected usage on
+    /// failure
+    fn check_arguments<S: ScriptingEngine>(&self, command: &command::Command) -> Result<(), String> {
+        let spec = match command.file().map(S::argspec) {
+            Some(Ok(Some(spec))) => spec,
+            Some(Err(err)) => {
+                warn!("Failed to read argspec for {}: {}", command.name(), err);
+                return Ok(());
+            },
+            _ => return Ok(()),
+        };
+        match command::ArgSpec::parse(&spec) {
+            Ok(spec) => spec.validate(&command.arguments).map_err(|reason| {
+                format!("{}. Usage: {} {}", reason, command.name(), spec)
+            }),
+            Err(err) => {
+                warn!("Invalid argspec for {}: {}", command.name(), err);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl EventHandler for Engine {
+
+    fn on_focus_element_change<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, element: Option<&ElementInfo>)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let mode = match element {
+            Some(element) if element.editable => InputMode::Insert,
+            _ => InputMode::Normal,
+        };
+        self.set_input_mode(ui, window_index, webview_index, mode);
+    }
+
+    fn on_fullscreen_change<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, fullscreen: bool)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        info!("Webview ({}, {}) {} fullscreen", window_index, webview_index,
+              if fullscreen { "entered" } else { "exited" });
+        let uri = ui.uri(window_index, webview_index);
+        self.on_buffer_event(ui, window_index, webview_index, Some(&uri), BufferEvent::Fullscreen(fullscreen));
+    }
+
+    fn on_js_dialog<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, kind: DialogKind,
+                          message: &str) -> DialogResponse
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let uri = ui.uri(window_index, webview_index);
+        if self.config.suppress_dialogs(&uri) {
+            info!("Suppressed {} dialog from {}: {}", kind.name(), uri, message);
+            return DialogResponse::Dismiss;
+        }
+        ui.show_dialog(window_index, webview_index, &kind, message)
+    }
+
+    fn on_new_frame_request<T, S>(&self, ui: &T, window_index: u32, uri: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        match self.config.open_in(uri) {
+            OpenIn::Tab => ui.open_webview::<_, config::Config>(window_index, Some(uri), None),
+            OpenIn::Window => {
+                ui.open_window::<_, config::Config>(Some(uri), None);
+            },
+            OpenIn::Background => {
+                let focused_index = ui.focused_webview_index(window_index);
+                ui.open_webview::<_, config::Config>(window_index, Some(uri), None);
+                if let Some(webview_index) = focused_index {
+                    ui.focus_webview(window_index, webview_index);
+                }
+            },
+        }
+    }
+
+    fn execute_command<T, S>(&self, ui: &T, window_index: Option<u32>, text: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        self.record_command(text);
+        if let Err(err) = self.run_command(ui, window_index, text) {
+            info!("Command not completed ({:?}): {}", err, text);
+        }
+    }
+
+    fn on_startup<T, S>(&self, ui: &T)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        for command in self.config.startup_commands() {
+            info!("Running startup command: {}", command);
+            if let Err(err) = self.run_command(ui, ui.focused_window_index(), &command) {
+                warn!("Startup command failed ({:?}): {}", err, command);
+            }
+        }
+        if let Some(path) = self.config.rc_file_path() {
+            self.run_script_file(ui, &path);
+        }
     }
 
     fn close<T, S>(&self, _ui: &T)
         where T: ApplicationUI<S>,
-              S: ScriptingEngine {}
+              S: ScriptingEngine {
+        if let Ok(mut store) = self.kv_store.lock() {
+            store.flush();
+        }
+    }
 
     fn command_completions<T, S>(&self, ui: &T, prefix: &str) -> Vec<String>
         where T: ApplicationUI<S>,
@@ -103,9 +1023,15 @@ impl EventHandler for Engine {
         if self.use_argument_completion(prefix) {
             if let Some(command) = command::Command::parse(prefix, &self.config, S::file_extension()) {
                 info!("Found command match for completion: {}", prefix);
+                if let Some(completions) = self.source_completions::<T, S>(ui, &command) {
+                    return completions;
+                }
                 if let Some(file) = command.file() {
                     info!("Completing command text using {}", command.path);
-                    return match S::autocomplete::<T, S>(file, command.arguments, prefix, ui, &self.run_config.path) {
+                    let index = command::active_argument(prefix);
+                    let name = String::from(command.name());
+                    return match S::autocomplete::<T, S>(file, &name, command.arguments, index, prefix, ui,
+                                                         &self.run_config.path) {
                         Err(err) => {
                             warn!("{}", err);
                             vec![]
@@ -115,21 +1041,526 @@ impl EventHandler for Engine {
                 }
             }
         }
-        command::Command::list_commands(prefix, &self.config)
+        let mut completions = command::Command::list_commands(prefix, &self.config);
+        for name in self.config.bookmarklet_names(prefix) {
+            if !completions.contains(&name) {
+                completions.push(name);
+            }
+        }
+        completions
+    }
+
+    fn on_command_text_change<T, S>(&self, ui: &T, window_index: Option<u32>, text: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        if self.config.incremental_find_delay().is_none() {
+            return;
+        }
+        let window_index = match window_index {
+            Some(index) => index,
+            None => return,
+        };
+        let webview_index = match ui.focused_webview_index(window_index) {
+            Some(index) => index,
+            None => return,
+        };
+        if text.trim().is_empty() {
+            return ui.hide_find_results(window_index, webview_index);
+        }
+        let uri = ui.uri(window_index, webview_index);
+        if let Some(command) = command::Command::parse_for_uri(text, &self.config, S::file_extension(), Some(&uri)) {
+            if command.name() == FIND_COMMAND {
+                match command.arguments.is_empty() {
+                    true => ui.hide_find_results(window_index, webview_index),
+                    false => ui.find_string(window_index, webview_index, &command.arguments.join(" ")),
+                }
+            }
+        }
+    }
+
+    fn on_console_message<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, message: ConsoleMessage)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let text = format!("console.{}: {} ({}:{})", message.level.name(), message.message,
+                           message.source, message.line);
+        match message.level {
+            ConsoleLevel::Error => error!("{}", text),
+            ConsoleLevel::Warn => warn!("{}", text),
+            ConsoleLevel::Info | ConsoleLevel::Log => info!("{}", text),
+            ConsoleLevel::Debug => debug!("{}", text),
+        }
+        let uri = ui.uri(window_index, webview_index);
+        self.on_buffer_event(ui, window_index, webview_index, Some(&uri), BufferEvent::Console(message));
+    }
+
+    fn on_geolocation_request<T, S>(&self, _ui: &T, _window_index: u32, _webview_index: u32, uri: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let granted = self.config.allow_geolocation(uri);
+        info!("{} location access for {}", if granted { "Granted" } else { "Denied" }, uri);
+        granted
+    }
+
+    fn on_media_capture_request<T, S>(&self, _ui: &T, _window_index: u32, _webview_index: u32, host: &str,
+                                      kind: MediaCaptureKind) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let granted = self.config.allow_media_capture(host, kind);
+        info!("{} {} access for {}", if granted { "Granted" } else { "Denied" }, kind.name(), host);
+        granted
+    }
+
+    fn on_notification_permission_request<T, S>(&self, _ui: &T, _window_index: u32, _webview_index: u32, uri: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let host = match uri_host(uri) {
+            Some(host) => host,
+            None => return false,
+        };
+        let mut permissions = match self.notification_permissions.lock() {
+            Ok(permissions) => permissions,
+            Err(_) => return false,
+        };
+        if let Some(granted) = permissions.get(&host) {
+            return *granted;
+        }
+        let granted = self.config.allow_notifications(uri);
+        info!("{} notification permission for {}", if granted { "Granted" } else { "Denied" }, host);
+        permissions.insert(host, granted);
+        granted
+    }
+
+    fn on_web_notification<T, S>(&self, ui: &T, _window_index: u32, _webview_index: u32, uri: &str,
+                                 title: &str, body: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let granted = uri_host(uri)
+            .and_then(|host| self.notification_permissions.lock().ok()
+                .and_then(|permissions| permissions.get(&host).cloned()))
+            .unwrap_or(false);
+        match granted {
+            true => ui.notify(title, body),
+            false => info!("Blocked notification from {}", uri),
+        }
+    }
+
+    fn on_download_progress<T, S>(&self, ui: &T, download_id: u32, received: u64, total: Option<u64>)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let complete = total == Some(received);
+        let report = self.download_progress.lock()
+            .map(|mut throttle| throttle.allow(download_id.to_string(), Instant::now()))
+            .unwrap_or(true);
+        if report || complete {
+            self.run_download_commands(ui, download_id, DownloadEvent::Progress(received, total));
+        }
+    }
+
+    fn on_download_finish<T, S>(&self, ui: &T, download_id: u32, path: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        info!("Download {} saved to {}", download_id, path);
+        if let Ok(mut throttle) = self.download_progress.lock() {
+            throttle.clear(&download_id.to_string());
+        }
+        self.run_download_commands(ui, download_id, DownloadEvent::Finish(String::from(path)));
+    }
+
+    fn on_download_fail<T, S>(&self, ui: &T, download_id: u32, message: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        warn!("Download {} failed: {}", download_id, message);
+        if let Ok(mut throttle) = self.download_progress.lock() {
+            throttle.clear(&download_id.to_string());
+        }
+        self.run_download_commands(ui, download_id, DownloadEvent::Fail(String::from(message)));
+    }
+
+    fn on_page_event<T, S>(&self, ui: &T, command: &str, window_index: u32, webview_index: u32,
+                           event: PageEvent)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let file = command::Command::parse(command, &self.config, S::file_extension())
+            .and_then(|command| command.file());
+        match file {
+            Some(file) => {
+                if let Err(err) = S::on_page_event::<T, S>(file, command, ui, &self.run_config.path,
+                                                          window_index, webview_index, &event) {
+                    warn!("{}", err);
+                }
+            },
+            None => warn!("Command not found for page response: {}", command),
+        }
     }
 
     fn on_buffer_event<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: Option<&str>, event: BufferEvent)
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
-        for name in self.config.on_buffer_event_commands(&event) {
-            if let Some(command) = command::Command::parse(&name, &self.config, S::file_extension()) {
-                if let Some(file) = command.file() {
-                    match S::on_buffer_event::<T, S>(file, ui, &self.run_config.path, window_index, webview_index, uri, &event) {
-                        Err(err) => warn!("{}", err),
-                        Ok(_) => (),
-                    }
+        self.update_load_queue(ui, window_index, webview_index, &event);
+        let event = match self.detect_fail_loop(uri, event) {
+            Some(event) => event,
+            None => return,
+        };
+        match (&event, uri) {
+            (&BufferEvent::Request(_), _) => {
+                self.set_input_mode(ui, window_index, webview_index, InputMode::Normal);
+            },
+            (&BufferEvent::Fail(ref message), Some(uri)) |
+            (&BufferEvent::FailLoop(ref message), Some(uri)) => {
+                self.show_error_page(ui, window_index, webview_index, uri, message);
+            },
+            (&BufferEvent::Load, _) | (&BufferEvent::Focus, _) => {
+                self.update_represented_uri(ui, window_index, webview_index);
+            },
+            _ => (),
+        }
+        for command in self.uri_event_commands::<S>(&event, uri) {
+            if let Some(file) = command.file() {
+                let name = String::from(command.name());
+                match S::on_buffer_event::<T, S>(file, &name, command.arguments, ui, &self.run_config.path,
+                                                 window_index, webview_index, uri, &event) {
+                    Err(err) => warn!("{}", err),
+                    Ok(_) => (),
                 }
             }
         }
     }
 }
+
+/// Replace the `{uri}` and `{error}` tokens of an error page template
+///
+/// ```
+/// use webkitten::render_error_page;
+///
+/// let html = render_error_page("<a href=\"{uri}\">{error}</a>", "https://example.com/?a&b", "<timeout>");
+/// assert_eq!("<a href=\"https://example.com/?a&amp;b\">&lt;timeout&gt;</a>", html);
+/// ```
+pub fn render_error_page(template: &str, uri: &str, error: &str) -> String {
+    template.replace("{uri}", &escape_html(uri)).replace("{error}", &escape_html(error))
+}
+
+/// Write a page describing a configuration error to a temporary file,
+/// returning its URI
+fn write_config_error_page(path: &str, err: &config::ConfigError) -> Option<String> {
+    let details = match err {
+        &config::ConfigError::InvalidSyntax(ref errors) => errors.join("\n"),
+        err => err.to_string(),
+    };
+    let contents = format!(r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Configuration error</title></head>
+<body>
+<h1>Configuration error</h1>
+<p>The configuration file <code>{}</code> could not be parsed. The default
+configuration is in use until the file is fixed and reloaded.</p>
+<pre>{}</pre>
+</body>
+</html>
+"#, escape_html(path), escape_html(&details));
+    let page = env::temp_dir().join(CONFIG_ERROR_PAGE);
+    match fs::write(&page, contents) {
+        Ok(()) => page.to_str().map(|page| format!("file://{}", page)),
+        Err(err) => {
+            warn!("Failed to write configuration error page: {}", err);
+            None
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use headless::HeadlessUI;
+    use headless::test_support::TestFixture;
+    use script::LuaEngine;
+
+    #[test]
+    fn launch_with_invalid_config() {
+        let path = env::temp_dir().join("webkitten-invalid-config.toml");
+        let path = String::from(path.to_str().unwrap());
+        fs::write(&path, "[general]\nprivate-browsing = ").unwrap();
+        let engine = Engine::new(optparse::RunConfiguration {
+            path: path.clone(),
+            start_pages: vec![String::from("https://example.com")],
+            command: None,
+            exit_status: None,
+        }).unwrap();
+        assert!(engine.config_error().is_some());
+        assert_eq!(Some(false), engine.config.lookup_bool("general.private-browsing"));
+        assert_eq!(2, engine.initial_pages().len());
+        assert!(engine.initial_pages()[0].ends_with(CONFIG_ERROR_PAGE));
+        fs::write(&path, "[general]\nprivate-browsing = true").unwrap();
+        assert!(engine.reload());
+        assert_eq!(None, engine.config_error());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn refuse_to_save_over_invalid_config() {
+        let path = env::temp_dir().join("webkitten-unsaved-config.toml");
+        let path = String::from(path.to_str().unwrap());
+        let contents = "[general]\nprivate-browsing = ";
+        fs::write(&path, contents).unwrap();
+        let engine = Engine::new(optparse::RunConfiguration {
+            path: path.clone(),
+            start_pages: vec![],
+            command: None,
+            exit_status: None,
+        }).unwrap();
+        assert!(!engine.set_site_private_browsing("https://example.com", true));
+        assert!(!engine.set_command_disabled::<script::LuaEngine>("go", true));
+        assert_eq!(contents, fs::read_to_string(&path).unwrap());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn take_closed_windows_in_reverse_order() {
+        let path = env::temp_dir().join("webkitten-closed-windows.toml");
+        let path = String::from(path.to_str().unwrap());
+        fs::write(&path, config::DEFAULT_CONFIG).unwrap();
+        let engine = Engine::new(optparse::RunConfiguration {
+            path: path.clone(),
+            start_pages: vec![],
+            command: None,
+            exit_status: None,
+        }).unwrap();
+        engine.record_closed_window(vec![String::from("https://example.com")], vec![]);
+        engine.record_closed_window(vec![], vec![]);
+        engine.record_closed_window(vec![String::from("https://example.org")], vec![Chrome::CommandBar]);
+        assert_eq!(Some(vec![String::from("https://example.org")]),
+                   engine.take_closed_window().map(|window| window.uris));
+        assert_eq!(Some(vec![String::from("https://example.com")]),
+                   engine.take_closed_window().map(|window| window.uris));
+        assert_eq!(None, engine.take_closed_window());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn report_compiled_filter_rules() {
+        let ui = TestFixture::new("[general]\ncontent-filter = \"{dir}/filter.json\"\n\
+                                   [block]\ndomains = [\"example.net\"]");
+        fs::write(ui.dir.join("filter.json"), r#"[{"trigger":{"url-filter":"ads"},"action":{"type":"block"}},
+                              {"trigger":{"url-filter":"track"},"action":{"type":"block"}}]"#).unwrap();
+        ui.write_command("reloadfilters", r#"
+        function description() return "" end
+        function run()
+          return reload_filters(focused_window_index())
+        end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert_eq!(None, ui.submit_command("reloadfilters").error);
+        ui.assert_called("set_command_field_text(0, Compiled 4 content rules)");
+    }
+
+    #[test]
+    fn open_new_frame_in_background() {
+        let ui = TestFixture::new("[sites.\"example.org\"]\nopen-in = \"background\"");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.engine.on_new_frame_request::<HeadlessUI<LuaEngine>, LuaEngine>(&ui.ui, 0, "https://example.org");
+        assert_eq!(2, ui.webview_count(0));
+        assert_eq!(Some(0), ui.focused_webview_index(0));
+        ui.engine.on_new_frame_request::<HeadlessUI<LuaEngine>, LuaEngine>(&ui.ui, 0, "https://example.net");
+        assert_eq!(2, ui.window_count());
+    }
+
+    #[test]
+    fn switch_input_mode_on_focus() {
+        let ui = TestFixture::new("on-input-mode-change = [\"showmode\"]\n\
+                                   normal-mode-keybindings = [\"back\"]");
+        ui.write_command("showmode", r#"
+        function description() return "" end
+        function on_input_mode_change()
+          set_window_title(window_index, input_mode_name)
+        end
+        "#);
+        ui.write_command("back", r#"
+        function description() return "" end
+        function run()
+          go_back(0, 0)
+          return true
+        end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.set_uri(0, 0, "https://example.org");
+        assert_eq!(InputMode::Normal, ui.input_mode(0, 0));
+        ui.focus_element(0, 0, ElementInfo::new("textarea", "", true));
+        assert_eq!(InputMode::Insert, ui.input_mode(0, 0));
+        assert_eq!("insert", ui.window_title(0));
+        ui.engine.run_keybinding::<HeadlessUI<LuaEngine>, LuaEngine>(&ui.ui, Some(0), "back");
+        ui.assert_uri(0, 0, "https://example.org");
+        ui.focus_element(0, 0, ElementInfo::new("button", "submit", false));
+        assert_eq!("normal", ui.window_title(0));
+        ui.engine.run_keybinding::<HeadlessUI<LuaEngine>, LuaEngine>(&ui.ui, Some(0), "back");
+        ui.assert_uri(0, 0, "https://example.com");
+    }
+
+    #[test]
+    fn report_fullscreen_changes() {
+        let ui = TestFixture::new("on-fullscreen-change = [\"chrome\"]");
+        ui.write_command("chrome", r#"
+        function description() return "" end
+        function on_fullscreen_change()
+          set_command_field_visible(window_index, not fullscreen)
+        end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.set_element_fullscreen(0, 0, true);
+        assert!(ui.is_element_fullscreen(0, 0));
+        ui.assert_called("set_command_field_visible(0, false)");
+        ui.set_element_fullscreen(0, 0, false);
+        assert!(!ui.is_element_fullscreen(0, 0));
+        ui.assert_called("set_command_field_visible(0, true)");
+    }
+
+    #[test]
+    fn answer_or_suppress_dialogs() {
+        let ui = TestFixture::new("[sites.\"example.org\"]\nsuppress-dialogs = true");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.open_webview(0, Some("https://example.org"), None::<Config>);
+        ui.set_dialog_response(DialogResponse::Text(String::from("kitten")));
+        assert_eq!(DialogResponse::Text(String::from("kitten")),
+                   ui.open_dialog(0, 0, DialogKind::Prompt(String::new()), "Name?"));
+        ui.assert_called("show_dialog(0, 0, prompt, Name?)");
+        ui.clear_calls();
+        assert_eq!(DialogResponse::Dismiss, ui.open_dialog(0, 1, DialogKind::Alert, "Subscribe!"));
+        assert!(!ui.called("show_dialog"));
+    }
+
+    #[test]
+    fn complete_from_command_history() {
+        let ui = TestFixture::new("");
+        ui.write_command("go", r#"
+        function description() return "" end
+        function run() return true end
+        "#);
+        ui.write_command("history", r#"
+        function description() return "" end
+        function completion_source() return "history" end
+        function run() return true end
+        "#);
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.submit_command("go example.com");
+        ui.submit_command("go example.org");
+        ui.submit_command("go example.com ");
+        assert_eq!(vec!["go example.com", "go example.org"], ui.engine.search_history("example"));
+        assert_eq!(vec!["go example.org"], ui.completions("history .org"));
+    }
+
+    #[test]
+    fn toggle_caret_browsing() {
+        let ui = TestFixture::new("[general]\ncaret-browsing = true");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.write_command("caret", r#"
+        function description() return "" end
+        function run()
+          set_caret_browsing(not caret_browsing())
+          return true
+        end
+        "#);
+        assert!(ui.caret_browsing());
+        assert_eq!(None, ui.submit_command("caret").error);
+        ui.assert_called("set_caret_browsing(false)");
+        assert!(!ui.caret_browsing());
+        assert!(ui.reload_config());
+        assert!(!ui.caret_browsing());
+    }
+
+    #[test]
+    fn execute_command_exit_code() {
+        let ui = TestFixture::new("");
+        ui.write_command("fail", r#"
+        function description() return "" end
+        function run()
+          error("failed")
+        end
+        "#);
+        ui.write_command("refuse", r#"
+        function description() return "" end
+        function run()
+          return false
+        end
+        "#);
+        assert_eq!(2, ui.execute("missing").exit_code());
+        ui.assert_called("run()");
+        assert_eq!(3, ui.execute("fail").exit_code());
+        assert_eq!(3, ui.execute("refuse").exit_code());
+        assert_eq!(5, ui.execute("").exit_code());
+    }
+
+    #[test]
+    fn show_error_page_on_fail() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.fail_load(0, 0, "timed out");
+        assert!(ui.calls().iter().any(|call| call.starts_with("load_html(0, 0, <!DOCTYPE html>")
+                                      && call.contains("<pre>timed out</pre>")));
+        ui.assert_uri(0, 0, "about:blank");
+        let ui = TestFixture::new("[general]\nerror-page-template = \"{dir}/error.html\"");
+        fs::write(ui.dir.join("error.html"), "{uri}: {error}").unwrap();
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.fail_load(0, 0, "timed out");
+        ui.assert_called("load_html(0, 0, https://example.com: timed out, None)");
+    }
+
+    #[test]
+    fn queue_webviews_while_loading() {
+        let ui = TestFixture::new("[general]\nmax-concurrent-loads = 1");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.write_command("many", r#"
+        function description() return "" end
+        function run()
+          for i = 1, #arguments do
+            queue_webview(focused_window_index(), arguments[i])
+          end
+          return true
+        end
+        "#);
+        ui.engine.on_buffer_event::<HeadlessUI<LuaEngine>, LuaEngine>(
+            &ui.ui, 0, 0, Some("https://example.com"), BufferEvent::Request(NavigationType::Other));
+        assert_eq!(None, ui.submit_command("many https://example.org https://example.net").error);
+        assert_eq!(1, ui.webview_count(0));
+        ui.engine.on_buffer_event::<HeadlessUI<LuaEngine>, LuaEngine>(
+            &ui.ui, 0, 0, Some("https://example.com"), BufferEvent::Load);
+        assert_eq!(3, ui.webview_count(0));
+        ui.assert_uri(0, 2, "https://example.net");
+    }
+
+    #[test]
+    fn list_commands_for_uri_event() {
+        let ui = TestFixture::new(r#"
+        on-request-uri = ["block {host}", "missing"]
+        on-load-uri = ["log"]
+        disabled = ["log"]
+        [commands.aliases]
+        block = "blocker"
+        "#);
+        ui.write_command("blocker", "");
+        ui.write_command("log", "");
+        let event = BufferEvent::parse_uri_event("request").unwrap();
+        assert_eq!(vec![String::from("blocker example.com")],
+                   ui.commands_for_uri_event(&event, "https://example.com/page"));
+        assert!(ui.commands_for_uri_event(&BufferEvent::Load, "https://example.com").is_empty());
+        assert!(BufferEvent::parse_uri_event("console").is_none());
+    }
+
+    #[test]
+    fn represent_focused_document() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert_eq!(Some(String::from("https://example.com")), ui.represented_uri(0));
+        ui.open_webview(0, Some("https://example.org"), None::<Config>);
+        assert_eq!(Some(String::from("https://example.org")), ui.represented_uri(0));
+        ui.focus_webview(0, 0);
+        assert_eq!(Some(String::from("https://example.com")), ui.represented_uri(0));
+        assert!(ui.load_html(0, 1, "<p>Generated</p>", None));
+        assert_eq!(Some(String::from("https://example.com")), ui.represented_uri(0));
+        assert!(ui.load_html(0, 0, "<p>Generated</p>", None));
+        assert_eq!(None, ui.represented_uri(0));
+        ui.assert_called("set_represented_uri(0, None)");
+    }
+}
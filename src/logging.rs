@@ -0,0 +1,167 @@
+//! Application log output to standard output or a file, configured using
+//! `general.log-level`, `general.log-file`, and `general.log-file-max-size`
+use std::env;
+use std::fs::{self,File,OpenOptions};
+use std::io::{self,Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use log::{self,Log,LevelFilter,Metadata,Record};
+
+use ui::BrowserConfiguration;
+
+/// Environment variable read for the log level if `general.log-level` is
+/// unset
+const LOG_LEVEL_VAR: &'static str = "RUST_LOG";
+/// Log level used if neither `general.log-level` nor `RUST_LOG` is set
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: Logger = Logger { output: Mutex::new(None) };
+
+/// Writes log records to a file if configured, otherwise to standard output
+struct Logger {
+    output: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!("{}:{}: {}\n", record.level(),
+                           record.module_path().unwrap_or(""), record.args());
+        match self.output.lock().as_mut().map(|output| output.as_mut()) {
+            Ok(Some(file)) => file.write(&line),
+            _ => print!("{}", line),
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(Some(file)) = self.output.lock().as_mut().map(|output| output.as_mut()) {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// A log file which is rotated once it reaches a maximum size, keeping the
+/// previous contents in a single file with the suffix `.1`
+struct LogFile {
+    path: String,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+impl LogFile {
+
+    /// Open a log file for appending, rotating it first if it already
+    /// exceeds the maximum size
+    fn open(path: &str, max_size: Option<u64>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let mut log_file = LogFile {
+            path: String::from(path),
+            file: file,
+            size: size,
+            max_size: max_size,
+        };
+        if max_size.map_or(false, |max_size| size >= max_size) {
+            log_file.rotate()?;
+        }
+        Ok(log_file)
+    }
+
+    fn write(&mut self, line: &str) {
+        let length = line.len() as u64;
+        if self.size > 0 && self.max_size.map_or(false, |max_size| self.size + length > max_size) {
+            if let Err(err) = self.rotate() {
+                eprintln!("Failed to rotate log file ({}): {}", self.path, err);
+            }
+        }
+        match self.file.write_all(line.as_bytes()) {
+            Ok(()) => self.size += length,
+            Err(_) => print!("{}", line),
+        }
+    }
+
+    /// Move the current contents to the rotated path and start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, rotated_path(&self.path))?;
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// The path to which the previous contents of a log file are moved when the
+/// file is rotated
+pub fn rotated_path(path: &str) -> String {
+    format!("{}.1", path)
+}
+
+/// Install the application logger, writing to standard output at the level
+/// set in `RUST_LOG`, or `info` if unset. Should be called once at launch,
+/// before logging anything.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(env_level().unwrap_or(DEFAULT_LEVEL));
+    }
+}
+
+/// Apply the logging options in configuration. Falls back to `RUST_LOG` if
+/// `general.log-level` is unset and to standard output if the log file
+/// cannot be opened.
+pub fn configure<C: BrowserConfiguration>(config: &C) {
+    log::set_max_level(config.log_level().or_else(env_level).unwrap_or(DEFAULT_LEVEL));
+    let mut error = None;
+    let output = config.log_file().and_then(|path| {
+        match LogFile::open(&path, config.log_file_max_size()) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                error = Some(format!("Failed to open log file ({}): {}", path, err));
+                None
+            }
+        }
+    });
+    if let Ok(mut current) = LOGGER.output.lock() {
+        *current = output;
+    }
+    if let Some(message) = error {
+        warn!("{}", message);
+    }
+}
+
+fn env_level() -> Option<LevelFilter> {
+    env::var(LOG_LEVEL_VAR).ok()
+        .and_then(|value| LevelFilter::from_str(&value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn rotate_log_file_past_max_size() {
+        let path = env::temp_dir().join("webkitten-rotate-test.log");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(rotated_path(path));
+        fs::write(path, "0123456789\n").unwrap();
+        let mut log_file = LogFile::open(path, Some(16)).unwrap();
+        assert_eq!(11, log_file.size);
+        log_file.write("abcdefghij\n");
+        assert_eq!("0123456789\n", fs::read_to_string(rotated_path(path)).unwrap());
+        assert_eq!("abcdefghij\n", fs::read_to_string(path).unwrap());
+        drop(log_file);
+        let log_file = LogFile::open(path, Some(8)).unwrap();
+        assert_eq!(0, log_file.size);
+        assert_eq!("abcdefghij\n", fs::read_to_string(rotated_path(path)).unwrap());
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(rotated_path(path));
+    }
+}
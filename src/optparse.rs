@@ -1,8 +1,5 @@
-use std::{env,fs};
-use std::io::Write;
-use std::path::Path;
+use std::env;
 use getopts::Options;
-use super::config;
 
 /// The runtime configuration of an instance of a webkitten application
 pub struct RunConfiguration {
@@ -10,6 +7,8 @@ pub struct RunConfiguration {
     pub path: String,
     /// Pages to open on initial load
     pub start_pages: Vec<String>,
+    /// A command to run without a GUI before exiting
+    pub command: Option<String>,
     /// The exit status, set if the application has completed execution
     pub exit_status: Option<(i32, String)>
 }
@@ -20,17 +19,18 @@ pub fn parse_opts(default_config_path: &str) -> RunConfiguration {
     let mut exit_status: Option<(i32, String)> = None;
     let program = args[0].clone();
     opts.optopt("c", "config", "Set the configuration path", "PATH");
+    opts.optopt("e", "execute", "Run a command without a GUI and exit", "COMMAND");
     opts.optflag("h", "help", "Print this help text");
     match opts.parse(&args[1..]) {
         Ok(matches) => {
             let path = matches.opt_str("c")
                 .unwrap_or(String::from(default_config_path));
-            validate_config_path(&path);
             if matches.opt_present("h") {
                 exit_status = Some((0, usage(program, opts)));
             }
             RunConfiguration {
                 path: path,
+                command: matches.opt_str("e"),
                 start_pages: matches.free,
                 exit_status: exit_status
             }
@@ -40,6 +40,7 @@ pub fn parse_opts(default_config_path: &str) -> RunConfiguration {
             RunConfiguration {
                 path: String::from(default_config_path),
                 start_pages: vec![],
+                command: None,
                 exit_status: Some((1, message))
             }
         }
@@ -50,41 +51,3 @@ fn usage(program: String, opts: Options) -> String {
     let brief = format!("Usage: {} [options] [URI ...]", program);
     return opts.usage(&brief);
 }
-
-/// Check if the given config path exists, creating if not required to already
-/// exist
-fn validate_config_path(config_path: &str) {
-    if let Some(parent) = Path::new(config_path).parent() {
-        let metadata = fs::metadata(parent);
-        if metadata.is_err() || !metadata.unwrap().is_dir() {
-            write_config_dir(parent);
-        }
-    }
-    if !fs::metadata(config_path).is_ok() {
-        write_default_config(config_path);
-    }
-}
-
-fn write_config_dir(path: &Path) {
-    match fs::create_dir_all(path) {
-        Ok(()) => (),
-        Err(e) => panic!("Unable to create config dir ({}): {}", path.display(), e)
-    }
-}
-
-fn write_default_config(config_path: &str) {
-    match fs::File::create(config_path) {
-        Ok(ref mut file) => {
-            if file.write(config::DEFAULT_CONFIG.as_bytes()).is_ok() {
-                let result = file.flush();
-                if result.is_err() {
-                    panic!("Unable to create default config ({}): {}",
-                           config_path,
-                           result.err().unwrap());
-                }
-            }
-        },
-        Err(e) => panic!("Unable to create default config ({}): {}", config_path, e)
-    }
-}
-
@@ -1,13 +1,17 @@
 extern crate hlua;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 
-use self::hlua::{Lua,LuaError,function0,function1,function2,function3};
+use self::hlua::{Lua,LuaError,function0,function1,function2,function3,function4};
 use self::hlua::any::AnyLuaValue;
 use self::hlua::functions_read::LuaFunction;
 
-use ui::{ApplicationUI,BrowserConfiguration,BufferEvent,WindowArea};
+use ui::{ApplicationUI,BrowserConfiguration,BufferEvent,CacheMode,Chrome,ColorScheme,DownloadEvent,
+         ElementInfo,FindOptions,InputMode,PageEvent,SaveFormat,SortKey,WindowArea};
+use bundle;
+use command;
 use config::Config;
 
 use super::{ScriptingEngine,ScriptError,ScriptResult,NOT_FOUND};
@@ -37,10 +41,18 @@ impl ScriptingEngine for LuaEngine {
         }
     }
 
-    fn execute<T, S>(file: File, arguments: Vec<String>, ui: &T, config_path: &str) -> ScriptResult<bool>
+    fn argspec(file: File) -> ScriptResult<Option<String>> {
+        optional_string_trigger(file, "argspec")
+    }
+
+    fn completion_source(file: File) -> ScriptResult<Option<String>> {
+        optional_string_trigger(file, "completion_source")
+    }
+
+    fn execute<T, S>(file: File, name: &str, arguments: Vec<String>, ui: &T, config_path: &str) -> ScriptResult<bool>
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
-        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned());
+        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned(), name.to_owned());
         lua.set("arguments", arguments);
         if let Err(err) = lua.execute_from_reader::<(), _>(file) {
             Err(lua_to_script_error("script parsing failed", Some(err)))
@@ -54,12 +66,13 @@ impl ScriptingEngine for LuaEngine {
         }
     }
 
-    fn autocomplete<T, S>(file: File, arguments: Vec<String>, prefix: &str, ui: &T, config_path: &str) -> ScriptResult<Vec<String>>
+    fn autocomplete<T, S>(file: File, name: &str, arguments: Vec<String>, argument_index: usize, prefix: &str, ui: &T, config_path: &str) -> ScriptResult<Vec<String>>
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
-        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned());
+        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned(), name.to_owned());
         lua.set("prefix", prefix);
         lua.set("arguments", arguments);
+        lua.set("argument_index", argument_index as u32 + 1);
         if let Err(err) = lua.execute_from_reader::<(), _>(file) {
             Err(lua_to_script_error("script parsing failed", Some(err)))
         } else {
@@ -73,12 +86,13 @@ impl ScriptingEngine for LuaEngine {
         }
     }
 
-    fn on_buffer_event<T, S>(file: File, ui: &T, config_path: &str, window_index: u32,
+    fn on_buffer_event<T, S>(file: File, name: &str, arguments: Vec<String>, ui: &T, config_path: &str, window_index: u32,
                              webview_index: u32, requested_uri: Option<&str>,
                              event: &BufferEvent) -> ScriptResult<()>
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
-        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned());
+        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned(), name.to_owned());
+        lua.set("arguments", arguments);
         if let Some(requested_uri) = requested_uri {
             lua.set("requested_uri", requested_uri);
         }
@@ -90,11 +104,98 @@ impl ScriptingEngine for LuaEngine {
             let func: Option<LuaFunction<_>> = match event {
                 &BufferEvent::Load => lua.get("on_load_uri"),
                 &BufferEvent::Focus => lua.get("on_focus"),
-                &BufferEvent::Request => lua.get("on_request_uri"),
+                &BufferEvent::Request(navigation_type) => {
+                    lua.set("navigation_type", navigation_type.name());
+                    lua.get("on_request_uri")
+                },
                 &BufferEvent::Fail(ref message) => {
                     lua.set("error_message", message.clone());
                     lua.get("on_fail_uri")
                 },
+                &BufferEvent::FailLoop(ref message) => {
+                    lua.set("error_message", message.clone());
+                    lua.get("on_fail_loop_uri")
+                },
+                &BufferEvent::Console(ref message) => {
+                    lua.set("console_level", message.level.name());
+                    lua.set("console_message", message.message.clone());
+                    lua.set("console_source", message.source.clone());
+                    lua.set("console_line", message.line);
+                    lua.get("on_console_message")
+                },
+                &BufferEvent::Fullscreen(fullscreen) => {
+                    lua.set("fullscreen", fullscreen);
+                    lua.get("on_fullscreen_change")
+                },
+                &BufferEvent::InputMode(mode) => {
+                    lua.set("input_mode_name", mode.name());
+                    lua.get("on_input_mode_change")
+                },
+            };
+            if let Some(mut func) = func {
+                resolve_script_output::<()>(func.call())
+            } else {
+                Err(lua_to_script_error(&format!("{:?} event method missing", event), None))
+            }
+        }
+    }
+
+    fn on_download_event<T, S>(file: File, name: &str, ui: &T, config_path: &str, download_id: u32,
+                               event: &DownloadEvent) -> ScriptResult<()>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned(), name.to_owned());
+        lua.set("download_id", download_id);
+        if let Err(err) = lua.execute_from_reader::<(), _>(file) {
+            Err(lua_to_script_error("script parsing failed", Some(err)))
+        } else {
+            let func: Option<LuaFunction<_>> = match event {
+                &DownloadEvent::Progress(received, total) => {
+                    lua.set("bytes_received", received as f64);
+                    if let Some(total) = total {
+                        lua.set("bytes_expected", total as f64);
+                    }
+                    lua.get("on_download_progress")
+                },
+                &DownloadEvent::Finish(ref path) => {
+                    lua.set("download_path", path.clone());
+                    lua.get("on_download_finish")
+                },
+                &DownloadEvent::Fail(ref message) => {
+                    lua.set("error_message", message.clone());
+                    lua.get("on_download_fail")
+                },
+            };
+            if let Some(mut func) = func {
+                resolve_script_output::<()>(func.call())
+            } else {
+                Err(lua_to_script_error(&format!("{:?} event method missing", event), None))
+            }
+        }
+    }
+
+    fn on_page_event<T, S>(file: File, name: &str, ui: &T, config_path: &str, window_index: u32,
+                           webview_index: u32, event: &PageEvent) -> ScriptResult<()>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine {
+        let mut lua = create_runtime::<T, S>(ui, config_path.to_owned(), name.to_owned());
+        lua.set("webview_index", webview_index);
+        lua.set("window_index", window_index);
+        if let Err(err) = lua.execute_from_reader::<(), _>(file) {
+            Err(lua_to_script_error("script parsing failed", Some(err)))
+        } else {
+            let func: Option<LuaFunction<_>> = match event {
+                &PageEvent::ElementsQueried(ref selector, ref elements) => {
+                    lua.set("selector", selector.clone());
+                    lua.set("elements", elements.iter().cloned()
+                        .map(element_table)
+                        .collect::<Vec<HashMap<String, AnyLuaValue>>>());
+                    lua.get("on_elements_queried")
+                },
+                &PageEvent::FormFilled(count) => {
+                    lua.set("filled_count", count as u32);
+                    lua.get("on_form_filled")
+                },
             };
             if let Some(mut func) = func {
                 resolve_script_output::<()>(func.call())
@@ -119,6 +220,51 @@ fn lua_to_script_error(description: &str, error: Option<LuaError>) -> ScriptErro
     ScriptError { description: full_description }
 }
 
+/// Parse headers formatted as `Name: value`, one per line
+fn parse_header_lines(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            line.find(':').map(|index| {
+                (String::from(line[.. index].trim()), String::from(line[index + 1 ..].trim()))
+            })
+        })
+        .collect()
+}
+
+/// Evaluate the contents of a file and call a function returning a string if
+/// it is defined
+fn optional_string_trigger(file: File, name: &str) -> ScriptResult<Option<String>> {
+    let mut lua = Lua::new();
+    if let Err(err) = lua.execute_from_reader::<(), _>(file) {
+        Err(lua_to_script_error("script parsing failed", Some(err)))
+    } else {
+        let run: Option<LuaFunction<_>> = lua.get(name);
+        if let Some(mut run) = run {
+            resolve_script_output::<String>(run.call()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Describe an element as a table of its tag name, input type, editability,
+/// text, bounds, and queried attributes
+fn element_table(element: ElementInfo) -> HashMap<String, AnyLuaValue> {
+    let mut table = HashMap::new();
+    for (name, value) in element.attributes {
+        table.insert(name, AnyLuaValue::LuaString(value));
+    }
+    table.insert(String::from("tag_name"), AnyLuaValue::LuaString(element.tag_name));
+    table.insert(String::from("input_type"), AnyLuaValue::LuaString(element.input_type.unwrap_or(String::new())));
+    table.insert(String::from("editable"), AnyLuaValue::LuaBoolean(element.editable));
+    table.insert(String::from("text"), AnyLuaValue::LuaString(element.text));
+    table.insert(String::from("x"), AnyLuaValue::LuaNumber(element.bounds.x));
+    table.insert(String::from("y"), AnyLuaValue::LuaNumber(element.bounds.y));
+    table.insert(String::from("width"), AnyLuaValue::LuaNumber(element.bounds.width));
+    table.insert(String::from("height"), AnyLuaValue::LuaNumber(element.bounds.height));
+    table
+}
+
 fn coerce_lua_array(raw_value: AnyLuaValue) -> ScriptResult<Vec<String>> {
     if let AnyLuaValue::LuaString(value) = raw_value {
         if value.len() == 0 {
@@ -135,7 +281,10 @@ fn resolve_script_output<T>(output: Result<T, LuaError>) -> ScriptResult<T> {
     output.map_err(|err| lua_to_script_error("script failed to execute", Some(err)))
 }
 
-fn create_runtime<T, S>(ui: &T, config_path: String) -> Lua
+/// Create a runtime with bindings to the UI, storing values in the namespace
+/// of the command being run. Bindings which capture `ui` or another local
+/// must `move` it, since the runtime outlives this function's arguments.
+fn create_runtime<T, S>(ui: &T, config_path: String, command_name: String) -> Lua
         where T: ApplicationUI<S>,
               S: ScriptingEngine {
     let mut lua = Lua::new();
@@ -147,14 +296,32 @@ fn create_runtime<T, S>(ui: &T, config_path: String) -> Lua
     lua.set("log_debug", function1(|message: String| {
         debug!("{}", message);
     }));
-    lua.set("copy", function1(|message: String| {
+    lua.set("complete_path", function1(|prefix: String| -> Vec<String> {
+        info!("complete_path: {}", prefix);
+        command::complete_path(&prefix)
+    }));
+    lua.set("copy", function1(move |message: String| {
         info!("copy");
         ui.copy(&message);
     }));
-    lua.set("run_command", function2(|window_index: u32, command: String| {
+    lua.set("notify", function2(move |title: String, body: String| {
+        info!("notify: {}", title);
+        ui.notify(&title, &body);
+    }));
+    lua.set("reveal_in_file_manager", function1(move |path: String| {
+        info!("reveal_in_file_manager: {}", path);
+        ui.reveal_in_file_manager(&path);
+    }));
+    lua.set("run_command", function2(move |window_index: u32, command: String| {
         info!("run_command");
         ui.execute_command(coerce_optional_index(window_index), &command);
     }));
+    lua.set("run_command_in_all_windows", function1(move |command: String| {
+        info!("run_command_in_all_windows");
+        ui.execute_command_in_all_windows(&command).into_iter()
+            .map(|(window_index, _)| window_index)
+            .collect::<Vec<u32>>()
+    }));
     lua.set("config_file_path", config_path);
     lua.set("lookup_bool", function2(|config_path: String, key: String| {
         info!("lookup_bool ({}): {}", config_path, key);
@@ -177,138 +344,514 @@ fn create_runtime<T, S>(ui: &T, config_path: String) -> Lua
         }
         String::new()
     }));
-    lua.set("focus_window", function1(|index: u32| {
+    lua.set("lookup_value", function2(|config_path: String, key: String| {
+        info!("lookup_value ({}): {}", config_path, key);
+        Config::parse_file(&config_path)
+            .and_then(|config| config.lookup_value(&key))
+            .map(|value| value.to_string())
+            .unwrap_or(String::new())
+    }));
+    lua.set("command_config", function2(|config_path: String, name: String| {
+        info!("command_config ({}): {}", config_path, name);
+        let mut options: HashMap<String, String> = HashMap::new();
+        let config = Config::parse_file(&config_path);
+        let value = config.and_then(|config| config.command_config(&name));
+        if let Some(table) = value.as_ref().and_then(|value| value.as_table()) {
+            for (key, value) in table {
+                let value = value.as_str().map(String::from).unwrap_or(value.to_string());
+                options.insert(key.clone(), value);
+            }
+        }
+        options
+    }));
+    lua.set("focus_window", function1(move |index: u32| {
         info!("focus_window: {}", index);
         ui.focus_window(index);
     }));
-    lua.set("focus_webview_in_window", function1(|index: u32| {
+    lua.set("focus_webview_in_window", function1(move |index: u32| {
         info!("focus_webview_in_window: {}", index);
         ui.focus_window_area(index, WindowArea::WebView);
     }));
-    lua.set("focus_commandbar_in_window", function1(|index: u32| {
+    lua.set("focus_commandbar_in_window", function1(move |index: u32| {
         info!("focus_commandbar_in_window: {}", index);
         ui.focus_window_area(index, WindowArea::CommandBar);
     }));
-    lua.set("open_window", function1(|uri: String| {
+    lua.set("open_window", function1(move |uri: String| {
         info!("open_window");
         ui.open_window::<_, Config>(coerce_optional_str(uri), None)
     }));
-    lua.set("open_custom_window", function2(|uri: String, config: String| {
+    lua.set("open_custom_window", function2(move |uri: String, config: String| {
         info!("open_window");
         ui.open_window(coerce_optional_str(uri), Config::parse(&config))
     }));
-    lua.set("close_window", function1(|window_index: u32| {
+    lua.set("open_private_window", function0(move || {
+        info!("open_private_window");
+        ui.open_private_window()
+    }));
+    lua.set("close_window", function1(move |window_index: u32| {
         info!("close_window: {}", window_index);
         ui.close_window(window_index);
     }));
-    lua.set("window_count", function0(|| {
+    lua.set("reopen_closed_window", function0(move || {
+        info!("reopen_closed_window");
+        ui.reopen_closed_window().unwrap_or(NOT_FOUND)
+    }));
+    lua.set("window_count", function0(move || {
         info!("get window_count");
         ui.window_count()
     }));
-    lua.set("focused_window_index", function0(|| {
+    lua.set("find_webview", function1(move |uri: String| {
+        info!("find_webview: {}", uri);
+        ui.find_webview_by_uri(&uri).unwrap_or((NOT_FOUND, NOT_FOUND))
+    }));
+    lua.set("window_exists", function1(move |window_index: u32| {
+        info!("window_exists: {}", window_index);
+        ui.window_exists(window_index)
+    }));
+    lua.set("focused_window_index", function0(move || {
         info!("get focused_window_index");
         ui.focused_window_index().unwrap_or(NOT_FOUND)
     }));
-    lua.set("window_title", function1(|window_index: u32| {
+    lua.set("window_title", function1(move |window_index: u32| {
         info!("window_title: {}", window_index);
         ui.window_title(window_index)
     }));
-    lua.set("set_window_title", function2(|window_index: u32, title: String| {
+    lua.set("set_window_title", function2(move |window_index: u32, title: String| {
         info!("set_window_title: {}", window_index);
         ui.set_window_title(window_index, &title);
     }));
-    lua.set("hide_window", function1(|window_index: u32| {
+    lua.set("represented_uri", function1(move |window_index: u32| {
+        info!("represented_uri: {}", window_index);
+        ui.represented_uri(window_index).unwrap_or(String::new())
+    }));
+    lua.set("set_represented_uri", function2(move |window_index: u32, uri: String| {
+        info!("set_represented_uri: {} {}", window_index, uri);
+        ui.set_represented_uri(window_index, coerce_optional_str(uri).as_ref().map(|uri| uri.as_str()));
+    }));
+    lua.set("hide_window", function1(move |window_index: u32| {
         info!("hide_window: {}", window_index);
         ui.toggle_window(window_index, false);
     }));
-    lua.set("show_window", function1(|window_index: u32| -> () {
+    lua.set("show_window", function1(move |window_index: u32| -> () {
         info!("show_window: {}", window_index);
         ui.toggle_window(window_index, true);
     }));
-    lua.set("open_webview", function2(|window_index: u32, uri: String| {
+    lua.set("open_webview", function2(move |window_index: u32, uri: String| {
         info!("open_webview: {}", window_index);
         ui.open_webview::<_, Config>(window_index, coerce_optional_str(uri), None);
     }));
-    lua.set("open_custom_webview", function3(|window_index: u32, uri: String, config: String| {
+    lua.set("queue_webview", function2(move |window_index: u32, uri: String| {
+        info!("queue_webview: {}", window_index);
+        ui.queue_webview(window_index, &uri);
+    }));
+    lua.set("open_custom_webview", function3(move |window_index: u32, uri: String, config: String| {
         info!("open_custom_webview: {} {}", window_index, config);
         ui.open_webview::<_, Config>(window_index, coerce_optional_str(uri), Config::parse(&config));
     }));
-    lua.set("webview_count", function1(|window_index: u32| {
+    lua.set("all_webviews", function0(move || {
+        info!("get all_webviews");
+        ui.all_webviews().into_iter()
+            .map(|(window_index, webview_index)| vec![window_index, webview_index])
+            .collect::<Vec<Vec<u32>>>()
+    }));
+    lua.set("webview_count", function1(move |window_index: u32| {
         info!("get webview_count: {}", window_index);
         ui.webview_count(window_index)
     }));
-    lua.set("set_command_field_visible", function2(|window_index: u32, visible: bool| {
+    lua.set("webview_exists", function2(move |window_index: u32, webview_index: u32| {
+        info!("webview_exists: ({}, {})", window_index, webview_index);
+        ui.webview_exists(window_index, webview_index)
+    }));
+    lua.set("set_command_field_visible", function2(move |window_index: u32, visible: bool| {
         info!("set command_field_visible");
         ui.set_command_field_visible(window_index, visible);
     }));
-    lua.set("set_command_field_text", function2(|window_index: u32, text: String| {
+    lua.set("chrome_visible", function2(move |window_index: u32, component: String| {
+        info!("chrome_visible: {} {}", window_index, component);
+        match Chrome::parse(&component) {
+            Some(component) => ui.chrome_visible(window_index, component),
+            None => {
+                warn!("Invalid window chrome: {}", component);
+                false
+            }
+        }
+    }));
+    lua.set("set_chrome_visible", function3(move |window_index: u32, component: String, visible: bool| {
+        info!("set_chrome_visible: {} {} {}", window_index, component, visible);
+        match Chrome::parse(&component) {
+            Some(component) => {
+                ui.set_chrome_visible(window_index, component, visible);
+                true
+            },
+            None => {
+                warn!("Invalid window chrome: {}", component);
+                false
+            }
+        }
+    }));
+    lua.set("set_command_field_text", function2(move |window_index: u32, text: String| {
         info!("set command_field_text");
         ui.set_command_field_text(window_index, &text);
     }));
-    lua.set("command_field_visible", function1(|window_index: u32| {
+    lua.set("command_field_visible", function1(move |window_index: u32| {
         info!("get command_field_visible");
         ui.command_field_visible(window_index)
     }));
-    lua.set("command_field_text", function1(|window_index: u32| {
+    lua.set("command_field_text", function1(move |window_index: u32| {
         info!("get command_field_text");
         ui.command_field_text(window_index)
     }));
-    lua.set("focused_webview_index", function1(|window_index: u32| {
+    lua.set("focused_webview_index", function1(move |window_index: u32| {
         info!("get focused_webview_index");
         ui.focused_webview_index(window_index).unwrap_or(NOT_FOUND)
     }));
-    lua.set("resize_window", function3(|window_index: u32, width: u32, height: u32| {
+    lua.set("focused_webview", function0(move || {
+        info!("get focused_webview");
+        ui.focused_webview().unwrap_or((NOT_FOUND, NOT_FOUND))
+    }));
+    lua.set("resize_window", function3(move |window_index: u32, width: u32, height: u32| {
         info!("resize_window: {} => ({}, {})", window_index, width, height);
         ui.resize_window(window_index, width, height);
     }));
-    lua.set("close_webview", function2(|window_index: u32, webview_index: u32| {
+    lua.set("window_minimized", function1(move |window_index: u32| {
+        info!("window_minimized: {}", window_index);
+        ui.window_minimized(window_index)
+    }));
+    lua.set("minimize_window", function1(move |window_index: u32| {
+        info!("minimize_window: {}", window_index);
+        ui.minimize_window(window_index);
+    }));
+    lua.set("restore_window", function1(move |window_index: u32| {
+        info!("restore_window: {}", window_index);
+        ui.restore_window(window_index);
+    }));
+    lua.set("tile_windows", function0(move || {
+        info!("tile_windows");
+        ui.tile_windows()
+    }));
+    lua.set("close_webview", function2(move |window_index: u32, webview_index: u32| {
         info!("close_webview: ({}, {})", window_index, webview_index);
         ui.close_webview(window_index, webview_index);
     }));
-    lua.set("reload_webview", function3(|window_index: u32, webview_index: u32, disable_filters: bool| {
+    lua.set("reload_webview", function3(move |window_index: u32, webview_index: u32, disable_filters: bool| {
         info!("reload_webview: ({}, {})", window_index, webview_index);
         ui.reload_webview(window_index, webview_index, disable_filters);
     }));
-    lua.set("focus_webview", function2(|window_index: u32, webview_index: u32| {
+    lua.set("reload_all", function1(move |disable_filters: bool| {
+        info!("reload_all");
+        ui.reload_all(disable_filters)
+    }));
+    lua.set("stop_webview", function2(move |window_index: u32, webview_index: u32| {
+        info!("stop_webview: ({}, {})", window_index, webview_index);
+        ui.stop_webview(window_index, webview_index)
+    }));
+    lua.set("stop_all", function0(move || {
+        info!("stop_all");
+        ui.stop_all()
+    }));
+    lua.set("reload_webview_focused", function1(move |disable_filters: bool| {
+        info!("reload_webview_focused");
+        ui.reload_webview_focused(disable_filters)
+    }));
+    lua.set("reset_webview", function2(move |window_index: u32, webview_index: u32| {
+        info!("reset_webview: ({}, {})", window_index, webview_index);
+        ui.reset_webview(window_index, webview_index)
+    }));
+    lua.set("focus_webview", function2(move |window_index: u32, webview_index: u32| {
         info!("focus_webview: ({}, {})", window_index, webview_index);
         ui.focus_webview(window_index, webview_index);
     }));
-    lua.set("load_uri", function3(|window_index: u32, webview_index: u32, uri: String| {
+    lua.set("focus_next_webview", function1(move |window_index: u32| {
+        info!("focus_next_webview: {}", window_index);
+        ui.focus_next_webview(window_index);
+    }));
+    lua.set("focus_previous_webview", function1(move |window_index: u32| {
+        info!("focus_previous_webview: {}", window_index);
+        ui.focus_previous_webview(window_index);
+    }));
+    lua.set("load_uri", function3(move |window_index: u32, webview_index: u32, uri: String| {
         info!("load_uri: ({}, {})", window_index, webview_index);
         ui.set_uri(window_index, webview_index, &uri);
     }));
-    lua.set("go_back", function2(|window_index: u32, webview_index: u32| {
+    lua.set("load_html", function4(move |window_index: u32, webview_index: u32, html: String, base_uri: String| {
+        info!("load_html: ({}, {})", window_index, webview_index);
+        let base_uri = coerce_optional_str(base_uri);
+        ui.load_html(window_index, webview_index, &html, base_uri.as_ref().map(|uri| uri.as_str()))
+    }));
+    lua.set("load_uri_focused", function1(move |uri: String| {
+        info!("load_uri_focused");
+        ui.set_uri_focused(&uri)
+    }));
+    lua.set("can_go_back", function2(move |window_index: u32, webview_index: u32| {
+        info!("can_go_back: ({}, {})", window_index, webview_index);
+        ui.can_go_back(window_index, webview_index)
+    }));
+    lua.set("can_go_forward", function2(move |window_index: u32, webview_index: u32| {
+        info!("can_go_forward: ({}, {})", window_index, webview_index);
+        ui.can_go_forward(window_index, webview_index)
+    }));
+    lua.set("go_back", function2(move |window_index: u32, webview_index: u32| {
         info!("go_back: ({}, {})", window_index, webview_index);
         ui.go_back(window_index, webview_index);
     }));
-    lua.set("go_forward", function2(|window_index: u32, webview_index: u32| {
+    lua.set("go_forward", function2(move |window_index: u32, webview_index: u32| {
         info!("go_forward: ({}, {})", window_index, webview_index);
         ui.go_forward(window_index, webview_index);
     }));
-    lua.set("webview_uri", function2(|window_index: u32, webview_index: u32| {
+    lua.set("go_back_focused", function0(move || {
+        info!("go_back_focused");
+        ui.go_back_focused()
+    }));
+    lua.set("go_forward_focused", function0(move || {
+        info!("go_forward_focused");
+        ui.go_forward_focused()
+    }));
+    lua.set("webview_uri", function2(move |window_index: u32, webview_index: u32| {
         info!("get webview_uri: ({}, {})", window_index, webview_index);
         ui.uri(window_index, webview_index)
     }));
-    lua.set("webview_title", function2(|window_index: u32, webview_index: u32| {
+    lua.set("move_webview", function3(move |window_index: u32, from: u32, to: u32| {
+        info!("move_webview: {} ({} -> {})", window_index, from, to);
+        ui.move_webview(window_index, from, to)
+    }));
+    lua.set("sort_webviews", function2(move |window_index: u32, key: String| {
+        info!("sort_webviews: {} {}", window_index, key);
+        match SortKey::parse(&key) {
+            Some(key) => ui.sort_webviews(window_index, key),
+            None => {
+                warn!("Invalid sort key: {}", key);
+                false
+            }
+        }
+    }));
+    lua.set("webview_age", function2(move |window_index: u32, webview_index: u32| {
+        info!("get webview_age: ({}, {})", window_index, webview_index);
+        ui.webview_age(window_index, webview_index).as_secs_f64()
+    }));
+    lua.set("webview_idle", function2(move |window_index: u32, webview_index: u32| {
+        info!("get webview_idle: ({}, {})", window_index, webview_index);
+        ui.webview_idle(window_index, webview_index).as_secs_f64()
+    }));
+    lua.set("webview_title", function2(move |window_index: u32, webview_index: u32| {
         info!("get webview_title: ({}, {})", window_index, webview_index);
         ui.webview_title(window_index, webview_index)
     }));
-    lua.set("find", function3(|window_index: u32, webview_index: u32, query: String| {
+    lua.set("find", function3(move |window_index: u32, webview_index: u32, query: String| {
         info!("find: ({}, {})", window_index, webview_index);
         ui.find_string(window_index, webview_index, &query);
     }));
-    lua.set("hide_find", function2(|window_index: u32, webview_index: u32| {
+    lua.set("find_with_options", function4(move |window_index: u32, webview_index: u32, query: String, highlight_all: bool| {
+        info!("find_with_options: ({}, {}) {}", window_index, webview_index, highlight_all);
+        ui.find_string_with_options(window_index, webview_index, &query, FindOptions { highlight_all: highlight_all });
+    }));
+    lua.set("hide_find", function2(move |window_index: u32, webview_index: u32| {
         info!("hide_find: ({}, {})", window_index, webview_index);
         ui.hide_find_results(window_index, webview_index)
     }));
-    lua.set("run_javascript", function3(|window_index: u32, webview_index: u32, script: String| {
+    lua.set("set_find_highlight", function3(move |window_index: u32, webview_index: u32, color: String| {
+        info!("set_find_highlight: ({}, {}) {}", window_index, webview_index, color);
+        let color = Some(color.as_str()).filter(|color| !color.is_empty());
+        ui.set_find_highlight(window_index, webview_index, color)
+    }));
+    lua.set("run_javascript", function3(move |window_index: u32, webview_index: u32, script: String| {
         info!("run_javascript: ({}, {})", window_index, webview_index);
         ui.run_javascript(window_index, webview_index, &script);
     }));
-    lua.set("add_styles", function3(|window_index: u32, webview_index: u32, styles: String| {
+    lua.set("inject_user_script", function4(move |window_index: u32, webview_index: u32, script: String, at_start: bool| {
+        info!("inject_user_script: ({}, {})", window_index, webview_index);
+        ui.inject_user_script(window_index, webview_index, &script, at_start);
+    }));
+    lua.set("clear_user_scripts", function2(move |window_index: u32, webview_index: u32| {
+        info!("clear_user_scripts: ({}, {})", window_index, webview_index);
+        ui.clear_user_scripts(window_index, webview_index);
+    }));
+    lua.set("save_page", function4(move |window_index: u32, webview_index: u32, path: String, format: String| {
+        info!("save_page: ({}, {}) => {}", window_index, webview_index, path);
+        match SaveFormat::parse(&format) {
+            Some(format) => ui.save_page(window_index, webview_index, &path, format),
+            None => {
+                warn!("Invalid save format: {}", format);
+                false
+            }
+        }
+    }));
+    lua.set("set_custom_headers", function3(move |window_index: u32, webview_index: u32, headers: String| {
+        info!("set_custom_headers: ({}, {})", window_index, webview_index);
+        ui.set_custom_headers(window_index, webview_index, &parse_header_lines(&headers));
+    }));
+    lua.set("page_statistics", function2(move |window_index: u32, webview_index: u32| {
+        info!("page_statistics: ({}, {})", window_index, webview_index);
+        ui.page_statistics(window_index, webview_index)
+            .map(|stats| (stats.word_count, stats.reading_minutes))
+            .unwrap_or((NOT_FOUND, NOT_FOUND))
+    }));
+    lua.set("page_timing", function2(move |window_index: u32, webview_index: u32| {
+        info!("page_timing: ({}, {})", window_index, webview_index);
+        let timing = ui.page_timing(window_index, webview_index);
+        (timing.dns, timing.tcp, timing.first_byte, timing.dom_content_loaded, timing.load)
+    }));
+    lua.set("webview_process_stats", function2(move |window_index: u32, webview_index: u32| {
+        info!("webview_process_stats: ({}, {})", window_index, webview_index);
+        let stats = ui.webview_process_stats(window_index, webview_index);
+        (stats.pid, stats.memory_bytes as f64)
+    }));
+    lua.set("focus_element_by_index", function3(move |window_index: u32, webview_index: u32, index: u32| {
+        info!("focus_element_by_index: ({}, {}) {}", window_index, webview_index, index);
+        ui.focus_element_by_index(window_index, webview_index, index);
+    }));
+    lua.set("click_element_by_index", function3(move |window_index: u32, webview_index: u32, index: u32| {
+        info!("click_element_by_index: ({}, {}) {}", window_index, webview_index, index);
+        ui.click_element_by_index(window_index, webview_index, index);
+    }));
+    lua.set("focused_element", function2(move |window_index: u32, webview_index: u32| {
+        info!("focused_element: ({}, {})", window_index, webview_index);
+        ui.focused_element(window_index, webview_index)
+            .map(|info| (info.tag_name, info.input_type.unwrap_or(String::new()), info.editable))
+            .unwrap_or((String::new(), String::new(), false))
+    }));
+    let requester = command_name.clone();
+    lua.set("fill_form", function3(move |config_path: String, window_index: u32, webview_index: u32| {
+        info!("fill_form ({}): ({}, {})", config_path, window_index, webview_index);
+        Config::parse_file(&config_path)
+            .map(|config| ui.fill_form_fields(window_index, webview_index, &config.form_autofill_values(),
+                                              &requester))
+            .unwrap_or(false)
+    }));
+    let requester = command_name.clone();
+    lua.set("query_selector_all", function3(move |window_index: u32, webview_index: u32, selector: String| {
+        info!("query_selector_all: ({}, {}) {}", window_index, webview_index, selector);
+        ui.query_selector_all(window_index, webview_index, &selector, &requester)
+    }));
+    lua.set("fit_to_window", function2(move |window_index: u32, webview_index: u32| {
+        info!("fit_to_window: ({}, {})", window_index, webview_index);
+        ui.fit_to_window(window_index, webview_index)
+    }));
+    lua.set("is_element_fullscreen", function2(move |window_index: u32, webview_index: u32| {
+        info!("is_element_fullscreen: ({}, {})", window_index, webview_index);
+        ui.is_element_fullscreen(window_index, webview_index)
+    }));
+    lua.set("exit_element_fullscreen", function2(move |window_index: u32, webview_index: u32| {
+        info!("exit_element_fullscreen: ({}, {})", window_index, webview_index);
+        ui.exit_element_fullscreen(window_index, webview_index);
+    }));
+    lua.set("input_mode", function2(move |window_index: u32, webview_index: u32| {
+        info!("get input_mode: ({}, {})", window_index, webview_index);
+        ui.input_mode(window_index, webview_index).name()
+    }));
+    lua.set("set_input_mode", function3(move |window_index: u32, webview_index: u32, mode: String| {
+        info!("set_input_mode: ({}, {}) {}", window_index, webview_index, mode);
+        match InputMode::parse(&mode) {
+            Some(mode) => ui.set_input_mode(window_index, webview_index, mode),
+            None => {
+                warn!("Invalid input mode: {}", mode);
+                false
+            }
+        }
+    }));
+    lua.set("private_browsing", function2(move |window_index: u32, webview_index: u32| {
+        info!("get private_browsing: ({}, {})", window_index, webview_index);
+        ui.private_browsing(window_index, webview_index)
+    }));
+    lua.set("set_private_browsing", function3(move |window_index: u32, webview_index: u32, enabled: bool| {
+        info!("set_private_browsing: ({}, {}) {}", window_index, webview_index, enabled);
+        ui.set_private_browsing(window_index, webview_index, enabled)
+    }));
+    lua.set("add_styles", function3(move |window_index: u32, webview_index: u32, styles: String| {
         info!("add_styles: ({}, {})", window_index, webview_index);
         ui.apply_styles(window_index, webview_index, &styles);
     }));
+    lua.set("add_styles_all", function1(move |styles: String| {
+        info!("add_styles_all");
+        ui.apply_styles_all(&styles);
+    }));
+    lua.set("scroll_to", function4(move |window_index: u32, webview_index: u32, x: f64, y: f64| {
+        info!("scroll_to: ({}, {}) => ({}, {})", window_index, webview_index, x, y);
+        ui.scroll_to(window_index, webview_index, x, y);
+    }));
+    lua.set("set_color_scheme", function3(move |window_index: u32, webview_index: u32, scheme: String| {
+        info!("set_color_scheme: ({}, {}) {}", window_index, webview_index, scheme);
+        match ColorScheme::parse(&scheme) {
+            Some(scheme) => ui.set_color_scheme(window_index, webview_index, scheme),
+            None => warn!("Unknown color scheme: {}", scheme)
+        }
+    }));
+    lua.set("export_bundle", function2(|config_path: String, destination: String| {
+        info!("export_bundle ({}): {}", config_path, destination);
+        match bundle::export(&config_path, &destination) {
+            Ok(count) => (count as u32, String::new()),
+            Err(err) => (NOT_FOUND, err.to_string()),
+        }
+    }));
+    lua.set("import_bundle", function2(move |config_path: String, source: String| {
+        info!("import_bundle ({}): {}", config_path, source);
+        match bundle::import(&source, &config_path) {
+            Ok(count) => {
+                ui.reload_config();
+                (count as u32, String::new())
+            },
+            Err(err) => (NOT_FOUND, err.to_string()),
+        }
+    }));
+    lua.set("reload_config", function0(move || {
+        info!("reload_config");
+        ui.reload_config()
+    }));
+    lua.set("explain_command", function1(move |text: String| {
+        info!("explain_command: {}", text);
+        let explanation = ui.explain_command(&text);
+        (explanation.name,
+         explanation.alias,
+         explanation.path.unwrap_or(String::new()),
+         explanation.arguments,
+         explanation.disabled)
+    }));
+    lua.set("commands_for_uri_event", function2(move |event: String, uri: String| {
+        info!("commands_for_uri_event: {} {}", event, uri);
+        match BufferEvent::parse_uri_event(&event) {
+            Some(event) => ui.commands_for_uri_event(&event, &uri),
+            None => {
+                warn!("Invalid URI event: {}", event);
+                vec![]
+            }
+        }
+    }));
+    lua.set("caret_browsing", function0(move || {
+        info!("get caret_browsing");
+        ui.caret_browsing()
+    }));
+    lua.set("set_caret_browsing", function1(move |enabled: bool| {
+        info!("set_caret_browsing: {}", enabled);
+        ui.set_caret_browsing(enabled);
+    }));
+    lua.set("set_command_disabled", function2(move |name: String, disabled: bool| {
+        info!("set_command_disabled: {} {}", name, disabled);
+        ui.set_command_disabled(&name, disabled)
+    }));
+    let namespace = command_name.clone();
+    lua.set("kv_get", function1(move |key: String| {
+        info!("kv_get: {} {}", namespace, key);
+        ui.kv_get(&namespace, &key).unwrap_or(String::new())
+    }));
+    let namespace = command_name;
+    lua.set("kv_set", function2(move |key: String, value: String| {
+        info!("kv_set: {} {}", namespace, key);
+        ui.kv_set(&namespace, &key, coerce_optional_str(value).as_ref().map(|value| value.as_str()))
+    }));
+    lua.set("reload_filters", function1(move |window_index: u32| {
+        info!("reload_filters: {}", window_index);
+        ui.reload_filters(coerce_optional_index(window_index))
+    }));
+    lua.set("reload_stylesheets", function0(move || {
+        info!("reload_stylesheets");
+        ui.reload_stylesheets();
+    }));
+    lua.set("set_cache_mode", function1(move |mode: String| {
+        info!("set_cache_mode: {}", mode);
+        match CacheMode::parse(&mode) {
+            Some(mode) => ui.set_cache_mode(mode),
+            None => warn!("Unknown cache mode: {}", mode)
+        }
+    }));
     lua
 }
 
@@ -336,6 +879,10 @@ mod tests {
     use std::path::PathBuf;
     use std::slice;
     use script::{ScriptingEngine,LuaEngine};
+    use config::Config;
+    use headless::HeadlessUI;
+    use headless::test_support::TestFixture;
+    use ui::*;
 
     #[test]
     fn describe_missing_method() {
@@ -375,6 +922,162 @@ mod tests {
         cleanup_script(path);
     }
 
+    #[test]
+    fn argspec_optional_method() {
+        let path = create_script("mail_argspec", r#"
+            function argspec()
+                return "recipient [subject]"
+            end
+        "#);
+        let file = File::open(path.clone()).ok().unwrap();
+        let result = LuaEngine::argspec(file).ok().unwrap();
+        assert_eq!(Some(String::from("recipient [subject]")), result);
+        cleanup_script(path);
+        let path = create_script("mail_no_argspec", r#"
+            function description()
+                return "Sends mail"
+            end
+        "#);
+        let file = File::open(path.clone()).ok().unwrap();
+        assert_eq!(None, LuaEngine::argspec(file).ok().unwrap());
+        cleanup_script(path);
+    }
+
+    #[test]
+    fn parse_header_lines_skips_invalid_lines() {
+        let headers = super::parse_header_lines("X-Token: a:b\nmissing separator\nReferer:\n");
+        assert_eq!(vec![(String::from("X-Token"), String::from("a:b")),
+                        (String::from("Referer"), String::new())],
+                   headers);
+    }
+
+    #[test]
+    fn report_focused_element() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert_eq!(None, ui.focused_element(0, 0));
+        ui.focus_element(0, 0, ElementInfo::new("INPUT", "email", true));
+        ui.write_command("focused", r#"
+        function description() return "" end
+        function run()
+          local tag, kind, editable = focused_element(0, 0)
+          set_command_field_text(0, string.format("%s %s %s", tag, kind, tostring(editable)))
+          return true
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("focused").error);
+        ui.assert_command_field_text(0, "input email true");
+        ui.set_uri(0, 0, "https://example.org");
+        assert_eq!(None, ui.focused_element(0, 0));
+    }
+
+    #[test]
+    fn report_unavailable_process_stats() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.write_command("memory", r#"
+        function description() return "" end
+        function run()
+          local pid, memory = webview_process_stats(0, 0)
+          set_command_field_text(0, string.format("%d %d", pid, memory))
+          return true
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("memory").error);
+        ui.assert_command_field_text(0, "0 0");
+    }
+
+    #[test]
+    fn query_elements_in_page() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        let mut link = ElementInfo::new("a", "", false).unwrap();
+        link.text = String::from("More");
+        link.attributes = vec![(String::from("href"), String::from("/more"))];
+        link.bounds = ElementBounds { x: 10.0, y: 20.0, width: 40.0, height: 12.0 };
+        ui.set_query_result(0, 0, "a[href]", vec![link]);
+        ui.write_command("links", r#"
+        function description() return "" end
+        function run()
+          return query_selector_all(0, 0, "a[href]")
+        end
+        function on_elements_queried()
+          local link = elements[1]
+          set_command_field_text(window_index, string.format("%d %s %s %s %s %d", #elements, selector,
+                                                             link.tag_name, link.text, link.href, link.width))
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("links").error);
+        ui.assert_command_field_text(0, "1 a[href] a More /more 40");
+        assert!(!ui.query_selector_all(0, 1, "a[href]", "links"));
+    }
+
+    #[test]
+    fn fill_form_from_config() {
+        let ui = TestFixture::new("[form-autofill]\nemail = \"me@example.com\"\npassword = \"secret\"");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        let field = ElementInfo::new("input", "email", true).unwrap();
+        ui.set_query_result(0, 0, &autofill_selector("email"), vec![field.clone(), field]);
+        ui.write_command("fill", r#"
+        function description() return "" end
+        function run()
+          return fill_form(config_file_path, 0, 0)
+        end
+        function on_form_filled()
+          set_command_field_text(window_index, tostring(filled_count))
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("fill").error);
+        ui.assert_command_field_text(0, "2");
+        assert!(ui.calls().iter().any(|call| call.contains("\"me@example.com\"]")));
+        assert!(!ui.calls().iter().any(|call| call.contains("secret")));
+    }
+
+    #[test]
+    fn load_html_from_command() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.write_command("html", r#"
+        function description() return "" end
+        function run()
+          local window_index = focused_window_index()
+          local webview_index = focused_webview_index(window_index)
+          return load_html(window_index, webview_index, "<p>kitten</p>", arguments[1] or "")
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("html https://example.org/").error);
+        ui.assert_called("load_html(0, 0, <p>kitten</p>, Some(\"https://example.org/\"))");
+        ui.assert_uri(0, 0, "https://example.org/");
+        assert_eq!(None, ui.submit_command("html").error);
+        ui.assert_called("load_html(0, 0, <p>kitten</p>, None)");
+        ui.assert_uri(0, 0, "about:blank");
+        assert!(ui.can_go_back(0, 0));
+    }
+
+    #[test]
+    fn persist_command_values() {
+        let ui = TestFixture::new("");
+        let counter = r#"
+        function description() return "" end
+        function run()
+          local count = tonumber(kv_get("count")) or 0
+          return kv_set("count", tostring(count + 1))
+        end
+        "#;
+        ui.write_command("counter", counter);
+        ui.write_command("other", counter);
+        assert_eq!(None, ui.submit_command("counter").error);
+        assert_eq!(None, ui.submit_command("other").error);
+        assert_eq!(0, ui.execute("counter").exit_code());
+        ui.assert_called("kv_set(counter, count, Some(\"2\"))");
+        ui.assert_called("kv_set(other, count, Some(\"1\"))");
+        assert!(ui.dir.join("kv-store.toml").is_file());
+        let reopened = HeadlessUI::<LuaEngine>::with_config_path(ui.dir.join("config.toml").to_str().unwrap()).unwrap();
+        assert_eq!(Some(String::from("2")), reopened.kv_get("counter", "count"));
+        assert_eq!(Some(String::from("1")), reopened.kv_get("other", "count"));
+        assert_eq!(None, reopened.kv_get("search", "count"));
+    }
+
     #[allow(unused_must_use)]
     fn cleanup_script(path: PathBuf) {
         remove_file(path);
@@ -6,7 +6,7 @@ use std::error::Error;
 use std::fs::File;
 use std::fmt;
 
-use super::ui::{ApplicationUI,BufferEvent};
+use super::ui::{ApplicationUI,BufferEvent,DownloadEvent,PageEvent};
 
 /// A sentinel value for representing empty optional numbers to scripting
 /// languages without optionals
@@ -49,26 +49,54 @@ pub trait ScriptingEngine {
     /// the description event trigger
     fn describe(file: File) -> ScriptResult<String>;
 
+    /// Evaluate the contents of a file within the scripting runtime and
+    /// execute the optional argument specification event trigger, returning
+    /// `None` if the file does not declare one
+    fn argspec(file: File) -> ScriptResult<Option<String>>;
+
+    /// Evaluate the contents of a file within the scripting runtime and
+    /// execute the optional completion source event trigger, returning `None`
+    /// if the file does not declare one
+    fn completion_source(file: File) -> ScriptResult<Option<String>>;
+
     /// Evaluate the contents of a file within the scripting runtime and execute
     /// the event trigger for running a command directly, providing the
-    /// arguments to the scope
-    fn execute<T, S>(file: File, arguments: Vec<String>, ui: &T, config_path: &str) -> ScriptResult<bool>
+    /// arguments to the scope. Values stored by the command are kept in a
+    /// namespace named after the command.
+    fn execute<T, S>(file: File, name: &str, arguments: Vec<String>, ui: &T, config_path: &str) -> ScriptResult<bool>
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 
     /// Evaluate the contents of a file within the scripting runtime and execute
     /// the event trigger for getting autocompletion results, providing the
-    /// arguments and prefix to the scope
-    fn autocomplete<T, S>(file: File, arguments: Vec<String>, prefix: &str, ui: &T, config_path: &str) -> ScriptResult<Vec<String>>
+    /// arguments, index of the argument being completed, and prefix to the
+    /// scope
+    fn autocomplete<T, S>(file: File, name: &str, arguments: Vec<String>, argument_index: usize, prefix: &str, ui: &T, config_path: &str) -> ScriptResult<Vec<String>>
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 
     /// Evaluate the contents of a file within the scripting runtime and execute
-    /// the event trigger matching the BufferEvent, provided the window index,
-    /// webview index, and requested URI to the scope.
-    fn on_buffer_event<T, S>(file: File, ui: &T, config_path: &str, window_index: u32,
+    /// the event trigger matching the BufferEvent, provided the arguments,
+    /// window index, webview index, and requested URI to the scope.
+    fn on_buffer_event<T, S>(file: File, name: &str, arguments: Vec<String>, ui: &T, config_path: &str, window_index: u32,
                              webview_index: u32, requested_uri: Option<&str>,
                              event: &BufferEvent) -> ScriptResult<()>
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
+
+    /// Evaluate the contents of a file within the scripting runtime and execute
+    /// the event trigger matching the DownloadEvent, providing the download
+    /// identifier and event details to the scope.
+    fn on_download_event<T, S>(file: File, name: &str, ui: &T, config_path: &str, download_id: u32,
+                               event: &DownloadEvent) -> ScriptResult<()>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Evaluate the contents of a file within the scripting runtime and execute
+    /// the event trigger matching the PageEvent, providing the window index,
+    /// webview index, and page response to the scope.
+    fn on_page_event<T, S>(file: File, name: &str, ui: &T, config_path: &str, window_index: u32,
+                           webview_index: u32, event: &PageEvent) -> ScriptResult<()>
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
 }
@@ -0,0 +1,154 @@
+//! Persistent key-value storage for commands
+//!
+//! Commands save small pieces of state, such as the last search or a
+//! counter, which are kept across restarts in a TOML file. Each namespace,
+//! the name of the command for values saved by scripts, is stored as a
+//! table so that commands using the same keys do not collide.
+//!
+//! ```toml
+//! [search]
+//! last = "rust ownership"
+//!
+//! [counter]
+//! count = "3"
+//! ```
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration,Instant};
+
+use toml::{Parser,Table,Value};
+
+/// String values grouped into namespaces, written to a file when flushed
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::store::KeyValueStore;
+///
+/// let mut store = KeyValueStore::open(None);
+/// store.set("search", "last", Some("rust"));
+/// assert_eq!(Some(String::from("rust")), store.get("search", "last"));
+/// assert_eq!(None, store.get("counter", "last"));
+/// store.set("search", "last", None);
+/// assert_eq!(None, store.get("search", "last"));
+/// ```
+pub struct KeyValueStore {
+    path: Option<String>,
+    values: Table,
+    modified: bool,
+    last_flush: Option<Instant>,
+}
+
+impl KeyValueStore {
+
+    /// Open a store backed by the file at a path, reading any saved values.
+    /// Without a path, values are only kept in memory.
+    pub fn open(path: Option<String>) -> Self {
+        let values = path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| {
+                let table = Parser::new(&contents).parse();
+                if table.is_none() {
+                    warn!("Ignoring invalid key-value store: {}", path.as_ref().unwrap());
+                }
+                table
+            })
+            .unwrap_or(BTreeMap::new());
+        KeyValueStore { path: path, values: values, modified: false, last_flush: None }
+    }
+
+    /// The path of the file backing the store, if any
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_ref().map(|path| path.as_str())
+    }
+
+    /// The value of a key within a namespace
+    pub fn get(&self, namespace: &str, key: &str) -> Option<String> {
+        match self.values.get(namespace) {
+            Some(&Value::Table(ref table)) => table.get(key)
+                .and_then(|value| value.as_str())
+                .map(String::from),
+            _ => None
+        }
+    }
+
+    /// Set the value of a key within a namespace, removing the key if the
+    /// value is `None`. Empty namespaces are removed.
+    pub fn set(&mut self, namespace: &str, key: &str, value: Option<&str>) {
+        let mut table = match self.values.remove(namespace) {
+            Some(Value::Table(table)) => table,
+            _ => BTreeMap::new(),
+        };
+        match value {
+            Some(value) => { table.insert(String::from(key), Value::String(String::from(value))); },
+            None => { table.remove(key); },
+        }
+        if !table.is_empty() {
+            self.values.insert(String::from(namespace), Value::Table(table));
+        }
+        self.modified = true;
+    }
+
+    /// Write the store to its file if values changed since the last flush,
+    /// creating any missing parent directories
+    pub fn flush(&mut self) -> bool {
+        self.last_flush = Some(Instant::now());
+        if !self.modified {
+            return true;
+        }
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => return true,
+        };
+        if let Some(parent) = Path::new(&path).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let contents = Value::Table(self.values.clone()).to_string();
+        match fs::write(&path, contents) {
+            Ok(()) => {
+                self.modified = false;
+                true
+            },
+            Err(err) => {
+                warn!("Failed to write key-value store ({}): {}", path, err);
+                false
+            }
+        }
+    }
+
+    /// Flush the store if `interval` has passed since the last flush
+    pub fn flush_after(&mut self, interval: Duration) -> bool {
+        match self.last_flush {
+            Some(last) if last.elapsed() < interval => true,
+            _ => self.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn persist_values_when_flushed() {
+        let path = temp_dir().join("webkitten-store").join("kv.toml");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let path = path.to_str().map(String::from);
+        let mut store = KeyValueStore::open(path.clone());
+        store.set("search", "last", Some("rust"));
+        store.set("counter", "count", Some("1"));
+        assert!(store.flush_after(Duration::from_secs(60)));
+        store.set("counter", "count", Some("2"));
+        assert!(store.flush_after(Duration::from_secs(60)));
+        assert_eq!(Some(String::from("1")), KeyValueStore::open(path.clone()).get("counter", "count"));
+        assert!(store.flush());
+        let store = KeyValueStore::open(path.clone());
+        assert_eq!(Some(String::from("rust")), store.get("search", "last"));
+        assert_eq!(Some(String::from("2")), store.get("counter", "count"));
+        let _ = fs::remove_dir_all(temp_dir().join("webkitten-store"));
+    }
+}
@@ -1,7 +1,16 @@
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use log::LevelFilter;
+use percent_encoding::percent_decode_str;
+use toml::Value;
 use url::Url;
 use script::ScriptingEngine;
 
+use command::CommandExplanation;
+use contentblock::escape_json;
 use keybinding;
 
 
@@ -16,23 +25,53 @@ pub trait ApplicationUI<S>: Sized where S: ScriptingEngine {
     /// Copy text to the system clipboard
     fn copy(&self, text: &str);
 
+    /// Show a file or directory in the system file manager, revealing the
+    /// nearest existing parent directory if the path does not exist. Does
+    /// nothing by default.
+    fn reveal_in_file_manager(&self, path: &str) {
+        warn!("Revealing files is not supported: {}", path);
+    }
+
+    /// Display a desktop notification, unless notifications are disabled
+    /// using `general.notifications`. Does nothing by default.
+    fn notify(&self, title: &str, body: &str) {
+        warn!("Notifications are not supported: {}: {}", title, body);
+    }
+
     /// Send a command to the event handler to be run in a particular window
     fn execute_command(&self, window_index: Option<u32>, text: &str);
 
+    /// Run a command in every open window, returning the index of each
+    /// window where the command failed along with the failure
+    fn execute_command_in_all_windows(&self, text: &str) -> Vec<(u32, CommandError)>;
+
     /// The index of the focused window
     fn focused_window_index(&self) -> Option<u32>;
 
     /// Number of open windows
     fn window_count(&self) -> u32;
 
+    /// `true` if a window exists at a given index
+    fn window_exists(&self, window_index: u32) -> bool;
+
     /// Open a new window
     fn open_window<U, B>(&self, uri: Option<U>, config: Option<B>) -> u32
         where U: Into<String>,
               B: BrowserConfiguration;
 
+    /// Open a window where all webviews use private browsing, regardless
+    /// of site options, returning the index of the new window. Browsing data
+    /// is discarded when the window closes.
+    fn open_private_window(&self) -> u32;
+
     /// Close a window
     fn close_window(&self, index: u32);
 
+    /// Reopen the most recently closed window with a webview for each
+    /// non-private webview open when it closed, returning the index of the
+    /// new window
+    fn reopen_closed_window(&self) -> Option<u32>;
+
     /// Focus window at index
     fn focus_window(&self, index: u32);
 
@@ -45,6 +84,41 @@ pub trait ApplicationUI<S>: Sized where S: ScriptingEngine {
     /// Change the dimensions of a specified window
     fn resize_window(&self, window_index: u32, width: u32, height: u32);
 
+    /// The area of the screen showing the focused window available to
+    /// windows, excluding menu bars and docks, or `None` if unknown
+    fn screen_frame(&self) -> Option<WindowFrame>;
+
+    /// Change the position and dimensions of a specified window
+    fn set_window_frame(&self, window_index: u32, frame: WindowFrame);
+
+    /// `true` if a specified window is minimized
+    fn window_minimized(&self, window_index: u32) -> bool;
+
+    /// Minimize a specified window into the Dock or task bar, if not
+    /// already minimized
+    fn minimize_window(&self, window_index: u32);
+
+    /// Restore a specified window from the Dock or task bar, if minimized
+    fn restore_window(&self, window_index: u32);
+
+    /// Arrange all windows which are not minimized into a grid filling the
+    /// screen showing the focused window, returning the number of windows
+    /// arranged
+    fn tile_windows(&self) -> u32 {
+        let screen = match self.screen_frame() {
+            Some(screen) => screen,
+            None => return 0,
+        };
+        let windows: Vec<u32> = (0 .. self.window_count())
+            .filter(|index| !self.window_minimized(*index))
+            .collect();
+        let frames = WindowFrame::grid(windows.len() as u32, screen);
+        for (window_index, frame) in windows.iter().zip(frames) {
+            self.set_window_frame(*window_index, frame);
+        }
+        windows.len() as u32
+    }
+
     /// Text in the command bar of a specified window
     fn command_field_text(&self, window_index: u32) -> String;
 
@@ -57,12 +131,41 @@ pub trait ApplicationUI<S>: Sized where S: ScriptingEngine {
     /// Set the visibility in the command bar of a specified window
     fn set_command_field_visible(&self, window_index: u32, visible: bool);
 
+    /// `true` if a part of the chrome of a specified window is visible
+    fn chrome_visible(&self, window_index: u32, component: Chrome) -> bool {
+        match component {
+            Chrome::CommandBar => self.command_field_visible(window_index),
+        }
+    }
+
+    /// Show or hide a part of the chrome of a specified window. A hidden
+    /// command bar is shown again when focused, such as by a keybinding.
+    fn set_chrome_visible(&self, window_index: u32, component: Chrome, visible: bool) {
+        match component {
+            Chrome::CommandBar => self.set_command_field_visible(window_index, visible),
+        }
+    }
+
+    /// The parts of the chrome of a specified window which are hidden
+    fn hidden_chrome(&self, window_index: u32) -> Vec<Chrome> {
+        ALL_CHROME.iter()
+            .filter(|component| !self.chrome_visible(window_index, **component))
+            .cloned()
+            .collect()
+    }
+
     /// Title of a specified window
     fn window_title(&self, window_index: u32) -> String;
 
     /// Set the title of a specified window
     fn set_window_title(&self, window_index: u32, title: &str);
 
+    /// The URI of the document a window represents, if any
+    fn represented_uri(&self, window_index: u32) -> Option<String>;
+
+    /// Set the URI of the document a window represents, such as by an icon in
+    /// its title bar which can be dragged, or clear it if `None`
+    fn set_represented_uri(&self, window_index: u32, uri: Option<&str>);
 
     /// Index of the webview currently visible in a specified window
     fn focused_webview_index(&self, window_index: u32) -> Option<u32>;
@@ -70,53 +173,1012 @@ pub trait ApplicationUI<S>: Sized where S: ScriptingEngine {
     /// Number of webviews in a window
     fn webview_count(&self, window_index: u32) -> u32;
 
+    /// The time since a webview was opened, or zero if it does not exist
+    fn webview_age(&self, window_index: u32, webview_index: u32) -> Duration;
+
+    /// The time since a webview was last focused, which is zero for the
+    /// focused webview of a window or if the webview does not exist
+    fn webview_idle(&self, window_index: u32, webview_index: u32) -> Duration;
+
+    /// `true` if a webview exists at a given index in a specified window
+    fn webview_exists(&self, window_index: u32, webview_index: u32) -> bool {
+        webview_index < self.webview_count(window_index)
+    }
+
+    /// Check that a webview exists at a given index in a specified window,
+    /// describing which index is invalid if not
+    fn check_webview(&self, window_index: u32, webview_index: u32) -> UiResult<()> {
+        if !self.window_exists(window_index) {
+            Err(UiError::WindowNotFound(window_index))
+        } else if !self.webview_exists(window_index, webview_index) {
+            Err(UiError::WebViewNotFound(window_index, webview_index))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The window and webview indices of every open webview, ordered by
+    /// window then webview
+    fn all_webviews(&self) -> Vec<(u32, u32)> {
+        (0 .. self.window_count())
+            .flat_map(|window_index| {
+                (0 .. self.webview_count(window_index)).map(move |webview_index| (window_index, webview_index))
+            })
+            .collect()
+    }
+
+    /// The window and webview indices of the first open webview showing a
+    /// URI, scanning windows then webviews. Webviews showing the exact URI are
+    /// preferred to webviews showing another page on the same host.
+    fn find_webview_by_uri(&self, uri: &str) -> Option<(u32, u32)> {
+        self.find_webview_matching(uri, TabMatch::Uri)
+            .or_else(|| self.find_webview_matching(uri, TabMatch::Host))
+    }
+
+    /// The window and webview indices of the first open webview matching a
+    /// URI, scanning windows then webviews
+    fn find_webview_matching(&self, uri: &str, matching: TabMatch) -> Option<(u32, u32)> {
+        self.all_webviews().into_iter()
+            .find(|&(window_index, webview_index)| matching.matches(&self.uri(window_index, webview_index), uri))
+    }
+
+    /// The URIs of open webviews containing a query in the URI or title,
+    /// ignoring case, scanning windows then webviews. Each URI is listed once.
+    fn tab_completions(&self, query: &str) -> Vec<String> {
+        let query = query.to_lowercase();
+        let mut uris: Vec<String> = vec![];
+        for (window_index, webview_index) in self.all_webviews() {
+            let uri = self.uri(window_index, webview_index);
+            if uri.is_empty() || uris.contains(&uri) {
+                continue;
+            }
+            if uri.to_lowercase().contains(&query)
+                || self.webview_title(window_index, webview_index).to_lowercase().contains(&query) {
+                uris.push(uri);
+            }
+        }
+        uris
+    }
+
     /// Open a new webview in a specified window
     fn open_webview<'a, U, B>(&self, window_index: u32, uri: Option<U>, config: Option<B>)
         where U: Into<String>,
               B: BrowserConfiguration;
 
+    /// Open a new webview for a URI in a specified window once fewer than
+    /// `general.max-concurrent-loads` pages are loading
+    fn queue_webview(&self, window_index: u32, uri: &str);
+
     /// Close a webview in a specified window
     fn close_webview(&self, window_index: u32, webview_index: u32);
 
+    /// Move a webview to another index in a window, shifting the webviews
+    /// between. The focused webview stays focused. Returns `false` if either
+    /// index is out of bounds.
+    fn move_webview(&self, window_index: u32, from: u32, to: u32) -> bool;
+
+    /// Reorder the webviews of a window using `move_webview`, keeping the
+    /// order of webviews with equal keys. Returns `false` if the window does
+    /// not exist.
+    fn sort_webviews(&self, window_index: u32, key: SortKey) -> bool {
+        if !self.window_exists(window_index) {
+            return false;
+        }
+        let count = self.webview_count(window_index);
+        let mut order: Vec<u32> = (0 .. count).collect();
+        match key {
+            SortKey::Host => order.sort_by_cached_key(|&index| {
+                let uri = self.uri(window_index, index);
+                uri_host(&uri).unwrap_or(uri).to_lowercase()
+            }),
+            SortKey::Title => order.sort_by_cached_key(|&index| {
+                self.webview_title(window_index, index).to_lowercase()
+            }),
+            SortKey::Recency => order.sort_by_cached_key(|&index| self.webview_idle(window_index, index)),
+        }
+        // The original index of the webview at each position as moves are made
+        let mut positions: Vec<u32> = (0 .. count).collect();
+        for (to, index) in order.into_iter().enumerate() {
+            if let Some(from) = positions.iter().position(|&position| position == index) {
+                if from != to && self.move_webview(window_index, from as u32, to as u32) {
+                    let moved = positions.remove(from);
+                    positions.insert(to, moved);
+                }
+            }
+        }
+        true
+    }
+
     /// Focus a webview in a specified window, hiding the current webview
     fn focus_webview(&self, window_index: u32, webview_index: u32);
 
+    /// Focus the webview after the focused webview in a window, wrapping
+    /// around to the first webview. Does nothing if fewer than two webviews
+    /// are open.
+    fn focus_next_webview(&self, window_index: u32) {
+        let count = self.webview_count(window_index);
+        if let Some(index) = self.focused_webview_index(window_index)
+            .and_then(|index| tab_switch_index(TabSwitchOrder::Index, &[], index, count, true)) {
+            self.focus_webview(window_index, index);
+        }
+    }
+
+    /// Focus the webview before the focused webview in a window, wrapping
+    /// around to the last webview. Does nothing if fewer than two webviews
+    /// are open.
+    fn focus_previous_webview(&self, window_index: u32) {
+        let count = self.webview_count(window_index);
+        if let Some(index) = self.focused_webview_index(window_index)
+            .and_then(|index| tab_switch_index(TabSwitchOrder::Index, &[], index, count, false)) {
+            self.focus_webview(window_index, index);
+        }
+    }
+
     /// Reload a webview in a specified window
     fn reload_webview(&self, window_index: u32, webview_index: u32, disable_filters: bool);
 
+    /// Stop loading the page in a webview, returning `false` if the webview
+    /// does not exist or was not loading
+    fn stop_webview(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// Reload every open webview, returning the number of webviews reloaded
+    fn reload_all(&self, disable_filters: bool) -> u32 {
+        let webviews = self.all_webviews();
+        for &(window_index, webview_index) in &webviews {
+            self.reload_webview(window_index, webview_index, disable_filters);
+        }
+        webviews.len() as u32
+    }
+
+    /// Stop loading every open webview, returning the number of webviews
+    /// which were loading
+    fn stop_all(&self) -> u32 {
+        self.all_webviews().into_iter()
+            .filter(|&(window_index, webview_index)| self.stop_webview(window_index, webview_index))
+            .count() as u32
+    }
+
+    /// Return a webview to a clean state without changing its index by
+    /// stopping any load, clearing the back/forward list and injected user
+    /// scripts, and loading the start page. Returns `false` if the webview
+    /// does not exist.
+    fn reset_webview(&self, window_index: u32, webview_index: u32) -> bool;
+
     /// Load a URI in a webview
     fn set_uri(&self, window_index: u32, webview_index: u32, uri: &str);
 
+    /// Display an HTML string in a webview, resolving relative URIs against
+    /// `base_uri`. Returns whether the load started.
+    fn load_html(&self, window_index: u32, webview_index: u32, html: &str, base_uri: Option<&str>) -> bool;
+
     /// Go back to the previously loaded resource in a webview
     fn go_back(&self, window_index: u32, webview_index: u32) -> bool;
 
     /// Go forward to the next loaded resource in a webview
     fn go_forward(&self, window_index: u32, webview_index: u32) -> bool;
 
+    /// Whether a webview has a previously loaded resource to go back to
+    fn can_go_back(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// Whether a webview has a next loaded resource to go forward to
+    fn can_go_forward(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// The window and webview indices of the webview visible in the focused
+    /// window, or `None` if no window is open
+    fn focused_webview(&self) -> Option<(u32, u32)> {
+        self.focused_window_index().and_then(|window_index| {
+            self.focused_webview_index(window_index)
+                .map(|webview_index| (window_index, webview_index))
+        })
+    }
+
+    /// Load a URI in the focused webview, returning `false` if there is no
+    /// focused webview
+    fn set_uri_focused(&self, uri: &str) -> bool {
+        self.focused_webview()
+            .map(|(window_index, webview_index)| self.set_uri(window_index, webview_index, uri))
+            .is_some()
+    }
+
+    /// Go back to the previously loaded resource in the focused webview
+    fn go_back_focused(&self) -> bool {
+        self.focused_webview()
+            .map(|(window_index, webview_index)| self.go_back(window_index, webview_index))
+            .unwrap_or(false)
+    }
+
+    /// Go forward to the next loaded resource in the focused webview
+    fn go_forward_focused(&self) -> bool {
+        self.focused_webview()
+            .map(|(window_index, webview_index)| self.go_forward(window_index, webview_index))
+            .unwrap_or(false)
+    }
+
+    /// Reload the focused webview, returning `false` if there is no focused
+    /// webview
+    fn reload_webview_focused(&self, disable_filters: bool) -> bool {
+        self.focused_webview()
+            .map(|(window_index, webview_index)| self.reload_webview(window_index, webview_index, disable_filters))
+            .is_some()
+    }
+
     /// Get the currently loaded URI or empty string
     fn uri(&self, window_index: u32, webview_index: u32) -> String;
 
-    /// Find a string within the selected web view
-    fn find_string(&self, window_index: u32, webview_index: u32, query: &str);
+    /// Find a string within the selected web view using the options last
+    /// passed to `find_string_with_options` for the webview, if any
+    fn find_string(&self, window_index: u32, webview_index: u32, query: &str) {
+        let options = self.find_options(window_index, webview_index);
+        self.find_string_with_options(window_index, webview_index, query, options);
+    }
+
+    /// Find a string within a webview, keeping the options for later calls
+    /// to `find_string` in the webview
+    fn find_string_with_options(&self, window_index: u32, webview_index: u32, query: &str, options: FindOptions);
+
+    /// The options used when finding text in a webview, which are the
+    /// defaults until set using `find_string_with_options`
+    fn find_options(&self, window_index: u32, webview_index: u32) -> FindOptions;
 
-    /// Hide results from a previous find invocation (if applicable)
+    /// Hide results from a previous find invocation (if applicable),
+    /// including all highlighted matches
     fn hide_find_results(&self, window_index: u32, webview_index: u32);
 
+    /// Set the color used to highlight the current find match in a webview,
+    /// overriding `find-highlight-color`, or restore the configured color if
+    /// `color` is `None`. Returns `false` if the color is not valid.
+    fn set_find_highlight(&self, window_index: u32, webview_index: u32, color: Option<&str>) -> bool;
+
     /// Get the title of the currently loaded URI or empty string
     fn webview_title(&self, window_index: u32, webview_index: u32) -> String;
 
     /// Run a JavaScript snippet in a webview
     fn run_javascript(&self, window_index: u32, webview_index: u32, script: &str);
 
+    /// Focus a form field in a webview by its position among the visible
+    /// fields of the page, starting from zero. Does nothing if there are not
+    /// enough fields.
+    fn focus_element_by_index(&self, window_index: u32, webview_index: u32, index: u32) {
+        let script = indexed_element_script(FORM_FIELD_SELECTOR, index, "focus");
+        self.run_javascript(window_index, webview_index, &script);
+    }
+
+    /// Click a link in a webview by its position among the visible links of
+    /// the page, starting from zero. Does nothing if there are not enough
+    /// links.
+    fn click_element_by_index(&self, window_index: u32, webview_index: u32, index: u32) {
+        let script = indexed_element_script(LINK_SELECTOR, index, "click");
+        self.run_javascript(window_index, webview_index, &script);
+    }
+
+    /// Enter values into the form fields of the page in a webview whose
+    /// `name` or `autocomplete` attribute matches each key. Password fields
+    /// are never filled. Once the page responds, the number of fields filled
+    /// is passed to `Engine::on_page_event` for the command named `command`.
+    /// Returns `false` if the webview does not exist.
+    fn fill_form_fields(&self, window_index: u32, webview_index: u32, values: &[(String, String)],
+                        command: &str) -> bool;
+
+    /// Add a script to a webview which runs in each document loaded, either
+    /// before (`at_start`) or after the document content is loaded
+    fn inject_user_script(&self, window_index: u32, webview_index: u32, script: &str, at_start: bool);
+
+    /// Remove all scripts added to a webview using `inject_user_script`
+    fn clear_user_scripts(&self, window_index: u32, webview_index: u32);
+
+    /// Save the content of a webview to a file, creating any missing parent
+    /// directories. The content is written asynchronously. Returns `true` if
+    /// the webview exists and the parent directory could be created.
+    fn save_page(&self, window_index: u32, webview_index: u32, path: &str, format: SaveFormat) -> bool;
+
+    /// Set headers added to requests made when navigating in a webview,
+    /// replacing any previously set headers. Headers with empty values are
+    /// removed from requests. Headers with invalid names or values are
+    /// ignored.
+    fn set_custom_headers(&self, window_index: u32, webview_index: u32, headers: &[(String, String)]);
+
+    /// Statistics for the text of the page in a webview, excluding script and
+    /// style content, or `None` if the page has not finished loading
+    fn page_statistics(&self, window_index: u32, webview_index: u32) -> Option<PageStats>;
+
+    /// Navigation timing of the page in a webview, with zeros for any
+    /// measurement which is not yet available
+    fn page_timing(&self, window_index: u32, webview_index: u32) -> PageTiming;
+
+    /// The process rendering the content of a webview and its memory use,
+    /// with zeros for values which are not available
+    fn webview_process_stats(&self, window_index: u32, webview_index: u32) -> ProcessStats;
+
+    /// The element with keyboard focus in the page of a webview, or `None`
+    /// if no element in the page is focused
+    fn focused_element(&self, window_index: u32, webview_index: u32) -> Option<ElementInfo>;
+
+    /// Find the elements in the page of a webview matching a CSS selector.
+    /// Once the page responds, the elements are passed in document order to
+    /// `Engine::on_page_event` for the command named `command`, with no
+    /// elements if the selector is invalid. Returns `false` if the webview
+    /// does not exist.
+    fn query_selector_all(&self, window_index: u32, webview_index: u32, selector: &str, command: &str) -> bool;
+
+    /// Display a JavaScript dialog opened by the page in a webview, blocking
+    /// until it is answered
+    fn show_dialog(&self, window_index: u32, webview_index: u32, kind: &DialogKind, message: &str) -> DialogResponse;
+
+    /// Zoom the image or PDF document in a webview so it fits within the
+    /// viewport, fitting it again as the window resizes until another page is
+    /// loaded. Returns `false` without changing the zoom for other documents,
+    /// such as HTML pages.
+    fn fit_to_window(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// Whether an element in the page of a webview, such as a video, is
+    /// displayed fullscreen
+    fn is_element_fullscreen(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// Return an element displayed fullscreen in a webview to the page
+    fn exit_element_fullscreen(&self, window_index: u32, webview_index: u32) {
+        self.run_javascript(window_index, webview_index, EXIT_FULLSCREEN_SCRIPT);
+    }
+
+    /// The input mode of a webview, which is `Normal` until an editable
+    /// element is focused or the mode is changed using `set_input_mode`
+    fn input_mode(&self, window_index: u32, webview_index: u32) -> InputMode;
+
+    /// Change the input mode of a webview, invoking the commands in
+    /// `commands.on-input-mode-change`. Returns `true` if the mode changed.
+    fn set_input_mode(&self, window_index: u32, webview_index: u32, mode: InputMode) -> bool;
+
+    /// Whether private browsing is configured for the URI of a webview
+    fn private_browsing(&self, window_index: u32, webview_index: u32) -> bool;
+
+    /// Enable or disable private browsing for the host of the URI of a
+    /// webview, saving the option to the configuration file, and reopen the
+    /// webview at the same index with a data store matching the option. The
+    /// back and forward history of the webview is lost. Returns `true` if the
+    /// configuration was saved.
+    fn set_private_browsing(&self, window_index: u32, webview_index: u32, enabled: bool) -> bool;
+
     /// Apply a stylesheet to a webview
     fn apply_styles(&self, window_index: u32, webview_index: u32, styles: &str);
+
+    /// Apply a stylesheet to every open webview. Webviews which are loading
+    /// receive the stylesheet once the load finishes.
+    fn apply_styles_all(&self, styles: &str);
+
+    /// Override the cache policy used for subsequent loads
+    fn set_cache_mode(&self, mode: CacheMode);
+
+    /// Scroll the content of a webview to a position
+    fn scroll_to(&self, window_index: u32, webview_index: u32, x: f64, y: f64);
+
+    /// Change the color scheme preference reported to content in a webview
+    fn set_color_scheme(&self, window_index: u32, webview_index: u32, scheme: ColorScheme);
+
+    /// Replace the configured stylesheets in all webviews with the current
+    /// contents of the stylesheet files
+    fn reload_stylesheets(&self);
+
+    /// Reload configuration from disk, applying updated content blocking
+    /// rules and stylesheets. Returns `true` if the configuration was parsed
+    /// successfully.
+    fn reload_config(&self) -> bool;
+
+    /// Recompile content blocking rules from configuration and filter lists
+    /// and apply them to all webviews, showing the number of rules compiled
+    /// in the command bar of a window once finished. Returns `false` if no
+    /// content filtering is configured.
+    fn reload_filters(&self, window_index: Option<u32>) -> bool;
+
+    /// Describe how command bar text would be parsed into a command
+    fn explain_command(&self, text: &str) -> CommandExplanation;
+
+    /// The commands which would run in response to a buffer event for a URI,
+    /// with tokens replaced and aliases resolved
+    fn commands_for_uri_event(&self, event: &BufferEvent, uri: &str) -> Vec<String>;
+
+    /// Disable or enable a command by name, saving the change to the
+    /// configuration file. Returns `true` if the configuration was saved.
+    fn set_command_disabled(&self, name: &str, disabled: bool) -> bool;
+
+    /// The value a command saved for a key within a namespace, kept across
+    /// restarts
+    fn kv_get(&self, namespace: &str, key: &str) -> Option<String>;
+
+    /// Save a value for a key within a namespace, usually the name of the
+    /// command, or remove the key if the value is `None`
+    fn kv_set(&self, namespace: &str, key: &str, value: Option<&str>) -> bool;
+
+    /// Whether pages can be navigated using a movable text cursor
+    fn caret_browsing(&self) -> bool;
+
+    /// Turn caret browsing on or off in all webviews and new webviews for the
+    /// rest of the session, overriding `general.caret-browsing`
+    fn set_caret_browsing(&self, enabled: bool);
+
+    /// Compile content blocking rules and apply them to all webviews,
+    /// keeping the previous rules if compilation fails. The result is passed
+    /// to `Engine::on_content_filter_compiled` along with `window_index`.
+    fn set_content_filter(&self, filter: &str, window_index: Option<u32>);
+}
+
+pub type UiResult<T> = Result<T, UiError>;
+
+/// Errors resulting from addressing UI components which do not exist
+#[derive(Debug,Clone,PartialEq)]
+pub enum UiError {
+    /// No window exists at the index
+    WindowNotFound(u32),
+    /// No webview exists at the webview index (second) in the window at the
+    /// window index (first)
+    WebViewNotFound(u32, u32),
+}
+
+impl Error for UiError {
+
+    fn description(&self) -> &str {
+        match self {
+            &UiError::WindowNotFound(_) => "window not found",
+            &UiError::WebViewNotFound(_, _) => "webview not found",
+        }
+    }
+}
+
+impl fmt::Display for UiError {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &UiError::WindowNotFound(window_index) =>
+                write!(f, "No window found at index {}", window_index),
+            &UiError::WebViewNotFound(window_index, webview_index) =>
+                write!(f, "No webview found at index {} in window {}",
+                       webview_index, window_index),
+        }
+    }
+}
+
+/// File formats for saving the content of a webview
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SaveFormat {
+    /// The serialized document
+    Html,
+    /// The document and its subresources as a web archive
+    WebArchive,
+}
+
+impl SaveFormat {
+
+    /// Parse a save format from a name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "html" => Some(SaveFormat::Html),
+            "webarchive" => Some(SaveFormat::WebArchive),
+            _ => None
+        }
+    }
+}
+
+/// How open webviews are matched against a URI
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TabMatch {
+    /// Match webviews showing the exact URI
+    Uri,
+    /// Match webviews showing any page on the same host
+    Host,
+}
+
+impl TabMatch {
+
+    /// Parse a match type from a configuration value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "uri" => Some(TabMatch::Uri),
+            "host" => Some(TabMatch::Host),
+            _ => None
+        }
+    }
+
+    /// Whether the URI shown in a webview matches a URI
+    pub fn matches(&self, webview_uri: &str, uri: &str) -> bool {
+        match *self {
+            TabMatch::Uri => webview_uri == uri,
+            TabMatch::Host => {
+                let host = uri_host(uri);
+                host.is_some() && uri_host(webview_uri) == host
+            },
+        }
+    }
+}
+
+/// Average number of words read per minute when estimating reading time
+const WORDS_PER_MINUTE: u32 = 200;
+/// Milliseconds to wait after typing stops before updating incremental find
+/// results if `commands.incremental-find-delay` is unset
+const DEFAULT_INCREMENTAL_FIND_DELAY: u64 = 150;
+/// Number of failed loads of a URI in quick succession treated as a failure
+/// loop if `general.fail-loop-threshold` is unset
+const DEFAULT_FAIL_LOOP_THRESHOLD: u32 = 3;
+/// The smallest width of windows arranged into a grid
+const MIN_TILE_WIDTH: u32 = 400;
+/// The smallest height of windows arranged into a grid
+const MIN_TILE_HEIGHT: u32 = 300;
+/// The directory where downloads are saved if `general.download-dir` is unset
+const DEFAULT_DOWNLOAD_DIR: &str = "~/Downloads";
+/// The URI scheme prefixing bookmarklet scripts, removed before running them
+const BOOKMARKLET_PREFIX: &str = "javascript:";
+/// Script returning an element displayed fullscreen to the page
+const EXIT_FULLSCREEN_SCRIPT: &str = "(document.exitFullscreen || \
+    document.webkitExitFullscreen || function() {}).call(document);";
+/// Elements counted by `click_element_by_index`
+const LINK_SELECTOR: &str = "a[href], area[href]";
+/// Attributes of the elements listed by `query_selector_all`
+pub const QUERIED_ATTRIBUTES: [&str; 10] = ["id", "class", "name", "href", "src",
+                                                    "alt", "title", "value", "role", "aria-label"];
+/// Parts of `form-autofill` keys which name fields holding secrets
+const SENSITIVE_AUTOFILL_KEYS: [&str; 4] = ["password", "passwd", "cc-", "one-time-code"];
+/// Elements counted by `focus_element_by_index`
+const FORM_FIELD_SELECTOR: &str = "input:not([type=hidden]):not([disabled]), \
+    select:not([disabled]), textarea:not([disabled]), [contenteditable=true]";
+
+/// A script calling a method of the element at a position among the
+/// visible elements matching a selector, if any
+fn indexed_element_script(selector: &str, index: u32, method: &str) -> String {
+    format!("(function() {{
+  var elements = Array.prototype.filter.call(document.querySelectorAll('{}'), function(element) {{
+    return element.getClientRects().length > 0;
+  }});
+  var element = elements[{}];
+  if (element) {{ element.{}(); }}
+}})();", selector, index, method)
+}
+
+/// A script listing the elements matching a selector in the page as an
+/// array of objects with the keys `tag`, `type`, `editable`, `text`,
+/// `attributes` (an array of name and value pairs), `x`, `y`, `width`, and
+/// `height`. An invalid selector lists no elements.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::query_selector_script;
+///
+/// let script = query_selector_script(r#"a[title="x"]"); alert(1); ("#);
+/// assert!(script.contains(r#"querySelectorAll("a[title=\"x\"]\"); alert(1); (")"#));
+/// ```
+pub fn query_selector_script(selector: &str) -> String {
+    let attributes: Vec<String> = QUERIED_ATTRIBUTES.iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect();
+    format!("(function() {{
+  var elements;
+  try {{ elements = document.querySelectorAll(\"{}\"); }} catch (e) {{ return []; }}
+  var names = [{}];
+  return Array.prototype.map.call(elements, function(element) {{
+    var rect = element.getBoundingClientRect();
+    return {{
+      tag: element.tagName,
+      type: element.getAttribute('type') || '',
+      editable: element.isContentEditable || /^(input|textarea|select)$/i.test(element.tagName),
+      text: (element.textContent || '').trim(),
+      attributes: names.filter(function(name) {{ return element.hasAttribute(name); }})
+        .map(function(name) {{ return [name, element.getAttribute(name)]; }}),
+      x: rect.left, y: rect.top, width: rect.width, height: rect.height
+    }};
+  }});
+}})();", escape_json(selector), attributes.join(", "))
+}
+
+/// A selector matching the enabled, visible text fields, text areas, and
+/// menus whose `name` attribute or `autocomplete` tokens match a key,
+/// ignoring case. Password fields are excluded.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::autofill_selector;
+///
+/// let selector = autofill_selector("email");
+/// assert!(selector.starts_with(r#"input[name="email" i]:not([type=hidden])"#));
+/// assert!(selector.contains(r#"select[autocomplete~="email" i]"#));
+/// ```
+pub fn autofill_selector(key: &str) -> String {
+    let key = escape_json(key);
+    let fields: Vec<String> = ["input", "textarea", "select"].iter()
+        .flat_map(|tag| vec![format!("{}[name=\"{}\" i]", tag, key),
+                             format!("{}[autocomplete~=\"{}\" i]", tag, key)])
+        .map(|field| format!("{}:not([type=hidden]):not([type=password]):not([disabled])", field))
+        .collect();
+    fields.join(", ")
+}
+
+/// A script setting the value of each form field matching the
+/// `autofill_selector` of a key to the value of the key, notifying the page
+/// of the change as if the value had been typed. The script returns the
+/// number of fields filled.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::fill_form_script;
+///
+/// let script = fill_form_script(&[(String::from("email"), String::from("\"me\""))]);
+/// assert!(script.contains(r#""\"me\""]"#));
+/// ```
+pub fn fill_form_script(values: &[(String, String)]) -> String {
+    let fields: Vec<String> = values.iter()
+        .map(|&(ref key, ref value)| {
+            format!("[\"{}\", \"{}\"]", escape_json(&autofill_selector(key)), escape_json(value))
+        })
+        .collect();
+    format!("(function() {{
+  var count = 0;
+  [{}].forEach(function(field) {{
+    Array.prototype.forEach.call(document.querySelectorAll(field[0]), function(element) {{
+      element.value = field[1];
+      element.dispatchEvent(new Event('input', {{ bubbles: true }}));
+      element.dispatchEvent(new Event('change', {{ bubbles: true }}));
+      count += 1;
+    }});
+  }});
+  return count;
+}})();", fields.join(", "))
+}
+
+/// The order in which webviews are focused when switching to the next or
+/// previous webview in a window
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TabSwitchOrder {
+    /// Position in the window
+    Index,
+    /// Most recently focused first
+    Mru,
+}
+
+impl TabSwitchOrder {
+
+    /// Parse a switching order from a configuration value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "index" => Some(TabSwitchOrder::Index),
+            "mru" => Some(TabSwitchOrder::Mru),
+            _ => None
+        }
+    }
+}
+
+/// The order of webviews arranged by `sort_webviews`
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum SortKey {
+    /// Alphabetically by the host of the URI
+    Host,
+    /// Alphabetically by title, ignoring case
+    Title,
+    /// Most recently focused first
+    Recency,
+}
+
+impl SortKey {
+
+    /// Parse a sort key from a name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "host" => Some(SortKey::Host),
+            "title" => Some(SortKey::Title),
+            "recent" => Some(SortKey::Recency),
+            _ => None
+        }
+    }
+}
+
+/// Where a link requesting a new frame opens
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum OpenIn {
+    /// A new webview in the window of the link, which is focused
+    Tab,
+    /// A new window
+    Window,
+    /// A new webview in the window of the link, leaving the focused webview
+    /// unchanged
+    Background,
+}
+
+impl OpenIn {
+
+    /// Parse a destination from a configuration value
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tab" => Some(OpenIn::Tab),
+            "window" => Some(OpenIn::Window),
+            "background" => Some(OpenIn::Background),
+            _ => None
+        }
+    }
+}
+
+/// The webviews of a closed window, which can be used to reopen the window
+#[derive(Debug,Clone,PartialEq)]
+pub struct ClosedWindow {
+    /// The URI of each webview in the window, in order
+    pub uris: Vec<String>,
+    /// The parts of the chrome of the window which were hidden
+    pub hidden_chrome: Vec<Chrome>,
+}
+
+/// The position and size of a window in screen coordinates, measured from the
+/// top left corner of the primary screen
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct WindowFrame {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowFrame {
+
+    /// Divide a screen area into a grid of frames for `count` windows, with
+    /// as many rows as columns, or one fewer. Cells are no smaller than the
+    /// minimum size, so windows which do not fit in the grid are stacked on
+    /// the cells from the top left again.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::WindowFrame;
+    ///
+    /// let screen = WindowFrame { x: 0, y: 20, width: 1200, height: 800 };
+    /// let frames = WindowFrame::grid(3, screen);
+    /// assert_eq!(vec![WindowFrame { x: 0, y: 20, width: 600, height: 400 },
+    ///                 WindowFrame { x: 600, y: 20, width: 600, height: 400 },
+    ///                 WindowFrame { x: 0, y: 420, width: 600, height: 400 }],
+    ///            frames);
+    /// ```
+    pub fn grid(count: u32, screen: WindowFrame) -> Vec<WindowFrame> {
+        if count == 0 {
+            return vec![];
+        }
+        let max_columns = (screen.width / MIN_TILE_WIDTH).max(1);
+        let max_rows = (screen.height / MIN_TILE_HEIGHT).max(1);
+        let columns = ((count as f64).sqrt().ceil() as u32).min(max_columns);
+        let rows = ((count + columns - 1) / columns).min(max_rows);
+        let width = screen.width / columns;
+        let height = screen.height / rows;
+        (0 .. count).map(|index| {
+            let cell = index % (columns * rows);
+            WindowFrame {
+                x: screen.x + ((cell % columns) * width) as i32,
+                y: screen.y + ((cell / columns) * height) as i32,
+                width,
+                height,
+            }
+        }).collect()
+    }
+}
+
+/// Options for finding text in a webview
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct FindOptions {
+    /// Whether every match is highlighted along with the current match
+    pub highlight_all: bool,
+}
+
+/// The web content process of a webview, which may be shared with other
+/// webviews, with zeros for values which are not available
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct ProcessStats {
+    /// The process identifier
+    pub pid: u32,
+    /// The resident memory size of the process in bytes
+    pub memory_bytes: u64,
+}
+
+/// Durations in milliseconds of the stages of loading a page, from the
+/// Navigation Timing values of the page
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct PageTiming {
+    /// Time spent resolving the host name
+    pub dns: u32,
+    /// Time spent connecting to the server
+    pub tcp: u32,
+    /// Time from the start of navigation until the first byte of the
+    /// response
+    pub first_byte: u32,
+    /// Time from the start of navigation until `DOMContentLoaded` handlers
+    /// finished
+    pub dom_content_loaded: u32,
+    /// Time from the start of navigation until `load` handlers finished
+    pub load: u32,
+}
+
+impl PageTiming {
+
+    /// Parse comma-separated timestamps in milliseconds from
+    /// `performance.timing`, in the order `navigationStart`,
+    /// `domainLookupStart`, `domainLookupEnd`, `connectStart`, `connectEnd`,
+    /// `responseStart`, `domContentLoadedEventEnd`, and `loadEventEnd`.
+    /// Durations ending at an unrecorded timestamp are zero.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::PageTiming;
+    ///
+    /// let timing = PageTiming::from_marks("1000,1002,1005,1005,1015,1120,1450,0");
+    /// assert_eq!(3, timing.dns);
+    /// assert_eq!(10, timing.tcp);
+    /// assert_eq!(120, timing.first_byte);
+    /// assert_eq!(450, timing.dom_content_loaded);
+    /// assert_eq!(0, timing.load);
+    /// ```
+    pub fn from_marks(marks: &str) -> Self {
+        let marks: Vec<f64> = marks.split(',')
+            .map(|mark| mark.trim().parse::<f64>().unwrap_or(0.))
+            .collect();
+        if marks.len() < 8 {
+            return PageTiming::default();
+        }
+        let duration = |start: f64, end: f64| -> u32 {
+            match start > 0. && end > start {
+                true => (end - start).round() as u32,
+                false => 0,
+            }
+        };
+        PageTiming {
+            dns: duration(marks[1], marks[2]),
+            tcp: duration(marks[3], marks[4]),
+            first_byte: duration(marks[0], marks[5]),
+            dom_content_loaded: duration(marks[0], marks[6]),
+            load: duration(marks[0], marks[7]),
+        }
+    }
+}
+
+/// Word count and estimated reading time of the text of a page
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct PageStats {
+    pub word_count: u32,
+    /// Estimated time to read the text in minutes, rounded up
+    pub reading_minutes: u32,
+}
+
+impl PageStats {
+
+    /// Count the whitespace-separated words in text which contain at least
+    /// one alphanumeric character
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::PageStats;
+    ///
+    /// let stats = PageStats::from_text("The quick brown fox - jumps");
+    /// assert_eq!(5, stats.word_count);
+    /// assert_eq!(1, stats.reading_minutes);
+    /// ```
+    pub fn from_text(text: &str) -> Self {
+        let word_count = text.split_whitespace()
+            .filter(|word| word.chars().any(|c| c.is_alphanumeric()))
+            .count() as u32;
+        PageStats {
+            word_count,
+            reading_minutes: (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE,
+        }
+    }
+}
+
+/// A description of an element in a page
+#[derive(Debug,Clone,PartialEq)]
+pub struct ElementInfo {
+    /// The tag name of the element in lowercase, such as `input`
+    pub tag_name: String,
+    /// The `type` attribute of the element, if any
+    pub input_type: Option<String>,
+    /// Whether the element accepts text input
+    pub editable: bool,
+    /// The text content of the element with surrounding whitespace removed
+    pub text: String,
+    /// The names and values of the attributes of the element which are
+    /// listed in `QUERIED_ATTRIBUTES`, in that order
+    pub attributes: Vec<(String, String)>,
+    /// The position and size of the element relative to the viewport
+    pub bounds: ElementBounds,
+}
+
+/// The position and size of an element in CSS pixels
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct ElementBounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl ElementInfo {
+
+    /// Describe an element, normalizing the tag name and type to lowercase
+    /// and treating an empty type as missing. Returns `None` if the tag name
+    /// is empty.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::ElementInfo;
+    ///
+    /// let info = ElementInfo::new("INPUT", "Search", true).unwrap();
+    /// assert_eq!("input", info.tag_name);
+    /// assert_eq!(Some(String::from("search")), info.input_type);
+    /// assert!(ElementInfo::new("", "", false).is_none());
+    /// ```
+    pub fn new(tag_name: &str, input_type: &str, editable: bool) -> Option<Self> {
+        let tag_name = tag_name.trim().to_lowercase();
+        if tag_name.is_empty() {
+            return None;
+        }
+        let input_type = input_type.trim().to_lowercase();
+        Some(ElementInfo {
+            tag_name,
+            input_type: if input_type.is_empty() { None } else { Some(input_type) },
+            editable,
+            text: String::new(),
+            attributes: vec![],
+            bounds: ElementBounds::default(),
+        })
+    }
+
+    /// The value of an attribute of the element, if it was queried
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+/// The kind of window in which a page is opened, which may select a
+/// different start page
+#[derive(Debug,Clone,PartialEq)]
+pub enum WindowContext {
+    /// A window using the default configuration
+    Default,
+    /// A window where every webview uses private browsing
+    Private,
+    /// A window using a named profile from `profiles."[NAME]"`
+    Profile(String),
 }
 
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum WindowArea {
     CommandBar,
     WebView,
 }
 
+/// The parts of a window surrounding its webviews, which can be hidden for
+/// distraction-free browsing
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Chrome {
+    /// The bar for entering commands and addresses
+    CommandBar,
+}
+
+/// Every part of the chrome of a window
+pub const ALL_CHROME: [Chrome; 1] = [Chrome::CommandBar];
+
+impl Chrome {
+
+    /// Parse the name of a part of the chrome, such as `command-bar`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "command-bar" => Some(Chrome::CommandBar),
+            _ => None
+        }
+    }
+
+    /// A name for the part of the chrome, such as `"command-bar"`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Chrome::CommandBar => "command-bar",
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
 pub enum CommandError {
     /// No command matches the given text
     CommandNotFound,
@@ -128,17 +1190,429 @@ pub enum CommandError {
     NoCommandSpecified,
 }
 
+impl CommandError {
+
+    /// The status for a process to exit with after a command fails, distinct
+    /// for each error and from the status for invalid options, `1`
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            CommandError::CommandNotFound => 2,
+            CommandError::ErrorDuringExecution => 3,
+            CommandError::InvalidArguments => 4,
+            CommandError::NoCommandSpecified => 5,
+        }
+    }
+}
+
+/// The result of running a command
+#[derive(Debug,Clone,PartialEq)]
 pub struct CommandOutput {
     pub error: Option<CommandError>,
     pub message: Option<String>,
 }
 
+impl CommandOutput {
+
+    /// The status for a process to exit with after running the command,
+    /// which is `0` unless an error occurred
+    ///
+    /// ```
+    /// use webkitten::ui::{CommandError,CommandOutput};
+    ///
+    /// let output = CommandOutput { error: None, message: None };
+    /// assert_eq!(0, output.exit_code());
+    /// let output = CommandOutput { error: Some(CommandError::CommandNotFound), message: None };
+    /// assert_eq!(2, output.exit_code());
+    /// ```
+    pub fn exit_code(&self) -> i32 {
+        self.error.as_ref().map(|error| error.exit_code()).unwrap_or(0)
+    }
+}
+
 #[derive(Debug,Clone)]
 pub enum BufferEvent {
+    Console(ConsoleMessage),
     Fail(String),
+    FailLoop(String),
     Focus,
+    /// An element entered (`true`) or exited fullscreen
+    Fullscreen(bool),
+    InputMode(InputMode),
     Load,
-    Request,
+    Request(NavigationType),
+}
+
+impl BufferEvent {
+
+    /// Parse the name of an event triggering the commands in
+    /// `commands.on-[NAME]-uri`, such as `load` or `fail-loop`. Details of the
+    /// event, such as error messages, are left empty.
+    pub fn parse_uri_event(name: &str) -> Option<Self> {
+        match name {
+            "fail" => Some(BufferEvent::Fail(String::new())),
+            "fail-loop" => Some(BufferEvent::FailLoop(String::new())),
+            "focus" => Some(BufferEvent::Focus),
+            "load" => Some(BufferEvent::Load),
+            "request" => Some(BufferEvent::Request(NavigationType::Other)),
+            _ => None
+        }
+    }
+}
+
+/// Whether keys typed in a webview are meant for the page or for the browser
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum InputMode {
+    /// Keys control the browser, and keybindings for all commands apply
+    Normal,
+    /// Keys are typed into the page, and keybindings for the commands in
+    /// `commands.normal-mode-keybindings` are ignored
+    Insert,
+}
+
+impl InputMode {
+
+    /// Parse an input mode name, such as `insert`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "normal" => Some(InputMode::Normal),
+            "insert" => Some(InputMode::Insert),
+            _ => None
+        }
+    }
+
+    /// A name for the input mode, such as `"insert"`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            InputMode::Normal => "normal",
+            InputMode::Insert => "insert",
+        }
+    }
+}
+
+/// The kind of document loaded in a webview, based on the MIME type of the
+/// response
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum DocumentKind {
+    /// A single image, such as `image/png`
+    Image,
+    /// A PDF document
+    Pdf,
+    /// An HTML page or any other content
+    Other,
+}
+
+impl DocumentKind {
+
+    /// Classify a MIME type, ignoring case and any parameters
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::DocumentKind;
+    ///
+    /// assert_eq!(DocumentKind::Image, DocumentKind::from_mime_type("image/PNG"));
+    /// assert_eq!(DocumentKind::Pdf, DocumentKind::from_mime_type("application/pdf; qs=0.5"));
+    /// assert_eq!(DocumentKind::Other, DocumentKind::from_mime_type("text/html"));
+    /// ```
+    pub fn from_mime_type(mime_type: &str) -> Self {
+        let essence = mime_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        if essence.starts_with("image/") {
+            DocumentKind::Image
+        } else if essence == "application/pdf" {
+            DocumentKind::Pdf
+        } else {
+            DocumentKind::Other
+        }
+    }
+
+    /// Whether the document can be zoomed to fit the window
+    pub fn can_fit(&self) -> bool {
+        *self != DocumentKind::Other
+    }
+}
+
+/// The zoom at which content of a size fits within a viewport, keeping its
+/// proportions, or `None` if either has no area
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::fit_scale;
+///
+/// assert_eq!(Some(0.5), fit_scale((1600., 600.), (800., 600.)));
+/// assert_eq!(Some(2.), fit_scale((100., 200.), (800., 400.)));
+/// assert_eq!(None, fit_scale((0., 200.), (800., 400.)));
+/// ```
+pub fn fit_scale(content: (f64, f64), viewport: (f64, f64)) -> Option<f64> {
+    if content.0 <= 0. || content.1 <= 0. || viewport.0 <= 0. || viewport.1 <= 0. {
+        return None;
+    }
+    Some((viewport.0 / content.0).min(viewport.1 / content.1))
+}
+
+/// A change in the state of a file download
+#[derive(Debug,Clone,PartialEq)]
+pub enum DownloadEvent {
+    /// Bytes received so far and the expected total, if known
+    Progress(u64, Option<u64>),
+    /// The download completed, saved to a file path
+    Finish(String),
+    /// The download failed, with a description of the error
+    Fail(String),
+}
+
+/// The response of a page to a query requested by a command
+#[derive(Debug,Clone,PartialEq)]
+pub enum PageEvent {
+    /// The elements matching a CSS selector
+    ElementsQueried(String, Vec<ElementInfo>),
+    /// The number of form fields filled
+    FormFilled(usize),
+}
+
+/// The severity of a message logged to the JavaScript console
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ConsoleLevel {
+    Debug,
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+impl ConsoleLevel {
+
+    /// Parse the name of the `console` method used to log a message
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "debug" => Some(ConsoleLevel::Debug),
+            "log" => Some(ConsoleLevel::Log),
+            "info" => Some(ConsoleLevel::Info),
+            "warn" => Some(ConsoleLevel::Warn),
+            "error" => Some(ConsoleLevel::Error),
+            _ => None
+        }
+    }
+
+    /// The name of the `console` method used to log a message
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ConsoleLevel::Debug => "debug",
+            ConsoleLevel::Log => "log",
+            ConsoleLevel::Info => "info",
+            ConsoleLevel::Warn => "warn",
+            ConsoleLevel::Error => "error",
+        }
+    }
+}
+
+/// A message logged to the JavaScript console of a page
+#[derive(Debug,Clone,PartialEq)]
+pub struct ConsoleMessage {
+    pub level: ConsoleLevel,
+    pub message: String,
+    /// The URL of the script which logged the message
+    pub source: String,
+    /// The line of the script which logged the message, or 0 if unknown
+    pub line: u32,
+}
+
+/// The cause of a request to load a URI in a buffer
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum NavigationType {
+    /// A link was activated
+    LinkActivated,
+    /// A form was submitted
+    FormSubmitted,
+    /// An item in the back-forward list was requested
+    BackForward,
+    /// The page was reloaded
+    Reload,
+    /// A previously submitted form was resubmitted
+    FormResubmitted,
+    /// Any other cause, such as loading a URI from a command or a redirect
+    Other,
+}
+
+impl NavigationType {
+
+    /// A name for the navigation type, such as `"link"` or `"reload"`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            NavigationType::LinkActivated => "link",
+            NavigationType::FormSubmitted => "form-submit",
+            NavigationType::BackForward => "back-forward",
+            NavigationType::Reload => "reload",
+            NavigationType::FormResubmitted => "form-resubmit",
+            NavigationType::Other => "other",
+        }
+    }
+}
+
+/// Policy for using cached content when loading a resource
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CacheMode {
+    /// Use the protocol's default caching behavior
+    Default,
+    /// Always load from the origin, ignoring cached data
+    ReloadIgnoringCache,
+    /// Only load from cache, failing if no cached data is available
+    Offline,
+}
+
+impl CacheMode {
+
+    /// Parse a cache mode name, such as `offline`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "default" => Some(CacheMode::Default),
+            "reload-ignoring-cache" => Some(CacheMode::ReloadIgnoringCache),
+            "offline" => Some(CacheMode::Offline),
+            _ => None
+        }
+    }
+}
+
+/// Color scheme preference reported to web content through the
+/// `prefers-color-scheme` media feature
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+    /// Follow the system appearance
+    System,
+}
+
+impl ColorScheme {
+
+    /// Parse a color scheme name, such as `dark`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "light" => Some(ColorScheme::Light),
+            "dark" => Some(ColorScheme::Dark),
+            "system" => Some(ColorScheme::System),
+            _ => None
+        }
+    }
+}
+
+/// How much of the referring page is sent in the `Referer` header when
+/// navigating to another page
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ReferrerPolicy {
+    /// Send the referrer chosen by the webview
+    Default,
+    /// Never send a referrer
+    NoReferrer,
+    /// Send the referrer only when navigating within the same origin
+    SameOrigin,
+    /// Send only the origin of the referring page, and nothing when
+    /// navigating from HTTPS to HTTP
+    StrictOrigin,
+}
+
+impl ReferrerPolicy {
+
+    /// Parse a policy name, such as `no-referrer`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "default" => Some(ReferrerPolicy::Default),
+            "no-referrer" => Some(ReferrerPolicy::NoReferrer),
+            "same-origin" => Some(ReferrerPolicy::SameOrigin),
+            "strict-origin" => Some(ReferrerPolicy::StrictOrigin),
+            _ => None
+        }
+    }
+
+    /// The `Referer` header to send when navigating from a referring page to
+    /// a target URI, or `None` if the header should be removed
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::ui::ReferrerPolicy;
+    ///
+    /// let referrer = "https://example.com/search?q=kittens";
+    /// let policy = ReferrerPolicy::StrictOrigin;
+    /// assert_eq!(Some(String::from("https://example.com/")),
+    ///            policy.referrer(referrer, "https://example.org/"));
+    /// assert_eq!(None, policy.referrer(referrer, "http://example.org/"));
+    /// assert_eq!(None, ReferrerPolicy::SameOrigin.referrer(referrer, "https://example.org/"));
+    /// ```
+    pub fn referrer(&self, referrer: &str, target: &str) -> Option<String> {
+        let (source, destination) = match (Url::parse(referrer), Url::parse(target)) {
+            (Ok(source), Ok(destination)) => (source, destination),
+            _ => return match *self {
+                ReferrerPolicy::Default => Some(String::from(referrer)),
+                _ => None,
+            },
+        };
+        match *self {
+            ReferrerPolicy::Default => Some(String::from(referrer)),
+            ReferrerPolicy::NoReferrer => None,
+            ReferrerPolicy::SameOrigin if source.origin() == destination.origin() =>
+                Some(String::from(referrer)),
+            ReferrerPolicy::SameOrigin => None,
+            ReferrerPolicy::StrictOrigin if source.scheme() == "https" && destination.scheme() != "https" =>
+                None,
+            ReferrerPolicy::StrictOrigin => Some(format!("{}/", source.origin().ascii_serialization())),
+        }
+    }
+}
+
+/// The devices a page requests to capture media from
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum MediaCaptureKind {
+    Camera,
+    Microphone,
+    CameraAndMicrophone,
+}
+
+impl MediaCaptureKind {
+
+    /// The name of the devices, such as `camera`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MediaCaptureKind::Camera => "camera",
+            MediaCaptureKind::Microphone => "microphone",
+            MediaCaptureKind::CameraAndMicrophone => "camera and microphone",
+        }
+    }
+}
+
+/// A JavaScript dialog opened by a page
+#[derive(Debug,Clone,PartialEq)]
+pub enum DialogKind {
+    /// Displays a message using `alert()`
+    Alert,
+    /// Asks to accept or cancel using `confirm()`
+    Confirm,
+    /// Asks for text using `prompt()`, with the default text
+    Prompt(String),
+}
+
+impl DialogKind {
+
+    /// The name of the function opening the dialog, such as `confirm`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DialogKind::Alert => "alert",
+            DialogKind::Confirm => "confirm",
+            DialogKind::Prompt(_) => "prompt",
+        }
+    }
+}
+
+/// The answer to a JavaScript dialog
+#[derive(Debug,Clone,PartialEq)]
+pub enum DialogResponse {
+    /// Close an alert, accept a confirmation, or submit the default text of
+    /// a prompt
+    Accept,
+    /// Close an alert, or cancel a confirmation or prompt
+    Dismiss,
+    /// Submit text entered into a prompt, or accept other dialogs
+    Text(String),
 }
 
 pub trait EventHandler {
@@ -148,6 +1622,14 @@ pub trait EventHandler {
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 
+    /// Handle the UI finishing initialization, after the first window opens
+    /// with the pages specified at launch or the start page. Runs the
+    /// commands in `commands.on-startup` and then `general.rc-file` against
+    /// the focused window.
+    fn on_startup<T, S>(&self, ui: &T)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
     /// Close the application
     fn close<T, S>(&self, ui: &T)
         where T: ApplicationUI<S>,
@@ -158,6 +1640,12 @@ pub trait EventHandler {
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 
+    /// Handle a change to the command bar text after typing pauses,
+    /// highlighting matches as a `find` command is typed
+    fn on_command_text_change<T, S>(&self, ui: &T, window_index: Option<u32>, text: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
     /// Handle a buffer event
     ///
     /// ## Events
@@ -167,12 +1655,99 @@ pub trait EventHandler {
     ///   necessarily after subresources load
     /// * `BufferEvent::Focus`: Invoke after a buffer is focused in a window
     /// * `BufferEvent::Fail`: Invoke after a document fails to load
+    /// * `BufferEvent::FailLoop`: Invoke instead of `Fail` once a document
+    ///   fails to load repeatedly in quick succession, such as in a redirect
+    ///   loop. Further failures are ignored until the loop ends.
+    /// * `BufferEvent::Console`: Invoke after a page logs a console message
+    /// * `BufferEvent::InputMode`: Invoke after the input mode of a buffer
+    ///   changes
+    /// * `BufferEvent::Fullscreen`: Invoke after an element in a buffer
+    ///   enters or exits fullscreen
     fn on_buffer_event<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: Option<&str>, event: BufferEvent)
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 
-    /// Handle a request to open a URI in a new frame
-    fn on_new_frame_request<T, S>(&self, ui: &T, window_index: u32, uri: &str)
+    /// Handle a message logged to the JavaScript console of a page if
+    /// `general.capture-console` is enabled
+    fn on_console_message<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, message: ConsoleMessage)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle focus moving to an element in the page of a webview, or leaving
+    /// the page if `None`, switching to `InputMode::Insert` while an editable
+    /// element is focused and `InputMode::Normal` otherwise
+    fn on_focus_element_change<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, element: Option<&ElementInfo>)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle an element in the page of a webview, such as a video, entering
+    /// (`fullscreen` is `true`) or exiting fullscreen
+    fn on_fullscreen_change<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, fullscreen: bool)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a page opening a JavaScript dialog, returning the answer.
+    /// Dialogs from hosts with `sites."[HOST]".suppress-dialogs` enabled are
+    /// dismissed without being displayed.
+    fn on_js_dialog<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, kind: DialogKind,
+                          message: &str) -> DialogResponse
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a request to open a URI in a new frame
+    fn on_new_frame_request<T, S>(&self, ui: &T, window_index: u32, uri: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a page requesting the location of the device, returning
+    /// whether access is granted
+    fn on_geolocation_request<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a page on a host requesting to capture media from a camera or
+    /// microphone, returning whether access is granted
+    fn on_media_capture_request<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, host: &str,
+                                      kind: MediaCaptureKind) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a page requesting permission to display notifications using
+    /// the Web Notifications API, returning whether permission is granted.
+    /// The decision for each host is remembered until the configuration is
+    /// reloaded.
+    fn on_notification_permission_request<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: &str) -> bool
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a page creating a notification using the Web Notifications
+    /// API, displaying it if the host of the page was granted permission
+    fn on_web_notification<T, S>(&self, ui: &T, window_index: u32, webview_index: u32, uri: &str,
+                                 title: &str, body: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle bytes received by a download, with the expected total if
+    /// known. Progress is reported at most every few hundred milliseconds
+    /// per download, apart from completing the expected total.
+    fn on_download_progress<T, S>(&self, ui: &T, download_id: u32, received: u64, total: Option<u64>)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a download finishing, saved to a file path
+    fn on_download_finish<T, S>(&self, ui: &T, download_id: u32, path: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle a download failing, with a description of the error
+    fn on_download_fail<T, S>(&self, ui: &T, download_id: u32, message: &str)
+        where T: ApplicationUI<S>,
+              S: ScriptingEngine;
+
+    /// Handle the response of the page in a webview to a query requested by
+    /// a command, such as by `query_selector_all`, passing it to the command
+    fn on_page_event<T, S>(&self, ui: &T, command: &str, window_index: u32, webview_index: u32,
+                           event: PageEvent)
         where T: ApplicationUI<S>,
               S: ScriptingEngine;
 }
@@ -182,10 +1757,24 @@ pub trait BrowserConfiguration: Sized {
     /// Parse a string literal into a `BrowserConfiguration`
     fn parse(raw_input: &str) -> Option<Self>;
 
-    /// The page opened with each new window or empty buffer based on
-    /// `window.start-page`
-    fn start_page(&self) -> Option<String> {
-        self.lookup_str("window.start-page")
+    /// The page opened with each new window or empty buffer in a window
+    ///
+    /// ## Resolution order
+    ///
+    /// * `WindowContext::Private`: `window.private-start-page`
+    /// * `WindowContext::Profile`: `profiles."[NAME]".start-page`
+    ///
+    /// Each falls back to `window.start-page`, which is also used for
+    /// `WindowContext::Default`.
+    fn start_page(&self, context: &WindowContext) -> Option<String> {
+        let page = match context {
+            &WindowContext::Default => None,
+            &WindowContext::Private => self.lookup_str("window.private-start-page"),
+            &WindowContext::Profile(ref name) => {
+                self.lookup_str(&format!("profiles.\"{}\".start-page", name))
+            },
+        };
+        page.or_else(|| self.lookup_str("window.start-page"))
     }
 
     /// Whether to open a buffer in the focused window or a new window when
@@ -196,25 +1785,176 @@ pub trait BrowserConfiguration: Sized {
             .unwrap_or(false)
     }
 
+    /// Where a link to a URI requesting a new frame opens based on
+    /// `sites."[HOST]".open-in`, either `tab`, `window`, or `background`.
+    /// Defaults to `tab` if `new-frame.opens-in-focused-window` is `true`,
+    /// otherwise `window`.
+    fn open_in(&self, uri: &str) -> OpenIn {
+        let default = match self.new_frame_uses_focused_window() {
+            true => OpenIn::Tab,
+            false => OpenIn::Window,
+        };
+        match self.lookup_site_str(uri, "open-in") {
+            Some(value) => OpenIn::parse(&value).unwrap_or_else(|| {
+                warn!("Invalid open-in value for {}: {}", uri, value);
+                default
+            }),
+            None => default
+        }
+    }
+
     /// The directory to replace instances of CONFIG_DIR in the configuration
     /// file
     fn config_dir(&self) -> Option<String> {
         self.lookup_raw_str("general.config-dir")
     }
 
+    /// The options table of a command, from `command-config."[COMMAND]"`.
+    /// Commands keep their own settings in this table rather than adding
+    /// top-level options.
+    fn command_config(&self, name: &str) -> Option<Value> {
+        self.lookup_value(&format!("command-config.\"{}\"", name))
+    }
+
     /// The name of the scripting engine to use for evaluating command files.
     /// Defaults to "lua".
     fn command_interpreter(&self) -> Option<String> {
         self.lookup_str("commands.interpreter").or(Some(String::from("lua")))
     }
 
-    /// The name of a command resolving any matching alias in `commands.aliases`
-    fn resolved_command_name(&self, name: &str) -> Option<String> {
-        let command = self.lookup_str(&format!("commands.aliases.{}", name))
-            .unwrap_or(String::from(name));
+    /// The name of a command resolving any matching alias, checking the
+    /// site-specific aliases in `sites."[HOST]".aliases` for the URI (if any)
+    /// before the global aliases in `commands.aliases`
+    fn resolved_command_name(&self, name: &str, uri: Option<&str>) -> Option<String> {
+        let command = self.command_alias(name, uri);
         if self.command_disabled(&command) { None } else { Some(command) }
     }
 
+    /// The name of a command after resolving any matching alias, including
+    /// disabled commands
+    fn command_alias(&self, name: &str, uri: Option<&str>) -> String {
+        let alias_key = format!("aliases.{}", name);
+        uri.and_then(|uri| self.site_lookup_key(uri, &alias_key))
+            .and_then(|key| self.lookup_str(&key))
+            .or(self.lookup_str(&format!("commands.{}", alias_key)))
+            .unwrap_or(String::from(name))
+    }
+
+    /// The cache policy to use when loading resources based on
+    /// `general.cache-mode`. Defaults to `CacheMode::Default`.
+    fn cache_mode(&self) -> CacheMode {
+        if let Some(value) = self.lookup_str("general.cache-mode") {
+            if let Some(mode) = CacheMode::parse(&value) {
+                return mode;
+            }
+            warn!("Unknown cache mode: {}", value);
+        }
+        CacheMode::Default
+    }
+
+    /// Whether loads for a host should always ignore cached data based on the
+    /// site-specific option `sites."[HOST]".always-reload`. Defaults to
+    /// `false`.
+    fn always_reload(&self, uri: &str) -> bool {
+        self.site_lookup_key(uri, "always-reload")
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(false)
+    }
+
+    /// Whether pages on a host may access the location of the device based
+    /// on the site-specific option `sites."[HOST]".allow-geolocation`.
+    /// Defaults to `false`.
+    fn allow_geolocation(&self, uri: &str) -> bool {
+        self.site_lookup_key(uri, "allow-geolocation")
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(false)
+    }
+
+    /// Whether pages on a host may capture media from a camera or microphone
+    /// based on the site-specific options `sites."[HOST]".allow-camera` and
+    /// `sites."[HOST]".allow-microphone`. Both options are required to
+    /// capture from both devices. Defaults to `false`.
+    fn allow_media_capture(&self, uri: &str, kind: MediaCaptureKind) -> bool {
+        let allowed = |key: &str| self.site_lookup_key(uri, key)
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(false);
+        match kind {
+            MediaCaptureKind::Camera => allowed("allow-camera"),
+            MediaCaptureKind::Microphone => allowed("allow-microphone"),
+            MediaCaptureKind::CameraAndMicrophone => allowed("allow-camera") && allowed("allow-microphone"),
+        }
+    }
+
+    /// Whether JavaScript dialogs opened by pages on a host are dismissed
+    /// without being displayed based on the site-specific option
+    /// `sites."[HOST]".suppress-dialogs`. Defaults to `false`.
+    fn suppress_dialogs(&self, uri: &str) -> bool {
+        self.site_lookup_key(uri, "suppress-dialogs")
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(false)
+    }
+
+    /// Whether pages on a host may display notifications using the Web
+    /// Notifications API based on the site-specific option
+    /// `sites."[HOST]".allow-notifications`. Defaults to `false`.
+    fn allow_notifications(&self, uri: &str) -> bool {
+        self.site_lookup_key(uri, "allow-notifications")
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(false)
+    }
+
+    /// The path to an HTML file displayed when a page fails to load based on
+    /// `general.error-page-template`
+    fn error_page_template_path(&self) -> Option<String> {
+        self.lookup_str("general.error-page-template")
+    }
+
+    /// The path to the file storing values saved by commands based on
+    /// `general.kv-store`
+    fn kv_store_path(&self) -> Option<String> {
+        self.lookup_str("general.kv-store")
+    }
+
+    /// The path to a file of commands to run at startup based on
+    /// `general.rc-file`
+    fn rc_file_path(&self) -> Option<String> {
+        self.lookup_str("general.rc-file")
+    }
+
+    /// Whether to continue running the commands in the startup file after a
+    /// command fails based on `general.rc-continue-on-error`. Defaults to
+    /// `false`.
+    fn rc_continue_on_error(&self) -> bool {
+        self.lookup_bool("general.rc-continue-on-error")
+            .unwrap_or(false)
+    }
+
+    /// The maximum level of log messages based on `general.log-level`, one
+    /// of `off`, `error`, `warn`, `info`, `debug`, or `trace`
+    fn log_level(&self) -> Option<LevelFilter> {
+        self.lookup_str("general.log-level").and_then(|value| {
+            let level = LevelFilter::from_str(&value).ok();
+            if level.is_none() {
+                warn!("Invalid log level: {}", value);
+            }
+            level
+        })
+    }
+
+    /// The path to a file to write log messages to instead of standard output
+    /// based on `general.log-file`
+    fn log_file(&self) -> Option<String> {
+        self.lookup_str("general.log-file")
+    }
+
+    /// The size in bytes at which the log file is rotated based on
+    /// `general.log-file-max-size`. The log file is never rotated if unset.
+    fn log_file_max_size(&self) -> Option<u64> {
+        self.lookup_integer("general.log-file-max-size")
+            .filter(|size| *size > 0)
+            .map(|size| size as u64)
+    }
+
     /// Font to use in the command bar
     fn bar_font(&self) -> Option<(String, i64)> {
         if let Some(family) = self.lookup_str("general.bar-font.family") {
@@ -269,6 +2009,174 @@ pub trait BrowserConfiguration: Sized {
         self.lookup_str("general.content-filter")
     }
 
+    /// Domains to block resources from loading, including subdomains, based
+    /// on `block.domains`
+    fn block_domains(&self) -> Vec<String> {
+        self.lookup_str_vec("block.domains").unwrap_or(vec![])
+    }
+
+    /// Regular expressions matching resource URLs to block from loading
+    /// based on `block.patterns`
+    fn block_patterns(&self) -> Vec<String> {
+        self.lookup_str_vec("block.patterns").unwrap_or(vec![])
+    }
+
+    /// Paths to filter lists in Adblock Plus format to convert into content
+    /// blocking rules based on `block.filter-lists`
+    fn filter_list_paths(&self) -> Vec<String> {
+        self.lookup_str_vec("block.filter-lists").unwrap_or(vec![])
+    }
+
+    /// CSS selectors matching elements hidden in buffers based on
+    /// `block.hide-selectors` combined with the site-specific option
+    /// `sites."[HOST]".hide-selectors`
+    fn hide_selectors(&self, uri: &str) -> Vec<String> {
+        let mut selectors = self.lookup_str_vec("block.hide-selectors").unwrap_or(vec![]);
+        if let Some(site_selectors) = self.site_lookup_key(uri, "hide-selectors")
+            .and_then(|key| self.lookup_str_vec(&key)) {
+            selectors.extend(site_selectors);
+        }
+        selectors
+    }
+
+    /// How to match open webviews when opening a URI to focus an existing
+    /// webview instead, based on `general.deduplicate-tabs` and
+    /// `general.deduplicate-tabs-match`. Returns `None` if deduplication is
+    /// disabled, which is the default. Matches exact URIs by default.
+    fn deduplicate_tabs(&self) -> Option<TabMatch> {
+        if !self.lookup_bool("general.deduplicate-tabs").unwrap_or(false) {
+            return None;
+        }
+        let matching = self.lookup_str("general.deduplicate-tabs-match");
+        match matching.as_ref().map(|value| (value, TabMatch::parse(value))) {
+            Some((_, Some(matching))) => Some(matching),
+            Some((value, None)) => {
+                warn!("Invalid tab match type: {}", value);
+                Some(TabMatch::Uri)
+            },
+            None => Some(TabMatch::Uri),
+        }
+    }
+
+    /// Values entered into form fields by `fill_form_fields` based on the
+    /// table `form-autofill`, keyed by field name or `autocomplete` token and
+    /// sorted by key. Keys for passwords, payment cards, and one-time codes
+    /// are skipped with a warning so secrets are not kept in configuration.
+    fn form_autofill_values(&self) -> Vec<(String, String)> {
+        let mut values: Vec<(String, String)> = self.lookup_str_table("form-autofill")
+            .map(|table| table.into_iter().collect())
+            .unwrap_or(vec![]);
+        values.retain(|&(ref key, _)| {
+            let key = key.to_lowercase();
+            let sensitive = SENSITIVE_AUTOFILL_KEYS.iter().any(|word| key.contains(word));
+            if sensitive {
+                warn!("Skipping sensitive form-autofill field: {}", key);
+            }
+            !sensitive
+        });
+        values.sort();
+        values
+    }
+
+    /// The policy for sending the `Referer` header when navigating away from
+    /// a page, based on the site-specific option
+    /// `sites."[HOST]".general.referrer-policy` for the referring page or the
+    /// global option `general.referrer-policy`. Defaults to
+    /// `ReferrerPolicy::Default`.
+    fn referrer_policy(&self, uri: &str) -> ReferrerPolicy {
+        match self.lookup_site_str(uri, "general.referrer-policy") {
+            Some(value) => ReferrerPolicy::parse(&value).unwrap_or_else(|| {
+                warn!("Invalid referrer policy: {}", value);
+                ReferrerPolicy::Default
+            }),
+            None => ReferrerPolicy::Default
+        }
+    }
+
+    /// The `DNT` and `Sec-GPC` headers asking pages on a site not to track
+    /// or sell data about the user, based on the options
+    /// `general.send-dnt` and `general.send-gpc` or their site-specific
+    /// overrides. Both default to `false`.
+    fn privacy_headers(&self, uri: &str) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = vec![];
+        if self.lookup_site_bool(uri, "general.send-dnt").unwrap_or(false) {
+            headers.push((String::from("DNT"), String::from("1")));
+        }
+        if self.lookup_site_bool(uri, "general.send-gpc").unwrap_or(false) {
+            headers.push((String::from("Sec-GPC"), String::from("1")));
+        }
+        headers
+    }
+
+    /// Headers added to requests for pages on a site based on the
+    /// site-specific option `sites."[HOST]".headers`, a table of header names
+    /// and values sorted by name. Headers with invalid names or values are
+    /// skipped.
+    fn site_headers(&self, uri: &str) -> Vec<(String, String)> {
+        let table = self.site_lookup_key(uri, "headers")
+            .and_then(|key| self.lookup_str_table(&key));
+        let mut headers: Vec<(String, String)> = table.map(|table| table.into_iter().collect())
+            .unwrap_or(vec![]);
+        headers.retain(|&(ref name, ref value)| {
+            let valid = valid_header(name, value);
+            if !valid {
+                warn!("Skipping invalid header for {}: {}", uri, name);
+            }
+            valid
+        });
+        headers.sort();
+        headers
+    }
+
+    /// The color used to highlight the current find match in pages on a site
+    /// based on the site-specific option `sites."[HOST]".find-highlight-color`
+    /// or the global option `general.find-highlight-color`. Invalid colors are
+    /// skipped. If unset, the default highlight is used.
+    fn find_highlight_color(&self, uri: &str) -> Option<String> {
+        self.site_lookup_key(uri, "find-highlight-color")
+            .and_then(|key| self.lookup_str(&key))
+            .or(self.lookup_str("general.find-highlight-color"))
+            .and_then(|color| {
+                if valid_css_color(&color) {
+                    return Some(color);
+                }
+                warn!("Invalid find highlight color: {}", color);
+                None
+            })
+    }
+
+    /// The color drawn behind page content in webviews showing pages on a
+    /// site, such as between navigations, based on the site-specific option
+    /// `sites."[HOST]".webview-background-color` or the global option
+    /// `general.webview-background-color`. Invalid colors are skipped.
+    fn webview_background_color(&self, uri: &str) -> Option<(f64, f64, f64, f64)> {
+        self.site_lookup_key(uri, "webview-background-color")
+            .and_then(|key| self.lookup_str(&key))
+            .or(self.lookup_str("general.webview-background-color"))
+            .and_then(|color| {
+                let components = parse_hex_color(&color);
+                if components.is_none() {
+                    warn!("Invalid webview background color: {}", color);
+                }
+                components
+            })
+    }
+
+    /// The directory containing scripts injected into matching pages based on
+    /// `general.user-scripts-directory`
+    fn user_scripts_directory(&self) -> Option<String> {
+        self.lookup_str("general.user-scripts-directory")
+    }
+
+    /// The path to a stylesheet applied to buffers based on the site-specific
+    /// option `sites."[HOST]".stylesheet`, falling back to
+    /// `general.global-stylesheet`
+    fn stylesheet_path(&self, uri: &str) -> Option<String> {
+        self.site_lookup_key(uri, "stylesheet")
+            .and_then(|key| self.lookup_str(&key))
+            .or(self.lookup_str("general.global-stylesheet"))
+    }
+
     /// Whether to skip content filtering based on the site-specific option
     /// `sites."[HOST]".general.skip-content-filter`. Defaults to `false`.
     fn skip_content_filter(&self, uri: &str) -> bool {
@@ -300,6 +2208,200 @@ pub trait BrowserConfiguration: Sized {
             .unwrap_or(false)
     }
 
+    /// The color scheme preference of a buffer based on the global option
+    /// `general.prefer-dark` and site-specific option
+    /// `sites."[HOST]".general.prefer-dark`. Defaults to following the system
+    /// appearance.
+    fn color_scheme(&self, uri: &str) -> ColorScheme {
+        match self.lookup_site_bool(uri, "general.prefer-dark") {
+            Some(true) => ColorScheme::Dark,
+            Some(false) => ColorScheme::Light,
+            None => ColorScheme::System,
+        }
+    }
+
+    /// The number of seconds after which the content of a webview which is
+    /// not focused is unloaded to save memory based on
+    /// `general.discard-background-tabs-after`. Webviews are never discarded
+    /// if unset.
+    fn discard_background_tabs_after(&self) -> Option<u64> {
+        self.lookup_integer("general.discard-background-tabs-after")
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| seconds as u64)
+    }
+
+    /// The number of seconds after a page loads before reloading it, based on
+    /// the site-specific option `sites."[HOST]".refresh-interval`. Pages are
+    /// not reloaded automatically if the value is unset or `0`.
+    fn refresh_interval(&self, uri: &str) -> Option<u64> {
+        self.site_lookup_key(uri, "refresh-interval")
+            .and_then(|key| self.lookup_integer(&key))
+            .filter(|seconds| *seconds > 0)
+            .map(|seconds| seconds as u64)
+    }
+
+    /// Whether to reload a page matching `sites."[HOST]".refresh-interval`
+    /// while it is the focused webview, based on the site-specific option
+    /// `sites."[HOST]".refresh-when-focused`. Defaults to `true`.
+    fn refresh_when_focused(&self, uri: &str) -> bool {
+        self.site_lookup_key(uri, "refresh-when-focused")
+            .and_then(|key| self.lookup_bool(&key))
+            .unwrap_or(true)
+    }
+
+    /// The most pages loading at once in webviews opened by `queue_webview`
+    /// based on `general.max-concurrent-loads`. Loads are unlimited if the
+    /// value is unset or `0`.
+    fn max_concurrent_loads(&self) -> Option<usize> {
+        self.lookup_integer("general.max-concurrent-loads")
+            .filter(|limit| *limit > 0)
+            .map(|limit| limit as usize)
+    }
+
+    /// Whether pages can be navigated using a movable text cursor based on
+    /// `general.caret-browsing`. Defaults to `false`.
+    fn caret_browsing(&self) -> bool {
+        self.lookup_bool("general.caret-browsing")
+            .unwrap_or(false)
+    }
+
+    /// Whether messages logged to the JavaScript console of pages are
+    /// captured based on `general.capture-console`. Applies to new webviews.
+    /// Defaults to `false`.
+    fn capture_console(&self) -> bool {
+        self.lookup_bool("general.capture-console")
+            .unwrap_or(false)
+    }
+
+    /// The number of times a URI fails to load in quick succession before
+    /// the failures are treated as a loop based on
+    /// `general.fail-loop-threshold`. Defaults to 3. Returns `None` if the
+    /// option is 0, disabling loop detection.
+    fn fail_loop_threshold(&self) -> Option<u32> {
+        let threshold = self.lookup_integer("general.fail-loop-threshold")
+            .filter(|threshold| *threshold >= 0)
+            .map(|threshold| threshold as u32)
+            .unwrap_or(DEFAULT_FAIL_LOOP_THRESHOLD);
+        if threshold > 0 { Some(threshold) } else { None }
+    }
+
+    /// The path of a Unix domain socket on which to accept commands from
+    /// other processes based on `general.control-socket`. No socket is
+    /// opened if unset.
+    fn control_socket(&self) -> Option<String> {
+        self.lookup_str("general.control-socket")
+    }
+
+    /// Whether a part of the chrome is visible in new windows based on
+    /// `window.show-command-bar`. Defaults to `true`.
+    fn show_chrome(&self, component: Chrome) -> bool {
+        let key = match component {
+            Chrome::CommandBar => "window.show-command-bar",
+        };
+        self.lookup_bool(key).unwrap_or(true)
+    }
+
+    /// Whether webviews restored when reopening a closed window load their
+    /// content only when first focused based on `window.lazy-restore`.
+    /// Defaults to `false`.
+    fn lazy_restore(&self) -> bool {
+        self.lookup_bool("window.lazy-restore")
+            .unwrap_or(false)
+    }
+
+    /// A fixed latitude and longitude reported to pages permitted to access
+    /// the location of the device in place of the actual location, based on
+    /// `general.fake-location`
+    fn fake_location(&self) -> Option<(f64, f64)> {
+        let value = self.lookup_value("general.fake-location");
+        let coordinates: Vec<f64> = value.as_ref()
+            .and_then(|value| value.as_slice())
+            .map(|values| values.iter()
+                 .filter_map(|value| value.as_float().or(value.as_integer().map(|value| value as f64)))
+                 .collect())
+            .unwrap_or(vec![]);
+        match coordinates.as_slice() {
+            &[latitude, longitude] => Some((latitude, longitude)),
+            _ => {
+                if value.is_some() {
+                    warn!("Invalid fake location, expected [latitude, longitude]");
+                }
+                None
+            }
+        }
+    }
+
+    /// Whether commands may display desktop notifications based on
+    /// `general.notifications`. Defaults to `true`.
+    fn notifications(&self) -> bool {
+        self.lookup_bool("general.notifications")
+            .unwrap_or(true)
+    }
+
+    /// Whether webviews share a single web content process pool based on
+    /// `general.share-process-pool`. Defaults to `true`.
+    fn share_process_pool(&self) -> bool {
+        self.lookup_bool("general.share-process-pool")
+            .unwrap_or(true)
+    }
+
+    /// Whether to restore the scroll position when revisiting a URI during a
+    /// session based on `general.restore-scroll`. Defaults to `false`.
+    fn restore_scroll(&self) -> bool {
+        self.lookup_bool("general.restore-scroll")
+            .unwrap_or(false)
+    }
+
+    /// The directory where downloaded files are saved based on
+    /// `general.download-dir`. Defaults to `~/Downloads`.
+    fn download_dir(&self) -> String {
+        self.lookup_str("general.download-dir")
+            .unwrap_or(String::from(DEFAULT_DOWNLOAD_DIR))
+    }
+
+    /// Whether JavaScript bookmarklets configured in `bookmarklets` may be
+    /// run from the command bar based on `general.allow-bookmarklets`.
+    /// Defaults to `false`.
+    fn allow_bookmarklets(&self) -> bool {
+        self.lookup_bool("general.allow-bookmarklets")
+            .unwrap_or(false)
+    }
+
+    /// The sorted names of bookmarklets beginning with a prefix, or an empty
+    /// list if bookmarklets are not allowed
+    fn bookmarklet_names(&self, prefix: &str) -> Vec<String> {
+        if !self.allow_bookmarklets() {
+            return vec![];
+        }
+        let mut names: Vec<String> = self.lookup_str_table("bookmarklets")
+            .unwrap_or(HashMap::new())
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The script of the bookmarklet configured as `bookmarklets."[NAME]"`
+    /// with any `javascript:` prefix removed and the rest of the URI
+    /// percent-decoded, or `None` if bookmarklets are not allowed
+    fn bookmarklet(&self, name: &str) -> Option<String> {
+        if !self.allow_bookmarklets() {
+            return None;
+        }
+        self.lookup_raw_str(&format!("bookmarklets.\"{}\"", name))
+            .map(|script| match script.starts_with(BOOKMARKLET_PREFIX) {
+                true => {
+                    let body = &script[BOOKMARKLET_PREFIX.len() ..];
+                    percent_decode_str(body).decode_utf8()
+                        .map(|decoded| decoded.into_owned())
+                        .unwrap_or(String::from(body))
+                },
+                false => script,
+            })
+    }
+
     /// Paths to search for command scripts using configuration option
     /// `command.search-paths`
     fn command_search_paths(&self) -> Vec<String> {
@@ -312,6 +2414,75 @@ pub trait BrowserConfiguration: Sized {
         self.lookup_str("commands.default")
     }
 
+    /// The command run in place of loading a URI with a scheme such as
+    /// `mailto` using configuration option `external-schemes."[SCHEME]"`
+    fn external_scheme_command(&self, scheme: &str) -> Option<String> {
+        self.lookup_str(&format!("external-schemes.\"{}\"", scheme))
+    }
+
+    /// The milliseconds to wait after the command bar text changes before
+    /// highlighting matches of a `find` command using configuration option
+    /// `commands.incremental-find-delay`. Defaults to 150. Returns `None` if
+    /// `commands.incremental-find` is `false`.
+    fn incremental_find_delay(&self) -> Option<u64> {
+        if !self.lookup_bool("commands.incremental-find").unwrap_or(true) {
+            return None;
+        }
+        Some(self.lookup_integer("commands.incremental-find-delay")
+            .filter(|delay| *delay >= 0)
+            .map(|delay| delay as u64)
+            .unwrap_or(DEFAULT_INCREMENTAL_FIND_DELAY))
+    }
+
+    /// The fewest characters of command bar text for which completions are
+    /// listed using configuration option `commands.completion-min-length`.
+    /// Defaults to 0.
+    fn completion_min_length(&self) -> usize {
+        self.lookup_integer("commands.completion-min-length")
+            .filter(|length| *length >= 0)
+            .map(|length| length as usize)
+            .unwrap_or(0)
+    }
+
+    /// The milliseconds to wait after the command bar text changes before
+    /// listing completions using configuration option
+    /// `commands.completion-delay-ms`. Returns `None` if unset, in which case
+    /// completions are only listed on request.
+    fn completion_delay(&self) -> Option<u64> {
+        self.lookup_integer("commands.completion-delay-ms")
+            .filter(|delay| *delay >= 0)
+            .map(|delay| delay as u64)
+    }
+
+    /// The order in which webviews are focused when switching to the next or
+    /// previous webview using configuration option
+    /// `commands.tab-switch-order`, either `index` or `mru`. Defaults to
+    /// `index`.
+    fn tab_switch_order(&self) -> TabSwitchOrder {
+        match self.lookup_str("commands.tab-switch-order") {
+            Some(value) => TabSwitchOrder::parse(&value).unwrap_or_else(|| {
+                warn!("Invalid tab switch order: {}", value);
+                TabSwitchOrder::Index
+            }),
+            None => TabSwitchOrder::Index
+        }
+    }
+
+    /// Whether keybindings for a command are ignored in
+    /// `InputMode::Insert`, using configuration option
+    /// `commands.normal-mode-keybindings`
+    fn normal_mode_keybinding(&self, name: &str) -> bool {
+        self.lookup_str_vec("commands.normal-mode-keybindings")
+            .map(|names| names.iter().any(|command| command == name))
+            .unwrap_or(false)
+    }
+
+    /// Commands to run once at startup using configuration option
+    /// `commands.on-startup`
+    fn startup_commands(&self) -> Vec<String> {
+        self.lookup_str_vec("commands.on-startup").unwrap_or(vec![])
+    }
+
     /// Commands triggered by a buffer event
     ///
     /// ## Events
@@ -320,12 +2491,37 @@ pub trait BrowserConfiguration: Sized {
     /// * `Request`: invokes all commands listed in `commands.on-request-uri`
     /// * `Focus`: invokes all commands listed in `commands.on-focus-uri`
     /// * `Fail`: invokes all commands listed in `commands.on-fail-uri`
+    /// * `InputMode`: invokes all commands listed in
+    ///   `commands.on-input-mode-change`
+    /// * `Fullscreen`: invokes all commands listed in
+    ///   `commands.on-fullscreen-change`
     fn on_buffer_event_commands(&self, event: &BufferEvent) -> Vec<String> {
         let key = match event {
             &BufferEvent::Load => "commands.on-load-uri",
-            &BufferEvent::Request => "commands.on-request-uri",
+            &BufferEvent::Request(_) => "commands.on-request-uri",
             &BufferEvent::Focus => "commands.on-focus-uri",
             &BufferEvent::Fail(_) => "commands.on-fail-uri",
+            &BufferEvent::FailLoop(_) => "commands.on-fail-loop-uri",
+            &BufferEvent::Console(_) => "commands.on-console-message",
+            &BufferEvent::InputMode(_) => "commands.on-input-mode-change",
+            &BufferEvent::Fullscreen(_) => "commands.on-fullscreen-change",
+        };
+        self.lookup_str_vec(key).unwrap_or(vec![])
+    }
+
+    /// Commands triggered by a download event
+    ///
+    /// ## Events
+    ///
+    /// * `Progress`: invokes all commands listed in
+    ///   `commands.on-download-progress`
+    /// * `Finish`: invokes all commands listed in `commands.on-download-finish`
+    /// * `Fail`: invokes all commands listed in `commands.on-download-fail`
+    fn on_download_event_commands(&self, event: &DownloadEvent) -> Vec<String> {
+        let key = match event {
+            &DownloadEvent::Progress(_, _) => "commands.on-download-progress",
+            &DownloadEvent::Finish(_) => "commands.on-download-finish",
+            &DownloadEvent::Fail(_) => "commands.on-download-fail",
         };
         self.lookup_str_vec(key).unwrap_or(vec![])
     }
@@ -350,11 +2546,33 @@ pub trait BrowserConfiguration: Sized {
     /// Look up the string table value of a configuration option matching key
     fn lookup_str_table(&self, key: &str) -> Option<HashMap<String, String>>;
 
+    /// Look up the raw TOML value of a configuration option matching key,
+    /// such as a table of custom settings. Callers must handle each TOML type
+    /// themselves, and strings are returned without replacing variables.
+    fn lookup_value(&self, key: &str) -> Option<Value>;
+
+    /// The key of a site-specific option for a URI, formatted as
+    /// `sites."[SITE]".[key]`. Sites may be a host or a host followed by a
+    /// path prefix, such as `example.com/admin`. The longest matching site
+    /// which sets the option is used, falling back to the host alone.
+    fn site_lookup_key(&self, uri: &str, key: &str) -> Option<String> {
+        let keys: Vec<String> = site_names(uri).iter()
+            .map(|site| format!("sites.\"{}\".{}", site, key))
+            .collect();
+        if keys.is_empty() {
+            warn!("Failed to parse URI: {}", uri);
+        }
+        keys.iter()
+            .find(|key| self.lookup_value(key).is_some())
+            .or(keys.last())
+            .cloned()
+    }
+
     /// Look up the bool value of a configuration option matching key
     /// formatted as `sites."[HOST]".[key]`, falling back to `[key]` if no
     /// match is found.
     fn lookup_site_bool<'a>(&'a self, uri: &str, key: &'a str) -> Option<bool> {
-        construct_lookup_key(uri, key)
+        self.site_lookup_key(uri, key)
             .and_then(|key| self.lookup_bool(&key))
             .or(self.lookup_bool(&key))
     }
@@ -363,7 +2581,7 @@ pub trait BrowserConfiguration: Sized {
     /// formatted as `sites."[HOST]".[key]`, falling back to `[key]` if no
     /// match is found.
     fn lookup_site_str<'a>(&'a self, uri: &str, key: &'a str) -> Option<String> {
-        construct_lookup_key(uri, key)
+        self.site_lookup_key(uri, key)
             .and_then(|key| self.lookup_str(&key))
             .or(self.lookup_str(&key))
     }
@@ -372,15 +2590,198 @@ pub trait BrowserConfiguration: Sized {
     /// formatted as `sites."[HOST]".[key]`, falling back to `[key]` if no
     /// match is found.
     fn lookup_site_str_vec<'a>(&'a self, uri: &str, key: &'a str) -> Option<Vec<String>> {
-        construct_lookup_key(uri, key)
+        self.site_lookup_key(uri, key)
             .and_then(|key| self.lookup_str_vec(&key))
             .or(self.lookup_str_vec(&key))
     }
 }
 
-/// Determine the hostname component of a URI if possible and construct
-/// the key for looking up an option
-fn construct_lookup_key(uri: &str, key: &str) -> Option<String> {
+/// Whether a header name is a valid HTTP token and the value contains no line
+/// breaks
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::valid_header;
+///
+/// assert!(valid_header("X-Api-Token", "abc123"));
+/// assert!(!valid_header("X Api Token", "abc123"));
+/// assert!(!valid_header("Referer", "a\r\nHost: example.com"));
+/// ```
+pub fn valid_header(name: &str, value: &str) -> bool {
+    const TOKEN_SYMBOLS: &str = "!#$%&'*+-.^_`|~";
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || TOKEN_SYMBOLS.contains(c))
+        && !value.contains(|c| c == '\r' || c == '\n')
+}
+
+/// Whether a value is a CSS color which is safe to include in a stylesheet:
+/// a hex color, a color function such as `rgb()` or `hsl()`, or a color name
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::valid_css_color;
+///
+/// assert!(valid_css_color("#ff8800"));
+/// assert!(valid_css_color("rgba(255, 136, 0, 0.5)"));
+/// assert!(valid_css_color("orange"));
+/// assert!(!valid_css_color("#ff880"));
+/// assert!(!valid_css_color("red; } body { display: none"));
+/// ```
+pub fn valid_css_color(color: &str) -> bool {
+    const COLOR_FUNCTIONS: [&str; 4] = ["rgb", "rgba", "hsl", "hsla"];
+    if color.starts_with('#') {
+        let hex = &color[1 ..];
+        return [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let (Some(open), true) = (color.find('('), color.ends_with(')')) {
+        return COLOR_FUNCTIONS.contains(&&color[.. open])
+            && color[open + 1 .. color.len() - 1].chars()
+                .all(|c| c.is_ascii_alphanumeric() || " ,.%/+-".contains(c));
+    }
+    !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Parse a hex color of the form `#rgb`, `#rgba`, `#rrggbb`, or `#rrggbbaa`
+/// into red, green, blue, and alpha components between 0 and 1
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::parse_hex_color;
+///
+/// assert_eq!(Some((1.0, 0.0, 1.0, 1.0)), parse_hex_color("#ff00ff"));
+/// assert_eq!(Some((0.0, 0.0, 0.0, 0.0)), parse_hex_color("#0000"));
+/// assert_eq!(None, parse_hex_color("black"));
+/// assert_eq!(None, parse_hex_color("#ff000"));
+/// ```
+pub fn parse_hex_color(color: &str) -> Option<(f64, f64, f64, f64)> {
+    if !color.starts_with('#') || !color[1 ..].chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = &color[1 ..];
+    let digits: Vec<String> = match hex.len() {
+        3 | 4 => hex.chars().map(|c| format!("{}{}", c, c)).collect(),
+        6 | 8 => (0 .. hex.len() / 2).map(|i| String::from(&hex[i * 2 .. i * 2 + 2])).collect(),
+        _ => return None,
+    };
+    let components: Vec<f64> = digits.iter()
+        .filter_map(|pair| u8::from_str_radix(pair, 16).ok())
+        .map(|value| value as f64 / 255.)
+        .collect();
+    Some((components[0], components[1], components[2], components.get(3).cloned().unwrap_or(1.)))
+}
+
+/// The index of the webview to focus after (or before if `forward` is
+/// `false`) the focused webview at `current` of `count` webviews. When
+/// switching in order of most recent use, `history` lists the indices of
+/// previously focused webviews, most recent first. The next webview is then
+/// the most recently focused webview other than the current webview and the
+/// previous webview is the least recently focused. Webviews missing from the
+/// history are least recent, in positional order. Returns `None` if there
+/// are fewer than two webviews.
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::{TabSwitchOrder,tab_switch_index};
+///
+/// assert_eq!(Some(2), tab_switch_index(TabSwitchOrder::Index, &[], 1, 3, true));
+/// assert_eq!(Some(0), tab_switch_index(TabSwitchOrder::Mru, &[1, 0, 2], 1, 3, true));
+/// assert_eq!(Some(2), tab_switch_index(TabSwitchOrder::Mru, &[1, 0, 2], 1, 3, false));
+/// ```
+pub fn tab_switch_index(order: TabSwitchOrder, history: &[u32], current: u32, count: u32, forward: bool) -> Option<u32> {
+    if order == TabSwitchOrder::Index || count < 2 {
+        return adjacent_index(current, count, forward);
+    }
+    let mut indices = vec![current];
+    for index in history.iter().cloned().chain(0 .. count) {
+        if index < count && !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    match forward {
+        true => indices.get(1).cloned(),
+        false => indices.last().cloned(),
+    }
+}
+
+/// The index after (or before if `forward` is `false`) an index in a list of
+/// `count` items, wrapping around at either end. Returns `None` if there are
+/// fewer than two items.
+fn adjacent_index(index: u32, count: u32, forward: bool) -> Option<u32> {
+    if count < 2 {
+        return None;
+    }
+    match forward {
+        true => Some((index + 1) % count),
+        false => Some((index + count - 1) % count),
+    }
+}
+
+/// The names of the sites matching a URI in `sites."[SITE]"`, most specific
+/// first: the host followed by each prefix of the path, ending with the host
+/// alone
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::site_names;
+///
+/// assert_eq!(vec!["example.com/admin/users", "example.com/admin", "example.com"],
+///            site_names("https://example.com/admin/users/?page=2"));
+/// assert_eq!(vec!["example.com"], site_names("example.com"));
+/// ```
+pub fn site_names(uri: &str) -> Vec<String> {
+    let url = match parse_uri(uri) {
+        Some(url) => url,
+        None => return vec![],
+    };
+    let host = match url.host_str() {
+        Some(host) => String::from(host),
+        None => return vec![],
+    };
+    let mut names = vec![host.clone()];
+    let mut prefix = host;
+    for segment in url.path().split('/').filter(|segment| !segment.is_empty()) {
+        prefix = format!("{}/{}", prefix, segment);
+        names.push(prefix.clone());
+    }
+    names.reverse();
+    names
+}
+
+/// Whether a URI refers to a document which a window can represent, being a
+/// web page or a local file rather than generated content
+///
+/// ## Examples
+///
+/// ```
+/// use webkitten::ui::is_document_uri;
+///
+/// assert!(is_document_uri("https://example.com/page"));
+/// assert!(is_document_uri("file:///tmp/page.html"));
+/// assert!(!is_document_uri("about:blank"));
+/// assert!(!is_document_uri("data:text/html,hello"));
+/// ```
+pub fn is_document_uri(uri: &str) -> bool {
+    match Url::parse(uri) {
+        Ok(url) => match url.scheme() {
+            "http" | "https" | "file" => true,
+            _ => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// The hostname component of a URI, assuming HTTP if no scheme is present
+pub fn uri_host(uri: &str) -> Option<String> {
+    parse_uri(uri).and_then(|url| url.host_str().map(String::from))
+}
+
+/// Parse a URI, assuming HTTP if no scheme is present
+fn parse_uri(uri: &str) -> Option<Url> {
     const URI_DELIMITER: &'static str = "://";
     const HTTP_PROTOCOL: &'static str = "http";
     let formatted_uri = if !uri.contains(URI_DELIMITER) {
@@ -388,11 +2789,352 @@ fn construct_lookup_key(uri: &str, key: &str) -> Option<String> {
     } else {
         String::from(uri)
     };
-    if let Ok(url) = Url::parse(&formatted_uri) {
-        if let Some(host) = url.host_str() {
-            return Some(format!("sites.\"{}\".{}", host, key))
+    Url::parse(&formatted_uri).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use script::LuaEngine;
+    use config::Config;
+    use headless::test_support::TestFixture;
+    use Engine;
+
+    /// A UI with a fixed set of webview URIs per window index
+    struct StubUI {
+        webviews: Vec<Vec<&'static str>>,
+    }
+
+    impl ApplicationUI<LuaEngine> for StubUI {
+        fn new(_engine: Engine) -> Option<Self> { None }
+        fn run(&self) {}
+        fn copy(&self, _text: &str) {}
+        fn execute_command(&self, _window_index: Option<u32>, _text: &str) {}
+        fn execute_command_in_all_windows(&self, _text: &str) -> Vec<(u32, CommandError)> { vec![] }
+        fn focused_window_index(&self) -> Option<u32> {
+            if self.webviews.is_empty() { None } else { Some(0) }
+        }
+        fn window_count(&self) -> u32 { self.webviews.len() as u32 }
+        fn window_exists(&self, window_index: u32) -> bool {
+            window_index < self.window_count()
+        }
+        fn open_window<U, B>(&self, _uri: Option<U>, _config: Option<B>) -> u32
+            where U: Into<String>, B: BrowserConfiguration { 0 }
+        fn open_private_window(&self) -> u32 { 0 }
+        fn close_window(&self, _index: u32) {}
+        fn reopen_closed_window(&self) -> Option<u32> { None }
+        fn focus_window(&self, _index: u32) {}
+        fn focus_window_area(&self, _index: u32, _area: WindowArea) {}
+        fn toggle_window(&self, _index: u32, _visible: bool) {}
+        fn resize_window(&self, _window_index: u32, _width: u32, _height: u32) {}
+        fn screen_frame(&self) -> Option<WindowFrame> { None }
+        fn set_window_frame(&self, _window_index: u32, _frame: WindowFrame) {}
+        fn window_minimized(&self, _window_index: u32) -> bool { false }
+        fn minimize_window(&self, _window_index: u32) {}
+        fn restore_window(&self, _window_index: u32) {}
+        fn command_field_text(&self, _window_index: u32) -> String { String::new() }
+        fn set_command_field_text(&self, _window_index: u32, _text: &str) {}
+        fn command_field_visible(&self, _window_index: u32) -> bool { false }
+        fn set_command_field_visible(&self, _window_index: u32, _visible: bool) {}
+        fn window_title(&self, _window_index: u32) -> String { String::new() }
+        fn set_window_title(&self, _window_index: u32, _title: &str) {}
+        fn represented_uri(&self, _window_index: u32) -> Option<String> { None }
+        fn set_represented_uri(&self, _window_index: u32, _uri: Option<&str>) {}
+        fn focused_webview_index(&self, window_index: u32) -> Option<u32> {
+            if self.webview_count(window_index) > 0 { Some(0) } else { None }
+        }
+        fn webview_count(&self, window_index: u32) -> u32 {
+            self.webviews.get(window_index as usize).map_or(0, |uris| uris.len() as u32)
         }
+        fn webview_age(&self, _window_index: u32, _webview_index: u32) -> Duration { Duration::default() }
+        fn webview_idle(&self, _window_index: u32, _webview_index: u32) -> Duration { Duration::default() }
+        fn open_webview<'a, U, B>(&self, _window_index: u32, _uri: Option<U>, _config: Option<B>)
+            where U: Into<String>, B: BrowserConfiguration {}
+        fn queue_webview(&self, _window_index: u32, _uri: &str) {}
+        fn close_webview(&self, _window_index: u32, _webview_index: u32) {}
+        fn move_webview(&self, _window_index: u32, _from: u32, _to: u32) -> bool { false }
+        fn focus_webview(&self, _window_index: u32, _webview_index: u32) {}
+        fn reload_webview(&self, _window_index: u32, _webview_index: u32, _disable_filters: bool) {}
+        fn stop_webview(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn reset_webview(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn set_uri(&self, _window_index: u32, _webview_index: u32, _uri: &str) {}
+        fn load_html(&self, _window_index: u32, _webview_index: u32, _html: &str, _base_uri: Option<&str>) -> bool { false }
+        fn go_back(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn go_forward(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn can_go_back(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn can_go_forward(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn uri(&self, window_index: u32, webview_index: u32) -> String {
+            String::from(self.webviews[window_index as usize][webview_index as usize])
+        }
+        fn find_string_with_options(&self, _window_index: u32, _webview_index: u32, _query: &str, _options: FindOptions) {}
+        fn find_options(&self, _window_index: u32, _webview_index: u32) -> FindOptions { FindOptions::default() }
+        fn hide_find_results(&self, _window_index: u32, _webview_index: u32) {}
+        fn set_find_highlight(&self, _window_index: u32, _webview_index: u32, _color: Option<&str>) -> bool { false }
+        fn webview_title(&self, _window_index: u32, _webview_index: u32) -> String { String::new() }
+        fn run_javascript(&self, _window_index: u32, _webview_index: u32, _script: &str) {}
+        fn inject_user_script(&self, _window_index: u32, _webview_index: u32, _script: &str, _at_start: bool) {}
+        fn clear_user_scripts(&self, _window_index: u32, _webview_index: u32) {}
+        fn save_page(&self, _window_index: u32, _webview_index: u32, _path: &str, _format: SaveFormat) -> bool { false }
+        fn set_custom_headers(&self, _window_index: u32, _webview_index: u32, _headers: &[(String, String)]) {}
+        fn page_statistics(&self, _window_index: u32, _webview_index: u32) -> Option<PageStats> { None }
+        fn page_timing(&self, _window_index: u32, _webview_index: u32) -> PageTiming { PageTiming::default() }
+        fn webview_process_stats(&self, _window_index: u32, _webview_index: u32) -> ProcessStats { ProcessStats::default() }
+        fn focused_element(&self, _window_index: u32, _webview_index: u32) -> Option<ElementInfo> { None }
+        fn fill_form_fields(&self, _window_index: u32, _webview_index: u32, _values: &[(String, String)], _command: &str) -> bool { false }
+        fn query_selector_all(&self, _window_index: u32, _webview_index: u32, _selector: &str, _command: &str) -> bool { false }
+        fn show_dialog(&self, _window_index: u32, _webview_index: u32, _kind: &DialogKind, _message: &str) -> DialogResponse { DialogResponse::Dismiss }
+        fn is_element_fullscreen(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn fit_to_window(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn input_mode(&self, _window_index: u32, _webview_index: u32) -> InputMode { InputMode::Normal }
+        fn set_input_mode(&self, _window_index: u32, _webview_index: u32, _mode: InputMode) -> bool { false }
+        fn private_browsing(&self, _window_index: u32, _webview_index: u32) -> bool { false }
+        fn set_private_browsing(&self, _window_index: u32, _webview_index: u32, _enabled: bool) -> bool { false }
+        fn apply_styles(&self, _window_index: u32, _webview_index: u32, _styles: &str) {}
+        fn apply_styles_all(&self, _styles: &str) {}
+        fn set_cache_mode(&self, _mode: CacheMode) {}
+        fn scroll_to(&self, _window_index: u32, _webview_index: u32, _x: f64, _y: f64) {}
+        fn set_color_scheme(&self, _window_index: u32, _webview_index: u32, _scheme: ColorScheme) {}
+        fn reload_stylesheets(&self) {}
+        fn reload_config(&self) -> bool { false }
+        fn reload_filters(&self, _window_index: Option<u32>) -> bool { false }
+        fn explain_command(&self, _text: &str) -> CommandExplanation { CommandExplanation::default() }
+        fn commands_for_uri_event(&self, _event: &BufferEvent, _uri: &str) -> Vec<String> { vec![] }
+        fn set_command_disabled(&self, _name: &str, _disabled: bool) -> bool { false }
+        fn kv_get(&self, _namespace: &str, _key: &str) -> Option<String> { None }
+        fn kv_set(&self, _namespace: &str, _key: &str, _value: Option<&str>) -> bool { false }
+        fn caret_browsing(&self) -> bool { false }
+        fn set_caret_browsing(&self, _enabled: bool) {}
+        fn set_content_filter(&self, _filter: &str, _window_index: Option<u32>) {}
+    }
+
+    #[test]
+    fn all_webviews_in_window_order() {
+        let ui = StubUI { webviews: vec![vec![""; 2], vec![], vec![""]] };
+        assert_eq!(vec![(0, 0), (0, 1), (2, 0)], ui.all_webviews());
+    }
+
+    #[test]
+    fn webview_exists() {
+        let ui = StubUI { webviews: vec![vec![""; 2], vec![]] };
+        for &(window_index, webview_index, exists) in &[(0, 0, true),
+                                                        (0, 1, true),
+                                                        (0, 2, false),
+                                                        (1, 0, false),
+                                                        (2, 0, false)] {
+            assert_eq!(exists, ui.webview_exists(window_index, webview_index),
+                       "webview ({}, {})", window_index, webview_index);
+        }
+        assert!(ui.window_exists(1));
+        assert!(!ui.window_exists(2));
+    }
+
+    #[test]
+    fn check_webview_in_missing_window() {
+        let ui = StubUI { webviews: vec![vec![""; 1]] };
+        assert_eq!(Err(UiError::WindowNotFound(3)), ui.check_webview(3, 0));
+    }
+
+    #[test]
+    fn check_webview_past_last_index() {
+        let ui = StubUI { webviews: vec![vec![""; 1]] };
+        assert_eq!(Err(UiError::WebViewNotFound(0, 1)), ui.check_webview(0, 1));
+        assert_eq!(Ok(()), ui.check_webview(0, 0));
+    }
+
+    #[test]
+    fn find_webview_by_exact_uri() {
+        let ui = StubUI { webviews: vec![vec!["https://example.com/a"],
+                                         vec!["https://example.com/b", "https://example.org/"]] };
+        assert_eq!(Some((1, 0)), ui.find_webview_by_uri("https://example.com/b"));
+        assert_eq!(Some((1, 1)), ui.find_webview_by_uri("https://example.org/"));
+    }
+
+    #[test]
+    fn find_webview_by_host() {
+        let ui = StubUI { webviews: vec![vec!["https://example.org/"],
+                                         vec!["https://example.com/a", "https://example.com/b"]] };
+        assert_eq!(Some((1, 0)), ui.find_webview_by_uri("https://example.com/c"));
+        assert_eq!(None, ui.find_webview_by_uri("https://example.net/"));
+    }
+
+    #[test]
+    fn find_webview_matching_host() {
+        let ui = StubUI { webviews: vec![vec!["https://example.com/a"]] };
+        assert_eq!(None, ui.find_webview_matching("https://example.com/b", TabMatch::Uri));
+        assert_eq!(Some((0, 0)), ui.find_webview_matching("https://example.com/b", TabMatch::Host));
+    }
+
+    #[test]
+    fn focused_webview_without_windows() {
+        let ui = StubUI { webviews: vec![] };
+        assert_eq!(None, ui.focused_webview());
+        assert!(!ui.set_uri_focused("https://example.com"));
+        let ui = StubUI { webviews: vec![vec!["https://example.com"]] };
+        assert_eq!(Some((0, 0)), ui.focused_webview());
+        assert!(ui.set_uri_focused("https://example.com"));
+    }
+
+    #[test]
+    fn grid_respects_minimum_size() {
+        let screen = WindowFrame { x: 0, y: 0, width: 1000, height: 700 };
+        let frames = WindowFrame::grid(5, screen);
+        assert_eq!(5, frames.len());
+        assert!(frames.iter().all(|frame| frame.width == 500 && frame.height == 350));
+        assert_eq!(frames[0], frames[4]);
+        assert_eq!(vec![screen], WindowFrame::grid(1, screen));
+        assert!(WindowFrame::grid(0, screen).is_empty());
+    }
+
+    #[test]
+    fn tab_completions_match_uri() {
+        let ui = StubUI { webviews: vec![vec!["https://example.com/a", "https://Example.org/"],
+                                         vec!["https://example.com/a", ""]] };
+        assert_eq!(vec![String::from("https://example.com/a"), String::from("https://Example.org/")],
+                   ui.tab_completions(""));
+        assert_eq!(vec![String::from("https://Example.org/")], ui.tab_completions("example.ORG"));
+    }
+
+    #[test]
+    fn page_timing_from_invalid_marks() {
+        assert_eq!(PageTiming::default(), PageTiming::from_marks(""));
+        assert_eq!(PageTiming::default(), PageTiming::from_marks("0,0,0,0,0,0,0,0"));
+        let timing = PageTiming::from_marks("10,x,5,5,2,30,20,40");
+        assert_eq!(PageTiming { dns: 0, tcp: 0, first_byte: 20, dom_content_loaded: 10, load: 30 }, timing);
+    }
+
+    #[test]
+    fn page_stats_reading_time() {
+        assert_eq!(PageStats { word_count: 0, reading_minutes: 0 }, PageStats::from_text(" \n "));
+        let text = vec!["word"; 401].join("\n");
+        assert_eq!(PageStats { word_count: 401, reading_minutes: 3 }, PageStats::from_text(&text));
+    }
+
+    #[test]
+    fn adjacent_index_wraps_around() {
+        assert_eq!(Some(0), adjacent_index(2, 3, true));
+        assert_eq!(Some(2), adjacent_index(0, 3, false));
+        assert_eq!(Some(1), adjacent_index(2, 3, false));
+        assert_eq!(None, adjacent_index(0, 1, true));
+    }
+
+    #[test]
+    fn tab_switch_index_by_recent_use() {
+        assert_eq!(Some(3), tab_switch_index(TabSwitchOrder::Mru, &[0, 3], 0, 4, true));
+        assert_eq!(Some(2), tab_switch_index(TabSwitchOrder::Mru, &[0, 3], 0, 4, false));
+        assert_eq!(Some(1), tab_switch_index(TabSwitchOrder::Mru, &[5, 0], 0, 2, true));
+        assert_eq!(None, tab_switch_index(TabSwitchOrder::Mru, &[0], 0, 1, true));
+    }
+
+    #[test]
+    fn list_all_webviews_by_window() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.open_window(Some("https://example.org"), None::<Config>);
+        ui.open_webview(0, Some("https://example.net"), None::<Config>);
+        assert_eq!(vec![(0, 0), (0, 1), (1, 0)], ui.all_webviews());
+        assert_eq!(3, ui.reload_all(true));
+        ui.assert_called("reload_webview(1, 0, true)");
+        assert_eq!(0, ui.stop_all());
+        ui.write_command("reloadall", r#"
+        function description() return "" end
+        function run()
+          for _, webview in ipairs(all_webviews()) do
+            reload_webview(webview[1], webview[2], false)
+          end
+          return true
+        end
+        "#);
+        ui.clear_calls();
+        assert_eq!(None, ui.submit_command("reloadall").error);
+        let reloads: Vec<String> = ui.calls().into_iter()
+            .filter(|call| call.starts_with("reload_webview("))
+            .collect();
+        assert_eq!(vec!["reload_webview(0, 0, false)", "reload_webview(0, 1, false)",
+                        "reload_webview(1, 0, false)"], reloads);
+    }
+
+    #[test]
+    fn act_on_element_by_index() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.clear_calls();
+        ui.click_element_by_index(0, 0, 2);
+        ui.focus_element_by_index(0, 0, 0);
+        let scripts: Vec<String> = ui.calls().into_iter()
+            .filter(|call| call.starts_with("run_javascript(0, 0, "))
+            .collect();
+        assert_eq!(2, scripts.len());
+        assert!(scripts[0].contains("querySelectorAll('a[href], area[href]')"));
+        assert!(scripts[0].contains("elements[2]") && scripts[0].contains("element.click()"));
+        assert!(scripts[1].contains("elements[0]") && scripts[1].contains("element.focus()"));
+    }
+
+    #[test]
+    fn keep_find_options_per_webview() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        ui.open_webview(0, Some("https://example.org"), None::<Config>);
+        ui.write_command("findall", r#"
+        function description() return "" end
+        function run()
+          find_with_options(0, 0, arguments[1], true)
+          return true
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("findall kitten").error);
+        ui.find_string(0, 0, "cat");
+        ui.find_string(0, 1, "cat");
+        ui.assert_called("find_string(0, 0, cat, FindOptions { highlight_all: true })");
+        ui.assert_called("find_string(0, 1, cat, FindOptions { highlight_all: false })");
+    }
+
+    #[test]
+    fn sort_webviews_keeping_focus() {
+        let ui = TestFixture::new("");
+        ui.open_window(Some("https://c.example.com"), None::<Config>);
+        ui.open_webview(0, Some("https://a.example.com"), None::<Config>);
+        ui.open_webview(0, Some("https://b.example.com"), None::<Config>);
+        ui.focus_webview(0, 0);
+        ui.write_command("sorttabs", r#"
+        function description() return "" end
+        function run()
+          return sort_webviews(focused_window_index(), arguments[1])
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("sorttabs host").error);
+        ui.assert_uri(0, 0, "https://a.example.com");
+        ui.assert_uri(0, 1, "https://b.example.com");
+        ui.assert_uri(0, 2, "https://c.example.com");
+        assert_eq!(Some(2), ui.focused_webview_index(0));
+        assert_eq!(None, ui.submit_command("sorttabs recent").error);
+        ui.assert_uri(0, 0, "https://c.example.com");
+        assert_eq!(Some(0), ui.focused_webview_index(0));
+        assert!(!ui.move_webview(0, 0, 3));
+    }
+
+    #[test]
+    fn hide_and_restore_chrome() {
+        let ui = TestFixture::new("[window]\nshow-command-bar = false");
+        ui.open_window(Some("https://example.com"), None::<Config>);
+        assert!(!ui.chrome_visible(0, Chrome::CommandBar));
+        ui.write_command("chrome", r#"
+        function description() return "" end
+        function run()
+          local windex = focused_window_index()
+          return set_chrome_visible(windex, arguments[1], not chrome_visible(windex, arguments[1]))
+        end
+        "#);
+        assert_eq!(None, ui.submit_command("chrome command-bar").error);
+        assert!(ui.chrome_visible(0, Chrome::CommandBar));
+        assert_eq!(Some(CommandError::ErrorDuringExecution), ui.submit_command("chrome status-bar").error);
+        assert!(ui.chrome_visible(0, Chrome::CommandBar));
+        ui.set_chrome_visible(0, Chrome::CommandBar, false);
+        ui.close_window(0);
+        ui.open_window(Some("https://example.org"), None::<Config>);
+        ui.set_chrome_visible(0, Chrome::CommandBar, true);
+        assert_eq!(Some(1), ui.reopen_closed_window());
+        assert_eq!(vec![Chrome::CommandBar], ui.hidden_chrome(1));
+        ui.focus_window_area(1, WindowArea::CommandBar);
+        assert!(ui.chrome_visible(1, Chrome::CommandBar));
     }
-    warn!("Failed to parse URI: {}", uri);
-    None
 }
@@ -0,0 +1,229 @@
+//! Loading of user scripts from a directory, injected into pages matching the
+//! patterns declared in the header of each script
+use std::fs::{self,File};
+use std::io::Read;
+use std::path::Path;
+
+/// File extension of scripts loaded from a user scripts directory
+const SCRIPT_EXTENSION: &'static str = "js";
+/// Match pattern matching any URL with a supported scheme
+const ALL_URLS: &'static str = "<all_urls>";
+
+/// When a user script runs relative to loading a document
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum RunAt {
+    /// Before any document content is loaded
+    DocumentStart,
+    /// After the document finishes loading
+    DocumentEnd,
+}
+
+impl RunAt {
+
+    /// Parse the value of a `@run-at` header
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "document-start" => Some(RunAt::DocumentStart),
+            "document-end" => Some(RunAt::DocumentEnd),
+            _ => None
+        }
+    }
+}
+
+/// A script injected into pages with URIs matching any of a set of patterns
+#[derive(Debug,Clone,PartialEq)]
+pub struct UserScript {
+    /// The file name of the script
+    pub name: String,
+    /// Match patterns declared using `@match`. Scripts without patterns run
+    /// in every page.
+    pub matches: Vec<String>,
+    pub run_at: RunAt,
+    pub source: String,
+}
+
+impl UserScript {
+
+    /// Create a user script, parsing the `@match` and `@run-at` headers from
+    /// the leading comment lines of the source
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use webkitten::userscripts::{RunAt,UserScript};
+    ///
+    /// let script = UserScript::parse("dark.js", r#"
+    /// // ==UserScript==
+    /// // @match https://*.example.com/*
+    /// // @run-at document-start
+    /// // ==/UserScript==
+    /// document.documentElement.style.background = "black";
+    /// "#);
+    /// assert_eq!(vec![String::from("https://*.example.com/*")], script.matches);
+    /// assert_eq!(RunAt::DocumentStart, script.run_at);
+    /// ```
+    pub fn parse(name: &str, source: &str) -> Self {
+        let mut matches = vec![];
+        let mut run_at = RunAt::DocumentEnd;
+        let header = source.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .take_while(|line| line.starts_with("//"));
+        for line in header {
+            let mut parts = line.trim_start_matches('/').split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("@match"), Some(pattern)) => matches.push(String::from(pattern)),
+                (Some("@run-at"), Some(value)) => match RunAt::parse(value) {
+                    Some(value) => run_at = value,
+                    None => warn!("Unsupported @run-at value in {}: {}", name, value),
+                },
+                _ => (),
+            }
+        }
+        UserScript {
+            name: String::from(name),
+            matches: matches,
+            run_at: run_at,
+            source: String::from(source),
+        }
+    }
+
+    /// The script source wrapped in a check that the URI of the page matches
+    /// one of the declared patterns. Returns `None` if patterns are declared
+    /// and none are valid.
+    pub fn guarded_source(&self) -> Option<String> {
+        if self.matches.is_empty() {
+            return Some(self.source.clone());
+        }
+        let conditions: Vec<String> = self.matches.iter()
+            .filter_map(|pattern| {
+                let regex = match_pattern_regex(pattern);
+                if regex.is_none() {
+                    warn!("Invalid @match pattern in {}: {}", self.name, pattern);
+                }
+                regex
+            })
+            .map(|regex| format!("/{}/.test(window.location.href)", regex))
+            .collect();
+        if conditions.is_empty() {
+            return None;
+        }
+        Some(format!("if ({}) {{\n{}\n}}", conditions.join(" || "), self.source))
+    }
+}
+
+/// Load each script with the extension `.js` in a directory, sorted by file
+/// name
+pub fn load_directory(path: &str) -> Vec<UserScript> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Failed to read user scripts directory ({}): {}", path, err);
+            return vec![];
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == SCRIPT_EXTENSION))
+        .collect();
+    paths.sort();
+    paths.iter().filter_map(|path| load_file(path)).collect()
+}
+
+fn load_file(path: &Path) -> Option<UserScript> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let mut source = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut source)) {
+        Ok(_) => {
+            info!("Loaded user script: {}", name);
+            Some(UserScript::parse(name, &source))
+        },
+        Err(err) => {
+            warn!("Failed to read user script ({}): {}", name, err);
+            None
+        }
+    }
+}
+
+/// Convert a match pattern formatted as `[SCHEME]://[HOST]/[PATH]` into a
+/// JavaScript regular expression literal body. The scheme may be `*` to match
+/// HTTP and HTTPS, the host may be `*` or begin with `*.` to match
+/// subdomains, and the path may contain `*` wildcards.
+fn match_pattern_regex(pattern: &str) -> Option<String> {
+    if pattern == ALL_URLS {
+        return Some(String::from(r"^(https?|file|ftp):"));
+    }
+    let delimiter = match pattern.find("://") {
+        Some(index) => index,
+        None => return None,
+    };
+    let (scheme, rest) = (&pattern[.. delimiter], &pattern[delimiter + 3 ..]);
+    let path_start = match rest.find('/') {
+        Some(index) => index,
+        None => return None,
+    };
+    let (host, path) = (&rest[.. path_start], &rest[path_start ..]);
+    let scheme = match scheme {
+        "*" => String::from("https?"),
+        "" => return None,
+        scheme if scheme.chars().all(|c| c.is_ascii_alphabetic()) => String::from(scheme),
+        _ => return None,
+    };
+    let host = match host {
+        "*" => String::from(r"[^\/]*"),
+        host if host.starts_with("*.") => format!(r"([^\/]*\.)?{}", escape_regex(&host[2 ..])),
+        host if host.contains('*') => return None,
+        host => escape_regex(host),
+    };
+    let path: Vec<String> = path.split('*').map(escape_regex).collect();
+    Some(format!(r"^{}:\/\/{}(:[0-9]+)?{}$", scheme, host, path.join(".*")))
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' |
+            '(' | ')' | '[' | ']' | '{' | '}' | '/' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header_stops_at_code() {
+        let script = UserScript::parse("a.js", "// @match http://a.com/*\nvar b;\n// @match http://b.com/*\n");
+        assert_eq!(vec![String::from("http://a.com/*")], script.matches);
+        assert_eq!(RunAt::DocumentEnd, script.run_at);
+    }
+
+    #[test]
+    fn match_pattern_with_subdomains() {
+        assert_eq!(Some(String::from(r"^https?:\/\/([^\/]*\.)?example\.com(:[0-9]+)?\/news\/.*$")),
+                   match_pattern_regex("*://*.example.com/news/*"));
+    }
+
+    #[test]
+    fn invalid_match_patterns() {
+        assert_eq!(None, match_pattern_regex("example.com"));
+        assert_eq!(None, match_pattern_regex("https://example.com"));
+        assert_eq!(None, match_pattern_regex("https://ex*ample.com/"));
+    }
+
+    #[test]
+    fn guarded_source_checks_patterns() {
+        let script = UserScript::parse("a.js", "// @match <all_urls>\n// @match bad\nalert(1);");
+        assert_eq!(Some(String::from("if (/^(https?|file|ftp):/.test(window.location.href)) {\n// @match <all_urls>\n// @match bad\nalert(1);\n}")),
+                   script.guarded_source());
+        let script = UserScript::parse("b.js", "// @match bad\nalert(1);");
+        assert_eq!(None, script.guarded_source());
+    }
+}
@@ -18,28 +18,8 @@ mod runtime;
 
 use webkitten::ui::ApplicationUI;
 
-static SIMPLE_LOGGER: SimpleLogger = SimpleLogger;
-
-struct SimpleLogger;
-
-impl log::Log for SimpleLogger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= log::Level::Info
-    }
-
-    fn log(&self, record: &log::Record) {
-        if self.enabled(record.metadata()) {
-            println!("{}:{}: {}", record.level(),
-                     record.module_path().unwrap(), record.args());
-        }
-    }
-
-    fn flush(&self) {}
-}
-
 fn main() {
-    log::set_logger(&SIMPLE_LOGGER).unwrap();
-    log::set_max_level(log::LevelFilter::Info);
+    webkitten::logging::init();
     runtime::declare_classes();
     ui::UI.run();
 }
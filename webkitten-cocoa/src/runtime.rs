@@ -1,20 +1,29 @@
 use objc::declare::ClassDecl;
 use objc::runtime::{Object,Sel,BOOL,YES,NO};
-use macos::{Id,ObjCClass};
+use macos::{Id,ObjCClass,nil};
 use macos::foundation::*;
 use macos::appkit::{NSControl,NSEvent,NSView,NSEventModifierFlags,
                     NSLayoutConstraint,NSWorkspace};
 use macos::core_services::register_default_scheme_handler;
 use macos::core_graphics::CGFloat;
 use macos::webkit::*;
-use webkitten::ui::{ApplicationUI,EventHandler,BrowserConfiguration,BufferEvent};
+use webkitten::ui::{ApplicationUI,EventHandler,BrowserConfiguration,BufferEvent,
+                    CommandError,CommandOutput,ConsoleLevel,ConsoleMessage,DialogKind,DocumentKind,
+                    DialogResponse,ElementInfo,MediaCaptureKind,NavigationType};
+use webkitten::control;
 use webkitten::{WEBKITTEN_APP_ID,WEBKITTEN_TITLE};
 use webkitten::config::Config;
 use block::Block;
 
-use ui::{CocoaUI,UI};
+use ui::{CocoaUI,UI,window};
 
 const APP_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+/// Seconds between checks for background webviews to discard
+const DISCARD_CHECK_INTERVAL: f64 = 30.;
+/// The header naming the page a navigation started from
+const REFERER_HEADER: &'static str = "Referer";
+/// The value of `NSWindowDidResizeNotification`
+const WINDOW_DID_RESIZE_NOTIFICATION: &'static str = "NSWindowDidResizeNotification";
 
 impl_objc_class!(CommandBarDelegate);
 impl_objc_class!(WebViewHistoryDelegate);
@@ -22,6 +31,13 @@ impl_objc_class!(WebViewContainerView);
 impl_objc_class!(KeyInputDelegate);
 impl_objc_class!(AppDelegate);
 impl_objc_class!(CommandBarView);
+impl_objc_class!(ConsoleMessageHandler);
+impl_objc_class!(DownloadDelegate);
+impl_objc_class!(NotificationHandler);
+impl_objc_class!(GeolocationHandler);
+impl_objc_class!(FocusHandler);
+impl_objc_class!(RefreshHandler);
+impl_objc_class!(WebViewUIDelegate);
 
 impl CommandBarDelegate {
     pub fn new() -> Self {
@@ -39,6 +55,62 @@ impl WebViewHistoryDelegate {
     }
 }
 
+impl ConsoleMessageHandler {
+    pub fn new() -> Self {
+        ConsoleMessageHandler {
+            ptr: unsafe { msg_send![class!(ConsoleMessageHandler), new] }
+        }
+    }
+}
+
+impl NotificationHandler {
+    pub fn new() -> Self {
+        NotificationHandler {
+            ptr: unsafe { msg_send![class!(NotificationHandler), new] }
+        }
+    }
+}
+
+impl GeolocationHandler {
+    pub fn new() -> Self {
+        GeolocationHandler {
+            ptr: unsafe { msg_send![class!(GeolocationHandler), new] }
+        }
+    }
+}
+
+impl FocusHandler {
+    pub fn new() -> Self {
+        FocusHandler {
+            ptr: unsafe { msg_send![class!(FocusHandler), new] }
+        }
+    }
+}
+
+impl RefreshHandler {
+    pub fn new() -> Self {
+        RefreshHandler {
+            ptr: unsafe { msg_send![class!(RefreshHandler), new] }
+        }
+    }
+}
+
+impl WebViewUIDelegate {
+    pub fn new() -> Self {
+        WebViewUIDelegate {
+            ptr: unsafe { msg_send![class!(WebViewUIDelegate), new] }
+        }
+    }
+}
+
+impl DownloadDelegate {
+    pub fn new() -> Self {
+        DownloadDelegate {
+            ptr: unsafe { msg_send![class!(DownloadDelegate), new] }
+        }
+    }
+}
+
 impl AppDelegate {
     pub fn new() -> Self {
         AppDelegate {
@@ -135,6 +207,13 @@ pub fn declare_classes() {
     declare_app_delegates();
     declare_bar_delegate();
     declare_webview_delegates();
+    declare_ui_delegate();
+    declare_console_handler();
+    declare_notification_handler();
+    declare_geolocation_handler();
+    declare_focus_handler();
+    declare_refresh_handler();
+    declare_download_delegate();
 }
 
 fn declare_view_classes() {
@@ -165,8 +244,16 @@ fn declare_app_delegates() {
             app_will_finish_launching as extern fn (&mut Object, Sel, Id));
         app_delegate.add_method(sel!(applicationDidFinishLaunching:),
             app_finished_launching as extern fn (&Object, Sel, Id));
+        app_delegate.add_method(sel!(applicationWillTerminate:),
+            app_will_terminate as extern fn (&Object, Sel, Id));
         app_delegate.add_method(sel!(application:openFile:),
             open_file as extern fn (&Object, Sel, Id, Id) -> BOOL);
+        app_delegate.add_method(sel!(runControlCommand:),
+            run_control_command as extern fn (&Object, Sel, Id));
+        app_delegate.add_method(sel!(discardBackgroundWebviews:),
+            discard_background_webviews as extern fn (&Object, Sel, Id));
+        app_delegate.add_method(sel!(windowDidResize:),
+            window_did_resize as extern fn (&Object, Sel, Id));
         app_delegate.add_method(sel!(setAsDefaultBrowser),
             set_as_default_browser as extern fn (&Object, Sel));
         app_delegate.add_method(sel!(handleGetURLEvent:withReplyEvent:),
@@ -180,6 +267,8 @@ fn declare_bar_delegate() {
     unsafe {
         decl.add_method(sel!(controlTextDidChange:),
             command_bar_text_changed as extern fn(&Object, Sel, Id));
+        decl.add_method(sel!(commandTextDidPause:),
+            command_bar_text_paused as extern fn(&Object, Sel, Id));
         decl.add_method(sel!(controlTextDidEndEditing:),
             command_bar_did_end_editing as extern fn(&Object, Sel, Id));
         decl.add_method(sel!(control:textView:completions:forPartialWordRange:indexOfSelectedItem:),
@@ -197,12 +286,101 @@ fn declare_webview_delegates() {
             webview_did_same_nav as extern fn (&Object, Sel, Id, Id, Id));
         decl.add_method(sel!(_webView:navigationDidFinishDocumentLoad:),
             webview_did_load as extern fn (&Object, Sel, Id, Id));
+        decl.add_method(sel!(webView:didCommitNavigation:),
+            webview_did_commit_navigation as extern fn (&Object, Sel, Id, Id));
+        decl.add_method(sel!(webView:didFinishNavigation:),
+            webview_did_finish_navigation as extern fn (&Object, Sel, Id, Id));
         decl.add_method(sel!(webView:didFailProvisionalNavigation:withError:),
             webview_load_failed as extern fn (&Object, Sel, Id, Id, Id));
         decl.add_method(sel!(webView:didFailNavigation:withError:),
             webview_load_failed as extern fn (&Object, Sel, Id, Id, Id));
         decl.add_method(sel!(webView:decidePolicyForNavigationAction:decisionHandler:),
             webview_will_navigate as extern fn (&Object, Sel, Id, Id, Id));
+        decl.add_method(sel!(webView:decidePolicyForNavigationResponse:decisionHandler:),
+            webview_will_receive_response as extern fn (&Object, Sel, Id, Id, Id));
+        decl.add_method(sel!(webView:navigationResponse:didBecomeDownload:),
+            webview_did_start_download as extern fn (&Object, Sel, Id, Id, Id));
+    }
+    decl.register();
+}
+
+fn declare_ui_delegate() {
+    let mut decl = ClassDecl::new(WebViewUIDelegate::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(_webView:requestGeolocationPermissionForFrame:decisionHandler:),
+            webview_requested_geolocation as extern fn (&Object, Sel, Id, Id, Id));
+        decl.add_method(sel!(webView:requestMediaCapturePermissionForOrigin:initiatedByFrame:type:decisionHandler:),
+            webview_requested_media_capture as extern fn (&Object, Sel, Id, Id, Id, NSInteger, Id));
+        decl.add_method(sel!(webView:runJavaScriptAlertPanelWithMessage:initiatedByFrame:completionHandler:),
+            webview_run_alert as extern fn (&Object, Sel, Id, Id, Id, Id));
+        decl.add_method(sel!(webView:runJavaScriptConfirmPanelWithMessage:initiatedByFrame:completionHandler:),
+            webview_run_confirm as extern fn (&Object, Sel, Id, Id, Id, Id));
+        decl.add_method(sel!(webView:runJavaScriptTextInputPanelWithPrompt:defaultText:initiatedByFrame:completionHandler:),
+            webview_run_prompt as extern fn (&Object, Sel, Id, Id, Id, Id, Id));
+        decl.add_method(sel!(_webViewDidEnterFullscreen:),
+            webview_did_enter_fullscreen as extern fn (&Object, Sel, Id));
+        decl.add_method(sel!(_webViewDidExitFullscreen:),
+            webview_did_exit_fullscreen as extern fn (&Object, Sel, Id));
+    }
+    decl.register();
+}
+
+fn declare_console_handler() {
+    let mut decl = ClassDecl::new(ConsoleMessageHandler::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(userContentController:didReceiveScriptMessage:),
+            console_message_received as extern fn (&Object, Sel, Id, Id));
+    }
+    decl.register();
+}
+
+fn declare_notification_handler() {
+    let mut decl = ClassDecl::new(NotificationHandler::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(userContentController:didReceiveScriptMessage:),
+            notification_message_received as extern fn (&Object, Sel, Id, Id));
+    }
+    decl.register();
+}
+
+fn declare_geolocation_handler() {
+    let mut decl = ClassDecl::new(GeolocationHandler::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(userContentController:didReceiveScriptMessage:),
+            geolocation_message_received as extern fn (&Object, Sel, Id, Id));
+    }
+    decl.register();
+}
+
+fn declare_focus_handler() {
+    let mut decl = ClassDecl::new(FocusHandler::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(userContentController:didReceiveScriptMessage:),
+            focus_message_received as extern fn (&Object, Sel, Id, Id));
+    }
+    decl.register();
+}
+
+fn declare_refresh_handler() {
+    let mut decl = ClassDecl::new(RefreshHandler::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(refreshWebview:),
+            refresh_webview as extern fn (&Object, Sel, Id));
+    }
+    decl.register();
+}
+
+fn declare_download_delegate() {
+    let mut decl = ClassDecl::new(DownloadDelegate::class_name(), class!(NSObject)).unwrap();
+    unsafe {
+        decl.add_method(sel!(download:decideDestinationUsingResponse:suggestedFilename:completionHandler:),
+            download_will_choose_destination as extern fn (&Object, Sel, Id, Id, Id, Id));
+        decl.add_method(sel!(downloadDidFinish:),
+            download_did_finish as extern fn (&Object, Sel, Id));
+        decl.add_method(sel!(download:didFailWithError:resumeData:),
+            download_did_fail as extern fn (&Object, Sel, Id, Id, Id));
+        decl.add_method(sel!(observeValueForKeyPath:ofObject:change:context:),
+            download_did_progress as extern fn (&Object, Sel, Id, Id, Id, Id));
     }
     decl.register();
 }
@@ -255,7 +433,65 @@ extern fn app_will_finish_launching(this: &mut Object, _cmd: Sel, _note: Id) {
     }
 }
 
-extern fn app_finished_launching(_: &Object, _cmd: Sel, _note: Id) {
+extern fn app_finished_launching(this: &Object, _cmd: Sel, _note: Id) {
+    if let Some(delegate) = AppDelegate::from_ptr(this as *const Object as Id) {
+        NSTimer::scheduled(DISCARD_CHECK_INTERVAL, &delegate,
+                           sel!(discardBackgroundWebviews:), true);
+        unsafe {
+            let center: Id = msg_send![class!(NSNotificationCenter), defaultCenter];
+            let () = msg_send![center, addObserver:delegate.ptr()
+                                          selector:sel!(windowDidResize:)
+                                              name:NSString::from(WINDOW_DID_RESIZE_NOTIFICATION).autorelease().ptr()
+                                            object:nil];
+        }
+    }
+}
+
+extern fn window_did_resize(_: &Object, _cmd: Sel, _notification: Id) {
+    UI.refit_webviews();
+}
+
+/// Accept requests on a control socket, handling each on the main thread
+/// using the app delegate
+pub fn listen_for_control_commands(delegate: &AppDelegate, path: &str) {
+    let delegate = delegate.ptr() as usize;
+    let result = control::listen(path, move |text| {
+        let mut text = NSString::from(text);
+        unsafe {
+            let () = msg_send![delegate as Id, performSelectorOnMainThread:sel!(runControlCommand:)
+                                                                 withObject:text.ptr()
+                                                              waitUntilDone:YES];
+        }
+        text.release();
+        UI.control_output.lock().ok()
+            .and_then(|mut output| output.take())
+            .unwrap_or(CommandOutput { error: Some(CommandError::ErrorDuringExecution), message: None })
+    });
+    if let Err(err) = result {
+        warn!("Failed to open control socket ({}): {}", path, err);
+    }
+}
+
+extern fn run_control_command(_: &Object, _cmd: Sel, text: Id) {
+    let text = NSString::from_ptr(text).and_then(|text| text.as_str()).unwrap_or("");
+    let output = UI.engine.execute_control_request::<CocoaUI<_>, _>(&UI, text);
+    if let Ok(mut control_output) = UI.control_output.lock() {
+        *control_output = Some(output);
+    }
+}
+
+extern fn app_will_terminate(_: &Object, _cmd: Sel, _note: Id) {
+    UI.engine.close::<CocoaUI<_>, _>(&UI);
+}
+
+extern fn refresh_webview(_: &Object, _cmd: Sel, timer: Id) {
+    if let Some(timer) = NSTimer::from_ptr(timer) {
+        window::refresh_webview(&timer);
+    }
+}
+
+extern fn discard_background_webviews(_: &Object, _cmd: Sel, _timer: Id) {
+    window::discard_background_webviews();
 }
 
 extern fn handle_get_url(_: &Object, _cmd: Sel, event: Id, _reply_event: Id) {
@@ -288,7 +524,7 @@ extern fn container_key_down(this: &mut Object, _cmd: Sel, event: Id) {
 extern fn run_keybinding_command(this: &mut Object, _cmd: Sel) {
     if let Some(key_delegate) = KeyInputDelegate::from_ptr(this) {
         if let Some(command) = key_delegate.command().and_then(|c| c.as_str()) {
-            UI.engine.execute_command::<CocoaUI<_>, _>(&UI, UI.focused_window_index(), command);
+            UI.engine.run_keybinding::<CocoaUI<_>, _>(&UI, UI.focused_window_index(), command);
         }
     }
 }
@@ -299,10 +535,25 @@ extern fn webview_will_navigate(_: &Object, _cmd: Sel, webview_ptr: Id, action:I
     if let Some(action) = WKNavigationAction::from_ptr(action) {
         if let Some(request) = action.request() {
             let url = request.url();
+            let external = url.scheme().as_str()
+                .map(|scheme| !PERMITTED_SCHEMES.contains(&scheme))
+                .unwrap_or(true);
+            if external {
+                run_nav_action_block(handler, WKNavigationActionPolicy::Cancel);
+                open_external_url(webview_ptr, url);
+                return;
+            }
             let openable_type = action.navigation_type() == WKNavigationType::LinkActivated;
             let cmd_pressed = action.modifier_flags() == NSEventModifierFlags::Command as NSUInteger;
             // Open in a new frame
             let new_frame = (openable_type && cmd_pressed) || action.target_frame().is_none();
+            let main_frame = action.target_frame().map(|frame| frame.is_main_frame()).unwrap_or(false);
+            if main_frame {
+                UI.set_navigation_type(webview_ptr, navigation_type(action.navigation_type()));
+            }
+            if let (false, true, Some(webview)) = (new_frame, main_frame, WKWebView::from_ptr(webview_ptr)) {
+                UI.save_scroll_position(&webview);
+            }
             if new_frame {
                 let window = NSView::from_ptr(webview_ptr)
                     .and_then(|view| view.window());
@@ -311,13 +562,26 @@ extern fn webview_will_navigate(_: &Object, _cmd: Sel, webview_ptr: Id, action:I
                     UI.engine.on_new_frame_request::<CocoaUI<_>, _>(&UI, window.number() as u32, url);
                     return;
                 }
-            } else if let Some(scheme) = url.scheme().as_str() {
+            } else {
                 // Open in the existing frame
-                if PERMITTED_SCHEMES.contains(&scheme) {
-                    run_nav_action_block(handler, WKNavigationActionPolicy::Allow);
-                    return;
+                let target = url.absolute_string();
+                let webview = WKWebView::from_ptr(webview_ptr);
+                // Only new page loads are reissued, since form submissions
+                // and history navigations cannot be repeated as a request
+                let reissuable = main_frame && match action.navigation_type() {
+                    WKNavigationType::LinkActivated | WKNavigationType::Other => true,
+                    _ => false,
+                };
+                if let (true, Some(target), Some(webview)) = (reissuable, target.as_str(), webview) {
+                    if let Some(updated) = updated_request(&request, webview_ptr, target) {
+                        // Reissue the request with the updated cache policy and headers
+                        run_nav_action_block(handler, WKNavigationActionPolicy::Cancel);
+                        webview.load_request(updated);
+                        return;
+                    }
                 }
-                info!("Unable to open scheme: {}", scheme);
+                run_nav_action_block(handler, WKNavigationActionPolicy::Allow);
+                return;
             }
             // Open in the default app
             run_nav_action_block(handler, WKNavigationActionPolicy::Cancel);
@@ -328,6 +592,121 @@ extern fn webview_will_navigate(_: &Object, _cmd: Sel, webview_ptr: Id, action:I
     run_nav_action_block(handler, WKNavigationActionPolicy::Cancel);
 }
 
+/// Open a URL which cannot be loaded in a webview using the command
+/// configured for its scheme, falling back to the default app
+fn open_external_url(webview_ptr: Id, url: NSURL) {
+    let window_index = NSView::from_ptr(webview_ptr)
+        .and_then(|view| view.window())
+        .map(|window| window.number() as u32);
+    if let Some(uri) = url.absolute_string().as_str() {
+        if UI.engine.open_external_uri::<CocoaUI<_>, _>(&UI, window_index, uri) {
+            return;
+        }
+        info!("Opening {} in the default app", uri);
+    }
+    NSWorkspace::shared_workspace().open_url(url);
+}
+
+extern fn webview_will_receive_response(_: &Object, _cmd: Sel, webview_ptr: Id, response: Id,
+                                        handler: Id) {
+    let response = WKNavigationResponse::from_ptr(response);
+    if let Some(ref response) = response.as_ref().filter(|response| response.is_for_main_frame()) {
+        let mime_type = response.mime_type();
+        let kind = DocumentKind::from_mime_type(mime_type.as_ref().and_then(|mime| mime.as_str()).unwrap_or(""));
+        UI.set_document_kind(webview_ptr, kind);
+    }
+    let policy = match response {
+        Some(ref response) if !response.can_show_mime_type() => WKNavigationResponsePolicy::Download,
+        _ => WKNavigationResponsePolicy::Allow,
+    };
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(WKNavigationResponsePolicy,), ()>);
+        block.call((policy,));
+    }
+}
+
+extern fn webview_did_start_download(_: &Object, _cmd: Sel, _webview_ptr: Id, _response: Id,
+                                     download: Id) {
+    if let Some(download) = WKDownload::from_ptr(download) {
+        UI.start_download(&download);
+    }
+}
+
+extern fn download_will_choose_destination(_: &Object, _cmd: Sel, download: Id, _response: Id,
+                                           suggested_name: Id, handler: Id) {
+    let suggested_name = NSString::from_ptr(suggested_name);
+    let name = suggested_name.as_ref().and_then(|name| name.as_str()).unwrap_or("");
+    let destination = match UI.download_destination(download, name) {
+        Some(path) => NSURL::file_url(NSString::from(&path)).ptr(),
+        None => nil,
+    };
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(Id,), ()>);
+        block.call((destination,));
+    }
+}
+
+extern fn download_did_progress(_: &Object, _cmd: Sel, _key_path: Id, progress: Id, _change: Id,
+                                _context: Id) {
+    if let Some(progress) = NSProgress::from_ptr(progress) {
+        UI.update_download_progress(&progress);
+    }
+}
+
+extern fn download_did_finish(_: &Object, _cmd: Sel, download: Id) {
+    if let Some(download) = WKDownload::from_ptr(download) {
+        UI.end_download(&download, None);
+    }
+}
+
+extern fn download_did_fail(_: &Object, _cmd: Sel, download: Id, error: Id, _resume_data: Id) {
+    let message = NSError::from_ptr(error)
+        .and_then(|error| error.localized_description())
+        .and_then(|description| description.as_str().map(String::from))
+        .unwrap_or(String::from("Unknown error"));
+    if let Some(download) = WKDownload::from_ptr(download) {
+        UI.end_download(&download, Some(message));
+    }
+}
+
+/// A copy of a navigation request ignoring cached data if the target host is
+/// configured to always reload, including any configured headers, and with
+/// the referrer limited by the referrer policy of the referring page, or
+/// `None` if the request needs no changes
+fn updated_request(request: &NSURLRequest, webview_ptr: Id, target: &str) -> Option<NSURLRequest> {
+    let uncached = request.cache_policy() == NSURLRequestCachePolicy::ReloadIgnoringLocalCacheData;
+    let reload = !uncached && UI.engine.config.always_reload(target);
+    let mut headers = UI.request_headers(webview_ptr, target);
+    if !headers.iter().any(|&(ref name, _)| name.eq_ignore_ascii_case(REFERER_HEADER)) {
+        let referrer = request.value_for_http_header_field(REFERER_HEADER);
+        if let Some(referrer) = referrer.as_ref().and_then(|referrer| referrer.as_str()) {
+            let policy = UI.engine.config.referrer_policy(referrer);
+            let value = policy.referrer(referrer, target).unwrap_or(String::new());
+            headers.push((String::from(REFERER_HEADER), value));
+        }
+    }
+    let missing_headers = headers.iter().any(|&(ref name, ref value)| {
+        let current = request.value_for_http_header_field(name);
+        match (current.as_ref().and_then(|current| current.as_str()), value.is_empty()) {
+            (None, true) => false,
+            (Some(current), false) => current != value,
+            _ => true,
+        }
+    });
+    if !reload && !missing_headers {
+        return None;
+    }
+    let updated = request.mutable_copy();
+    if reload {
+        updated.set_cache_policy(NSURLRequestCachePolicy::ReloadIgnoringLocalCacheData);
+    }
+    for (name, value) in headers {
+        let value = if value.is_empty() { None } else { Some(value.as_str()) };
+        updated.set_value_for_http_header_field(value, &name);
+    }
+    Some(updated.into_request())
+}
+
 fn run_nav_action_block(handler: Id, policy: WKNavigationActionPolicy) {
     unsafe {
         let ref block = *(handler as *mut _ as *mut Block<(WKNavigationActionPolicy,), ()>);
@@ -336,11 +715,29 @@ fn run_nav_action_block(handler: Id, policy: WKNavigationActionPolicy) {
 }
 
 extern fn webview_will_load(_: &Object, _cmd: Sel, webview_ptr: Id, nav_ptr: Id) {
-    register_uri_event(webview_ptr, nav_ptr, BufferEvent::Request);
+    let navigation_type = UI.take_navigation_type(webview_ptr);
+    UI.clear_page_statistics(webview_ptr);
+    register_uri_event(webview_ptr, nav_ptr, BufferEvent::Request(navigation_type));
+}
+
+fn navigation_type(navigation_type: WKNavigationType) -> NavigationType {
+    match navigation_type {
+        WKNavigationType::LinkActivated => NavigationType::LinkActivated,
+        WKNavigationType::FormSubmitted => NavigationType::FormSubmitted,
+        WKNavigationType::BackForward => NavigationType::BackForward,
+        WKNavigationType::Reload => NavigationType::Reload,
+        WKNavigationType::FormResubmitted => NavigationType::FormResubmitted,
+        WKNavigationType::Other => NavigationType::Other,
+    }
 }
 
 extern fn webview_load_failed(_: &Object, _cmd: Sel, webview_ptr: Id, nav_ptr: Id, error: Id) {
     if let Some(error) = NSError::from_ptr(error) {
+        if is_policy_interruption(&error) || is_cancellation(&error) {
+            // Navigations cancelled to open URLs elsewhere or replaced by
+            // another navigation are not failures
+            return;
+        }
         let mut message = String::new();
         if let Some(description) = error.localized_description().and_then(|d| d.as_str()) {
             message.push_str(&description);
@@ -352,12 +749,54 @@ extern fn webview_load_failed(_: &Object, _cmd: Sel, webview_ptr: Id, nav_ptr: I
     }
 }
 
+/// Whether an error describes a load interrupted by cancelling navigation,
+/// such as when opening a URL with an external scheme
+fn is_policy_interruption(error: &NSError) -> bool {
+    const FRAME_LOAD_INTERRUPTED: NSInteger = 102;
+    error.code() == FRAME_LOAD_INTERRUPTED
+        && error.domain().as_ref().and_then(|domain| domain.as_str()) == Some("WebKitErrorDomain")
+}
+
+/// Whether an error describes a load cancelled before completion, such as
+/// when another page is requested
+fn is_cancellation(error: &NSError) -> bool {
+    const URL_ERROR_CANCELLED: NSInteger = -999;
+    error.code() == URL_ERROR_CANCELLED
+        && error.domain().as_ref().and_then(|domain| domain.as_str()) == Some("NSURLErrorDomain")
+}
+
 extern fn webview_did_same_nav(_: &Object, _cmd: Sel, webview_ptr: Id, nav_ptr: Id, _nav_type: Id) {
     register_uri_event(webview_ptr, nav_ptr, BufferEvent::Load);
 }
 
 extern fn webview_did_load(_: &Object, _cmd: Sel, webview_ptr: Id, nav_ptr: Id) {
     register_uri_event(webview_ptr, nav_ptr, BufferEvent::Load);
+    if let Some(webview) = WKWebView::from_ptr(webview_ptr) {
+        UI.restore_scroll_position(&webview);
+        UI.update_page_statistics(&webview);
+        window::schedule_refresh(&webview);
+    }
+}
+
+/// Apply the options configured for the site of a page once the main frame
+/// begins displaying it
+extern fn webview_did_commit_navigation(_: &Object, _cmd: Sel, webview_ptr: Id, _nav_ptr: Id) {
+    if let Some(webview) = WKWebView::from_ptr(webview_ptr) {
+        let uri = webview.url()
+            .and_then(|url| url.absolute_string().as_str().map(String::from))
+            .unwrap_or(String::new());
+        window::apply_configured_stylesheets(&webview, &uri);
+        if let Some(view) = webview.coerce::<NSView>() {
+            view.set_appearance(window::appearance(UI.engine.config.color_scheme(&uri)));
+        }
+    }
+}
+
+extern fn webview_did_finish_navigation(_: &Object, _cmd: Sel, webview_ptr: Id, _nav_ptr: Id) {
+    if let Some(webview) = WKWebView::from_ptr(webview_ptr) {
+        UI.update_page_timing(&webview);
+        UI.apply_pending_styles(&webview);
+    }
 }
 
 extern fn command_bar_did_end_editing(_: &Object, _cmd: Sel, notification: Id) {
@@ -368,11 +807,50 @@ extern fn command_bar_did_end_editing(_: &Object, _cmd: Sel, notification: Id) {
     }
 }
 
-extern fn command_bar_text_changed(_: &Object, _cmd: Sel, notification: Id) {
+extern fn command_bar_text_changed(this: &Object, _cmd: Sel, notification: Id) {
     if let Some(text) = notification_object_text(notification) {
         if let Some(command) = UI.engine.config.command_matching_prefix(text) {
             UI.engine.execute_command::<CocoaUI<_>, _>(&UI, UI.focused_window_index(), &command);
+        } else if let Some(delay) = UI.engine.config.incremental_find_delay() {
+            // Restart the delay on each change so only the final text is used
+            let target = this as *const Object as Id;
+            let text = NSString::from(text).autorelease();
+            unsafe {
+                let () = msg_send![class!(NSObject), cancelPreviousPerformRequestsWithTarget:target];
+                let () = msg_send![target, performSelector:sel!(commandTextDidPause:)
+                                                withObject:text.ptr()
+                                                afterDelay:(delay as f64 / 1000.0)];
+            }
         }
+        if let Some(delay) = UI.engine.config.completion_delay() {
+            if text.chars().count() >= UI.engine.config.completion_min_length() {
+                schedule_completion(notification, delay);
+            }
+        }
+    }
+}
+
+/// Open the completion list of the field editor in a notification once the
+/// delay in milliseconds passes without further changes
+fn schedule_completion(notification: Id, delay: u64) {
+    let editor = NSNotification::from_ptr(notification)
+        .and_then(|note| note.user_info())
+        .and_then(|info| info.get::<NSView>("NSFieldEditor"));
+    if let Some(editor) = editor {
+        unsafe {
+            let () = msg_send![class!(NSObject), cancelPreviousPerformRequestsWithTarget:editor.ptr()
+                                                                                selector:sel!(complete:)
+                                                                                  object:nil];
+            let () = msg_send![editor.ptr(), performSelector:sel!(complete:)
+                                                  withObject:nil
+                                                  afterDelay:(delay as f64 / 1000.0)];
+        }
+    }
+}
+
+extern fn command_bar_text_paused(_: &Object, _cmd: Sel, text: Id) {
+    if let Some(text) = NSString::from_ptr(text).and_then(|text| text.as_str()) {
+        UI.engine.on_command_text_change::<CocoaUI<_>, _>(&UI, UI.focused_window_index(), text);
     }
 }
 
@@ -382,6 +860,9 @@ extern fn command_bar_get_completion(_: &Object, _cmd: Sel, control: Id, _: Id,
         .and_then(|control| control.text())
         .and_then(|string| string.as_str());
     if let Some(prefix) = prefix {
+        if prefix.chars().count() < UI.engine.config.completion_min_length() {
+            return NSArray::from_vec(Vec::<String>::new(), |item| NSString::from(item)).ptr();
+        }
         let completions = UI.engine.command_completions::<CocoaUI<_>, _>(&UI, prefix);
         NSArray::from_vec(completions, |item| NSString::from(&item)).ptr()
     } else {
@@ -389,6 +870,202 @@ extern fn command_bar_get_completion(_: &Object, _cmd: Sel, control: Id, _: Id,
     }
 }
 
+extern fn console_message_received(_: &Object, _cmd: Sel, _controller: Id, message: Id) {
+    let message = match WKScriptMessage::from_ptr(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let indices = message.web_view().and_then(|webview| reference_indices(webview.ptr()));
+    if let (Some((window_index, webview_index)), Some(body)) = (indices, message.body::<NSDictionary>()) {
+        let text = |key: &str| body.get::<NSString>(key)
+            .and_then(|value| value.as_str().map(String::from))
+            .unwrap_or(String::new());
+        let console_message = ConsoleMessage {
+            level: ConsoleLevel::parse(&text("level")).unwrap_or(ConsoleLevel::Log),
+            message: text("message"),
+            source: text("source"),
+            line: body.get::<NSNumber>("line").map(|line| line.integer_value().max(0) as u32).unwrap_or(0),
+        };
+        UI.engine.on_console_message::<CocoaUI<_>, _>(&UI, window_index, webview_index, console_message);
+    }
+}
+
+extern fn webview_requested_geolocation(_: &Object, _cmd: Sel, webview_ptr: Id, _frame: Id, handler: Id) {
+    let granted = geolocation_permission(webview_ptr);
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(BOOL,), ()>);
+        block.call((if granted { YES } else { NO },));
+    }
+}
+
+extern fn webview_requested_media_capture(_: &Object, _cmd: Sel, webview_ptr: Id, origin: Id, _frame: Id,
+                                          capture_type: NSInteger, handler: Id) {
+    let kind = match capture_type {
+        t if t == WKMediaCaptureType::Camera as NSInteger => MediaCaptureKind::Camera,
+        t if t == WKMediaCaptureType::Microphone as NSInteger => MediaCaptureKind::Microphone,
+        _ => MediaCaptureKind::CameraAndMicrophone,
+    };
+    let host = WKSecurityOrigin::from_ptr(origin)
+        .and_then(|origin| origin.host())
+        .and_then(|host| host.as_str().map(String::from));
+    let granted = match (reference_indices(webview_ptr), host) {
+        (Some((window_index, webview_index)), Some(host)) => {
+            UI.engine.on_media_capture_request::<CocoaUI<_>, _>(&UI, window_index, webview_index, &host, kind)
+        },
+        _ => {
+            info!("Denied {} access for an unknown host", kind.name());
+            false
+        },
+    };
+    let decision = if granted { WKPermissionDecision::Grant } else { WKPermissionDecision::Deny };
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(WKPermissionDecision,), ()>);
+        block.call((decision,));
+    }
+}
+
+extern fn webview_run_alert(_: &Object, _cmd: Sel, webview_ptr: Id, message: Id, _frame: Id, handler: Id) {
+    js_dialog_response(webview_ptr, DialogKind::Alert, message);
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(), ()>);
+        block.call(());
+    }
+}
+
+extern fn webview_run_confirm(_: &Object, _cmd: Sel, webview_ptr: Id, message: Id, _frame: Id, handler: Id) {
+    let accepted = match js_dialog_response(webview_ptr, DialogKind::Confirm, message) {
+        DialogResponse::Dismiss => NO,
+        _ => YES,
+    };
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(BOOL,), ()>);
+        block.call((accepted,));
+    }
+}
+
+extern fn webview_run_prompt(_: &Object, _cmd: Sel, webview_ptr: Id, prompt: Id, default_text: Id, _frame: Id,
+                             handler: Id) {
+    let default_text = NSString::from_ptr(default_text)
+        .and_then(|text| text.as_str().map(String::from))
+        .unwrap_or(String::new());
+    let text = match js_dialog_response(webview_ptr, DialogKind::Prompt(default_text.clone()), prompt) {
+        DialogResponse::Dismiss => None,
+        DialogResponse::Accept => Some(default_text),
+        DialogResponse::Text(text) => Some(text),
+    };
+    let text = text.map(|text| NSString::from(&text).autorelease());
+    unsafe {
+        let ref block = *(handler as *mut _ as *mut Block<(Id,), ()>);
+        block.call((text.map(|text| text.ptr()).unwrap_or(nil),));
+    }
+}
+
+/// Answer a JavaScript dialog opened by the page in a webview, dismissing
+/// dialogs from webviews which are not open
+fn js_dialog_response(webview_ptr: Id, kind: DialogKind, message: Id) -> DialogResponse {
+    let message = NSString::from_ptr(message)
+        .and_then(|message| message.as_str().map(String::from))
+        .unwrap_or(String::new());
+    match reference_indices(webview_ptr) {
+        Some((window_index, webview_index)) => {
+            UI.engine.on_js_dialog::<CocoaUI<_>, _>(&UI, window_index, webview_index, kind, &message)
+        },
+        None => DialogResponse::Dismiss,
+    }
+}
+
+extern fn webview_did_enter_fullscreen(_: &Object, _cmd: Sel, webview_ptr: Id) {
+    register_fullscreen_change(webview_ptr, true);
+}
+
+extern fn webview_did_exit_fullscreen(_: &Object, _cmd: Sel, webview_ptr: Id) {
+    register_fullscreen_change(webview_ptr, false);
+}
+
+fn register_fullscreen_change(webview_ptr: Id, fullscreen: bool) {
+    if !UI.set_element_fullscreen(webview_ptr, fullscreen) {
+        return;
+    }
+    if let Some((window_index, webview_index)) = reference_indices(webview_ptr) {
+        UI.engine.on_fullscreen_change::<CocoaUI<_>, _>(&UI, window_index, webview_index, fullscreen);
+    }
+}
+
+extern fn geolocation_message_received(_: &Object, _cmd: Sel, _controller: Id, message: Id) {
+    let message = match WKScriptMessage::from_ptr(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let request_id = message.body::<NSDictionary>()
+        .and_then(|body| body.get::<NSNumber>("id"))
+        .map(|id| id.integer_value());
+    if let (Some(webview), Some(request_id)) = (message.web_view(), request_id) {
+        let granted = geolocation_permission(webview.ptr());
+        window::resolve_fake_location(&webview, request_id as i64, granted);
+    }
+}
+
+/// Whether the page in a webview may access the location of the device
+fn geolocation_permission(webview_ptr: Id) -> bool {
+    let uri = WKWebView::from_ptr(webview_ptr)
+        .and_then(|webview| webview.url())
+        .and_then(|url| url.absolute_string().as_str().map(String::from));
+    match (reference_indices(webview_ptr), uri) {
+        (Some((window_index, webview_index)), Some(uri)) => {
+            UI.engine.on_geolocation_request::<CocoaUI<_>, _>(&UI, window_index, webview_index, &uri)
+        },
+        _ => false,
+    }
+}
+
+extern fn notification_message_received(_: &Object, _cmd: Sel, _controller: Id, message: Id) {
+    let message = match WKScriptMessage::from_ptr(message) {
+        Some(message) => message,
+        None => return,
+    };
+    let webview = match message.web_view() {
+        Some(webview) => webview,
+        None => return,
+    };
+    let uri = webview.url().and_then(|url| url.absolute_string().as_str().map(String::from));
+    let indices = reference_indices(webview.ptr());
+    if let (Some((window_index, webview_index)), Some(uri), Some(body)) = (indices, uri, message.body::<NSDictionary>()) {
+        let text = |key: &str| body.get::<NSString>(key)
+            .and_then(|value| value.as_str().map(String::from))
+            .unwrap_or(String::new());
+        match text("type").as_str() {
+            "request" => {
+                let granted = UI.engine.on_notification_permission_request::<CocoaUI<_>, _>(
+                    &UI, window_index, webview_index, &uri);
+                window::resolve_notification_permission(&webview, granted);
+            },
+            "show" => UI.engine.on_web_notification::<CocoaUI<_>, _>(
+                &UI, window_index, webview_index, &uri, &text("title"), &text("body")),
+            other => warn!("Unknown notification message: {}", other),
+        }
+    }
+}
+
+extern fn focus_message_received(_: &Object, _cmd: Sel, _controller: Id, message: Id) {
+    let message = match WKScriptMessage::from_ptr(message) {
+        Some(message) => message,
+        None => return,
+    };
+    if let (Some(webview), Some(body)) = (message.web_view(), message.body::<NSDictionary>()) {
+        let text = |key: &str| body.get::<NSString>(key)
+            .and_then(|value| value.as_str().map(String::from))
+            .unwrap_or(String::new());
+        let editable = body.get::<NSNumber>("editable")
+            .map(|editable| editable.integer_value() != 0)
+            .unwrap_or(false);
+        let element = ElementInfo::new(&text("tag"), &text("type"), editable);
+        UI.set_focused_element(webview.ptr(), element.clone());
+        if let Some((window_index, webview_index)) = reference_indices(webview.ptr()) {
+            UI.engine.on_focus_element_change::<CocoaUI<_>, _>(&UI, window_index, webview_index, element.as_ref());
+        }
+    }
+}
+
 fn register_uri_event(webview_ptr: Id, nav_ptr: Id, event: BufferEvent) {
     let uri = WKNavigation::from_ptr(nav_ptr)
         .and_then(|u| u.url_string())
@@ -403,7 +1080,7 @@ fn register_uri_event(webview_ptr: Id, nav_ptr: Id, event: BufferEvent) {
     }
 }
 
-fn reference_indices(webview: Id) -> Option<(u32, u32)> {
+pub fn reference_indices(webview: Id) -> Option<(u32, u32)> {
     if let Some(webview) = WKWebView::from_ptr(webview).and_then(|v| v.coerce::<NSView>()) {
         if let Some(window) = webview.window() {
             return Some((window.number() as u32,
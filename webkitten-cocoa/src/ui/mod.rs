@@ -1,26 +1,78 @@
 pub mod application;
 pub mod window;
 
-use std::fs::File;
-use std::io::Read;
+use std::collections::{HashMap,HashSet};
+use std::fs::{self,File};
+use std::io::Write;
 use std::marker::PhantomData;
+use std::path::{Path,PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::time::{Duration,Instant};
 
 use webkitten::ui::*;
 use webkitten::config::Config;
 use webkitten::Engine;
+use webkitten::cache::BoundedCache;
 use webkitten::script::{ScriptingEngine,LuaEngine};
+use webkitten::headless::HeadlessUI;
 use webkitten::optparse::parse_opts;
-use macos::foundation::{NSURLRequest,NSURL,NSString,NSAutoreleasePool};
-use macos::appkit::{NSPasteboard,nsapp};
+use webkitten::command;
+use macos::foundation::{NSURLRequest,NSURLRequestCachePolicy,NSURL,NSString,
+                        NSAutoreleasePool,NSArray,NSDictionary,NSNumber,NSData,NSProgress,
+                        NSUserNotification,NSUserNotificationCenter};
+use macos::appkit::{NSAlert,NSControl,NSPasteboard,NSTextField,NSView,NSWorkspace,nsapp,
+                    NS_ALERT_FIRST_BUTTON_RETURN};
 use macos::webkit::*;
-use macos::{Id,nil};
+use macos::core_graphics::CGFloat;
+use macos::libproc;
+use macos::{Id,ObjCClass};
 use block::ConcreteBlock;
 
-use runtime::log_error_description;
+use runtime::{self,DownloadDelegate,log_error_description};
 
 
 const DEFAULT_CONFIG_PATH: &'static str = ".config/webkitten/config.toml";
+/// Script measuring the natural size of the image in an image document
+const IMAGE_SIZE_SCRIPT: &str = "(function() { var image = document.images[0]; \
+    return image ? [image.naturalWidth, image.naturalHeight] : []; })()";
+/// Script serializing the document loaded in a webview, including the
+/// doctype
+const SERIALIZE_DOCUMENT_SCRIPT: &str = "new XMLSerializer().serializeToString(document)";
+/// Headers which are set by the URL loading system or webview and cannot be
+/// overridden
+const RESTRICTED_HEADERS: [&str; 8] = ["Authorization", "Connection", "Content-Length",
+                                               "Host", "Proxy-Authenticate", "Proxy-Authorization",
+                                               "User-Agent", "WWW-Authenticate"];
+/// Script returning the text of the article in a page, or the body if there
+/// is no article, without script and style content
+const PAGE_TEXT_SCRIPT: &str = "(function() {
+  var root = document.querySelector('article') || document.body;
+  if (!root) { return ''; }
+  root = root.cloneNode(true);
+  var hidden = root.querySelectorAll('script, style, noscript, template');
+  for (var i = 0; i < hidden.length; i++) { hidden[i].remove(); }
+  return root.textContent;
+})()";
+/// Identifier of the style element setting the find highlight color
+const FIND_HIGHLIGHT_STYLE_ID: &str = "webkitten-find-highlight";
+/// Script returning the Navigation Timing timestamps read by
+/// `PageTiming::from_marks`
+const PAGE_TIMING_SCRIPT: &str = "(function() {
+  var t = window.performance && window.performance.timing;
+  if (!t) { return ''; }
+  return [t.navigationStart, t.domainLookupStart, t.domainLookupEnd, t.connectStart,
+          t.connectEnd, t.responseStart, t.domContentLoadedEventEnd, t.loadEventEnd].join(',');
+})()";
+/// Maximum number of URIs with remembered scroll positions
+const SCROLL_POSITION_CAPACITY: usize = 100;
+/// The key path of download progress observed for progress events
+const DOWNLOAD_PROGRESS_KEY_PATH: &str = "fractionCompleted";
+/// The file name of downloads without a suggested name
+const DEFAULT_DOWNLOAD_NAME: &str = "download";
+/// The size of the text field in JavaScript prompt dialogs
+const DIALOG_FIELD_WIDTH: f64 = 260.;
+const DIALOG_FIELD_HEIGHT: f64 = 24.;
 
 lazy_static! {
     pub static ref UI: CocoaUI<LuaEngine> = {
@@ -31,8 +83,22 @@ lazy_static! {
                 print!("{}", message);
                 process::exit(status);
             }
-            Engine::new(run_config)
-                .and_then(|engine| CocoaUI::new(engine))
+            let command = run_config.command.clone();
+            let engine = Engine::new(run_config)
+                .unwrap_or_else(|| panic!("Unable to initialize application"));
+            if let Some(command) = command {
+                let ui = <HeadlessUI<LuaEngine> as ApplicationUI<LuaEngine>>::new(engine)
+                    .unwrap_or_else(|| panic!("Unable to initialize application"));
+                let output = ui.execute(&command);
+                if let Some(ref message) = output.message {
+                    println!("{}", message);
+                }
+                process::exit(output.exit_code());
+            }
+            if engine.forward_to_running_instance() {
+                process::exit(0);
+            }
+            CocoaUI::new(engine)
                 .unwrap_or_else(|| panic!("Unable to initialize application"))
         } else {
             panic!("Unable to locate home directory");
@@ -43,23 +109,445 @@ lazy_static! {
 
 pub struct CocoaUI<S: ScriptingEngine> {
     pub engine: Engine,
+    cache_mode: Mutex<Option<CacheMode>>,
+    scroll_positions: Mutex<BoundedCache<(f64, f64)>>,
+    /// The most recently compiled content blocking rules
+    content_filter: Mutex<Option<String>>,
+    /// The cause of the pending navigation in each webview, keyed by webview
+    /// address
+    navigation_types: Mutex<HashMap<usize, NavigationType>>,
+    /// Headers set using `set_custom_headers`, keyed by webview address
+    custom_headers: Mutex<HashMap<usize, Vec<(String, String)>>>,
+    /// Statistics for the loaded page in each webview, keyed by webview
+    /// address
+    page_stats: Mutex<HashMap<usize, PageStats>>,
+    /// Navigation timing of the loaded page in each webview, keyed by webview
+    /// address
+    page_timings: Mutex<HashMap<usize, PageTiming>>,
+    /// The focused element in the page of each webview, keyed by webview
+    /// address
+    focused_elements: Mutex<HashMap<usize, ElementInfo>>,
+    /// Addresses of webviews displaying an element fullscreen
+    fullscreen_webviews: Mutex<HashSet<usize>>,
+    /// The kind of document loaded in each webview, keyed by webview address
+    document_kinds: Mutex<HashMap<usize, DocumentKind>>,
+    /// Addresses of webviews zoomed using `fit_to_window`
+    fitted_webviews: Mutex<HashSet<usize>>,
+    /// Stylesheets passed to `apply_styles_all` while a webview was loading,
+    /// keyed by webview address
+    pending_styles: Mutex<HashMap<usize, Vec<String>>>,
+    /// Addresses of the stylesheets added for the site loaded in each
+    /// webview, keyed by webview address
+    pub configured_stylesheets: Mutex<HashMap<usize, Vec<usize>>>,
+    /// Colors set using `set_find_highlight`, keyed by webview address
+    find_highlights: Mutex<HashMap<usize, String>>,
+    /// Options set using `find_string_with_options`, keyed by webview address
+    find_options: Mutex<HashMap<usize, FindOptions>>,
+    /// Indices of focused webviews, most recent first, keyed by window index
+    focus_history: Mutex<HashMap<u32, Vec<u32>>>,
+    /// The address of the process pool shared by webviews, created with the
+    /// first webview and retained for the lifetime of the application
+    process_pool: Mutex<Option<usize>>,
+    /// When each webview was created and when it was last focused or left
+    /// focus, keyed by webview address
+    webview_times: Mutex<HashMap<usize, (Instant, Instant)>>,
+    /// The URIs of webviews whose content was unloaded or not yet loaded,
+    /// keyed by webview address
+    deferred_uris: Mutex<HashMap<usize, String>>,
+    /// Timers reloading webviews matching `sites."[HOST]".refresh-interval`,
+    /// keyed by webview address
+    refresh_timers: Mutex<HashMap<usize, usize>>,
+    /// The address of the non-persistent data store shared by the webviews
+    /// of each private window, keyed by window number
+    private_stores: Mutex<HashMap<u32, usize>>,
+    /// Downloads in progress, keyed by download address
+    downloads: Mutex<HashMap<usize, ActiveDownload>>,
+    /// The identifier assigned to the next download
+    next_download_id: Mutex<u32>,
+    /// The result of the last command received on the control socket, passed
+    /// from the main thread to the connection thread
+    pub control_output: Mutex<Option<CommandOutput>>,
     engine_type: PhantomData<S>
 }
 
+/// A download in progress
+struct ActiveDownload {
+    id: u32,
+    /// The file path where the download is saved, once chosen
+    path: Option<String>,
+    /// The address of the delegate receiving events of the download
+    delegate: usize,
+    /// The address of the observed progress of the download
+    progress: usize,
+}
+
 impl<S: ScriptingEngine> CocoaUI<S> {
 
-    fn compile_content_extensions<F>(&self, completion: F)
-        where F: Fn(bool) + 'static {
-        let filter_path = self.engine.config.content_filter_path();
-        if let Some(mut file) = filter_path.and_then(|p| File::open(p).ok()) {
-            let mut contents = String::new();
-            if let Some(_) = file.read_to_string(&mut contents).ok() {
-                let block = ConcreteBlock::new(move |_: Id, err: Id| {
-                    log_error_description(err);
-                    completion(err == nil);
+    /// The cache policy for loading a URI. Hosts configured to always reload
+    /// ignore cached data, otherwise any override set using `set_cache_mode`
+    /// is preferred to the configured value.
+    pub fn cache_mode(&self, uri: &str) -> CacheMode {
+        if self.engine.config.always_reload(uri) {
+            return CacheMode::ReloadIgnoringCache;
+        }
+        self.cache_mode.lock().ok()
+            .and_then(|mode| *mode)
+            .unwrap_or(self.engine.config.cache_mode())
+    }
+
+    /// Record the cause of a navigation which will begin in a webview
+    pub fn set_navigation_type(&self, webview: Id, navigation_type: NavigationType) {
+        if let Ok(mut types) = self.navigation_types.lock() {
+            types.insert(webview as usize, navigation_type);
+        }
+    }
+
+    /// The recorded cause of the navigation beginning in a webview, if any,
+    /// removing the record
+    pub fn take_navigation_type(&self, webview: Id) -> NavigationType {
+        self.navigation_types.lock().ok()
+            .and_then(|mut types| types.remove(&(webview as usize)))
+            .unwrap_or(NavigationType::Other)
+    }
+
+    /// The headers added to a navigation request in a webview, combining the
+    /// privacy headers and site-specific headers for the URI with any headers
+    /// set for the webview, with later headers replacing earlier ones
+    pub fn request_headers(&self, webview: Id, uri: &str) -> Vec<(String, String)> {
+        let mut headers = self.engine.config.privacy_headers(uri);
+        let custom_headers = self.custom_headers.lock().ok()
+            .and_then(|headers| headers.get(&(webview as usize)).cloned())
+            .unwrap_or(vec![]);
+        for (name, value) in self.engine.config.site_headers(uri).into_iter().chain(custom_headers) {
+            headers.retain(|&(ref existing, _)| !existing.eq_ignore_ascii_case(&name));
+            headers.push((name, value));
+        }
+        headers.retain(|&(ref name, _)| {
+            let restricted = RESTRICTED_HEADERS.iter()
+                .any(|restricted| restricted.eq_ignore_ascii_case(name));
+            if restricted {
+                warn!("Skipping restricted header: {}", name);
+            }
+            !restricted
+        });
+        headers
+    }
+
+    /// Focus an open webview matching a URI if `general.deduplicate-tabs` is
+    /// enabled, returning the indices of the focused webview
+    fn focus_duplicate_webview(&self, uri: &str) -> Option<(u32, u32)> {
+        self.engine.config.deduplicate_tabs()
+            .and_then(|matching| self.find_webview_matching(uri, matching))
+            .map(|(window_index, webview_index)| {
+                info!("Focusing open webview matching {}", uri);
+                self.focus_window(window_index);
+                self.focus_webview(window_index, webview_index);
+                (window_index, webview_index)
+            })
+    }
+
+    /// Compile content blocking rules into the content extension used by
+    /// webviews, calling `completion` with the compiled filter if successful
+    fn compile_content_extension<F>(&self, filter: &str, completion: F)
+        where F: Fn(Option<_WKUserContentFilter>) + 'static {
+        let block = ConcreteBlock::new(move |filter: Id, err: Id| {
+            log_error_description(err);
+            completion(_WKUserContentFilter::from_ptr(filter));
+        });
+        let store = _WKUserContentExtensionStore::default_store();
+        store.compile_content_extension("filter", filter, &block.copy());
+    }
+
+    /// Record the scroll position of the resource loaded in a webview if
+    /// `general.restore-scroll` is enabled
+    pub fn save_scroll_position(&self, webview: &WKWebView) {
+        if !self.engine.config.restore_scroll() {
+            return;
+        }
+        let uri = webview.url().and_then(|url| url.absolute_string().as_str());
+        if let Some(uri) = uri {
+            let uri = String::from(uri);
+            let block = ConcreteBlock::new(move |result: Id, _err: Id| {
+                let position = NSArray::from_ptr(result).and_then(|values| {
+                    match (values.get::<NSNumber>(0), values.get::<NSNumber>(1)) {
+                        (Some(x), Some(y)) => Some((x.double_value(), y.double_value())),
+                        _ => None
+                    }
+                });
+                if let (Some(position), Ok(mut positions)) = (position, UI.scroll_positions.lock()) {
+                    positions.insert(uri.as_str(), position);
+                }
+            });
+            webview.evaluate_javascript_with_completion("[window.scrollX, window.scrollY]",
+                                                        &block.copy());
+        }
+    }
+
+    /// Compute statistics for the text of the page loaded in a webview
+    pub fn update_page_statistics(&self, webview: &WKWebView) {
+        let key = webview.ptr() as usize;
+        let block = ConcreteBlock::new(move |result: Id, _err: Id| {
+            let text = NSString::from_ptr(result);
+            let stats = PageStats::from_text(text.as_ref().and_then(|text| text.as_str()).unwrap_or(""));
+            if let Ok(mut page_stats) = UI.page_stats.lock() {
+                page_stats.insert(key, stats);
+            }
+        });
+        webview.evaluate_javascript_with_completion(PAGE_TEXT_SCRIPT, &block.copy());
+    }
+
+    /// Record the focused element in the page of a webview, or that no
+    /// element is focused if `None`
+    pub fn set_focused_element(&self, webview: Id, element: Option<ElementInfo>) {
+        if let Ok(mut focused_elements) = self.focused_elements.lock() {
+            match element {
+                Some(element) => focused_elements.insert(webview as usize, element),
+                None => focused_elements.remove(&(webview as usize)),
+            };
+        }
+    }
+
+    /// Record whether a webview displays an element fullscreen, returning
+    /// `true` if the state changed
+    pub fn set_element_fullscreen(&self, webview: Id, fullscreen: bool) -> bool {
+        match self.fullscreen_webviews.lock() {
+            Ok(mut webviews) if fullscreen => webviews.insert(webview as usize),
+            Ok(mut webviews) => webviews.remove(&(webview as usize)),
+            Err(_) => false,
+        }
+    }
+
+    /// Record the kind of document loaded in a webview
+    pub fn set_document_kind(&self, webview: Id, kind: DocumentKind) {
+        if let Ok(mut kinds) = self.document_kinds.lock() {
+            kinds.insert(webview as usize, kind);
+        }
+    }
+
+    /// Zoom the document in a webview to fit the viewport again after the
+    /// window resizes, if it was fitted using `fit_to_window`
+    pub fn refit_webviews(&self) {
+        let fitted: Vec<usize> = self.fitted_webviews.lock()
+            .map(|webviews| webviews.iter().cloned().collect())
+            .unwrap_or(vec![]);
+        for webview in fitted.into_iter().filter_map(|ptr| WKWebView::from_ptr(ptr as Id)) {
+            self.fit_webview(&webview);
+        }
+    }
+
+    /// Zoom an image to fit the viewport of a webview, or restore the
+    /// original zoom of a PDF, which is displayed at the width of the
+    /// viewport. Returns `false` for other documents.
+    fn fit_webview(&self, webview: &WKWebView) -> bool {
+        let kind = self.document_kinds.lock().ok()
+            .and_then(|kinds| kinds.get(&(webview.ptr() as usize)).cloned())
+            .unwrap_or(DocumentKind::Other);
+        match kind {
+            DocumentKind::Other => return false,
+            DocumentKind::Pdf => webview.set_magnification(1.),
+            DocumentKind::Image => {
+                let key = webview.ptr() as usize;
+                let block = ConcreteBlock::new(move |result: Id, _err: Id| {
+                    let size = NSArray::from_ptr(result).and_then(|values| {
+                        match (values.get::<NSNumber>(0), values.get::<NSNumber>(1)) {
+                            (Some(width), Some(height)) => Some((width.double_value(), height.double_value())),
+                            _ => None
+                        }
+                    });
+                    if let (Some(size), Some(webview)) = (size, WKWebView::from_ptr(key as Id)) {
+                        let bounds = webview.bounds();
+                        let viewport = (bounds.size.width as f64, bounds.size.height as f64);
+                        if let Some(scale) = fit_scale(size, viewport) {
+                            webview.set_magnification(scale as CGFloat);
+                        }
+                    }
                 });
-                let store = _WKUserContentExtensionStore::default_store();
-                store.compile_content_extension("filter", &contents, &block.copy());
+                webview.evaluate_javascript_with_completion(IMAGE_SIZE_SCRIPT, &block.copy());
+            },
+        }
+        if let Ok(mut fitted) = self.fitted_webviews.lock() {
+            fitted.insert(webview.ptr() as usize);
+        }
+        true
+    }
+
+    /// Discard statistics, timing, and the focused element for the page
+    /// previously loaded in a webview, restoring the zoom if the page was
+    /// fitted to the window
+    pub fn clear_page_statistics(&self, webview: Id) {
+        self.set_focused_element(webview, None);
+        if let Ok(mut kinds) = self.document_kinds.lock() {
+            kinds.remove(&(webview as usize));
+        }
+        let fitted = self.fitted_webviews.lock()
+            .map(|mut fitted| fitted.remove(&(webview as usize)))
+            .unwrap_or(false);
+        if let (true, Some(webview)) = (fitted, WKWebView::from_ptr(webview)) {
+            webview.set_magnification(1.);
+        }
+        if let Ok(mut page_stats) = self.page_stats.lock() {
+            page_stats.remove(&(webview as usize));
+        }
+        if let Ok(mut page_timings) = self.page_timings.lock() {
+            page_timings.remove(&(webview as usize));
+        }
+    }
+
+    /// Record the navigation timing of the page loaded in a webview
+    pub fn update_page_timing(&self, webview: &WKWebView) {
+        let key = webview.ptr() as usize;
+        let block = ConcreteBlock::new(move |result: Id, _err: Id| {
+            let marks = NSString::from_ptr(result);
+            let timing = PageTiming::from_marks(marks.as_ref().and_then(|marks| marks.as_str()).unwrap_or(""));
+            if let Ok(mut page_timings) = UI.page_timings.lock() {
+                page_timings.insert(key, timing);
+            }
+        });
+        webview.evaluate_javascript_with_completion(PAGE_TIMING_SCRIPT, &block.copy());
+    }
+
+    /// Apply stylesheets deferred by `apply_styles_all` while a webview was
+    /// loading
+    pub fn apply_pending_styles(&self, webview: &WKWebView) {
+        let styles = self.pending_styles.lock().ok()
+            .and_then(|mut pending| pending.remove(&(webview.ptr() as usize)));
+        for sheet in styles.unwrap_or(vec![]) {
+            add_styles(webview, &sheet);
+        }
+    }
+
+    /// Discard stylesheets deferred for a webview which is closing or being
+    /// reset
+    pub fn forget_pending_styles(&self, webview: Id) {
+        if let Ok(mut pending) = self.pending_styles.lock() {
+            pending.remove(&(webview as usize));
+        }
+    }
+
+    /// Assign an identifier to a download which began in a webview and
+    /// observe its progress
+    pub fn start_download(&self, download: &WKDownload) {
+        let id = match self.next_download_id.lock() {
+            Ok(mut next_id) => {
+                *next_id += 1;
+                *next_id - 1
+            },
+            Err(_) => return,
+        };
+        let delegate = DownloadDelegate::new();
+        download.set_delegate(&delegate);
+        let progress = download.progress();
+        info!("Starting download {}", id);
+        if let Ok(mut downloads) = self.downloads.lock() {
+            downloads.insert(download.ptr() as usize, ActiveDownload {
+                id,
+                path: None,
+                delegate: delegate.ptr() as usize,
+                progress: progress.as_ref().map(|progress| progress.ptr() as usize).unwrap_or(0),
+            });
+        }
+        if let Some(progress) = progress {
+            progress.add_observer(&delegate, DOWNLOAD_PROGRESS_KEY_PATH);
+        }
+    }
+
+    /// Choose the file path where a download is saved within
+    /// `general.download-dir`, adding a number to the suggested file name if
+    /// a file already exists at the path
+    pub fn download_destination(&self, download: Id, suggested_name: &str) -> Option<String> {
+        let dir = command::expand_path(&self.engine.config.download_dir());
+        if let Err(err) = fs::create_dir_all(&dir) {
+            warn!("Unable to create download directory {}: {}", dir, err);
+            return None;
+        }
+        let name = Path::new(suggested_name).file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or(DEFAULT_DOWNLOAD_NAME);
+        let path = unique_path(Path::new(&dir), name);
+        let path = path.to_str().map(String::from);
+        if let (Some(path), Ok(mut downloads)) = (path.as_ref(), self.downloads.lock()) {
+            if let Some(active) = downloads.get_mut(&(download as usize)) {
+                active.path = Some(path.clone());
+            }
+        }
+        path
+    }
+
+    /// Report the bytes received by the download with an observed progress
+    pub fn update_download_progress(&self, progress: &NSProgress) {
+        let id = self.downloads.lock().ok()
+            .and_then(|downloads| {
+                downloads.values()
+                    .find(|active| active.progress == progress.ptr() as usize)
+                    .map(|active| active.id)
+            });
+        if let Some(id) = id {
+            let received = progress.completed_unit_count().max(0) as u64;
+            let total = progress.total_unit_count();
+            let total = if total < 0 { None } else { Some(total as u64) };
+            self.engine.on_download_progress::<CocoaUI<_>, _>(&UI, id, received, total);
+        }
+    }
+
+    /// Stop observing a download which finished or failed with an error
+    /// description and report the result
+    pub fn end_download(&self, download: &WKDownload, error: Option<String>) {
+        let active = self.downloads.lock().ok()
+            .and_then(|mut downloads| downloads.remove(&(download.ptr() as usize)));
+        let active = match active {
+            Some(active) => active,
+            None => return,
+        };
+        if let Some(mut delegate) = DownloadDelegate::from_ptr(active.delegate as Id) {
+            if let Some(progress) = download.progress() {
+                progress.remove_observer(&delegate, DOWNLOAD_PROGRESS_KEY_PATH);
+            }
+            delegate.release();
+        }
+        match (error, active.path) {
+            (None, Some(path)) => self.engine.on_download_finish::<CocoaUI<_>, _>(&UI, active.id, &path),
+            (Some(message), _) => self.engine.on_download_fail::<CocoaUI<_>, _>(&UI, active.id, &message),
+            (None, None) => self.engine.on_download_fail::<CocoaUI<_>, _>(&UI, active.id, "No destination chosen"),
+        }
+    }
+
+    /// Scroll a webview to the position recorded for its current resource if
+    /// `general.restore-scroll` is enabled
+    pub fn restore_scroll_position(&self, webview: &WKWebView) {
+        if !self.engine.config.restore_scroll() {
+            return;
+        }
+        let uri = webview.url().and_then(|url| url.absolute_string().as_str());
+        if let (Some(uri), Ok(positions)) = (uri, self.scroll_positions.lock()) {
+            if let Some(&(x, y)) = positions.get(uri) {
+                info!("Restoring scroll position for {}", uri);
+                webview.evaluate_javascript(&scroll_script(x, y));
+            }
+        }
+    }
+
+    /// Focus the webview after (or before if `forward` is `false`) the
+    /// focused webview in a window, in the order set by
+    /// `commands.tab-switch-order`
+    fn focus_adjacent_webview(&self, window_index: u32, forward: bool) {
+        let count = self.webview_count(window_index);
+        let order = self.engine.config.tab_switch_order();
+        let history = window::focus_history(window_index);
+        if let Some(index) = self.focused_webview_index(window_index)
+            .and_then(|index| tab_switch_index(order, &history, index, count, forward)) {
+            self.focus_webview(window_index, index);
+        }
+    }
+
+    /// The webview at an index in a window, logging a warning if it does not
+    /// exist
+    fn webview(&self, window_index: u32, webview_index: u32) -> Option<WKWebView> {
+        match self.check_webview(window_index, webview_index) {
+            Ok(()) => window::webview(window_index, webview_index),
+            Err(err) => {
+                warn!("{}", err);
+                None
             }
         }
     }
@@ -70,7 +558,7 @@ impl<S: ScriptingEngine> CocoaUI<S> {
                 self.open_window::<_, Config>(Some(page.as_str()), None);
             }
         } else {
-            self.open_window::<_, Config>(self.engine.config.start_page(), None);
+            self.open_window::<_, Config>(self.engine.config.start_page(&WindowContext::Default), None);
         }
     }
 }
@@ -78,14 +566,45 @@ impl<S: ScriptingEngine> CocoaUI<S> {
 impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
 
     fn new(engine: Engine) -> Option<Self> {
-        Some(CocoaUI { engine: engine, engine_type: PhantomData })
+        Some(CocoaUI {
+            engine,
+            cache_mode: Mutex::new(None),
+            scroll_positions: Mutex::new(BoundedCache::new(SCROLL_POSITION_CAPACITY)),
+            content_filter: Mutex::new(None),
+            navigation_types: Mutex::new(HashMap::new()),
+            custom_headers: Mutex::new(HashMap::new()),
+            page_stats: Mutex::new(HashMap::new()),
+            page_timings: Mutex::new(HashMap::new()),
+            focused_elements: Mutex::new(HashMap::new()),
+            fullscreen_webviews: Mutex::new(HashSet::new()),
+            document_kinds: Mutex::new(HashMap::new()),
+            fitted_webviews: Mutex::new(HashSet::new()),
+            pending_styles: Mutex::new(HashMap::new()),
+            configured_stylesheets: Mutex::new(HashMap::new()),
+            find_highlights: Mutex::new(HashMap::new()),
+            find_options: Mutex::new(HashMap::new()),
+            focus_history: Mutex::new(HashMap::new()),
+            process_pool: Mutex::new(None),
+            webview_times: Mutex::new(HashMap::new()),
+            deferred_uris: Mutex::new(HashMap::new()),
+            refresh_timers: Mutex::new(HashMap::new()),
+            private_stores: Mutex::new(HashMap::new()),
+            downloads: Mutex::new(HashMap::new()),
+            next_download_id: Mutex::new(1),
+            control_output: Mutex::new(None),
+            engine_type: PhantomData
+        })
     }
 
     fn run(&self) {
         let pool = NSAutoreleasePool::new();
-        self.compile_content_extensions(|_| {});
+        self.reload_filters(None);
         let delegate = application::initialize_app_env();
+        if let Some(path) = self.engine.config.control_socket() {
+            runtime::listen_for_control_commands(&delegate, &path);
+        }
         self.open_first_window();
+        self.engine.on_startup::<Self, S>(self);
         application::start_run_loop(&delegate);
         pool.drain();
     }
@@ -94,24 +613,84 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         NSPasteboard::general().copy(text);
     }
 
+    fn reveal_in_file_manager(&self, path: &str) {
+        let expanded = command::expand_path(path);
+        let mut target = Path::new(&expanded);
+        while !target.exists() {
+            match target.parent() {
+                Some(parent) => target = parent,
+                None => {
+                    warn!("No existing directory to reveal: {}", path);
+                    return;
+                },
+            }
+        }
+        if let Some(target) = target.to_str() {
+            info!("Revealing {}", target);
+            let urls = NSArray::from_vec(vec![target], |target| NSURL::file_url(NSString::from(target)));
+            NSWorkspace::shared_workspace().activate_file_viewer_selecting(urls);
+        }
+    }
+
+    fn notify(&self, title: &str, body: &str) {
+        if !self.engine.config.notifications() {
+            info!("Notifications are disabled: {}", title);
+            return;
+        }
+        let mut notification = NSUserNotification::new(title, body);
+        NSUserNotificationCenter::default_center().deliver(&notification);
+        notification.release();
+    }
+
     fn execute_command(&self, window_index: Option<u32>, text: &str) {
         UI.engine.execute_command::<CocoaUI<_>, _>(&UI, window_index, text);
     }
 
+    fn execute_command_in_all_windows(&self, text: &str) -> Vec<(u32, CommandError)> {
+        UI.engine.execute_in_all_windows::<CocoaUI<_>, _>(&UI, text)
+    }
+
     fn open_window<U, B>(&self, uri: Option<U>, config: Option<B>) -> u32
         where U: Into<String>,
               B: BrowserConfiguration {
         if let Some(uri) = uri {
             window::open(Some(uri), config)
         } else {
-            window::open(self.engine.config.start_page(), config)
+            window::open(self.engine.config.start_page(&WindowContext::Default), config)
         }
     }
 
+    fn open_private_window(&self) -> u32 {
+        window::open_private(self.engine.config.start_page(&WindowContext::Private))
+    }
+
     fn close_window(&self, index: u32) {
+        self.engine.record_closed_window(window::persistent_uris(index), self.hidden_chrome(index));
         window::close(index);
     }
 
+    fn reopen_closed_window(&self) -> Option<u32> {
+        self.engine.take_closed_window().and_then(|closed| {
+            let hidden_chrome = closed.hidden_chrome;
+            let mut uris = closed.uris.into_iter();
+            uris.next().map(|uri| {
+                let window_index = window::open(Some(uri), None::<Config>);
+                let lazy = self.engine.config.lazy_restore();
+                for uri in uris {
+                    match lazy {
+                        true => window::open_deferred_webview(window_index, Some(uri), None::<Config>),
+                        false => window::open_webview(window_index, Some(uri), None::<Config>),
+                    }
+                }
+                for component in ALL_CHROME.iter() {
+                    let visible = !hidden_chrome.contains(component);
+                    self.set_chrome_visible(window_index, *component, visible);
+                }
+                window_index
+            })
+        })
+    }
+
     fn focused_window_index(&self) -> Option<u32> {
         window::focused_index()
     }
@@ -128,6 +707,10 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         nsapp().ordered_windows().count() as u32
     }
 
+    fn window_exists(&self, window_index: u32) -> bool {
+        window::exists(window_index)
+    }
+
     fn toggle_window(&self, window_index: u32, visible: bool) {
         window::toggle(window_index, visible);
     }
@@ -136,6 +719,26 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         window::resize(window_index, width, height);
     }
 
+    fn screen_frame(&self) -> Option<WindowFrame> {
+        window::screen_frame()
+    }
+
+    fn set_window_frame(&self, window_index: u32, frame: WindowFrame) {
+        window::set_frame(window_index, frame);
+    }
+
+    fn window_minimized(&self, window_index: u32) -> bool {
+        window::minimized(window_index)
+    }
+
+    fn minimize_window(&self, window_index: u32) {
+        window::set_minimized(window_index, true);
+    }
+
+    fn restore_window(&self, window_index: u32) {
+        window::set_minimized(window_index, false);
+    }
+
     fn command_field_text(&self, window_index: u32) -> String {
         window::command_field_text(window_index)
     }
@@ -160,6 +763,14 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         window::set_title(window_index, title);
     }
 
+    fn represented_uri(&self, window_index: u32) -> Option<String> {
+        window::represented_uri(window_index)
+    }
+
+    fn set_represented_uri(&self, window_index: u32, uri: Option<&str>) {
+        window::set_represented_uri(window_index, uri);
+    }
+
     fn focused_webview_index(&self, window_index: u32) -> Option<u32> {
         window::focused_webview_index(window_index)
     }
@@ -168,41 +779,142 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         window::webview_count(window_index)
     }
 
+    fn webview_age(&self, window_index: u32, webview_index: u32) -> Duration {
+        match self.check_webview(window_index, webview_index) {
+            Ok(()) => window::webview_age(window_index, webview_index),
+            Err(err) => {
+                warn!("{}", err);
+                Duration::default()
+            }
+        }
+    }
+
+    fn webview_idle(&self, window_index: u32, webview_index: u32) -> Duration {
+        match self.check_webview(window_index, webview_index) {
+            Ok(()) => window::webview_idle(window_index, webview_index),
+            Err(err) => {
+                warn!("{}", err);
+                Duration::default()
+            }
+        }
+    }
+
     fn open_webview<U, B>(&self, window_index: u32, uri: Option<U>, config: Option<B>)
         where U: Into<String>,
               B: BrowserConfiguration {
-        if let Some(uri) = uri {
-            window::open_webview(window_index, Some(uri), config);
+        if let Some(uri) = uri.map(|uri| uri.into()) {
+            if self.focus_duplicate_webview(&uri).is_none() {
+                window::open_webview(window_index, Some(uri), config);
+            }
         } else {
-            window::open_webview(window_index, self.engine.config.start_page(), config);
+            let page = self.engine.config.start_page(&window::context(window_index));
+            window::open_webview(window_index, page, config);
+        }
+    }
+
+    fn queue_webview(&self, window_index: u32, uri: &str) {
+        self.engine.queue_webview::<CocoaUI<_>, _>(self, window_index, uri);
+    }
+
+    fn move_webview(&self, window_index: u32, from: u32, to: u32) -> bool {
+        match self.check_webview(window_index, from).and(self.check_webview(window_index, to)) {
+            Ok(()) => window::move_webview(window_index, from, to),
+            Err(err) => {
+                warn!("{}", err);
+                false
+            }
         }
     }
 
     fn close_webview(&self, window_index: u32, webview_index: u32) {
-        window::close_webview(window_index, webview_index);
+        match self.check_webview(window_index, webview_index) {
+            Ok(()) => window::close_webview(window_index, webview_index),
+            Err(err) => warn!("{}", err)
+        }
     }
 
     fn focus_webview(&self, window_index: u32, webview_index: u32) {
-        window::focus_webview(window_index, webview_index);
+        match self.check_webview(window_index, webview_index) {
+            Ok(()) => window::focus_webview(window_index, webview_index),
+            Err(err) => warn!("{}", err)
+        }
+    }
+
+    fn focus_next_webview(&self, window_index: u32) {
+        self.focus_adjacent_webview(window_index, true)
+    }
+
+    fn focus_previous_webview(&self, window_index: u32) {
+        self.focus_adjacent_webview(window_index, false)
     }
 
     fn reload_webview(&self, window_index: u32, webview_index: u32, disable_filters: bool) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
-            match disable_filters {
-                true  => webview.reload_without_content_blockers(),
-                false => webview.reload()
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            let uri = self.uri(window_index, webview_index);
+            match (disable_filters, self.cache_mode(&uri)) {
+                (true, _) => webview.reload_without_content_blockers(),
+                (false, CacheMode::ReloadIgnoringCache) => webview.reload_from_origin(),
+                (false, _) => webview.reload()
             }
         }
     }
 
+    fn stop_webview(&self, window_index: u32, webview_index: u32) -> bool {
+        match self.webview(window_index, webview_index) {
+            Some(ref webview) if webview.is_loading() => {
+                webview.stop_loading();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn reset_webview(&self, window_index: u32, webview_index: u32) -> bool {
+        let webview = match self.webview(window_index, webview_index) {
+            Some(webview) => webview,
+            None => return false,
+        };
+        let key = webview.ptr() as usize;
+        webview.stop_loading();
+        webview.back_forward_list().remove_all_items();
+        window::reset_user_scripts(&webview);
+        window::forget_background_state(&webview);
+        self.forget_pending_styles(webview.ptr());
+        self.clear_page_statistics(webview.ptr());
+        if let Ok(mut custom_headers) = self.custom_headers.lock() {
+            custom_headers.remove(&key);
+        }
+        if let Ok(mut colors) = self.find_highlights.lock() {
+            colors.remove(&key);
+        }
+        if let Ok(mut options) = self.find_options.lock() {
+            options.remove(&key);
+        }
+        let uri = self.engine.config.start_page(&window::context(window_index))
+            .unwrap_or(String::from("about:blank"));
+        webview.load_request(create_request(&uri, self.cache_mode(&uri)));
+        true
+    }
+
     fn set_uri(&self, window_index: u32, webview_index: u32, uri: &str) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
-            webview.load_request(create_request(uri));
+        let current_uri = self.uri(window_index, webview_index);
+        let shows_match = self.engine.config.deduplicate_tabs()
+            .map_or(false, |matching| matching.matches(&current_uri, uri));
+        if !shows_match && self.focus_duplicate_webview(uri).is_some() {
+            return;
         }
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            webview.load_request(create_request(uri, self.cache_mode(uri)));
+        }
+    }
+
+    fn load_html(&self, window_index: u32, webview_index: u32, html: &str, base_uri: Option<&str>) -> bool {
+        self.webview(window_index, webview_index)
+            .map_or(false, |webview| webview.load_html_string(html, base_uri))
     }
 
     fn go_back(&self, window_index: u32, webview_index: u32) -> bool {
-        if let Some(webview) = window::webview(window_index, webview_index) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
             if webview.can_go_back() {
                 webview.go_back();
                 return true;
@@ -212,7 +924,7 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
     }
 
     fn go_forward(&self, window_index: u32, webview_index: u32) -> bool {
-        if let Some(webview) = window::webview(window_index, webview_index) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
             if webview.can_go_forward() {
                 webview.go_forward();
                 return true;
@@ -221,65 +933,540 @@ impl<S: ScriptingEngine> ApplicationUI<S> for CocoaUI<S> {
         false
     }
 
+    fn can_go_back(&self, window_index: u32, webview_index: u32) -> bool {
+        self.webview(window_index, webview_index)
+            .map_or(false, |webview| webview.can_go_back())
+    }
+
+    fn can_go_forward(&self, window_index: u32, webview_index: u32) -> bool {
+        self.webview(window_index, webview_index)
+            .map_or(false, |webview| webview.can_go_forward())
+    }
+
     fn uri(&self, window_index: u32, webview_index: u32) -> String {
-        String::from(window::webview(window_index, webview_index)
+        let webview = self.webview(window_index, webview_index);
+        if let Some(uri) = webview.as_ref().and_then(|webview| window::deferred_uri(webview)) {
+            return uri;
+        }
+        String::from(webview
             .and_then(|webview| webview.url())
             .and_then(|u| u.absolute_string().as_str())
             .unwrap_or(""))
     }
 
     fn webview_title(&self, window_index: u32, webview_index: u32) -> String {
-        String::from(window::webview(window_index, webview_index)
+        String::from(self.webview(window_index, webview_index)
             .and_then(|webview| webview.title())
             .and_then(|title| title.as_str())
             .unwrap_or(""))
     }
 
-    fn find_string(&self, window_index: u32, webview_index: u32, query: &str) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
-            webview.find_string(query)
+    fn find_string_with_options(&self, window_index: u32, webview_index: u32, query: &str, options: FindOptions) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            if let Ok(mut find_options) = self.find_options.lock() {
+                find_options.insert(webview.ptr() as usize, options);
+            }
+            let color = self.find_highlights.lock().ok()
+                .and_then(|colors| colors.get(&(webview.ptr() as usize)).cloned())
+                .or_else(|| self.engine.config.find_highlight_color(&self.uri(window_index, webview_index)));
+            if let Some(color) = color {
+                webview.evaluate_javascript(&find_highlight_script(Some(&color)));
+            }
+            webview.find_string(query, options.highlight_all)
         }
     }
 
+    fn find_options(&self, window_index: u32, webview_index: u32) -> FindOptions {
+        self.webview(window_index, webview_index)
+            .and_then(|webview| {
+                self.find_options.lock().ok()
+                    .and_then(|options| options.get(&(webview.ptr() as usize)).cloned())
+            })
+            .unwrap_or(FindOptions::default())
+    }
+
     fn hide_find_results(&self, window_index: u32, webview_index: u32) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            webview.evaluate_javascript(&find_highlight_script(None));
             webview.hide_find_results()
         }
     }
 
+    fn set_find_highlight(&self, window_index: u32, webview_index: u32, color: Option<&str>) -> bool {
+        if let Some(color) = color.filter(|color| !valid_css_color(color)) {
+            warn!("Invalid find highlight color: {}", color);
+            return false;
+        }
+        match (self.webview(window_index, webview_index), self.find_highlights.lock()) {
+            (Some(webview), Ok(mut colors)) => {
+                let key = webview.ptr() as usize;
+                match color {
+                    Some(color) => colors.insert(key, String::from(color)),
+                    None => colors.remove(&key),
+                };
+                true
+            },
+            _ => false
+        }
+    }
+
     fn run_javascript(&self, window_index: u32, webview_index: u32, script: &str) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
             webview.evaluate_javascript(script)
         }
     }
 
-    fn apply_styles(&self, window_index: u32, webview_index: u32, styles: &str) {
-        if let Some(webview) = window::webview(window_index, webview_index) {
+    fn inject_user_script(&self, window_index: u32, webview_index: u32, script: &str, at_start: bool) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            let time = match at_start {
+                true => WKUserScriptInjectionTime::AtDocumentStart,
+                false => WKUserScriptInjectionTime::AtDocumentEnd,
+            };
             let controller = webview.configuration().user_content_controller();
-            if controller.can_add_user_style_sheet() {
-                let sheet = _WKUserStyleSheet::new(styles);
-                controller.add_user_style_sheet(sheet);
-            } else {
-                info!("Using fallback stylesheet");
-                let formatted_style = styles.replace("\"", "\\\"").replace("\n", "");
-                let script = format!(r#"
-                    var head = document.getElementsByTagName('head')[0],
-                        style = document.createElement('style'),
-                        content = document.createTextNode('{}');
-                    style.appendChild(content);
-                    if (head != undefined) {{
-                        head.appendChild(style);
-                        }}"#, formatted_style);
-                webview.evaluate_javascript(&script);
+            controller.add_user_script(WKUserScript::new(script, time));
+        }
+    }
+
+    fn clear_user_scripts(&self, window_index: u32, webview_index: u32) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            webview.configuration().user_content_controller().remove_all_user_scripts();
+        }
+    }
+
+    fn save_page(&self, window_index: u32, webview_index: u32, path: &str, format: SaveFormat) -> bool {
+        let webview = match self.webview(window_index, webview_index) {
+            Some(webview) => webview,
+            None => return false,
+        };
+        if let Some(parent) = Path::new(path).parent() {
+            if let Err(err) = fs::create_dir_all(parent) {
+                warn!("Failed to create directory for saved page ({}): {}", path, err);
+                return false;
             }
         }
+        let path = String::from(path);
+        info!("Saving page as {:?}: {}", format, path);
+        match format {
+            SaveFormat::Html => {
+                let block = ConcreteBlock::new(move |result: Id, err: Id| {
+                    log_error_description(err);
+                    if let Some(html) = NSString::from_ptr(result).and_then(|html| html.as_str()) {
+                        write_file(&path, html.as_bytes());
+                    }
+                });
+                webview.evaluate_javascript_with_completion(SERIALIZE_DOCUMENT_SCRIPT,
+                                                            &block.copy());
+            },
+            SaveFormat::WebArchive => {
+                let block = ConcreteBlock::new(move |data: Id, err: Id| {
+                    log_error_description(err);
+                    if let Some(data) = NSData::from_ptr(data) {
+                        write_file(&path, data.bytes());
+                    }
+                });
+                webview.web_archive_data(&block.copy());
+            },
+        }
+        true
+    }
+
+    fn set_custom_headers(&self, window_index: u32, webview_index: u32, headers: &[(String, String)]) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            let headers: Vec<(String, String)> = headers.iter()
+                .filter(|&&(ref name, ref value)| {
+                    let valid = valid_header(name, value);
+                    if !valid {
+                        warn!("Ignoring invalid header: {}", name);
+                    }
+                    valid
+                })
+                .cloned()
+                .collect();
+            if let Ok(mut custom_headers) = self.custom_headers.lock() {
+                custom_headers.insert(webview.ptr() as usize, headers);
+            }
+        }
+    }
+
+    fn page_statistics(&self, window_index: u32, webview_index: u32) -> Option<PageStats> {
+        self.webview(window_index, webview_index).and_then(|webview| {
+            self.page_stats.lock().ok()
+                .and_then(|page_stats| page_stats.get(&(webview.ptr() as usize)).cloned())
+        })
+    }
+
+    fn webview_process_stats(&self, window_index: u32, webview_index: u32) -> ProcessStats {
+        let pid = self.webview(window_index, webview_index)
+            .map(|webview| webview.web_process_identifier())
+            .unwrap_or(0);
+        if pid <= 0 {
+            return ProcessStats::default();
+        }
+        ProcessStats {
+            pid: pid as u32,
+            memory_bytes: libproc::resident_size(pid).unwrap_or(0),
+        }
+    }
+
+    fn focused_element(&self, window_index: u32, webview_index: u32) -> Option<ElementInfo> {
+        self.webview(window_index, webview_index).and_then(|webview| {
+            self.focused_elements.lock().ok()
+                .and_then(|elements| elements.get(&(webview.ptr() as usize)).cloned())
+        })
+    }
+
+    fn fill_form_fields(&self, window_index: u32, webview_index: u32, values: &[(String, String)],
+                        command: &str) -> bool {
+        let webview = match self.webview(window_index, webview_index) {
+            Some(webview) => webview,
+            None => return false,
+        };
+        let command = String::from(command);
+        let block = ConcreteBlock::new(move |value: Id, _err: Id| {
+            let count = NSNumber::from_ptr(value)
+                .map(|count| count.integer_value() as usize)
+                .unwrap_or(0);
+            UI.engine.on_page_event::<CocoaUI<_>, _>(&UI, &command, window_index, webview_index,
+                                                     PageEvent::FormFilled(count));
+        });
+        webview.evaluate_javascript_with_completion(&fill_form_script(values), &block.copy());
+        true
+    }
+
+    fn query_selector_all(&self, window_index: u32, webview_index: u32, selector: &str, command: &str) -> bool {
+        let webview = match self.webview(window_index, webview_index) {
+            Some(webview) => webview,
+            None => return false,
+        };
+        let selector = String::from(selector);
+        let command = String::from(command);
+        let script = query_selector_script(&selector);
+        let block = ConcreteBlock::new(move |value: Id, _err: Id| {
+            let elements = NSArray::from_ptr(value).map(|values| {
+                (0..values.count())
+                    .filter_map(|index| values.get::<NSDictionary>(index))
+                    .filter_map(|element| queried_element(&element))
+                    .collect()
+            });
+            UI.engine.on_page_event::<CocoaUI<_>, _>(&UI, &command, window_index, webview_index,
+                                                     PageEvent::ElementsQueried(selector.clone(),
+                                                                                elements.unwrap_or(vec![])));
+        });
+        webview.evaluate_javascript_with_completion(&script, &block.copy());
+        true
+    }
+
+    fn show_dialog(&self, window_index: u32, webview_index: u32, kind: &DialogKind, message: &str) -> DialogResponse {
+        let alert = NSAlert::new().autorelease();
+        let host = uri_host(&self.uri(window_index, webview_index)).unwrap_or(String::new());
+        alert.set_message_text(&host);
+        alert.set_informative_text(message);
+        alert.add_button("OK");
+        let field = match kind {
+            &DialogKind::Alert => None,
+            &DialogKind::Confirm => {
+                alert.add_button("Cancel");
+                None
+            },
+            &DialogKind::Prompt(ref default_text) => {
+                alert.add_button("Cancel");
+                let field = NSTextField::new().autorelease();
+                field.set_frame_size(DIALOG_FIELD_WIDTH, DIALOG_FIELD_HEIGHT);
+                if let Some(control) = NSControl::from_ptr(field.ptr()) {
+                    control.set_text(default_text);
+                }
+                alert.set_accessory_view(&field);
+                Some(field)
+            },
+        };
+        if alert.run_modal() != NS_ALERT_FIRST_BUTTON_RETURN {
+            return DialogResponse::Dismiss;
+        }
+        field.and_then(|field| NSControl::from_ptr(field.ptr()))
+            .and_then(|control| control.text())
+            .and_then(|text| text.as_str().map(String::from))
+            .map(DialogResponse::Text)
+            .unwrap_or(DialogResponse::Accept)
+    }
+
+    fn fit_to_window(&self, window_index: u32, webview_index: u32) -> bool {
+        self.webview(window_index, webview_index)
+            .map(|webview| self.fit_webview(&webview))
+            .unwrap_or(false)
+    }
+
+    fn is_element_fullscreen(&self, window_index: u32, webview_index: u32) -> bool {
+        self.webview(window_index, webview_index).map(|webview| {
+            self.fullscreen_webviews.lock()
+                .map(|webviews| webviews.contains(&(webview.ptr() as usize)))
+                .unwrap_or(false)
+        }).unwrap_or(false)
+    }
+
+    fn input_mode(&self, window_index: u32, webview_index: u32) -> InputMode {
+        self.engine.input_mode(window_index, webview_index)
+    }
+
+    fn set_input_mode(&self, window_index: u32, webview_index: u32, mode: InputMode) -> bool {
+        self.engine.set_input_mode::<Self, S>(self, window_index, webview_index, mode)
+    }
+
+    fn page_timing(&self, window_index: u32, webview_index: u32) -> PageTiming {
+        self.webview(window_index, webview_index)
+            .and_then(|webview| {
+                self.page_timings.lock().ok()
+                    .and_then(|page_timings| page_timings.get(&(webview.ptr() as usize)).cloned())
+            })
+            .unwrap_or_default()
+    }
+
+    fn private_browsing(&self, window_index: u32, webview_index: u32) -> bool {
+        self.engine.config.use_private_browsing(&self.uri(window_index, webview_index))
+    }
+
+    fn set_private_browsing(&self, window_index: u32, webview_index: u32, enabled: bool) -> bool {
+        let uri = self.uri(window_index, webview_index);
+        if uri.is_empty() || !self.engine.set_site_private_browsing(&uri, enabled) {
+            return false;
+        }
+        // The data store of a webview cannot be changed after creation
+        window::reopen_webview(window_index, webview_index, uri);
+        true
+    }
+
+    fn apply_styles(&self, window_index: u32, webview_index: u32, styles: &str) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            add_styles(&webview, styles);
+        }
+    }
+
+    fn apply_styles_all(&self, styles: &str) {
+        for (window_index, webview_index) in self.all_webviews() {
+            if let Some(webview) = self.webview(window_index, webview_index) {
+                if !webview.is_loading() {
+                    add_styles(&webview, styles);
+                } else if let Ok(mut pending) = self.pending_styles.lock() {
+                    pending.entry(webview.ptr() as usize)
+                        .or_insert_with(Vec::new)
+                        .push(String::from(styles));
+                }
+            }
+        }
+    }
+
+    fn scroll_to(&self, window_index: u32, webview_index: u32, x: f64, y: f64) {
+        if let Some(webview) = self.webview(window_index, webview_index) {
+            webview.evaluate_javascript(&scroll_script(x, y));
+        }
+    }
+
+    fn set_color_scheme(&self, window_index: u32, webview_index: u32, scheme: ColorScheme) {
+        let view = self.webview(window_index, webview_index)
+            .and_then(|webview| webview.coerce::<NSView>());
+        if let Some(view) = view {
+            view.set_appearance(window::appearance(scheme));
+        }
+    }
+
+    fn reload_stylesheets(&self) {
+        window::reload_stylesheets();
+    }
+
+    fn explain_command(&self, text: &str) -> command::CommandExplanation {
+        self.engine.explain_command::<S>(text)
+    }
+
+    fn commands_for_uri_event(&self, event: &BufferEvent, uri: &str) -> Vec<String> {
+        self.engine.commands_for_uri_event::<S>(event, Some(uri))
+    }
+
+    fn set_command_disabled(&self, name: &str, disabled: bool) -> bool {
+        self.engine.set_command_disabled::<S>(name, disabled)
+    }
+
+    fn kv_get(&self, namespace: &str, key: &str) -> Option<String> {
+        self.engine.kv_get(namespace, key)
+    }
+
+    fn kv_set(&self, namespace: &str, key: &str, value: Option<&str>) -> bool {
+        self.engine.kv_set(namespace, key, value)
+    }
+
+    fn reload_config(&self) -> bool {
+        if !self.engine.reload() {
+            warn!("Failed to reload configuration");
+            return false;
+        }
+        self.reload_filters(None);
+        self.reload_stylesheets();
+        window::reload_user_scripts();
+        window::reload_background_colors();
+        true
+    }
+
+    fn reload_filters(&self, window_index: Option<u32>) -> bool {
+        self.engine.reload_filters::<Self, S>(self, window_index)
+    }
+
+    fn caret_browsing(&self) -> bool {
+        self.engine.caret_browsing()
+    }
+
+    fn set_caret_browsing(&self, enabled: bool) {
+        self.engine.set_caret_browsing(enabled);
+        for (window_index, webview_index) in self.all_webviews() {
+            if let Some(webview) = self.webview(window_index, webview_index) {
+                webview.configuration().preferences().set_caret_browsing_enabled(enabled);
+            }
+        }
+    }
+
+    fn set_content_filter(&self, filter: &str, window_index: Option<u32>) {
+        let compiled_filter = String::from(filter);
+        self.compile_content_extension(filter, move |compiled| {
+            UI.engine.on_content_filter_compiled::<CocoaUI<_>, _>(&UI, window_index, &compiled_filter,
+                                                                 compiled.is_some());
+            if let Some(compiled) = compiled {
+                window::set_content_filter(&compiled);
+                if let Ok(mut content_filter) = UI.content_filter.lock() {
+                    *content_filter = Some(compiled_filter.clone());
+                }
+                return;
+            }
+            let previous = UI.content_filter.lock().ok().and_then(|filter| filter.clone());
+            if let Some(previous) = previous {
+                warn!("Failed to compile content filter, restoring previous rules");
+                UI.compile_content_extension(&previous, |_| {});
+            }
+        });
+    }
+
+    fn set_cache_mode(&self, mode: CacheMode) {
+        if let Ok(mut cache_mode) = self.cache_mode.lock() {
+            info!("Setting cache mode: {:?}", mode);
+            *cache_mode = Some(mode);
+        }
     }
 }
 
-pub fn create_request(uri: &str) -> NSURLRequest {
+fn write_file(path: &str, contents: &[u8]) {
+    match File::create(path).and_then(|mut file| file.write_all(contents)) {
+        Ok(_) => info!("Saved page: {}", path),
+        Err(err) => warn!("Failed to save page ({}): {}", path, err),
+    }
+}
+
+/// Script replacing the style of the current find match, which is selected
+/// in the page, or removing the style if `color` is `None`
+fn find_highlight_script(color: Option<&str>) -> String {
+    let style = color.map(|color| format!("::selection {{ background-color: {} !important; }}", color))
+        .unwrap_or(String::new());
+    format!("(function() {{
+  var style = document.getElementById('{id}');
+  if (style) {{ style.remove(); }}
+  if ({add}) {{
+    style = document.createElement('style');
+    style.id = '{id}';
+    style.textContent = '{style}';
+    (document.head || document.documentElement).appendChild(style);
+  }}
+}})()", id=FIND_HIGHLIGHT_STYLE_ID, add=color.is_some(), style=style)
+}
+
+/// Describe an element listed by `query_selector_script`
+fn queried_element(element: &NSDictionary) -> Option<ElementInfo> {
+    let text = |key: &str| element.get::<NSString>(key)
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or(String::new());
+    let number = |key: &str| element.get::<NSNumber>(key)
+        .map(|value| value.double_value())
+        .unwrap_or(0.);
+    let editable = element.get::<NSNumber>("editable")
+        .map(|value| value.integer_value() != 0)
+        .unwrap_or(false);
+    let attributes = element.get::<NSArray>("attributes").map(|pairs| {
+        (0..pairs.count())
+            .filter_map(|index| pairs.get::<NSArray>(index))
+            .filter_map(|pair| match (pair.get::<NSString>(0), pair.get::<NSString>(1)) {
+                (Some(name), Some(value)) => match (name.as_str(), value.as_str()) {
+                    (Some(name), Some(value)) => Some((String::from(name), String::from(value))),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    });
+    ElementInfo::new(&text("tag"), &text("type"), editable).map(|mut info| {
+        info.text = text("text");
+        info.attributes = attributes.unwrap_or(vec![]);
+        info.bounds = ElementBounds {
+            x: number("x"),
+            y: number("y"),
+            width: number("width"),
+            height: number("height"),
+        };
+        info
+    })
+}
+
+fn scroll_script(x: f64, y: f64) -> String {
+    format!("window.scrollTo({}, {});", x, y)
+}
+
+/// Add a stylesheet to a webview, falling back to inserting a style element
+/// when user stylesheets are unsupported
+fn add_styles(webview: &WKWebView, styles: &str) {
+    let controller = webview.configuration().user_content_controller();
+    if controller.can_add_user_style_sheet() {
+        let sheet = _WKUserStyleSheet::new(styles);
+        controller.add_user_style_sheet(sheet);
+    } else {
+        info!("Using fallback stylesheet");
+        let formatted_style = styles.replace("\"", "\\\"").replace("\n", "");
+        let script = format!(r#"
+            var head = document.getElementsByTagName('head')[0],
+                style = document.createElement('style'),
+                content = document.createTextNode('{}');
+            style.appendChild(content);
+            if (head != undefined) {{
+                head.appendChild(style);
+                }}"#, formatted_style);
+        webview.evaluate_javascript(&script);
+    }
+}
+
+/// A path in a directory for a file name which does not exist yet, adding
+/// a number to the name if needed, such as `report (2).pdf`
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let name_path = Path::new(name);
+    let stem = name_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(name);
+    let extension = name_path.extension().and_then(|ext| ext.to_str());
+    let mut count = 2;
+    loop {
+        let candidate = match extension {
+            Some(extension) => dir.join(format!("{} ({}).{}", stem, count, extension)),
+            None => dir.join(format!("{} ({})", stem, count)),
+        };
+        if !candidate.exists() {
+            return candidate;
+        }
+        count += 1;
+    }
+}
+
+pub fn create_request(uri: &str, mode: CacheMode) -> NSURLRequest {
     let mut target = String::from(uri);
     if !target.contains("://") {
         target = format!("http://{}", target);
     }
-    NSURLRequest::from(NSURL::from(NSString::from(&target)))
+    let policy = match mode {
+        CacheMode::Default => NSURLRequestCachePolicy::UseProtocolCachePolicy,
+        CacheMode::ReloadIgnoringCache => NSURLRequestCachePolicy::ReloadIgnoringLocalCacheData,
+        CacheMode::Offline => NSURLRequestCachePolicy::ReturnCacheDataDontLoad,
+    };
+    NSURLRequest::with_cache_policy(NSURL::from(NSString::from(&target)), policy)
 }
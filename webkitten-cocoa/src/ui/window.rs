@@ -1,18 +1,156 @@
+use std::fs::File;
+use std::io::Read;
+use std::time::{Duration,Instant};
+
 use macos::{Id,nil,ObjCClass};
-use macos::foundation::{NSRect,NSPoint,NSSize,NSArray,NSUInteger,NSInteger};
+use macos::foundation::{NSRect,NSPoint,NSSize,NSArray,NSUInteger,NSInteger,NSString,NSTimer,NSURL,
+                        NSURLRequest};
 use macos::appkit::*;
 use macos::core_graphics::{CGFloat,CGRect};
 use macos::webkit::*;
 use block::ConcreteBlock;
 use webkitten::WEBKITTEN_TITLE;
-use webkitten::ui::{BrowserConfiguration,WindowArea,BufferEvent,EventHandler};
+use webkitten::config::Config;
+use webkitten::contentblock::hiding_stylesheet;
+use webkitten::userscripts::RunAt;
+use webkitten::ui::{ApplicationUI,BrowserConfiguration,Chrome,ColorScheme,WindowArea,WindowContext,BufferEvent,
+                    EventHandler,WindowFrame};
 
-use ui::{CocoaUI,UI};
-use runtime::{CommandBarDelegate,WebViewHistoryDelegate,WebViewContainerView,
-              log_error_description,default_user_agent,CommandBarView};
+use ui::{CocoaUI,UI,create_request};
+use runtime::{CommandBarDelegate,ConsoleMessageHandler,FocusHandler,GeolocationHandler,
+              NotificationHandler,RefreshHandler,WebViewHistoryDelegate,WebViewContainerView,WebViewUIDelegate,
+              log_error_description,default_user_agent,reference_indices,CommandBarView};
 
 
 const BAR_HEIGHT: usize = 24;
+/// The name of the script message handler receiving console messages
+const CONSOLE_HANDLER_NAME: &str = "webkittenConsole";
+/// Script forwarding console messages to the console message handler,
+/// including the source and line of the caller from the stack trace
+const CONSOLE_CAPTURE_SCRIPT: &str = "(function() {
+  var handler = window.webkit && window.webkit.messageHandlers.webkittenConsole;
+  if (!handler) { return; }
+  ['debug', 'log', 'info', 'warn', 'error'].forEach(function(level) {
+    var original = console[level];
+    console[level] = function() {
+      var message = Array.prototype.map.call(arguments, function(arg) {
+        if (typeof arg === 'string') { return arg; }
+        try { return JSON.stringify(arg); } catch (e) { return String(arg); }
+      }).join(' ');
+      var caller = (new Error().stack || '').split('\\n')[1] || '';
+      var match = caller.match(/@?([^@\\s]+):(\\d+):\\d+$/);
+      handler.postMessage({level: level, message: message,
+                           source: match ? match[1] : window.location.href,
+                           line: match ? parseInt(match[2], 10) : 0});
+      return original.apply(console, arguments);
+    };
+  });
+})();";
+/// The name of the script message handler receiving notification requests
+const NOTIFICATION_HANDLER_NAME: &str = "webkittenNotification";
+/// Script replacing the Web Notifications API with one forwarding permission
+/// requests and notifications to the notification message handler. Pages
+/// may change the permission reported here, so notifications are checked
+/// again before they are displayed.
+const NOTIFICATION_SCRIPT: &str = "(function() {
+  var handler = window.webkit && window.webkit.messageHandlers.webkittenNotification;
+  if (!handler) { return; }
+  var pending = [];
+  function Notification(title, options) {
+    this.title = String(title);
+    this.body = options && options.body ? String(options.body) : '';
+    if (Notification.permission === 'granted') {
+      handler.postMessage({type: 'show', title: this.title, body: this.body});
+    }
+  }
+  Notification.permission = 'default';
+  Notification.prototype.close = function() {};
+  Notification.requestPermission = function(callback) {
+    return new Promise(function(resolve) {
+      pending.push(function(permission) {
+        if (callback) { callback(permission); }
+        resolve(permission);
+      });
+      handler.postMessage({type: 'request'});
+    });
+  };
+  window.webkittenResolveNotificationPermission = function(permission) {
+    Notification.permission = permission;
+    var callbacks = pending;
+    pending = [];
+    callbacks.forEach(function(callback) { callback(permission); });
+  };
+  window.Notification = Notification;
+})();";
+/// The name of the script message handler receiving focus changes
+const FOCUS_HANDLER_NAME: &str = "webkittenFocus";
+/// Script reporting the tag name, type, and editability of the focused
+/// element to the focus message handler whenever focus changes. An empty tag
+/// name is reported when focus leaves the page.
+const FOCUS_SCRIPT: &str = "(function() {
+  var handler = window.webkit && window.webkit.messageHandlers.webkittenFocus;
+  if (!handler || window !== window.top) { return; }
+  function report() {
+    var element = document.activeElement;
+    if (!element || element === document.body || element === document.documentElement) {
+      handler.postMessage({tag: '', type: '', editable: false});
+      return;
+    }
+    var tag = element.tagName.toLowerCase();
+    var type = element.getAttribute('type') || '';
+    var editable = element.isContentEditable || tag === 'textarea' ||
+      (tag === 'input' && !element.readOnly &&
+       !/^(button|checkbox|color|file|hidden|image|radio|range|reset|submit)$/i.test(type));
+    handler.postMessage({tag: tag, type: type, editable: editable});
+  }
+  document.addEventListener('focusin', report, true);
+  document.addEventListener('focusout', function() { setTimeout(report, 0); }, true);
+})();";
+/// The name of the script message handler receiving location requests
+const GEOLOCATION_HANDLER_NAME: &str = "webkittenGeolocation";
+/// Script replacing the Geolocation API with one reporting a fixed latitude
+/// and longitude once the location request handler grants access. Denied
+/// requests fail with a permission error, as the Geolocation API does. The
+/// coordinates are appended as the arguments of the function.
+const FAKE_LOCATION_SCRIPT: &str = "(function(latitude, longitude) {
+  var handler = window.webkit && window.webkit.messageHandlers.webkittenGeolocation;
+  if (!handler || !navigator.geolocation) { return; }
+  var pending = {};
+  var nextId = 1;
+  function request(success, error, watch) {
+    var id = nextId++;
+    pending[id] = {success: success, error: error, watch: watch};
+    handler.postMessage({id: id});
+    return id;
+  }
+  window.webkittenResolveLocation = function(id, granted) {
+    var callbacks = pending[id];
+    if (!callbacks) { return; }
+    if (!callbacks.watch || !granted) { delete pending[id]; }
+    if (granted) {
+      if (callbacks.success) {
+        callbacks.success({timestamp: Date.now(),
+                           coords: {latitude: latitude, longitude: longitude, accuracy: 100,
+                                    altitude: null, altitudeAccuracy: null, heading: null,
+                                    speed: null}});
+      }
+    } else if (callbacks.error) {
+      callbacks.error({code: 1, message: 'User denied Geolocation',
+                       PERMISSION_DENIED: 1, POSITION_UNAVAILABLE: 2, TIMEOUT: 3});
+    }
+  };
+  navigator.geolocation.getCurrentPosition = function(success, error) {
+    request(success, error, false);
+  };
+  navigator.geolocation.watchPosition = function(success, error) {
+    return request(success, error, true);
+  };
+  navigator.geolocation.clearWatch = function(id) { delete pending[id]; };
+})";
+/// The page loaded in place of discarded webview content
+const DISCARDED_PAGE: &str = "about:blank";
+/// Command bar placeholder marking windows using private browsing
+const PRIVATE_WINDOW_PLACEHOLDER: &str = "Private Browsing";
 
 pub fn toggle(window_index: u32, visible: bool) {
     if let Some(window) = window_for_index(window_index) {
@@ -27,10 +165,46 @@ pub fn open<T, B>(uri: Option<T>, config: Option<B>) -> u32
     where B: BrowserConfiguration,
           T: Into<String> {
     let window = create_nswindow();
-    add_and_focus_webview(window.number(), uri, config);
+    add_webview(window.number(), uri, config, false, None);
+    window.number()
+}
+
+/// Open a window where every webview shares a non-persistent data store,
+/// regardless of site private browsing options. The data store is discarded
+/// when the window closes.
+pub fn open_private<T>(uri: Option<T>) -> u32
+    where T: Into<String> {
+    let window = create_nswindow();
+    let store = WKWebsiteDataStore::nonpersistent_store().retain();
+    if let Ok(mut stores) = super::UI.private_stores.lock() {
+        stores.insert(window.number(), store.ptr() as usize);
+    }
+    if let Some(bar) = subview(&window, WindowArea::CommandBar).coerce::<NSTextField>() {
+        bar.set_placeholder(PRIVATE_WINDOW_PLACEHOLDER);
+    }
+    add_webview(window.number(), uri, None::<Config>, false, None);
     window.number()
 }
 
+/// The data store shared by the webviews of a private window, if any
+fn private_store(window_index: u32) -> Option<WKWebsiteDataStore> {
+    super::UI.private_stores.lock().ok()
+        .and_then(|stores| stores.get(&window_index).cloned())
+        .and_then(|address| WKWebsiteDataStore::from_ptr(address as Id))
+}
+
+/// The kind of window, for selecting a start page
+pub fn context(window_index: u32) -> WindowContext {
+    match private_store(window_index) {
+        Some(_) => WindowContext::Private,
+        None => WindowContext::Default,
+    }
+}
+
+pub fn exists(window_index: u32) -> bool {
+    window_for_index(window_index).is_some()
+}
+
 pub fn focus(window_index: u32) {
     if let Some(window) = window_for_index(window_index) {
         window.make_key_and_order_front();
@@ -74,11 +248,34 @@ pub fn focused_index() -> Option<u32> {
     windows.get::<NSWindow>(0).map(|window| window.number())
 }
 
+/// The URIs of the webviews in a window which do not use private browsing
+pub fn persistent_uris(window_index: u32) -> Vec<String> {
+    let mut uris = vec![];
+    if let Some(window) = window_for_index(window_index) {
+        let webviews = window_webviews(&window);
+        for index in 0 .. webviews.count() {
+            if let Some(view) = webviews.get::<WKWebView>(index) {
+                if !view.configuration().website_data_store().is_persistent() {
+                    continue;
+                }
+                if let Some(uri) = deferred_uri(&view) {
+                    uris.push(uri);
+                } else if let Some(uri) = view.url().and_then(|url| url.absolute_string().as_str()) {
+                    uris.push(String::from(uri));
+                }
+            }
+        }
+    }
+    uris
+}
+
 pub fn close(window_index: u32) {
     if let Some(mut window) = window_for_index(window_index) {
         let webviews = window_webviews(&window);
         for index in 0 .. webviews.count() {
             if let Some(view) = webviews.get::<WKWebView>(index) {
+                forget_background_state(&view);
+                super::UI.forget_pending_styles(view.ptr());
                 view.remove_from_superview();
                 view.release_delegates();
                 view.close();
@@ -87,6 +284,13 @@ pub fn close(window_index: u32) {
         window.release_delegate();
         window.close();
     }
+    let store = super::UI.private_stores.lock().ok()
+        .and_then(|mut stores| stores.remove(&window_index));
+    if let Some(mut store) = store.and_then(|address| WKWebsiteDataStore::from_ptr(address as Id)) {
+        info!("discarding private window data store");
+        store.release();
+    }
+    reset_focus_history(window_index);
 }
 
 pub fn title(window_index: u32) -> String {
@@ -103,10 +307,39 @@ pub fn set_title(window_index: u32, title: &str) {
     }
 }
 
+pub fn represented_uri(window_index: u32) -> Option<String> {
+    window_for_index(window_index)
+        .and_then(|window| window.represented_url())
+        .and_then(|url| url.absolute_string().as_str().map(String::from))
+}
+
+pub fn set_represented_uri(window_index: u32, uri: Option<&str>) {
+    if let Some(window) = window_for_index(window_index) {
+        let url = uri.map(|uri| NSURL::from(NSString::from(uri)));
+        window.set_represented_url(url.as_ref());
+    }
+}
+
 pub fn open_webview<T, B>(window_index: u32, uri: Option<T>, config: Option<B>)
     where B: BrowserConfiguration,
           T: Into<String> {
-    add_and_focus_webview(window_index, uri, config);
+    add_webview(window_index, uri, config, false, None);
+}
+
+/// Open a webview in the background of a window, deferring loading the URI
+/// until the webview is first focused
+pub fn open_deferred_webview<T, B>(window_index: u32, uri: Option<T>, config: Option<B>)
+    where B: BrowserConfiguration,
+          T: Into<String> {
+    add_webview(window_index, uri, config, true, None);
+}
+
+/// Replace a webview with a new webview loading a URI at the same position
+/// in the window, such as to change its data store. The back and forward
+/// history of the webview is not kept.
+pub fn reopen_webview(window_index: u32, webview_index: u32, uri: String) {
+    close_webview(window_index, webview_index);
+    add_webview(window_index, Some(uri), None::<Config>, false, Some(webview_index));
 }
 
 pub fn close_webview(window_index: u32, webview_index: u32) {
@@ -118,9 +351,12 @@ pub fn close_webview(window_index: u32, webview_index: u32) {
             for index in 0 .. webviews.count() {
                 if let Some(view) = webviews.get::<WKWebView>(index) {
                     if index == (webview_index as NSUInteger) {
+                        forget_background_state(&view);
+                        super::UI.forget_pending_styles(view.ptr());
                         view.remove_from_superview();
                         view.release_delegates();
                         view.close();
+                        reset_focus_history(window_index);
                         if is_focused {
                             if index as usize >= webviews.count() as usize {
                                 focus_webview(window_index, 0);
@@ -136,20 +372,41 @@ pub fn close_webview(window_index: u32, webview_index: u32) {
     }
 }
 
+/// Move a webview to another position in a window. The focused webview is
+/// the one which is not hidden, so focus moves with it.
+pub fn move_webview(window_index: u32, from: u32, to: u32) -> bool {
+    if let Some(window) = window_for_index(window_index) {
+        let count = window_webviews(&window).count() as u32;
+        if from < count && to < count {
+            subview(&window, WindowArea::WebView).move_subview(from as NSUInteger, to as NSUInteger);
+            reset_focus_history(window_index);
+            return true;
+        }
+    }
+    false
+}
+
 pub fn focus_webview(window_index: u32, webview_index: u32) {
     if let Some(window) = window_for_index(window_index) {
         info!("Focusing webview {} in window {}", webview_index, window_index);
-        let container = subview(&window, WindowArea::WebView);
-        let subviews = container.subviews().unwrap();
-        if webview_index as usize >= subviews.count() as usize {
+        if !UI.webview_exists(window_index, webview_index) {
             return
         }
+        let container = subview(&window, WindowArea::WebView);
+        let subviews = container.subviews().unwrap();
         for index in 0 .. subviews.count() {
             if let Some(view) = subviews.get::<NSView>(index) {
                 let hidden = (webview_index as NSUInteger) != index;
+                if !hidden || !view.hidden() {
+                    record_focus_time(view.ptr());
+                }
                 view.set_hidden(hidden);
                 if !hidden {
+                    if let Some(webview) = view.coerce::<WKWebView>() {
+                        load_deferred(&webview);
+                    }
                     view.coerce::<NSResponder>().unwrap().become_first_responder();
+                    record_focus(window_index, webview_index);
                     UI.engine.on_buffer_event::<CocoaUI<_>, _>(&UI, window_index,
                                                             webview_index,
                                                             None, BufferEvent::Focus);
@@ -160,6 +417,264 @@ pub fn focus_webview(window_index: u32, webview_index: u32) {
     }
 }
 
+/// Indices of the webviews focused in a window, most recent first
+pub fn focus_history(window_index: u32) -> Vec<u32> {
+    super::UI.focus_history.lock().ok()
+        .and_then(|history| history.get(&window_index).cloned())
+        .unwrap_or(vec![])
+}
+
+fn record_focus(window_index: u32, webview_index: u32) {
+    if let Ok(mut history) = super::UI.focus_history.lock() {
+        let indices = history.entry(window_index).or_insert(vec![]);
+        indices.retain(|index| *index != webview_index);
+        indices.insert(0, webview_index);
+    }
+}
+
+/// Discard the focus history of a window, as webview indices change when a
+/// webview is closed
+fn reset_focus_history(window_index: u32) {
+    if let Ok(mut history) = super::UI.focus_history.lock() {
+        history.remove(&window_index);
+    }
+}
+
+/// The URI of a webview whose content was discarded or not yet loaded
+pub fn deferred_uri(webview: &WKWebView) -> Option<String> {
+    super::UI.deferred_uris.lock().ok()
+        .and_then(|uris| uris.get(&(webview.ptr() as usize)).cloned())
+}
+
+/// The time since a webview was created
+pub fn webview_age(window_index: u32, webview_index: u32) -> Duration {
+    webview(window_index, webview_index)
+        .and_then(|webview| webview_times(&webview))
+        .map_or(Duration::default(), |(created, _)| created.elapsed())
+}
+
+/// The time since a webview was last focused, which is zero for the
+/// focused webview of a window
+pub fn webview_idle(window_index: u32, webview_index: u32) -> Duration {
+    if focused_webview_index(window_index) == Some(webview_index) {
+        return Duration::default();
+    }
+    webview(window_index, webview_index)
+        .and_then(|webview| webview_times(&webview))
+        .map_or(Duration::default(), |(_, focused)| focused.elapsed())
+}
+
+fn webview_times(webview: &WKWebView) -> Option<(Instant, Instant)> {
+    super::UI.webview_times.lock().ok()
+        .and_then(|times| times.get(&(webview.ptr() as usize)).cloned())
+}
+
+/// Record that a webview was focused or left focus, resetting its idle time
+fn record_focus_time(webview: Id) {
+    if let Ok(mut times) = super::UI.webview_times.lock() {
+        let now = Instant::now();
+        times.entry(webview as usize).or_insert((now, now)).1 = now;
+    }
+}
+
+/// Unload the content of webviews which have not been focused for the time
+/// set in `general.discard-background-tabs-after`, keeping the URI to reload
+/// when the webview is focused again
+pub fn discard_background_webviews() {
+    let limit = match UI.engine.config.discard_background_tabs_after() {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => return,
+    };
+    for window_index in 0 .. UI.window_count() {
+        let window = match window_for_index(window_index) {
+            Some(window) => window,
+            None => continue,
+        };
+        let webviews = window_webviews(&window);
+        for index in 0 .. webviews.count() {
+            if webview_idle(window_index, index as u32) < limit {
+                continue;
+            }
+            if let Some(webview) = webviews.get::<WKWebView>(index) {
+                discard(&webview);
+            }
+        }
+    }
+}
+
+fn discard(webview: &WKWebView) {
+    if deferred_uri(webview).is_some() {
+        return;
+    }
+    let uri = webview.url()
+        .and_then(|url| url.absolute_string().as_str().map(String::from))
+        .filter(|uri| !uri.is_empty() && uri != DISCARDED_PAGE);
+    if let (Some(uri), Ok(mut uris)) = (uri, super::UI.deferred_uris.lock()) {
+        info!("Discarding background webview content: {}", uri);
+        uris.insert(webview.ptr() as usize, uri);
+        webview.load_request(NSURLRequest::from(NSURL::from(NSString::from(DISCARDED_PAGE))));
+    }
+}
+
+/// Load the content of a discarded or deferred webview
+fn load_deferred(webview: &WKWebView) {
+    let key = webview.ptr() as usize;
+    let uri = super::UI.deferred_uris.lock().ok()
+        .and_then(|mut uris| uris.remove(&key));
+    if let Some(uri) = uri {
+        info!("Loading deferred webview content: {}", uri);
+        webview.load_request(create_request(&uri, UI.cache_mode(&uri)));
+    }
+}
+
+/// Schedule a reload of a webview after the time set in
+/// `sites."[HOST]".refresh-interval` for its page, replacing any reload
+/// scheduled for the previous page
+pub fn schedule_refresh(webview: &WKWebView) {
+    cancel_refresh(webview);
+    let interval = webview.url()
+        .and_then(|url| url.absolute_string().as_str().map(String::from))
+        .filter(|uri| !uri.is_empty() && uri != DISCARDED_PAGE)
+        .and_then(|uri| UI.engine.config.refresh_interval(&uri));
+    if let Some(seconds) = interval {
+        let timer = NSTimer::scheduled_with_info(seconds as f64, &RefreshHandler::new().autorelease(),
+                                                 sel!(refreshWebview:), webview);
+        if let Ok(mut timers) = super::UI.refresh_timers.lock() {
+            timers.insert(webview.ptr() as usize, timer.ptr() as usize);
+        }
+    }
+}
+
+/// Reload the webview of a refresh timer which fired. A focused webview is
+/// left alone for another interval if `sites."[HOST]".refresh-when-focused`
+/// is disabled.
+pub fn refresh_webview(timer: &NSTimer) {
+    let webview = match WKWebView::from_ptr(timer.user_info()) {
+        Some(webview) => webview,
+        None => return,
+    };
+    if let Ok(mut timers) = super::UI.refresh_timers.lock() {
+        timers.remove(&(webview.ptr() as usize));
+    }
+    if let Some((window_index, webview_index)) = reference_indices(webview.ptr()) {
+        let uri = UI.uri(window_index, webview_index);
+        if !UI.engine.config.refresh_when_focused(&uri)
+            && focused_webview_index(window_index) == Some(webview_index) {
+            schedule_refresh(&webview);
+        } else {
+            info!("Refreshing webview: {}", uri);
+            UI.reload_webview(window_index, webview_index, false);
+        }
+    }
+}
+
+/// Stop any scheduled reload of a webview, releasing the reference held by
+/// the timer
+fn cancel_refresh(webview: &WKWebView) {
+    let timer = super::UI.refresh_timers.lock().ok()
+        .and_then(|mut timers| timers.remove(&(webview.ptr() as usize)))
+        .and_then(|timer| NSTimer::from_ptr(timer as Id));
+    if let Some(timer) = timer {
+        timer.invalidate();
+    }
+}
+
+/// Discard background tracking for a webview which is closing, as the
+/// address may be reused by a new webview. Any scheduled reload is
+/// cancelled and configured stylesheets are removed.
+pub fn forget_background_state(webview: &WKWebView) {
+    cancel_refresh(webview);
+    remove_configured_stylesheets(webview);
+    let key = webview.ptr() as usize;
+    if let Ok(mut times) = super::UI.webview_times.lock() {
+        times.remove(&key);
+    }
+    if let Ok(mut uris) = super::UI.deferred_uris.lock() {
+        uris.remove(&key);
+    }
+}
+
+/// The process pool for a new webview. Webviews share one pool unless
+/// `general.share-process-pool` is disabled, while private webviews always
+/// have their own pool.
+fn process_pool(private_browsing: bool) -> WKProcessPool {
+    if private_browsing || !super::UI.engine.config.share_process_pool() {
+        return WKProcessPool::new().autorelease();
+    }
+    match super::UI.process_pool.lock() {
+        Ok(mut shared) => {
+            let address = *shared.get_or_insert_with(|| WKProcessPool::new().ptr() as usize);
+            WKProcessPool::from_ptr(address as Id).unwrap_or_else(|| WKProcessPool::new().autorelease())
+        },
+        Err(_) => WKProcessPool::new().autorelease(),
+    }
+}
+
+pub fn reload_stylesheets() {
+    let windows = nsapp().ordered_windows();
+    for index in 0 .. windows.count() {
+        if let Some(window) = windows.get::<NSWindow>(index) {
+            let webviews = window_webviews(&window);
+            for index in 0 .. webviews.count() {
+                if let Some(webview) = webviews.get::<WKWebView>(index) {
+                    let uri = webview.url()
+                        .and_then(|url| url.absolute_string().as_str())
+                        .unwrap_or("");
+                    apply_configured_stylesheets(&webview, uri);
+                }
+            }
+        }
+    }
+}
+
+/// Replace the user scripts of a webview with the configured user scripts,
+/// removing any injected scripts
+pub fn reset_user_scripts(webview: &WKWebView) {
+    let controller = webview.configuration().user_content_controller();
+    controller.remove_all_user_scripts();
+    add_user_scripts(&controller);
+}
+
+pub fn reload_user_scripts() {
+    let windows = nsapp().ordered_windows();
+    for index in 0 .. windows.count() {
+        if let Some(window) = windows.get::<NSWindow>(index) {
+            let webviews = window_webviews(&window);
+            for index in 0 .. webviews.count() {
+                if let Some(webview) = webviews.get::<WKWebView>(index) {
+                    reset_user_scripts(&webview);
+                }
+            }
+        }
+    }
+}
+
+/// Replace the content filters in all webviews, excluding webviews showing
+/// sites which skip content filtering
+pub fn set_content_filter(filter: &_WKUserContentFilter) {
+    let windows = nsapp().ordered_windows();
+    for index in 0 .. windows.count() {
+        if let Some(window) = windows.get::<NSWindow>(index) {
+            let webviews = window_webviews(&window);
+            for index in 0 .. webviews.count() {
+                if let Some(webview) = webviews.get::<WKWebView>(index) {
+                    let uri = webview.url()
+                        .and_then(|url| url.absolute_string().as_str())
+                        .unwrap_or("");
+                    let controller = webview.configuration().user_content_controller();
+                    controller.remove_all_user_content_filters();
+                    if super::UI.engine.config.skip_content_filter(uri) {
+                        continue;
+                    }
+                    if let Some(filter) = _WKUserContentFilter::from_ptr(filter.ptr()) {
+                        controller.add_user_content_filter(filter);
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub fn webview(window_index: u32, webview_index: u32) -> Option<WKWebView> {
     window_for_index(window_index)
         .and_then(|window| {
@@ -177,6 +692,64 @@ pub fn resize(window_index: u32, width: u32, height: u32) {
     }
 }
 
+/// The visible area of the screen showing the focused window
+pub fn screen_frame() -> Option<WindowFrame> {
+    focused_index()
+        .and_then(|index| window_for_index(index))
+        .and_then(|window| window.screen())
+        .map(|screen| to_window_frame(screen.visible_frame()))
+}
+
+pub fn set_frame(window_index: u32, frame: WindowFrame) {
+    if let Some(window) = window_for_index(window_index) {
+        window.set_frame(from_window_frame(frame));
+    }
+}
+
+pub fn minimized(window_index: u32) -> bool {
+    window_for_index(window_index)
+        .map(|window| window.is_miniaturized())
+        .unwrap_or(false)
+}
+
+pub fn set_minimized(window_index: u32, minimized: bool) {
+    if let Some(window) = window_for_index(window_index) {
+        match (minimized, window.is_miniaturized()) {
+            (true, false) => window.miniaturize(),
+            (false, true) => window.deminiaturize(),
+            _ => (),
+        }
+    }
+}
+
+/// The height of the primary screen, used to convert between screen
+/// coordinates measured from the bottom left and from the top left
+fn primary_screen_height() -> CGFloat {
+    NSScreen::screens().get::<NSScreen>(0)
+        .map(|screen| screen.frame().size.height)
+        .unwrap_or(0.)
+}
+
+fn to_window_frame(rect: NSRect) -> WindowFrame {
+    WindowFrame {
+        x: rect.origin.x as i32,
+        y: (primary_screen_height() - rect.origin.y - rect.size.height) as i32,
+        width: rect.size.width as u32,
+        height: rect.size.height as u32,
+    }
+}
+
+fn from_window_frame(frame: WindowFrame) -> NSRect {
+    let height = frame.height as CGFloat;
+    NSRect {
+        origin: NSPoint {
+            x: frame.x as CGFloat,
+            y: primary_screen_height() - frame.y as CGFloat - height,
+        },
+        size: NSSize { width: frame.width as CGFloat, height }
+    }
+}
+
 pub fn command_field_text(window_index: u32) -> String {
     field_text(window_index, WindowArea::CommandBar)
 }
@@ -254,7 +827,10 @@ fn subview(window: &NSWindow, area: WindowArea) -> NSView {
     subviews.get::<NSView>(index).unwrap()
 }
 
-fn add_and_focus_webview<T, B>(window_index: u32, uri: Option<T>, buffer_config: Option<B>)
+/// Add a webview to a window, either focusing it and loading the URI or
+/// adding it hidden with the URI deferred until it is focused
+fn add_webview<T, B>(window_index: u32, uri: Option<T>, buffer_config: Option<B>, deferred: bool,
+                     position: Option<u32>)
     where B: BrowserConfiguration,
           T: Into<String> {
     let store = _WKUserContentExtensionStore::default_store();
@@ -264,47 +840,95 @@ fn add_and_focus_webview<T, B>(window_index: u32, uri: Option<T>, buffer_config:
     let mut use_plugins = config.use_plugins(&uri);
     let mut skip_content_filter = config.skip_content_filter(&uri);
     let mut use_js = config.use_javascript(&uri);
+    let mut color_scheme = config.color_scheme(&uri);
+    let cache_mode = super::UI.cache_mode(&uri);
     if let Some(buffer_config) = buffer_config {
         private_browsing = buffer_config.use_private_browsing(&uri);
         use_plugins = buffer_config.use_plugins(&uri);
         skip_content_filter = buffer_config.skip_content_filter(&uri);
         use_js = buffer_config.use_javascript(&uri);
+        color_scheme = buffer_config.color_scheme(&uri);
     }
     let block = ConcreteBlock::new(move |filter: Id, err: Id| {
         if let Some(window) = window_for_index(window_index) {
+            let private_store = private_store(window_index);
+            let private_browsing = private_browsing || private_store.is_some();
             let container = subview(&window, WindowArea::WebView);
             let subviews = container.subviews().unwrap();
             for index in 0 .. subviews.count() {
-                if let Some(view) = subviews.get::<NSView>(index) {
+                if let Some(view) = subviews.get::<NSView>(index).filter(|_| !deferred) {
+                    if !view.hidden() {
+                        record_focus_time(view.ptr());
+                    }
                     view.set_hidden(true);
                 }
             }
             let config = WKWebViewConfiguration::new().autorelease();
-            if private_browsing {
+            if let Some(store) = private_store {
+                info!("using private window data store in buffer");
+                config.set_website_data_store(store);
+            } else if private_browsing {
                 info!("blocking data storage in buffer");
                 config.set_website_data_store(WKWebsiteDataStore::nonpersistent_store());
             }
+            config.set_process_pool(&process_pool(private_browsing));
             info!("setting plugins option to {}", use_plugins);
             config.preferences().set_plugins_enabled(use_plugins);
             info!("setting js option to {}", use_js);
             config.preferences().set_javascript_enabled(use_js);
+            config.preferences().set_fullscreen_enabled(true);
+            config.preferences().set_caret_browsing_enabled(super::UI.engine.caret_browsing());
+            if super::UI.engine.config.capture_console() {
+                config.user_content_controller()
+                    .add_script_message_handler(&ConsoleMessageHandler::new().autorelease(),
+                                                CONSOLE_HANDLER_NAME);
+            }
+            config.user_content_controller()
+                .add_script_message_handler(&NotificationHandler::new().autorelease(),
+                                            NOTIFICATION_HANDLER_NAME);
+            config.user_content_controller()
+                .add_script_message_handler(&GeolocationHandler::new().autorelease(),
+                                            GEOLOCATION_HANDLER_NAME);
+            config.user_content_controller()
+                .add_script_message_handler(&FocusHandler::new().autorelease(),
+                                            FOCUS_HANDLER_NAME);
+            add_user_scripts(&config.user_content_controller());
             if let Some(filter) = _WKUserContentFilter::from_ptr(filter) {
                 config.user_content_controller().add_user_content_filter(filter);
             } else if err != nil {
                 log_error_description(err);
             }
             let webview = WKWebView::new(CGRect::zero(), config).autorelease();
+            if let Ok(mut times) = super::UI.webview_times.lock() {
+                let now = Instant::now();
+                times.insert(webview.ptr() as usize, (now, now));
+            }
             webview.set_navigation_delegate(WebViewHistoryDelegate::new());
+            webview.set_ui_delegate(WebViewUIDelegate::new());
             webview.set_custom_user_agent(&default_user_agent());
+            set_background_color(&webview, &uri);
+            apply_configured_stylesheets(&webview, &uri);
             let webview_view = webview.coerce::<NSView>().unwrap();
+            webview_view.set_appearance(appearance(color_scheme));
             webview_view.disable_translates_autoresizing_mask_into_constraints();
+            webview_view.set_hidden(deferred);
             container.add_subview(&webview_view);
+            if !deferred {
+                record_focus(window_index, subviews.count() as u32);
+            }
+            if let Some(position) = position {
+                move_webview(window_index, subviews.count() as u32, position);
+            }
             container.add_constraint(NSLayoutConstraint::bind(&webview_view, NSLayoutAttribute::Top, &container, NSLayoutAttribute::Top));
             container.add_constraint(NSLayoutConstraint::bind(&webview_view, NSLayoutAttribute::Bottom, &container, NSLayoutAttribute::Bottom));
             container.add_constraint(NSLayoutConstraint::bind(&webview_view, NSLayoutAttribute::Left, &container, NSLayoutAttribute::Left));
             container.add_constraint(NSLayoutConstraint::bind(&webview_view, NSLayoutAttribute::Right, &container, NSLayoutAttribute::Right));
-            if !uri.is_empty() {
-                webview.load_request(super::create_request(&uri));
+            if deferred && !uri.is_empty() {
+                if let Ok(mut uris) = super::UI.deferred_uris.lock() {
+                    uris.insert(webview.ptr() as usize, uri.clone());
+                }
+            } else if !uri.is_empty() {
+                webview.load_request(super::create_request(&uri, cache_mode));
             }
         }
     });
@@ -316,6 +940,131 @@ fn add_and_focus_webview<T, B>(window_index: u32, uri: Option<T>, buffer_config:
     }
 }
 
+/// Set the color drawn behind page content configured for a URI, if any
+fn set_background_color(webview: &WKWebView, uri: &str) {
+    if let Some((red, green, blue, alpha)) = super::UI.engine.config.webview_background_color(uri) {
+        webview.set_background_color(&NSColor::from_rgba(red, green, blue, alpha));
+    }
+}
+
+/// Reapply the configured background color to all webviews
+pub fn reload_background_colors() {
+    let windows = nsapp().ordered_windows();
+    for index in 0 .. windows.count() {
+        if let Some(window) = windows.get::<NSWindow>(index) {
+            let webviews = window_webviews(&window);
+            for index in 0 .. webviews.count() {
+                if let Some(webview) = webviews.get::<WKWebView>(index) {
+                    let uri = webview.url().and_then(|url| url.absolute_string().as_str().map(String::from));
+                    set_background_color(&webview, &uri.unwrap_or(String::new()));
+                }
+            }
+        }
+    }
+}
+
+/// The appearance matching a color scheme, or `None` to follow the system
+pub fn appearance(scheme: ColorScheme) -> Option<NSAppearance> {
+    match scheme {
+        ColorScheme::Light => NSAppearance::named("NSAppearanceNameAqua"),
+        ColorScheme::Dark => NSAppearance::named("NSAppearanceNameDarkAqua"),
+        ColorScheme::System => None,
+    }
+}
+
+/// Replace the stylesheet and element hiding styles configured for the
+/// previous page of a webview with those configured for a URI, leaving
+/// stylesheets added using `apply_styles` in place
+pub fn apply_configured_stylesheets(webview: &WKWebView, uri: &str) {
+    remove_configured_stylesheets(webview);
+    let path = super::UI.engine.config.stylesheet_path(uri);
+    if let Some(mut file) = path.and_then(|p| File::open(p).ok()) {
+        let mut contents = String::new();
+        if file.read_to_string(&mut contents).is_ok() {
+            info!("adding configured stylesheet");
+            add_configured_stylesheet(webview, &contents);
+        }
+    }
+    let selectors = super::UI.engine.config.hide_selectors(uri);
+    if let Some(styles) = hiding_stylesheet(&selectors) {
+        info!("adding element hiding stylesheet");
+        add_configured_stylesheet(webview, &styles);
+    }
+}
+
+/// Add a stylesheet to a webview, recording it to be removed by
+/// `remove_configured_stylesheets`
+fn add_configured_stylesheet(webview: &WKWebView, styles: &str) {
+    let controller = webview.configuration().user_content_controller();
+    if !controller.can_add_user_style_sheet() {
+        return;
+    }
+    let sheet = _WKUserStyleSheet::new(styles);
+    let address = sheet.ptr() as usize;
+    controller.add_user_style_sheet(sheet);
+    if let Ok(mut sheets) = super::UI.configured_stylesheets.lock() {
+        sheets.entry(webview.ptr() as usize).or_insert(vec![]).push(address);
+    }
+}
+
+/// Remove the stylesheets added by `apply_configured_stylesheets` from a
+/// webview, releasing them
+fn remove_configured_stylesheets(webview: &WKWebView) {
+    let sheets = super::UI.configured_stylesheets.lock().ok()
+        .and_then(|mut sheets| sheets.remove(&(webview.ptr() as usize)));
+    let controller = webview.configuration().user_content_controller();
+    for address in sheets.unwrap_or(vec![]) {
+        if let Some(mut sheet) = _WKUserStyleSheet::from_ptr(address as Id) {
+            controller.remove_user_style_sheet(&sheet);
+            sheet.release();
+        }
+    }
+}
+
+/// Report the answer to a notification permission request to the page
+/// loaded in a webview
+pub fn resolve_notification_permission(webview: &WKWebView, granted: bool) {
+    let permission = if granted { "granted" } else { "denied" };
+    webview.evaluate_javascript(&format!("window.webkittenResolveNotificationPermission && \
+                                          window.webkittenResolveNotificationPermission('{}');",
+                                         permission));
+}
+
+/// Report the answer to a request for the fake location to the page loaded
+/// in a webview
+pub fn resolve_fake_location(webview: &WKWebView, request_id: i64, granted: bool) {
+    webview.evaluate_javascript(&format!("window.webkittenResolveLocation && \
+                                          window.webkittenResolveLocation({}, {});",
+                                         request_id, granted));
+}
+
+/// Add the configured user scripts to a content controller, along with the
+/// notification and focus scripts, the fake location script if a location is
+/// configured, and the console capture script if enabled
+fn add_user_scripts(controller: &WKUserContentController) {
+    controller.add_user_script(WKUserScript::new(NOTIFICATION_SCRIPT,
+                                                 WKUserScriptInjectionTime::AtDocumentStart));
+    controller.add_user_script(WKUserScript::new(FOCUS_SCRIPT,
+                                                 WKUserScriptInjectionTime::AtDocumentStart));
+    if let Some((latitude, longitude)) = super::UI.engine.config.fake_location() {
+        let script = format!("{}({}, {});", FAKE_LOCATION_SCRIPT, latitude, longitude);
+        controller.add_user_script(WKUserScript::new(&script, WKUserScriptInjectionTime::AtDocumentStart));
+    }
+    if super::UI.engine.config.capture_console() {
+        controller.add_user_script(WKUserScript::new(CONSOLE_CAPTURE_SCRIPT,
+                                                     WKUserScriptInjectionTime::AtDocumentStart));
+    }
+    for script in super::UI.engine.user_scripts() {
+        if let Some(source) = script.guarded_source() {
+            let time = match script.run_at {
+                RunAt::DocumentStart => WKUserScriptInjectionTime::AtDocumentStart,
+                RunAt::DocumentEnd => WKUserScriptInjectionTime::AtDocumentEnd,
+            };
+            controller.add_user_script(WKUserScript::new(&source, time));
+        }
+    }
+}
+
 fn window_webviews(window: &NSWindow) -> NSArray {
     subview(window, WindowArea::WebView).subviews().unwrap()
 }
@@ -341,10 +1090,11 @@ fn create_nswindow() -> NSWindow {
 
 fn layout_window_subviews(window: &NSWindow) {
     let container = WebViewContainerView::new().autorelease().coerce::<NSView>().unwrap();
+    let ref config = super::UI.engine.config;
     let command_bar = CommandBarView::new().autorelease();
     command_bar.set_delegate(&CommandBarDelegate::new());
-    command_bar.set_height(BAR_HEIGHT as CGFloat);
-    let ref config = super::UI.engine.config;
+    let bar_height = if config.show_chrome(Chrome::CommandBar) { BAR_HEIGHT } else { 0 };
+    command_bar.set_height(bar_height as CGFloat);
     let content_view = window.content_view().unwrap();
     let command_bar_view = command_bar.coerce::<NSView>().unwrap();
     content_view.add_subview(&container);